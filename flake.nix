@@ -139,6 +139,7 @@
 
               postPatch = ''
                 sed -i 's|path = "../rudelblinken-runtime", ||' Cargo.toml || true
+                sed -i 's|path = "../rudelblinken-protocol", ||' Cargo.toml || true
               '';
 
               nativeBuildInputs = [
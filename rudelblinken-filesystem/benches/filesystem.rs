@@ -0,0 +1,156 @@
+//! Benchmarks for the filesystem operations that are performed on the device
+//!
+//! Run with `cargo bench`. All benchmarks use a simulated storage with 256 blocks (1 MiB).
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rudelblinken_filesystem::{
+    storage::{simulated::SizedSimulatedStorage, Storage},
+    Filesystem,
+};
+
+type BenchStorage = SizedSimulatedStorage<256>;
+
+/// A filesystem together with the storage it lives on
+///
+/// The filesystem is declared first, so it gets dropped before the storage.
+struct Fixture {
+    filesystem: Filesystem<BenchStorage>,
+    storage: Box<BenchStorage>,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let storage = Box::new(BenchStorage::new());
+        let static_storage = unsafe { std::mem::transmute::<_, &'static BenchStorage>(&*storage) };
+        let filesystem = Filesystem::new(static_storage).unwrap();
+        return Fixture {
+            filesystem,
+            storage,
+        };
+    }
+
+    /// Write `count` files with `size` bytes each
+    fn with_files(count: usize, size: usize) -> Self {
+        let mut fixture = Self::new();
+        for index in 0..count {
+            fixture.write(&format!("file{}", index), size);
+        }
+        return fixture;
+    }
+
+    fn write(&mut self, name: &str, size: usize) {
+        self.filesystem
+            .write_file(name, &vec![0x55u8; size], &[0u8; 32])
+            .unwrap();
+    }
+
+    /// Reopen the filesystem on the same storage
+    fn remount(&self) -> Filesystem<BenchStorage> {
+        let static_storage =
+            unsafe { std::mem::transmute::<_, &'static BenchStorage>(&*self.storage) };
+        return Filesystem::new(static_storage).unwrap();
+    }
+}
+
+fn mount(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mount");
+    for count in [0, 10, 50, 100] {
+        let fixture = Fixture::with_files(count, 1024);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| black_box(fixture.remount()));
+        });
+    }
+    group.finish();
+}
+
+fn write_file(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write_file");
+    for size in [1024, 10 * 1024, 100 * 1024] {
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            b.iter_batched(
+                Fixture::new,
+                |mut fixture| {
+                    fixture.write("file", size);
+                    fixture
+                },
+                BatchSize::PerIteration,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn read_file(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read_file");
+    for size in [1024, 10 * 1024, 100 * 1024] {
+        let fixture = Fixture::with_files(1, size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| {
+                let file = fixture.filesystem.read_file("file0").unwrap();
+                let content = file.upgrade().unwrap();
+                black_box(content.iter().fold(0u8, |acc, byte| acc ^ byte));
+            });
+        });
+    }
+    group.finish();
+}
+
+/// Write into a filesystem where every other block is occupied, so finding free space has to skip over many gaps
+fn write_file_fragmented(c: &mut Criterion) {
+    c.bench_function("write_file_fragmented", |b| {
+        b.iter_batched(
+            || {
+                // Files that fit into a single block
+                let mut fixture = Fixture::with_files(BenchStorage::BLOCKS as usize - 2, 2048);
+                for index in (0..BenchStorage::BLOCKS as usize - 2).step_by(2) {
+                    fixture
+                        .filesystem
+                        .delete_file(&format!("file{}", index))
+                        .unwrap();
+                }
+                fixture
+            },
+            |mut fixture| {
+                fixture.write("new", 2048);
+                fixture
+            },
+            BatchSize::PerIteration,
+        );
+    });
+}
+
+/// Delete files and measure the cleanup that happens before the next write
+fn cleanup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cleanup");
+    for count in [10, 50, 100] {
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter_batched(
+                || {
+                    let mut fixture = Fixture::with_files(count, 2048);
+                    for index in 0..count {
+                        fixture
+                            .filesystem
+                            .delete_file(&format!("file{}", index))
+                            .unwrap();
+                    }
+                    fixture
+                },
+                |mut fixture| {
+                    fixture.write("new", 1024);
+                    fixture
+                },
+                BatchSize::PerIteration,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    mount,
+    write_file,
+    read_file,
+    write_file_fragmented,
+    cleanup
+);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+//! Parse arbitrary bytes as a file header
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rudelblinken_filesystem::{
+    file::{File, FileState},
+    storage::{simulated::SimulatedStorage, Storage},
+};
+
+fuzz_target!(|data: &[u8]| {
+    let owned_storage = SimulatedStorage::new();
+    let storage = unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+    let length = std::cmp::min(data.len(), SimulatedStorage::BLOCK_SIZE as usize);
+    storage.write(0, &data[..length]).unwrap();
+
+    let _ = File::<SimulatedStorage, { FileState::Reader }>::from_storage(storage, 0);
+});
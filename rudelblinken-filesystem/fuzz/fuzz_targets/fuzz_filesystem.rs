@@ -0,0 +1,23 @@
+//! Mount a filesystem on a storage filled with arbitrary bytes
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rudelblinken_filesystem::{
+    storage::{simulated::SimulatedStorage, Storage},
+    Filesystem,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let owned_storage = SimulatedStorage::new();
+    let storage = unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+    // Writes need to be smaller than the storage
+    let length = std::cmp::min(data.len(), SimulatedStorage::SIZE as usize - 1);
+    storage.write(0, &data[..length]).unwrap();
+
+    // Failing to fix the storage is fine, as long as it does not panic
+    let Ok(filesystem) = Filesystem::new(storage) else {
+        return;
+    };
+    let _ = filesystem.read_file("firmware");
+    drop(filesystem);
+});
@@ -1,16 +1,13 @@
 /// [File] provides a safe interface to read and write files.
 use crate::{
-    file_metadata::{FileMetadata, ReadMetadataError, WriteMetadataError},
+    file_metadata::{FileMetadata, HashAlgorithm, ReadMetadataError, WriteMetadataError},
     storage::{EraseStorageError, Storage, StorageError},
 };
-use std::{
-    fmt::Debug,
-    io::{SeekFrom, Write},
-    ops::Deref,
-    ptr::NonNull,
-    sync::RwLock,
-};
-use std::{io::Seek, marker::ConstParamTy};
+use crate::sync::RwLock;
+use alloc::boxed::Box;
+use core::{fmt::Debug, marker::ConstParamTy, ops::Deref, ptr::NonNull};
+#[cfg(feature = "std")]
+use std::io::{Read, Seek, SeekFrom, Write};
 use thiserror::Error;
 use zerocopy::IntoBytes;
 
@@ -130,6 +127,10 @@ struct InnerFile<T: Storage + 'static + Send + Sync> {
     storage_address: u32,
     /// Offset from the base address; only used for writer.
     current_offset: u32,
+    /// End of the furthest write from the base address; only used for writer.
+    written_length: u32,
+    /// Set once the content was committed. Writers that are dropped before that delete the file.
+    committed: bool,
     /// Destructor that will be called when the last strong reference is dropped.
     transition: Box<dyn FnOnce(FileContentTransition) + 'static + Send + Sync>,
     // We need to track this in memory because the flags in memory-mapped flash will be reset when a new file is created in the same place
@@ -137,8 +138,8 @@ struct InnerFile<T: Storage + 'static + Send + Sync> {
     has_been_deleted: bool,
 }
 
-impl<T: Storage + 'static + Send + Sync> std::fmt::Debug for InnerFile<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<T: Storage + 'static + Send + Sync> core::fmt::Debug for InnerFile<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("FileContentInfo")
             .field("weak_count", &self.weak_count)
             .field("reader_count", &self.reader_count)
@@ -189,6 +190,8 @@ pub struct File<T: Storage + 'static + Send + Sync, const STATE: FileState = { F
     content: &'static [u8],
     metadata: &'static FileMetadata,
     info: NonNull<RwLock<InnerFile<T>>>,
+    /// Offset of the next read from the start of the content; only used for readers.
+    position: u32,
 }
 
 unsafe impl<T: Storage + 'static + Send + Sync, const STATE: FileState> Send for File<T, STATE> {}
@@ -217,6 +220,7 @@ impl<T: Storage + 'static + Send + Sync> File<T, { FileState::Reader }> {
         let file = Self {
             content: data,
             metadata,
+            position: 0,
             info: Box::into_non_null(Box::new(RwLock::new(InnerFile {
                 reader_count: 1,
                 weak_count: 0,
@@ -224,6 +228,8 @@ impl<T: Storage + 'static + Send + Sync> File<T, { FileState::Reader }> {
                 storage,
                 storage_address,
                 current_offset: 0,
+                written_length: 0,
+                committed: true,
                 transition: Box::new(transition),
                 has_been_deleted: false,
             }))),
@@ -256,9 +262,12 @@ impl<T: Storage + 'static + Send + Sync> File<T, { FileState::Reader }> {
         address: u32,
     ) -> Result<Self, ReadFileFromStorageError> {
         let metadata = FileMetadata::from_storage(storage, address)?;
+        let content_address = address + size_of::<FileMetadata>() as u32;
         let content = storage
-            .read(address + size_of::<FileMetadata>() as u32, metadata.length)
-            .map_err(ReadFileError::from)?;
+            .read(content_address, metadata.length)
+            .map_err(|e| {
+                ReadFileError::from(e.with_context("reading file content", content_address))
+            })?;
         let file_content =
             File::<T, { FileState::Reader }>::new(content, metadata, storage, address, |_| ())?;
 
@@ -308,6 +317,7 @@ impl<T: Storage + 'static + Send + Sync> File<T, { FileState::Writer }> {
         Ok(Self {
             content: data,
             metadata,
+            position: 0,
             info: Box::into_non_null(Box::new(RwLock::new(InnerFile {
                 reader_count: 0,
                 weak_count: 0,
@@ -315,6 +325,8 @@ impl<T: Storage + 'static + Send + Sync> File<T, { FileState::Writer }> {
                 storage,
                 storage_address,
                 current_offset: 0,
+                written_length: 0,
+                committed: false,
                 transition: Box::new(transition),
                 has_been_deleted: false,
             }))),
@@ -330,9 +342,12 @@ impl<T: Storage + 'static + Send + Sync> File<T, { FileState::Writer }> {
         hash: &[u8; 32],
     ) -> Result<Self, WriteFileToStorageError> {
         let metadata = FileMetadata::new_to_storage(storage, address, name, length, &hash)?;
+        let content_address = address + size_of::<FileMetadata>() as u32;
         let content = storage
-            .read(address + size_of::<FileMetadata>() as u32, metadata.length)
-            .map_err(WriteFileError::from)?;
+            .read(content_address, metadata.length)
+            .map_err(|e| {
+                WriteFileError::from(e.with_context("mapping file content", content_address))
+            })?;
         let file_content = File::<T, { FileState::Writer }>::new_writer(
             content,
             metadata,
@@ -344,23 +359,42 @@ impl<T: Storage + 'static + Send + Sync> File<T, { FileState::Writer }> {
         Ok(file_content)
     }
 
+    /// Set the algorithm that was used for the hash of the content.
+    ///
+    /// New files use blake3. Once a file uses SHA-256 it can not be changed back, as flags can only be cleared.
+    pub fn set_hash_algorithm(
+        &self,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<(), WriteMetadataError> {
+        if hash_algorithm == HashAlgorithm::Blake3 {
+            return Ok(());
+        }
+        let info = unsafe { self.info.as_ref().read().unwrap() };
+        unsafe {
+            self.metadata
+                .set_sha256(info.storage, info.storage_address)?;
+        }
+        return Ok(());
+    }
+
     /// Commit the file content and convert it to a reader.
     ///
-    /// This will finalize the file and make it read-only.
+    /// This will finalize the file and make it read-only. If committing fails, the writer is dropped and the file gets deleted.
     pub fn commit(self) -> Result<File<T, { FileState::Reader }>, CommitFileContentError> {
         {
             let mut info = unsafe { (self.info.as_ref()).write().unwrap() };
             assert!(info.writer_count == 1);
             assert!(info.reader_count == 0);
-            info.writer_count = 0;
-            info.reader_count = 1;
             unsafe {
                 self.metadata
                     .set_ready(info.storage, info.storage_address)?;
             }
+            info.writer_count = 0;
+            info.reader_count = 1;
+            info.committed = true;
         }
         unsafe {
-            Ok(std::mem::transmute::<
+            Ok(core::mem::transmute::<
                 File<T, { FileState::Writer }>,
                 File<T, { FileState::Reader }>,
             >(self))
@@ -378,6 +412,7 @@ impl<T: Storage + 'static + Send + Sync, const STATE: FileState> File<T, STATE>
             content: self.content,
             metadata: self.metadata,
             info: self.info,
+            position: 0,
         }
     }
 
@@ -412,6 +447,7 @@ impl<T: Storage + 'static + Send + Sync, const STATE: FileState> File<T, STATE>
             content: self.content,
             metadata: self.metadata,
             info: self.info,
+            position: 0,
         })
     }
 
@@ -437,6 +473,33 @@ impl<T: Storage + 'static + Send + Sync, const STATE: FileState> File<T, STATE>
         return unsafe { self.info.as_ref().read().unwrap().writer_count };
     }
 
+    /// Get the name of the file.
+    ///
+    /// Unlike the content, the name can also be read from weak references and writers.
+    pub fn name(&self) -> &str {
+        self.metadata.name_str()
+    }
+
+    /// Get the length of the file content in bytes.
+    pub fn length(&self) -> u32 {
+        self.metadata.length
+    }
+
+    /// Check if the file is ready. Same as [File::ready].
+    pub fn is_ready(&self) -> bool {
+        self.ready()
+    }
+
+    /// Check if the file is deleted. Same as [File::deleted].
+    pub fn is_deleted(&self) -> bool {
+        self.deleted()
+    }
+
+    /// Check if the file is marked for deletion. Same as [File::marked_for_deletion].
+    pub fn is_marked_for_deletion(&self) -> bool {
+        self.marked_for_deletion()
+    }
+
     /// Check if the file is marked for deletion.
     pub fn marked_for_deletion(&self) -> bool {
         self.metadata.marked_for_deletion()
@@ -461,11 +524,51 @@ impl<T: Storage + 'static + Send + Sync, const STATE: FileState> File<T, STATE>
         self.metadata.important()
     }
 
+    /// Get the algorithm that was used for the hash of the file.
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.metadata.hash_algorithm()
+    }
+
     /// Check the age of the file.
     pub fn age(&self) -> u8 {
         self.metadata.age()
     }
 
+    /// Check if the file replaces a file that has not been marked for deletion yet.
+    pub fn replacement_pending(&self) -> bool {
+        self.metadata.replacement_pending()
+    }
+
+    /// Mark the file as a replacement for another file with the same name.
+    ///
+    /// The file stays pending until [File::set_replaced] is called.
+    pub(crate) fn set_replacement(&self) -> Result<(), WriteMetadataError> {
+        let info = unsafe { self.info.as_ref().read().unwrap() };
+
+        unsafe {
+            self.metadata
+                .set_replacement(info.storage, info.storage_address)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Mark the replacement as finished, after the replaced file has been marked for deletion.
+    pub(crate) fn set_replaced(&self) -> Result<(), WriteMetadataError> {
+        let info = unsafe { self.info.as_ref().read().unwrap() };
+
+        // The storage of a deleted file may already be used by another file
+        if info.has_been_deleted {
+            return Ok(());
+        }
+        unsafe {
+            self.metadata
+                .set_replaced(info.storage, info.storage_address)?;
+        }
+
+        return Ok(());
+    }
+
     /// Mark the file as important.
     pub fn set_important(&self) -> Result<(), WriteMetadataError> {
         let info = unsafe { self.info.as_ref().read().unwrap() };
@@ -500,13 +603,16 @@ impl<T: Storage + 'static + Send + Sync, const STATE: FileState> File<T, STATE>
     pub(crate) fn mark_for_deletion(&self) -> Result<(), DeleteFileContentError> {
         let info = unsafe { self.info.as_ref().read().unwrap() };
 
-        // TODO: Move this block in the !info.has_been_deleted guard
+        // The storage of a deleted file may already be used by another file
+        if info.has_been_deleted {
+            return Ok(());
+        }
         unsafe {
             self.metadata
                 .set_marked_for_deletion(info.storage, info.storage_address)
-                .unwrap();
+                .map_err(EraseStorageError::from)?;
         };
-        if !info.has_been_deleted && info.writer_count == 0 && info.reader_count == 0 {
+        if info.writer_count == 0 && info.reader_count == 0 {
             drop(info);
             unsafe { self.internal_delete()? };
         }
@@ -535,7 +641,7 @@ impl<T: Storage + 'static + Send + Sync, const STATE: FileState> File<T, STATE>
         > = &mut info.transition;
         let empty_transition: Box<dyn FnOnce(FileContentTransition) + 'static + Send + Sync> =
             Box::new(|_| ());
-        let transition = std::mem::replace(previous_transition, empty_transition);
+        let transition = core::mem::replace(previous_transition, empty_transition);
         (transition)(FileContentTransition::DropLastReader);
 
         self.metadata
@@ -544,10 +650,18 @@ impl<T: Storage + 'static + Send + Sync, const STATE: FileState> File<T, STATE>
         info.has_been_deleted = true;
 
         let full_file_length = self.metadata.length + size_of::<FileMetadata>() as u32;
-        let length = full_file_length.div_ceil(T::BLOCK_SIZE) * T::BLOCK_SIZE;
+        let block_size = info.storage.block_size();
+        let length = full_file_length.div_ceil(block_size) * block_size;
 
         // TODO: Make sure the block with the metadata gets erased last
-        info.storage.erase(info.storage_address, length)?;
+        // Storages can not erase across the end, so files that wrap around are erased in two parts
+        let storage_size = info.storage.block_count() * block_size;
+        let wrapped_length = (info.storage_address + length).saturating_sub(storage_size);
+        info.storage
+            .erase(info.storage_address, length - wrapped_length)?;
+        if wrapped_length > 0 {
+            info.storage.erase(0, wrapped_length)?;
+        }
         Ok(())
     }
 
@@ -592,10 +706,15 @@ impl<T: Storage + 'static + Send + Sync, const STATE: FileState> File<T, STATE>
 
         &self.metadata.hash == hash
     }
+
+    /// Get the hash from the metadata, even if the file is not ready yet
+    pub(crate) fn stored_hash(&self) -> &[u8; 32] {
+        &self.metadata.hash
+    }
 }
 
 impl<T: Storage + 'static + Send + Sync, const STATE: FileState> Debug for File<T, STATE> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("FileContent")
             // .field("content", &self.content)
             .field("metadata", &self.metadata)
@@ -612,6 +731,24 @@ impl<T: Storage + 'static + Send + Sync> Deref for File<T, { FileState::Reader }
     }
 }
 
+impl<T: Storage + 'static + Send + Sync> File<T, { FileState::Reader }> {
+    /// Iterate over the content in chunks of `chunk_size` bytes. The last chunk may be shorter.
+    ///
+    /// The chunks point directly into the memory-mapped storage, so nothing gets copied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn read_chunks(&self, chunk_size: usize) -> core::slice::Chunks<'_, u8> {
+        return self.content.chunks(chunk_size);
+    }
+
+    /// Check that the content matches the stored hash, using the stored hash algorithm
+    pub fn verify_hash(&self) -> bool {
+        return self.metadata.hash_algorithm().hash(self.content) == self.metadata.hash;
+    }
+}
+
 impl<T: Storage + 'static + Send + Sync> PartialEq<Self> for File<T, { FileState::Reader }> {
     fn eq(&self, other: &Self) -> bool {
         self.content == other.content
@@ -626,6 +763,7 @@ impl<T: Storage + 'static + Send + Sync> Clone for File<T, { FileState::Reader }
             content: self.content,
             metadata: self.metadata,
             info: self.info,
+            position: self.position,
         }
     }
 }
@@ -638,6 +776,7 @@ impl<T: Storage + 'static + Send + Sync> Clone for File<T, { FileState::Weak }>
             content: self.content,
             metadata: self.metadata,
             info: self.info,
+            position: 0,
         }
     }
 }
@@ -662,8 +801,10 @@ impl<T: Storage + 'static + Send + Sync, const STATE: FileState> Drop for File<T
 
         let weak_count = info.weak_count;
         let has_been_deleted = info.has_been_deleted;
+        // Committing turns the writer into a reader without dropping it, so this write was aborted
+        let aborted = !info.committed;
         drop(info);
-        if !has_been_deleted && self.metadata.marked_for_deletion() {
+        if !has_been_deleted && (aborted || self.metadata.marked_for_deletion()) {
             unsafe {
                 // We cant really handle a failed deletion here
                 // TODO: maybe log it
@@ -679,6 +820,57 @@ impl<T: Storage + 'static + Send + Sync, const STATE: FileState> Drop for File<T
     }
 }
 
+impl<T: Storage + 'static + Send + Sync> File<T, { FileState::Writer }> {
+    /// Write at the current offset and advance it. Returns the number of bytes that were written.
+    ///
+    /// Works without `std`. With `std` you can use the [std::io::Write] implementation instead.
+    ///
+    /// You can only flip bits from 1 to 0.
+    pub fn write_content(&mut self, buf: &[u8]) -> Result<usize, StorageError> {
+        let length = self.content.len() as u32;
+        let mut info = unsafe { self.info.as_ref().write().unwrap() };
+        let current_offset = info.current_offset;
+
+        let remaining_length = length.saturating_sub(current_offset);
+        let write_length = core::cmp::min(remaining_length, buf.len() as u32);
+
+        let writable_storage = info.storage;
+        let write_address =
+            info.storage_address + size_of::<FileMetadata>() as u32 + current_offset;
+        writable_storage
+            .write(write_address, &buf[0..write_length as usize])
+            .map_err(|e| e.with_context("writing file content", write_address))?;
+        info.current_offset += write_length;
+        info.written_length = core::cmp::max(info.written_length, info.current_offset);
+        Ok(write_length as usize)
+    }
+}
+
+/// Calculate the offset after seeking. The offset is clamped to the length of the content.
+#[cfg(feature = "std")]
+fn seek_offset(pos: SeekFrom, current_offset: u32, length: u32) -> u32 {
+    return match pos {
+        SeekFrom::Start(offset) => offset.try_into().unwrap_or(u32::MAX).clamp(0, length),
+        SeekFrom::End(offset) => length
+            .saturating_add_signed(
+                offset
+                    .clamp(isize::MIN as i64, isize::MAX as i64)
+                    .try_into()
+                    .unwrap(),
+            )
+            .clamp(0, length),
+        SeekFrom::Current(offset) => current_offset
+            .saturating_add_signed(
+                offset
+                    .clamp(isize::MIN as i64, isize::MAX as i64)
+                    .try_into()
+                    .unwrap(),
+            )
+            .clamp(0, length),
+    };
+}
+
+#[cfg(feature = "std")]
 impl<T: Storage + 'static + Send + Sync> Seek for File<T, { FileState::Writer }> {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
         let length = self.content.len() as u32;
@@ -690,56 +882,59 @@ impl<T: Storage + 'static + Send + Sync> Seek for File<T, { FileState::Writer }>
                 .map_err(|e| std::io::Error::other(e.to_string()))?
                 .current_offset
         };
-        let new_offset = match pos {
-            SeekFrom::Start(offset) => offset.try_into().unwrap_or(u32::MAX).clamp(0, length),
-            SeekFrom::End(offset) => length
-                .saturating_add_signed(
-                    offset
-                        .clamp(isize::MIN as i64, isize::MAX as i64)
-                        .try_into()
-                        .unwrap(),
-                )
-                .clamp(0, length),
-            SeekFrom::Current(offset) => current_offset
-                .saturating_add_signed(
-                    offset
-                        .clamp(isize::MIN as i64, isize::MAX as i64)
-                        .try_into()
-                        .unwrap(),
-                )
-                .clamp(0, length),
-        };
-
-        *current_offset = new_offset;
+        *current_offset = seek_offset(pos, *current_offset, length);
         Ok(*current_offset as u64)
     }
 }
 
-impl<T: Storage + 'static + Send + Sync> Write for File<T, { FileState::Writer }> {
-    /// The same as [std::io::Write::write] but you can only flip bits from 1 to 0.
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let length = self.content.len() as u32;
-        let info = unsafe {
-            &mut self
-                .info
+#[cfg(feature = "std")]
+impl<T: Storage + 'static + Send + Sync> Read for File<T, { FileState::Writer }> {
+    /// Read back content that has already been written, starting at the current offset.
+    ///
+    /// Reading and writing share the same offset, like with [std::fs::File]. Nothing is read past the end of the furthest write.
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut info = unsafe {
+            self.info
                 .as_ref()
                 .write()
-                .map_err(|_| std::io::ErrorKind::ResourceBusy)?
+                .map_err(|e| std::io::Error::other(e.to_string()))?
         };
-        let current_offset = info.current_offset;
+        let start = core::cmp::min(info.current_offset, info.written_length) as usize;
+        let written = &self.content[start..info.written_length as usize];
+        let read_length = core::cmp::min(written.len(), buf.len());
+        buf[0..read_length].copy_from_slice(&written[0..read_length]);
+        info.current_offset = (start + read_length) as u32;
+        Ok(read_length)
+    }
+}
 
-        let remaining_length = length.saturating_sub(current_offset);
-        let write_length = std::cmp::min(remaining_length, buf.len() as u32);
+#[cfg(feature = "std")]
+impl<T: Storage + 'static + Send + Sync> Seek for File<T, { FileState::Reader }> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.position = seek_offset(pos, self.position, self.content.len() as u32);
+        Ok(self.position as u64)
+    }
+}
 
-        let writable_storage = info.storage;
-        writable_storage
-            .write(
-                info.storage_address + size_of::<FileMetadata>() as u32 + current_offset,
-                &buf[0..write_length as usize],
-            )
-            .map_err(std::io::Error::other)?;
-        info.current_offset += write_length;
-        Ok(write_length as usize)
+#[cfg(feature = "std")]
+impl<T: Storage + 'static + Send + Sync> Read for File<T, { FileState::Reader }> {
+    /// Read from the current position and advance it.
+    ///
+    /// Every reader has its own position. Clones start at the position of the reader they were cloned from.
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = &self.content[self.position as usize..];
+        let read_length = core::cmp::min(remaining.len(), buf.len());
+        buf[0..read_length].copy_from_slice(&remaining[0..read_length]);
+        self.position += read_length as u32;
+        Ok(read_length)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Storage + 'static + Send + Sync> Write for File<T, { FileState::Writer }> {
+    /// The same as [std::io::Write::write] but you can only flip bits from 1 to 0.
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_content(buf).map_err(std::io::Error::other)
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
@@ -841,6 +1036,17 @@ mod tests {
         assert!(File::is_last(&content));
     }
 
+    #[test]
+    fn weak_references_know_the_name_and_length() {
+        let content = call_new();
+        let weak_content = content.downgrade();
+        assert_eq!(weak_content.name(), "toast");
+        assert_eq!(weak_content.length(), 100);
+        assert!(weak_content.is_ready());
+        assert!(!weak_content.is_deleted());
+        assert!(!weak_content.is_marked_for_deletion());
+    }
+
     #[test]
     fn upgrading_works() {
         let content = call_new();
@@ -890,6 +1096,44 @@ mod tests {
         };
     }
 
+    #[test]
+    fn reading_in_chunks_returns_the_whole_content() {
+        let content = call_new();
+        let chunks: Vec<&[u8]> = content.read_chunks(30).collect();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[3].len(), 10);
+        assert_eq!(chunks.concat(), content.as_ref());
+    }
+
+    #[test]
+    fn reading_and_seeking_a_reader_works() {
+        let (storage, content, metadata) = get_backing();
+        content[50] = 17;
+        let mut content =
+            File::<_, { FileState::Reader }>::new(content, metadata, storage, 0, |_| ()).unwrap();
+        let mut buffer = [0u8; 2];
+        content.seek(SeekFrom::Start(50)).unwrap();
+        content.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer[0], 17);
+        assert_eq!(content.seek(SeekFrom::Current(-2)).unwrap(), 50);
+        let mut rest = Vec::new();
+        assert_eq!(content.read_to_end(&mut rest).unwrap(), 50);
+        assert_eq!(rest.as_slice(), &content[50..]);
+    }
+
+    #[test]
+    fn a_writer_can_read_back_what_it_wrote() {
+        let storage = get_test_storage();
+        let mut writer =
+            File::<_, { FileState::Writer }>::to_storage(storage, 0, 100, "toast", &[0; 32])
+                .unwrap();
+        writer.write_all(&[1, 2, 3, 4]).unwrap();
+        writer.seek(SeekFrom::Start(1)).unwrap();
+        let mut written = Vec::new();
+        writer.read_to_end(&mut written).unwrap();
+        assert_eq!(written, [2, 3, 4]);
+    }
+
     #[test]
     fn deleting_when_there_is_no_reader_works() {
         let content = call_new();
@@ -2,9 +2,11 @@ use crate::{
     file::{
         DeleteFileContentError, File, FileState, ReadFileFromStorageError, WriteFileToStorageError,
     },
+    file_metadata::WriteMetadataError,
     storage::Storage,
 };
-use std::fmt::Formatter;
+use alloc::string::String;
+use core::fmt::Formatter;
 
 /// Internal proxy for a file that tracks some metadata in memory
 pub(crate) struct FileInformation<T: Storage + 'static + Send + Sync> {
@@ -30,8 +32,8 @@ impl<T: Storage + 'static + Send + Sync> Clone for FileInformation<T> {
     }
 }
 
-impl<T: Storage + 'static + Send + Sync> std::fmt::Debug for FileInformation<T> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+impl<T: Storage + 'static + Send + Sync> core::fmt::Debug for FileInformation<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("File")
             .field("address", &self.address)
             .field("length", &self.length)
@@ -111,6 +113,16 @@ impl<T: Storage + 'static + Send + Sync> FileInformation<T> {
         self.content.age()
     }
 
+    /// Check if the file replaces a file that has not been marked for deletion yet
+    pub fn replacement_pending(&self) -> bool {
+        self.content.replacement_pending()
+    }
+
+    /// Finish the replacement after the replaced file has been marked for deletion
+    pub fn set_replaced(&self) -> Result<(), WriteMetadataError> {
+        self.content.set_replaced()
+    }
+
     /// Check if the file is important
     pub fn can_be_deleted(&self) -> bool {
         self.content.can_be_deleted()
@@ -123,6 +135,11 @@ impl<T: Storage + 'static + Send + Sync> FileInformation<T> {
         self.content.compare_hash(hash)
     }
 
+    /// Get the hash of the file, even if it is not ready to be read
+    pub fn hash(&self) -> &[u8; 32] {
+        self.content.stored_hash()
+    }
+
     /// Read the file content
     pub fn read(&self) -> File<T, { FileState::Weak }> {
         self.content.clone()
@@ -5,7 +5,7 @@
 //! # Overview
 //!
 //! The `FileMetadata` struct represents the metadata segment of a file that is memory-mapped
-//! into storage. It includes fields for flags, length, hash, name, and padding. The struct
+//! into storage. It includes fields for flags, length, hash, name, a checksum, and padding. The struct
 //! provides methods for creating new metadata, reading existing metadata from storage, and
 //! setting various flags in the metadata.
 //!
@@ -20,16 +20,32 @@
 //! assumptions are violated. Use these methods with caution and ensure that the metadata
 //! is correctly memory-mapped before calling them.
 use crate::storage::{Storage, StorageError};
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use sha2::Digest;
 use thiserror::Error;
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
 
 /// Errors that can occur when reading metadata from storage.
 #[derive(Error, Debug)]
 pub enum ReadMetadataError {
+    /// The read metadata does not have valid marker flags
     #[error("The read metadata does not have valid marker flags")]
     InvalidMarkers,
+    /// Failed to interpret the storage as metadata
     #[error("Failed to interpret the storage as metadata: {0}")]
     FailedToInterpretStorageAsMetadata(String),
+    /// The checksum does not match the length, hash and name, for example after a power loss while writing them
+    #[error("The metadata checksum {stored:#010x} does not match the calculated checksum {calculated:#010x}")]
+    CrcMismatch {
+        /// The checksum in the metadata
+        stored: u32,
+        /// The checksum of the length, hash and name
+        calculated: u32,
+    },
+    /// Error in the storage layer
     #[error(transparent)]
     StorageError(#[from] StorageError),
 }
@@ -37,8 +53,10 @@ pub enum ReadMetadataError {
 /// Errors that can occur when writing metadata to storage.
 #[derive(Error, Debug)]
 pub enum WriteMetadataError {
+    /// Failed to interpret the storage as metadata
     #[error("Failed to interpret the storage as metadata: {0}")]
     FailedToInterpretStorageAsMetadata(String),
+    /// Error in the storage layer
     #[error(transparent)]
     StorageError(#[from] StorageError),
 }
@@ -54,6 +72,78 @@ impl FileFlags {
     const DELETED: u16 =             0b0000000001000000;
     /// Important files wont be deleted automatically if space is needed
     const IMPORTANT: u16 =           0b0000000010000000;
+    /// Replacements stay hidden until the file they replace has been marked for deletion
+    const REPLACEMENT: u16 =         0b0000000100000000;
+    /// The file that was replaced by this file has been marked for deletion
+    const REPLACED: u16 =            0b0000010000000000;
+    /// The hash is a SHA-256 hash. Files without this flag use blake3
+    const SHA256: u16 =              0b0000100000000000;
+    /// The metadata contains a checksum. Files written by older versions do not have one
+    const CRC: u16 =                 0b0001000000000000;
+}
+
+/// CRC-32C over the fields of the metadata that do not change after it was written
+const METADATA_CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
+
+/// The algorithm that was used to calculate the hash of a file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// The default for all files
+    #[default]
+    Blake3,
+    /// For compatibility with tools that only support SHA-256
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Calculate the hash of `content` with this algorithm
+    pub fn hash(&self, content: &[u8]) -> [u8; 32] {
+        match self {
+            HashAlgorithm::Blake3 => blake3::hash(content).into(),
+            HashAlgorithm::Sha256 => sha2::Sha256::digest(content).into(),
+        }
+    }
+
+    /// Create a [Hasher] that calculates the hash with this algorithm from multiple parts
+    pub fn hasher(&self) -> Hasher {
+        match self {
+            HashAlgorithm::Blake3 => Hasher::Blake3(blake3::Hasher::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+        }
+    }
+}
+
+/// Incrementally calculates a hash, so the content does not need to be in one slice
+///
+/// Created with [HashAlgorithm::hasher]
+// Boxing the blake3 state would allocate on every hash
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+pub enum Hasher {
+    /// Calculates a blake3 hash
+    Blake3(blake3::Hasher),
+    /// Calculates a SHA-256 hash
+    Sha256(sha2::Sha256),
+}
+
+impl Hasher {
+    /// Add the next part of the content
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Get the hash of all parts that were added
+    pub fn finalize(self) -> [u8; 32] {
+        match self {
+            Hasher::Blake3(hasher) => hasher.finalize().into(),
+            Hasher::Sha256(hasher) => hasher.finalize().into(),
+        }
+    }
 }
 
 /// Represents a the metadata segment of a file that is memory-mapped into storage.
@@ -69,16 +159,20 @@ pub struct FileMetadata {
     age: u16,
     /// Length in bytes
     pub length: u32,
-    /// SHA3-256 hash of the file
+    /// Hash of the file, calculated with [FileMetadata::hash_algorithm]
     pub hash: [u8; 32],
     /// Name of the file, null terminated or 16 chars
     pub name: [u8; 16],
+    /// CRC-32C of the length, hash and name. Only valid if the CRC flag is set
+    ///
+    /// The flags and the age are changed in place, so they are not covered.
+    metadata_crc: u32,
     /// Reserved space to fill the metadata to 64 byte
-    _padding: [u8; 8],
+    _padding: [u8; 4],
 }
 
-impl std::fmt::Debug for FileMetadata {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Debug for FileMetadata {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let hash_string = &self.hash.iter().fold(String::new(), |mut string, byte| {
             string.push_str(&format!("{:02x}", byte));
             string
@@ -90,6 +184,7 @@ impl std::fmt::Debug for FileMetadata {
             .field("deleted", &self.deleted())
             .field("length", &self.length)
             .field("hash", &hash_string)
+            .field("hash_algorithm", &self.hash_algorithm())
             .field("name", &self.name_str())
             .field("important", &self.important())
             .finish()
@@ -100,16 +195,35 @@ impl FileMetadata {
     /// Create a new file metadata object in ram
     fn new(name: &str, length: u32, hash: &[u8; 32]) -> Self {
         let mut metadata = FileMetadata {
-            flags: u16::MAX ^ FileFlags::LOW_MARKERS,
+            flags: u16::MAX ^ FileFlags::LOW_MARKERS ^ FileFlags::CRC,
             age: u16::MAX,
             length,
             hash: *hash,
             name: [0; 16],
-            _padding: [0; 8],
+            metadata_crc: 0,
+            _padding: [0; 4],
         };
         metadata.set_name(name);
+        metadata.metadata_crc = metadata.calculate_crc();
         metadata
     }
+    /// Calculate the checksum of the length, hash and name
+    fn calculate_crc(&self) -> u32 {
+        let mut digest = METADATA_CRC.digest();
+        digest.update(&self.length.to_le_bytes());
+        digest.update(&self.hash);
+        digest.update(&self.name);
+        digest.finalize()
+    }
+    /// Get the stored checksum of the length, hash and name
+    ///
+    /// Returns `None` for metadata that was written without a checksum.
+    pub fn metadata_crc(&self) -> Option<u32> {
+        if self.flags & FileFlags::CRC != 0 {
+            return None;
+        }
+        Some(self.metadata_crc)
+    }
     /// Assert that the marker flags have been set correctly for this file
     pub fn valid_marker(&self) -> bool {
         if self.flags & FileFlags::HIGH_MARKERS != FileFlags::HIGH_MARKERS {
@@ -123,7 +237,7 @@ impl FileMetadata {
     /// Convenience function to get the name as a string slice
     pub fn name_str(&self) -> &str {
         let nul_range_end = self.name.iter().position(|&c| c == b'\0').unwrap_or(16);
-        std::str::from_utf8(&self.name[0..nul_range_end]).unwrap_or_default()
+        core::str::from_utf8(&self.name[0..nul_range_end]).unwrap_or_default()
     }
     /// Internal function to set the name from a string slice
     fn set_name(&mut self, name: &str) {
@@ -142,7 +256,9 @@ impl FileMetadata {
         flags: u16,
     ) -> Result<(), StorageError> {
         let flags: u16 = self.flags & !flags;
-        storage.write(address, flags.as_bytes())
+        storage
+            .write(address, flags.as_bytes())
+            .map_err(|e| e.with_context("writing file flags", address))
     }
 
     /// Increase the age of the metadata in storage
@@ -154,7 +270,9 @@ impl FileMetadata {
         address: u32,
     ) -> Result<(), StorageError> {
         let new_age: u16 = self.age >> 1;
-        storage.write(address + 2, new_age.as_bytes())
+        storage
+            .write(address + 2, new_age.as_bytes())
+            .map_err(|e| e.with_context("writing file age", address + 2))
     }
 
     /// Set the ready flag of the metadata in storage
@@ -201,6 +319,39 @@ impl FileMetadata {
         self.set_flags(storage, address, FileFlags::IMPORTANT)
     }
 
+    /// Set the replacement flag of the metadata in storage
+    ///
+    /// Assumes that this metadata is located at `address`. Undefined behaviour if it is not or has since been deleted
+    pub unsafe fn set_replacement<T: Storage>(
+        &self,
+        storage: &T,
+        address: u32,
+    ) -> Result<(), StorageError> {
+        self.set_flags(storage, address, FileFlags::REPLACEMENT)
+    }
+
+    /// Set the replaced flag of the metadata in storage
+    ///
+    /// Assumes that this metadata is located at `address`. Undefined behaviour if it is not or has since been deleted
+    pub unsafe fn set_replaced<T: Storage>(
+        &self,
+        storage: &T,
+        address: u32,
+    ) -> Result<(), StorageError> {
+        self.set_flags(storage, address, FileFlags::REPLACED)
+    }
+
+    /// Set the flag for SHA-256 hashes in storage
+    ///
+    /// Assumes that this metadata is located at `address`. Undefined behaviour if it is not or has since been deleted
+    pub unsafe fn set_sha256<T: Storage>(
+        &self,
+        storage: &T,
+        address: u32,
+    ) -> Result<(), StorageError> {
+        self.set_flags(storage, address, FileFlags::SHA256)
+    }
+
     /// Check if the file is ready to be read
     pub fn ready(&self) -> bool {
         self.flags & FileFlags::READY == 0
@@ -221,6 +372,19 @@ impl FileMetadata {
         self.flags & FileFlags::IMPORTANT == 0
     }
 
+    /// Check if the file is a replacement for a file that has not been marked for deletion yet
+    pub fn replacement_pending(&self) -> bool {
+        self.flags & FileFlags::REPLACEMENT == 0 && self.flags & FileFlags::REPLACED != 0
+    }
+
+    /// Get the algorithm that was used for the hash
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        if self.flags & FileFlags::SHA256 == 0 {
+            return HashAlgorithm::Sha256;
+        }
+        HashAlgorithm::Blake3
+    }
+
     /// Get the age of the metadata.
     pub fn age(&self) -> u8 {
         self.age.count_ones() as u8
@@ -236,7 +400,9 @@ impl FileMetadata {
     ) -> Result<&'static Self, WriteMetadataError> {
         let new_metadata = Self::new(name, length, hash);
         let as_bytes = new_metadata.as_bytes();
-        let memory_mapped_metadata = storage.write_checked(address, as_bytes)?;
+        let memory_mapped_metadata = storage
+            .write_checked(address, as_bytes)
+            .map_err(|e| e.with_context("writing file metadata", address))?;
         FileMetadata::ref_from_bytes(memory_mapped_metadata)
             .map_err(|e| WriteMetadataError::FailedToInterpretStorageAsMetadata(e.to_string()))
     }
@@ -248,22 +414,52 @@ impl FileMetadata {
         storage: &T,
         address: u32,
     ) -> Result<&'static Self, ReadMetadataError> {
-        let data = storage.read(address, size_of::<FileMetadata>() as u32)?;
+        let data = storage
+            .read(address, size_of::<FileMetadata>() as u32)
+            .map_err(|e| e.with_context("reading file metadata", address))?;
 
         let metadata = FileMetadata::ref_from_bytes(data)
             .map_err(|e| ReadMetadataError::FailedToInterpretStorageAsMetadata(e.to_string()))?;
         if !metadata.valid_marker() {
             return Err(ReadMetadataError::InvalidMarkers);
         }
+        if let Some(stored) = metadata.metadata_crc() {
+            let calculated = metadata.calculate_crc();
+            if stored != calculated {
+                return Err(ReadMetadataError::CrcMismatch { stored, calculated });
+            }
+        }
         Ok(metadata)
     }
 }
 
+/// Check if a valid file starts at the given address
+///
+/// This does not require a [crate::Filesystem]. A block is valid if it starts with metadata that has the correct markers and a length that is not zero and fits into the storage.
+pub fn is_valid_block<T: Storage>(storage: &T, address: u32) -> bool {
+    let Ok(metadata) = FileMetadata::from_storage(storage, address) else {
+        return false;
+    };
+    return metadata.length != 0 && metadata.length <= storage.block_size() * storage.block_count();
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::storage::simulated::SimulatedStorage;
 
+    #[test]
+    fn hashing_in_parts_matches_hashing_at_once() {
+        let content: Vec<u8> = (0..10_000u32).map(|index| index as u8).collect();
+        for algorithm in [HashAlgorithm::Blake3, HashAlgorithm::Sha256] {
+            let mut hasher = algorithm.hasher();
+            for chunk in content.chunks(4096) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.finalize(), algorithm.hash(&content));
+        }
+    }
+
     #[test]
     fn storing_metadata_works() {
         let mut storage = SimulatedStorage::new();
@@ -290,4 +486,71 @@ mod tests {
         assert_eq!(read_metadata.name_str(), "toast");
         assert!(read_metadata.valid_marker());
     }
+
+    #[test]
+    fn corrupted_metadata_is_detected() {
+        let storage = SimulatedStorage::new();
+        let metadata =
+            FileMetadata::new_to_storage(&storage, 0, "toast", 300, &[0xff; 32]).unwrap();
+        assert!(metadata.metadata_crc().is_some());
+        // Flash can only clear bits, so this changes the first byte of the hash
+        storage.write(8, &[0x00]).unwrap();
+        let Err(ReadMetadataError::CrcMismatch { .. }) = FileMetadata::from_storage(&storage, 0)
+        else {
+            panic!("Reading corrupted metadata should fail");
+        };
+        assert!(!is_valid_block(&storage, 0));
+    }
+
+    #[test]
+    fn metadata_without_a_checksum_can_be_read() {
+        let storage = SimulatedStorage::new();
+        let mut metadata = FileMetadata::new("toast", 300, &[0; 32]);
+        metadata.flags |= FileFlags::CRC;
+        metadata.metadata_crc = 0;
+        storage.write(0, metadata.as_bytes()).unwrap();
+        let read_metadata = FileMetadata::from_storage(&storage, 0).unwrap();
+        assert_eq!(read_metadata.metadata_crc(), None);
+        assert_eq!(read_metadata.name_str(), "toast");
+    }
+
+    #[test]
+    fn block_with_metadata_is_valid() {
+        let storage = SimulatedStorage::new();
+        let _ = FileMetadata::new_to_storage(&storage, 4096, "toast", 300, &[0; 32]).unwrap();
+        assert!(is_valid_block(&storage, 4096));
+    }
+
+    #[test]
+    fn erased_block_is_not_valid() {
+        let storage = SimulatedStorage::new();
+        assert!(!is_valid_block(&storage, 0));
+    }
+
+    #[test]
+    fn block_outside_of_the_storage_is_not_valid() {
+        let storage = SimulatedStorage::new();
+        assert!(!is_valid_block(&storage, SimulatedStorage::SIZE));
+        assert!(!is_valid_block(&storage, u32::MAX));
+    }
+
+    #[test]
+    fn storage_errors_contain_the_operation_and_address() {
+        let storage = SimulatedStorage::new();
+        let Err(ReadMetadataError::StorageError(error)) =
+            FileMetadata::from_storage(&storage, SimulatedStorage::SIZE)
+        else {
+            panic!("Reading outside of the storage should fail");
+        };
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Storage operation failed while reading file metadata at address {:#x}: Accessing {} bytes at address {:#x} is out of bounds for a storage of {} bytes",
+                SimulatedStorage::SIZE,
+                size_of::<FileMetadata>(),
+                SimulatedStorage::SIZE,
+                SimulatedStorage::SIZE
+            )
+        );
+    }
 }
@@ -25,6 +25,7 @@
 //!
 //! The age of a file is determined by the number of ticks and reboots since it was created. It can be a number between 0 and 15. A file with age 16 has just been created, while a file with age 1 is the oldest file. Every reboot increases the age of all files by 1. You can manually call the tick method to age all files.
 //! Files with age 16 require 1 tick to go to 15. Files with age 15 require 2 ticks to go to 14. Files with age 14 require 3 ticks. The recommended tick rate is once per minute.
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(missing_docs)]
 #![allow(static_mut_refs)]
 #![feature(adt_const_params)]
@@ -47,28 +48,55 @@ use rudelblinken_filesystem::Filesystem;
 let storage = SimulatedStorage::new();
 // TODO: Improve the interface to allow storages with lifetimes
 let static_storage_ref = unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&storage) };
-let mut filesystem = Filesystem::new(static_storage_ref);
+let mut filesystem = Filesystem::new(static_storage_ref).unwrap();
 ```
 "##
 )]
-use file::{CommitFileContentError, File, FileState, WriteFileToStorageError};
+use file::{
+    CommitFileContentError, DeleteFileContentError, File, FileState, ReadFileError,
+    ReadFileFromStorageError, WriteFileToStorageError,
+};
 use file_information::FileInformation;
-use file_metadata::FileMetadata;
-use std::{
+use file_metadata::{FileMetadata, HashAlgorithm, ReadMetadataError, WriteMetadataError};
+use alloc::{
+    boxed::Box,
     collections::{BTreeMap, VecDeque},
-    io::Write,
-    ops::Bound::Included,
-    u16,
+    vec::Vec,
 };
-use storage::{EraseStorageError, Storage};
+use core::ops::Bound::Included;
+use storage::{EraseStorageError, MetadataError, Storage, StorageError};
 use thiserror::Error;
 
+extern crate alloc;
+
+/// Number of bytes that are copied at once by [Filesystem::copy_between]
+const COPY_CHUNK_SIZE: usize = 4096;
+
 /// [file::File] provides a safe interface to read and write files.
 pub mod file;
 mod file_information;
-mod file_metadata;
+/// Memory-mapped metadata at the start of each file
+pub mod file_metadata;
 /// Storage traits and implementations
 pub mod storage;
+mod sync;
+
+/// Errors that can occur when opening a filesystem with [Filesystem::new]
+#[derive(Error, Debug)]
+pub enum FilesystemInitError {
+    /// The first block could not be written to the storage metadata
+    #[error("Failed to write the first block to the storage metadata")]
+    MetadataWriteFailed(#[source] MetadataError),
+    /// A corrupted block could not be erased
+    #[error(transparent)]
+    EraseStorageError(#[from] EraseStorageError),
+    /// A file of an interrupted replacement could not be marked for deletion
+    #[error(transparent)]
+    DeleteFileContentError(#[from] DeleteFileContentError),
+    /// An interrupted replacement could not be marked as finished
+    #[error(transparent)]
+    WriteMetadataError(#[from] WriteMetadataError),
+}
 
 /// Errors that can occur when finding free space
 #[derive(Error, Debug, Clone)]
@@ -82,6 +110,9 @@ pub enum FindFreeSpaceError {
     /// Not enough space
     #[error("Not enough space")]
     NotEnoughSpace,
+    /// A file could not be deleted to make space
+    #[error("A file could not be deleted to make space")]
+    EvictionFailed,
 }
 
 /// Errors that can occur when writing a file
@@ -95,13 +126,25 @@ pub enum FilesystemWriteError {
     WriteFileToStorageError(#[from] WriteFileToStorageError),
     /// Some kind of io error
     #[error(transparent)]
-    IoError(#[from] std::io::Error),
+    IoError(#[from] MetadataError),
+    /// Error while writing the file content
+    #[error(transparent)]
+    StorageError(#[from] StorageError),
     /// Error while committing file content
     #[error(transparent)]
     CommitFileContentError(#[from] CommitFileContentError),
     /// There already exists a file with that name. Delete it first
     #[error("There already exists a file with that name. Delete it first")]
     NameAlreadyTaken,
+    /// Error while deleting the file that is being replaced
+    #[error(transparent)]
+    FilesystemDeleteError(#[from] FilesystemDeleteError),
+    /// The given hash is not the blake3 hash of the content
+    #[error("The given hash does not match the content")]
+    HashMismatch,
+    /// The filesystem already contains [Storage::max_files] files
+    #[error("The filesystem already contains the maximum number of files")]
+    TooManyFiles,
 }
 
 /// Errors that can occur when deleting a file
@@ -112,14 +155,98 @@ pub enum FilesystemDeleteError {
     EraseStorageError(#[from] EraseStorageError),
     /// Some kind of io error
     #[error(transparent)]
-    IoError(#[from] std::io::Error),
+    IoError(#[from] MetadataError),
+    /// The file could not be marked for deletion
+    #[error(transparent)]
+    DeleteFileContentError(#[from] DeleteFileContentError),
     /// The file does not exist
     #[error("The file does not exist")]
     FileNotFound,
 }
 
+/// Errors that can occur when renaming a file with [Filesystem::rename_file]
+#[derive(Error, Debug)]
+pub enum RenameFileError {
+    /// There is no readable file with the old name
+    #[error("The file does not exist")]
+    FileNotFound,
+    /// There already exists a file with the new name. Delete it first
+    #[error("There already exists a file with the new name. Delete it first")]
+    AlreadyExists,
+    /// The file was deleted while it was renamed
+    #[error(transparent)]
+    UpgradeFileError(#[from] file::UpgradeFileError),
+    /// Error while writing the file with the new name
+    #[error(transparent)]
+    FilesystemWriteError(#[from] FilesystemWriteError),
+    /// Error while marking the renamed file as important
+    #[error(transparent)]
+    WriteMetadataError(#[from] WriteMetadataError),
+    /// Error while deleting the file with the old name
+    #[error(transparent)]
+    FilesystemDeleteError(#[from] FilesystemDeleteError),
+}
+
+/// Errors that can occur when evicting files to make space
+#[derive(Error, Debug)]
+pub enum EvictError {
+    /// Even deleting all unimportant files would not free enough contiguous space
+    #[error("Even deleting all unimportant files would not free enough space")]
+    CannotFreeEnough,
+    /// Error in filesystem structure
+    #[error(transparent)]
+    FindFreeSpaceError(#[from] FindFreeSpaceError),
+    /// Error while deleting a file
+    #[error(transparent)]
+    FilesystemDeleteError(#[from] FilesystemDeleteError),
+}
+
+/// Errors that can occur when copying files between filesystems
+#[derive(Error, Debug)]
+pub enum CopyError {
+    /// A file in the source filesystem was deleted while it was copied
+    #[error(transparent)]
+    UpgradeFileError(#[from] file::UpgradeFileError),
+    /// Error while writing a file to the destination filesystem
+    #[error(transparent)]
+    FilesystemWriteError(#[from] FilesystemWriteError),
+    /// Error while marking a copied file as important
+    #[error(transparent)]
+    WriteMetadataError(#[from] WriteMetadataError),
+}
+
+/// Errors that can occur when reading a file with [Filesystem::read_file_verified]
+#[derive(Error, Debug)]
+pub enum ReadVerifyError {
+    /// There is no readable file with that name
+    #[error("The file does not exist")]
+    FileNotFound,
+    /// The file can not be read anymore
+    #[error(transparent)]
+    UpgradeFileError(#[from] file::UpgradeFileError),
+    /// The content does not match the hash in the metadata
+    #[error("The content does not match the stored hash")]
+    HashMismatch {
+        /// The hash stored in the metadata
+        expected: [u8; 32],
+        /// The hash of the content in storage
+        got: [u8; 32],
+    },
+}
+
+/// Statistics about the files copied by [Filesystem::copy_between]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopyStats {
+    /// Number of files that were copied
+    pub files_copied: usize,
+    /// Total content length of the copied files in bytes
+    pub bytes_copied: usize,
+}
+
 ///  A struct representing the filesystem backed by a generic storage type `T`.
 ///
+/// Reading only needs a shared reference, so if the filesystem is behind a `RwLock`, readers only need the read lock. Only writing and deleting files needs exclusive access.
+///
 /// # Type Parameters
 ///
 /// * `T` - A type that implements the `Storage` trait and is `'static`, `Send`, and `Sync`.
@@ -128,6 +255,12 @@ pub struct Filesystem<T: Storage + 'static + Send + Sync> {
     files: Vec<FileInformation<T>>,
 }
 
+/// A filesystem on a storage with a block size and block count that are only known at runtime
+#[cfg(any(test, feature = "simulated"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "simulated")))]
+pub type DynamicFilesystem<const MAX_SIZE: usize> =
+    Filesystem<storage::dynamic::DynamicStorage<MAX_SIZE>>;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Importance {
     Free,
@@ -152,8 +285,16 @@ struct Range {
 }
 
 impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
+    /// Size of a block in bytes
+    fn block_size(&self) -> u32 {
+        return self.storage.block_size();
+    }
+    /// Total number of blocks
+    fn block_count(&self) -> u32 {
+        return self.storage.block_count();
+    }
     /// Retrieves the first block number from the storage metadata.
-    fn get_first_block(&self) -> Result<u16, std::io::Error> {
+    fn get_first_block(&self) -> Result<u16, MetadataError> {
         let first_block_slice: Box<[u8; 2]> = self
             .storage
             .read_metadata("first_block")?
@@ -162,7 +303,7 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
         Ok(u16::from_le_bytes(*first_block_slice))
     }
     /// Sets the first block number in the storage metadata.
-    fn set_first_block(&self, first_block: u16) -> Result<(), std::io::Error> {
+    fn set_first_block(&self, first_block: u16) -> Result<(), MetadataError> {
         self.storage
             .write_metadata("first_block", &first_block.to_le_bytes())?;
         Ok(())
@@ -174,14 +315,15 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
     /// 1. Reads or initializes the first block pointer from metadata
     /// 2. Scans through blocks starting at first_block
     /// 3. Reconstructs file list from valid file headers
-    /// 4. Erases corrupted blocks (non-0xFF when invalid)
+    /// 4. Erases corrupted blocks (non-0xFF when invalid), unless some blocks could not be read
+    /// 5. Skips blocks with files that do not fit into the storage or overlap other files
     ///
     /// # Arguments
     /// * `storage` - Static reference to storage implementing the Storage trait
     ///
     /// # Returns
-    /// A new `Filesystem` instance with the reconstructed file list
-    pub fn new(storage: &'static T) -> Self {
+    /// A new `Filesystem` instance with the reconstructed file list, or an error if the storage could not be written while fixing it
+    pub fn new(storage: &'static T) -> Result<Self, FilesystemInitError> {
         // Create a fs with an empty files table
         let mut filesystem = Self {
             storage,
@@ -189,86 +331,297 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
         };
 
         // Find all files
-        let first_block = filesystem.get_first_block();
-        let first_block = first_block.unwrap_or_else(|_| {
-            filesystem.set_first_block(0).unwrap();
-            0
-        });
-        let mut block_number = 0;
-        while block_number < T::BLOCKS {
-            let current_block_number = (block_number + first_block as u32) % T::BLOCKS;
-            let file_information = FileInformation::from_storage(
-                filesystem.storage,
-                current_block_number * T::BLOCK_SIZE,
-            );
+        let first_block = match filesystem.get_first_block() {
+            Ok(first_block) => first_block,
+            // A new storage does not have a first block yet
+            Err(_) => {
+                filesystem
+                    .set_first_block(0)
+                    .map_err(FilesystemInitError::MetadataWriteFailed)?;
+                0
+            }
+        };
+        let block_size = filesystem.block_size();
+        let block_count = filesystem.block_count();
+        // Blocks that are not zeroed and do not belong to a file
+        let mut garbage_blocks = Vec::new();
+        // The blocks after an unreadable block may belong to a file in it, so they can not be erased as garbage
+        let mut found_unreadable_blocks = false;
+        // Start at the first block and wrap around to the start of the storage
+        let mut blocks = storage
+            .blocks()
+            .skip(first_block as usize)
+            .chain(storage.blocks().take(first_block as usize));
+        while let Some((current_block_number, current_block)) = blocks.next() {
+            // Do not erase blocks that could not be read, the data in them might still be fine
+            let Some(current_block) = current_block else {
+                tracing::warn!(
+                    "Skipping block {} because it can not be read",
+                    current_block_number
+                );
+                found_unreadable_blocks = true;
+                continue;
+            };
+            let file_information =
+                FileInformation::from_storage(storage, current_block_number * block_size);
             let file_information = match file_information {
                 Ok(file_information) => file_information,
+                Err(ReadFileFromStorageError::ReadMetadataError(
+                    ReadMetadataError::StorageError(_),
+                )) => {
+                    tracing::warn!(
+                        "Skipping block {} because it can not be read",
+                        current_block_number
+                    );
+                    found_unreadable_blocks = true;
+                    continue;
+                }
+                Err(ReadFileFromStorageError::ReadFileContentError(
+                    ReadFileError::StorageError(_),
+                )) => {
+                    tracing::warn!(
+                        "Skipping the file in block {} because its content can not be read",
+                        current_block_number
+                    );
+                    // The metadata was readable, so skip the other blocks of the file instead of erasing them as garbage
+                    if let Ok(metadata) =
+                        FileMetadata::from_storage(storage, current_block_number * block_size)
+                    {
+                        let file_blocks = (metadata.length as u64
+                            + size_of::<FileMetadata>() as u64)
+                            .div_ceil(block_size as u64);
+                        if file_blocks > 1 && file_blocks <= block_count as u64 {
+                            blocks.nth(file_blocks as usize - 2);
+                        }
+                    }
+                    continue;
+                }
                 Err(_) => {
-                    block_number += 1;
-                    let Ok(current_block) = filesystem
-                        .storage
-                        .read(current_block_number * T::BLOCK_SIZE, T::BLOCK_SIZE)
-                    else {
-                        continue;
-                    };
                     if current_block.iter().any(|b| *b != 0xff) {
-                        println!(
-                            "Erasing block {} because it is not zeroed",
-                            current_block_number
-                        );
-                        filesystem
-                            .storage
-                            .erase(current_block_number * T::BLOCK_SIZE, T::BLOCK_SIZE)
-                            .unwrap();
+                        garbage_blocks.push(current_block_number);
                     };
                     continue;
                 }
             };
-            block_number += ((file_information.length + 64) / T::BLOCK_SIZE) + 1;
+            // Skip files with corrupted metadata instead of trusting their length
+            if file_information.address % block_size != 0
+                || file_information.length
+                    > block_count * block_size - size_of::<FileMetadata>() as u32
+            {
+                tracing::warn!(
+                    "Skipping block {} because the file in it does not fit into the storage",
+                    current_block_number
+                );
+                continue;
+            }
+            let file_blocks = filesystem.blocks_of(&file_information);
+            let overlapping_file = filesystem.files.iter().find(|other| {
+                filesystem.ranges_overlap(
+                    current_block_number,
+                    file_blocks,
+                    other.address / block_size,
+                    filesystem.blocks_of(other),
+                )
+            });
+            if let Some(other) = overlapping_file {
+                tracing::warn!(
+                    "Skipping block {} because the file in it overlaps {}",
+                    current_block_number,
+                    other.name
+                );
+                continue;
+            }
+            // Skip the other blocks of the file
+            if file_blocks > 1 {
+                blocks.nth(file_blocks as usize - 2);
+            }
             filesystem.files.push(file_information);
         }
 
-        unsafe { filesystem.selfcheck() };
+        if found_unreadable_blocks {
+            tracing::warn!(
+                "Not erasing {} blocks that are not zeroed, because some blocks could not be read",
+                garbage_blocks.len()
+            );
+        } else {
+            for garbage_block in garbage_blocks {
+                // The first block can point into the middle of a file, whose metadata is only found at the end of the scan
+                let belongs_to_a_file = filesystem.files.iter().any(|file| {
+                    filesystem.ranges_overlap(
+                        garbage_block,
+                        1,
+                        file.address / block_size,
+                        filesystem.blocks_of(file),
+                    )
+                });
+                if belongs_to_a_file {
+                    continue;
+                }
+                tracing::debug!("Erasing block {} because it is not zeroed", garbage_block);
+                filesystem
+                    .storage
+                    .erase(garbage_block * block_size, block_size)?;
+            }
+        }
+
+        unsafe { filesystem.selfcheck()? };
 
-        filesystem
+        Ok(filesystem)
+    }
+
+    /// Erases the whole storage and creates an empty filesystem on it.
+    ///
+    /// # Safety
+    ///
+    /// All files on the storage are lost. No other [Filesystem] on the same storage and no files read from one may be used after this, because their content is erased while they still point to it.
+    pub unsafe fn format(storage: &'static T) -> Result<Self, FilesystemInitError> {
+        storage.erase(0, storage.block_count() * storage.block_size())?;
+        storage
+            .write_metadata("first_block", &0u16.to_le_bytes())
+            .map_err(FilesystemInitError::MetadataWriteFailed)?;
+        Self::new(storage)
+    }
+
+    /// Number of blocks occupied by a file, including its metadata
+    fn blocks_of(&self, file: &FileInformation<T>) -> u32 {
+        return (file.length + size_of::<FileMetadata>() as u32).div_ceil(self.block_size());
+    }
+
+    /// Check if two block ranges overlap. Ranges can wrap around the end of the storage.
+    fn ranges_overlap(&self, start_a: u32, length_a: u32, start_b: u32, length_b: u32) -> bool {
+        let distance_a_to_b = (start_b + self.block_count() - start_a) % self.block_count();
+        let distance_b_to_a = (start_a + self.block_count() - start_b) % self.block_count();
+        return distance_a_to_b < length_a || distance_b_to_a < length_b;
     }
 
     /// Check the filesystem for errors and try to fix them
     ///
     /// Only safe, if none of the files have been read yet. This should only be called in new.
-    unsafe fn selfcheck(&mut self) {
+    unsafe fn selfcheck(&mut self) -> Result<(), FilesystemInitError> {
         // Fix the first block number, if the first file is marked for deletion or deleted
         if let Some(first_file) = self.files.first() {
             if first_file.marked_for_deletion() || first_file.deleted() {
                 let new_first_block = self.find_new_first_block();
-                self.set_first_block(new_first_block).unwrap();
+                self.set_first_block(new_first_block)
+                    .map_err(FilesystemInitError::MetadataWriteFailed)?;
             }
         }
 
-        // TODO: Find and remove files with a duplicate name
+        // Finish replacements that were interrupted by a reset, or drop them if the new file is incomplete
+        for replacement in self.files.iter() {
+            if !replacement.replacement_pending()
+                || replacement.deleted()
+                || replacement.marked_for_deletion()
+            {
+                continue;
+            }
+            if !replacement.valid() {
+                tracing::info!("Deleting incomplete replacement for {}", replacement.name);
+                replacement.mark_for_deletion()?;
+                continue;
+            }
+            for replaced in self.files.iter().filter(|file| {
+                file.address != replacement.address
+                    && file.name == replacement.name
+                    && !file.replacement_pending()
+                    && !file.deleted()
+                    && !file.marked_for_deletion()
+            }) {
+                tracing::info!("Finishing replacement of {}", replaced.name);
+                replaced.mark_for_deletion()?;
+            }
+            replacement.set_replaced()?;
+        }
 
         // TODO: Cleanup
+        Ok(())
+    }
+
+    /// All files that can be read
+    fn readable_files(&self) -> impl Iterator<Item = &FileInformation<T>> {
+        return self.files.iter().filter(|file| {
+            !file.marked_for_deletion()
+                && !file.deleted()
+                && !file.replacement_pending()
+                && file.valid()
+        });
     }
 
     /// Finds a file by name and returns a reference to it.
     pub fn read_file(&self, name: &str) -> Option<File<T, { FileState::Weak }>> {
-        let file = self.files.iter().find(|file| {
-            file.name == name && !file.marked_for_deletion() && !file.deleted() && file.valid()
-        })?;
+        let file = self.readable_files().find(|file| file.name == name)?;
         Some(file.read())
     }
 
-    /// Finds a file by name and returns a reference to it.
+    /// Finds a file by its hash and returns a reference to it.
+    ///
+    /// Only files that are ready are returned, so files that are still being written are ignored.
     pub fn read_file_by_hash(&self, hash: &[u8; 32]) -> Option<File<T, { FileState::Weak }>> {
-        let file = self.files.iter().find(|file| {
-            file.compare_hash(hash)
-                && !file.marked_for_deletion()
-                && !file.deleted()
-                && file.valid()
-        })?;
+        let file = self.readable_files().find(|file| file.compare_hash(hash))?;
         Some(file.read())
     }
 
+    /// Finds a file by name and checks that its content matches the stored hash.
+    ///
+    /// This hashes the whole file, so it is a lot slower than [Filesystem::read_file]. Use it to detect corrupted storage, for example before running a program.
+    pub fn read_file_verified(
+        &self,
+        name: &str,
+    ) -> Result<File<T, { FileState::Weak }>, ReadVerifyError> {
+        let file = self.read_file(name).ok_or(ReadVerifyError::FileNotFound)?;
+        let content = file.upgrade()?;
+        let got = content.hash_algorithm().hash(&content);
+        if &got != content.hash() {
+            return Err(ReadVerifyError::HashMismatch {
+                expected: *content.hash(),
+                got,
+            });
+        }
+        Ok(file)
+    }
+
+    /// Number of files in the filesystem.
+    ///
+    /// Also counts files that are still being written and files that are marked for deletion but still have readers.
+    pub fn file_count(&self) -> usize {
+        self.files.iter().filter(|file| !file.deleted()).count()
+    }
+
+    /// Get references to all files that can be read.
+    ///
+    /// Files that are still being written, deleted files and files that are marked for deletion are not listed, even if there are still strong references to them.
+    pub fn list_files(&self) -> Vec<File<T, { FileState::Weak }>> {
+        self.readable_files().map(|file| file.read()).collect()
+    }
+
+    /// Copy all readable files from `src` to `dst`, for example to migrate to a different storage.
+    ///
+    /// The files keep their names, hashes, hash algorithms and importance. The content is copied in chunks, so it works without `std`. Files that are still being written are skipped. Stops at the first file that can not be written to `dst`, files that were already copied stay there.
+    pub fn copy_between<S: Storage + 'static + Send + Sync>(
+        src: &Filesystem<S>,
+        dst: &mut Filesystem<T>,
+    ) -> Result<CopyStats, CopyError> {
+        let mut stats = CopyStats::default();
+        for file in src.readable_files() {
+            let content = file.read().upgrade()?;
+            let mut writer =
+                dst.get_file_writer(&file.name, content.len() as u32, content.hash())?;
+            writer.set_hash_algorithm(content.hash_algorithm())?;
+            for chunk in content.read_chunks(COPY_CHUNK_SIZE) {
+                writer
+                    .write_content(chunk)
+                    .map_err(FilesystemWriteError::from)?;
+            }
+            let copied = writer.commit().map_err(FilesystemWriteError::from)?;
+            if content.important() {
+                copied.set_important()?;
+            }
+            stats.files_copied += 1;
+            stats.bytes_copied += content.len();
+        }
+        return Ok(stats);
+    }
+
     /// Get information about the free space in the storage
     fn analyze_free_space(&self) -> Result<BTreeMap<u16, Range>, FindFreeSpaceError> {
         let mut free_ranges: BTreeMap<u16, Range> = Default::default();
@@ -276,7 +629,7 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
             0,
             Range {
                 importance: Importance::Free,
-                length: T::BLOCKS as u16 * 2,
+                length: self.block_count() as u16 * 2,
             },
         );
 
@@ -287,9 +640,9 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
                 Importance::Unimportant { age: file.age() }
             };
 
-            let start_block = (file.address / T::BLOCK_SIZE) as u16;
+            let start_block = (file.address / self.block_size()) as u16;
             let length_in_blocks =
-                (file.length + size_of::<FileMetadata>() as u32).div_ceil(T::BLOCK_SIZE) as u16;
+                (file.length + size_of::<FileMetadata>() as u32).div_ceil(self.block_size()) as u16;
             let end_block = start_block + length_in_blocks;
 
             let Some((
@@ -338,7 +691,7 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
 
         // Remove all trailing free space
         let last_free_space_start = free_ranges.last_key_value().map_or(0, |(start, _)| *start);
-        let wraparound_length: i64 = last_free_space_start as i64 - T::BLOCKS as i64;
+        let wraparound_length: i64 = last_free_space_start as i64 - self.block_count() as i64;
 
         // Remove the free space that is occupied by the wraparound from the first block
         if wraparound_length > 0 {
@@ -362,7 +715,7 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
                 *end_space.0,
                 Range {
                     importance: end_space.1.importance,
-                    length: end_space.1.length - T::BLOCKS as u16,
+                    length: end_space.1.length - self.block_count() as u16,
                 },
             );
         }
@@ -371,12 +724,33 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
 
         // Duplicate all ranges to the back
         for range in free_ranges.clone().into_iter() {
-            free_ranges.insert(range.0 + T::BLOCKS as u16, range.1);
+            free_ranges.insert(range.0 + self.block_count() as u16, range.1);
         }
 
         return Ok(free_ranges);
     }
 
+    /// Size of the storage in bytes
+    pub fn get_total_space_bytes(&self) -> usize {
+        (self.block_count() * self.block_size()) as usize
+    }
+
+    /// Number of bytes in blocks that are not occupied by any file
+    ///
+    /// The free space can be split into multiple ranges, so a file of this size does not necessarily fit. Blocks of deleted files only count as free once they were erased. Returns 0 if the filesystem structure is broken.
+    pub fn get_free_space_bytes(&self) -> usize {
+        let Ok(free_ranges) = self.analyze_free_space() else {
+            return 0;
+        };
+        let free_blocks: u32 = free_ranges
+            .iter()
+            .filter(|(&start, _)| start < self.block_count() as u16)
+            .filter(|(_, range)| range.importance == Importance::Free)
+            .map(|(_, range)| range.length as u32)
+            .sum();
+        (free_blocks * self.block_size()) as usize
+    }
+
     /// Find a free space in storage of at least the given length.
     ///
     /// For now the space is guaranteed to start at a block boundary
@@ -384,30 +758,30 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
         let free_ranges = self.analyze_free_space()?;
 
         for range in free_ranges.iter() {
-            println!("Free range: {:?}", range);
+            tracing::trace!("Free range: {:?}", range);
         }
 
-        let length_in_blocks = length.div_ceil(T::BLOCK_SIZE) as u16;
+        let length_in_blocks = length.div_ceil(self.block_size()) as u16;
 
         if let Some((free_range_start, free_range_length)) = free_ranges
             .iter()
-            .filter(|(&start, _)| start < T::BLOCKS as u16)
+            .filter(|(&start, _)| start < self.block_count() as u16)
             .filter(|(_, range)| range.importance == Importance::Free)
             .filter(|(_, range)| range.length >= (length_in_blocks))
             .min_by(|(_, range_a), (_, range_b)| range_a.length.cmp(&range_b.length))
             .map(|(a, b)| (*a as u32, b.length as u32))
         {
             // let longest_range_start = longest_range.0 % (T::BLOCKS);
-            println!(
+            tracing::debug!(
                 "Found free space at {} with length {}",
                 free_range_start, free_range_length
             );
-            return Ok(free_range_start * T::BLOCK_SIZE);
+            return Ok(free_range_start * self.block_size());
         }
 
         let mut cheapest_range: VecDeque<(u16, Range)> = VecDeque::new();
         let mut cheapest_range_cost: u16 = u16::MAX;
-        let last_start = 0;
+        let mut last_start = 0;
         let mut last_length = 0;
         let mut current_range: VecDeque<(u16, Range)> = VecDeque::new();
         let mut current_range_cost: u16 = 0;
@@ -420,11 +794,12 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
                 current_range.clear();
                 current_range_cost = 0;
                 current_range_length = 0;
-                last_length = 0;
             }
             current_range.push_back((*check_start, *check_range));
             current_range_cost += cost as u16;
             current_range_length += check_range.length;
+            last_start = *check_start;
+            last_length = check_range.length;
 
             if current_range_length >= length_in_blocks {
                 // Try to remove from start of the current range, until it is shortest
@@ -440,7 +815,7 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
                 }
             }
             if let Some(front) = current_range.front() {
-                if front.0 >= T::BLOCKS as u16 {
+                if front.0 >= self.block_count() as u16 {
                     break;
                 }
             }
@@ -457,24 +832,27 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
         }
 
         for range in cheapest_range.iter() {
-            println!("Cheapest range: {:?}", range);
+            tracing::trace!("Cheapest range: {:?}", range);
             let matched_file = self
                 .files
                 .iter()
-                .find(|f| f.address == range.0 as u32 * T::BLOCK_SIZE);
+                .find(|f| f.address == (range.0 as u32 % self.block_count()) * self.block_size());
 
             if let Some(file) = matched_file {
-                file.mark_for_deletion().unwrap();
+                if let Err(error) = file.mark_for_deletion() {
+                    tracing::warn!("Failed to delete {} to make space: {}", file.name, error);
+                    return Err(FindFreeSpaceError::EvictionFailed);
+                }
                 if !file.deleted() {
-                    eprintln!("File should have been deleted");
+                    tracing::error!("File should have been deleted");
                     panic!("File should have been deleted");
                 }
             }
         }
 
         let first = cheapest_range.front().unwrap();
-        let start = first.0 as u32 * T::BLOCK_SIZE;
-        println!("Found unimportant space at {}", start);
+        let start = first.0 as u32 * self.block_size();
+        tracing::debug!("Found unimportant space at {}", start);
         return Ok(start);
 
         // todo!("Clear cheapest range and return it");
@@ -502,9 +880,128 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
         content: &[u8],
         _hash: &[u8; 32],
     ) -> Result<(), FilesystemWriteError> {
-        let mut writer = self.get_file_writer(name, content.len() as u32, _hash)?;
+        return self.write_file_with_hash_algorithm(name, content, _hash, HashAlgorithm::Blake3);
+    }
+
+    /// Write a file to storage, with a hash that was calculated with `hash_algorithm`
+    pub fn write_file_with_hash_algorithm(
+        &mut self,
+        name: &str,
+        content: &[u8],
+        hash: &[u8; 32],
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<(), FilesystemWriteError> {
+        let mut writer = self.get_file_writer(name, content.len() as u32, hash)?;
+
+        writer
+            .set_hash_algorithm(hash_algorithm)
+            .map_err(WriteFileToStorageError::from)?;
+        writer.write_content(content)?;
+        writer.commit()?;
+        Ok(())
+    }
+
+    /// Write a file to storage, after checking that `hash` is the blake3 hash of `content`
+    ///
+    /// Use this for content from untrusted sources, so a wrong hash is not stored.
+    pub fn write_file_validated(
+        &mut self,
+        name: &str,
+        content: &[u8],
+        hash: &[u8; 32],
+    ) -> Result<(), FilesystemWriteError> {
+        Self::validate_hash(content, hash)?;
+        return self.write_file(name, content, hash);
+    }
+
+    /// Write a file to storage, replacing the file with the same name if there is one.
+    ///
+    /// The new content is written next to the old file, so there needs to be enough free space for both. Flash can not be rewritten, so instead of using a temporary name the new file is flagged as a pending replacement. Pending replacements can not be read. Once the content is committed, the old file is marked for deletion and the pending flag is cleared, which makes the new file visible under its name. Readers of the old file keep working until they are dropped.
+    ///
+    /// If writing the new file fails, it is deleted again and the old file stays in place. If the device resets during the replacement, [Filesystem::new] finishes it if the new content was committed and deletes the new file otherwise.
+    pub fn write_or_replace(
+        &mut self,
+        name: &str,
+        content: &[u8],
+        hash: &[u8; 32],
+    ) -> Result<(), FilesystemWriteError> {
+        self.cleanup_files();
+        // The replaced file is deleted once the new one is written, so it does not count
+        let replaced_files = usize::from(self.read_file(name).is_some());
+        if self.file_count() - replaced_files >= self.storage.max_files() {
+            return Err(FilesystemWriteError::TooManyFiles);
+        }
+        // Keep the old file open, so it is not deleted to make space for its replacement
+        let old_content = self.read_file(name).and_then(|file| file.upgrade().ok());
+        let length = content.len() as u32;
+        let free_location = self.find_free_space(length + size_of::<FileMetadata>() as u32);
+        drop(old_content);
+        let free_location = free_location?;
+
+        let (file, writer) =
+            FileInformation::to_storage(self.storage, free_location, length, name, hash)?;
+        self.files.push(file);
+
+        let written = Self::write_replacement(writer, content);
+        let replaced = written.and_then(|_| {
+            let Some(index) = self.files.iter().position(|file| {
+                file.name == name
+                    && !file.replacement_pending()
+                    && !file.deleted()
+                    && !file.marked_for_deletion()
+            }) else {
+                return Ok(());
+            };
+            self.delete_file_at(index)?;
+            return Ok(());
+        });
+
+        let Some(replacement_index) = self
+            .files
+            .iter()
+            .position(|file| file.address == free_location && !file.deleted())
+        else {
+            return replaced;
+        };
+        if let Err(error) = replaced {
+            // Remove the incomplete replacement, the old file has not been touched
+            let _ = self.delete_file_at(replacement_index);
+            return Err(error);
+        }
+        self.files[replacement_index]
+            .set_replaced()
+            .map_err(WriteFileToStorageError::from)?;
+        Ok(())
+    }
+
+    /// Replace a file like [Filesystem::write_or_replace], after checking that `hash` is the blake3 hash of `content`
+    pub fn write_or_replace_validated(
+        &mut self,
+        name: &str,
+        content: &[u8],
+        hash: &[u8; 32],
+    ) -> Result<(), FilesystemWriteError> {
+        Self::validate_hash(content, hash)?;
+        return self.write_or_replace(name, content, hash);
+    }
+
+    /// Check that `hash` is the blake3 hash of `content`
+    fn validate_hash(content: &[u8], hash: &[u8; 32]) -> Result<(), FilesystemWriteError> {
+        if HashAlgorithm::Blake3.hash(content) != *hash {
+            return Err(FilesystemWriteError::HashMismatch);
+        }
+        return Ok(());
+    }
 
-        writer.write_all(content)?;
+    /// Flag a new file as a replacement and write its content
+    fn write_replacement(
+        mut writer: File<T, { FileState::Writer }>,
+        content: &[u8],
+    ) -> Result<(), FilesystemWriteError> {
+        writer
+            .set_replacement()
+            .map_err(WriteFileToStorageError::from)?;
+        writer.write_content(content)?;
         writer.commit()?;
         Ok(())
     }
@@ -517,6 +1014,19 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
         name: &str,
         length: u32,
         hash: &[u8; 32],
+    ) -> Result<File<T, { FileState::Writer }>, FilesystemWriteError> {
+        self.get_replacing_file_writer(name, length, hash, 0)
+    }
+
+    /// Get a writer for a file that replaces `replaced_files` other files.
+    ///
+    /// The replaced files are deleted once the new file is written, so they do not count towards [Storage::max_files]
+    fn get_replacing_file_writer(
+        &mut self,
+        name: &str,
+        length: u32,
+        hash: &[u8; 32],
+        replaced_files: usize,
     ) -> Result<File<T, { FileState::Writer }>, FilesystemWriteError> {
         self.cleanup_files();
         if self
@@ -526,6 +1036,9 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
         {
             return Err(FilesystemWriteError::NameAlreadyTaken);
         }
+        if self.file_count() - replaced_files >= self.storage.max_files() {
+            return Err(FilesystemWriteError::TooManyFiles);
+        }
         let free_location = self.find_free_space(length + size_of::<FileMetadata>() as u32)?;
 
         let (file, writer) =
@@ -534,25 +1047,61 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
         Ok(writer)
     }
 
+    /// Give a file a new name.
+    ///
+    /// The content is stored right after the metadata, so the file is copied to a new location with the new name before the old file is deleted. The hash, hash algorithm and importance are kept. The file can be read under at least one of the names at any time, after a power loss between the two steps both exist.
+    pub fn rename_file(&mut self, old_name: &str, new_name: &str) -> Result<(), RenameFileError> {
+        if self
+            .files
+            .iter()
+            .any(|file| !file.deleted() && !file.marked_for_deletion() && file.name == new_name)
+        {
+            return Err(RenameFileError::AlreadyExists);
+        }
+        let content = self
+            .read_file(old_name)
+            .ok_or(RenameFileError::FileNotFound)?
+            .upgrade()?;
+        // The old file is deleted after the copy, so it does not count towards the file limit
+        let mut writer =
+            self.get_replacing_file_writer(new_name, content.len() as u32, content.hash(), 1)?;
+        writer.set_hash_algorithm(content.hash_algorithm())?;
+        for chunk in content.read_chunks(COPY_CHUNK_SIZE) {
+            writer
+                .write_content(chunk)
+                .map_err(FilesystemWriteError::from)?;
+        }
+        let renamed = writer.commit().map_err(FilesystemWriteError::from)?;
+        if content.important() {
+            renamed.set_important()?;
+        }
+        // Deleting is deferred while there are readers
+        drop(content);
+        self.delete_file(old_name)?;
+        Ok(())
+    }
+
     /// Delete a file
     ///
     /// The file will only be deleted once there are no strong references to its content left. Strong references can be obtained by calling upgrade on the content of a file
     pub fn delete_file(&mut self, filename: &str) -> Result<(), FilesystemDeleteError> {
-        let Some((index, _)) = self
-            .files
-            .iter()
-            .enumerate()
-            .find(|(_, file)| file.name == filename)
-        else {
+        let Some((index, _)) = self.files.iter().enumerate().find(|(_, file)| {
+            file.name == filename && !file.deleted() && !file.replacement_pending()
+        }) else {
             return Err(FilesystemDeleteError::FileNotFound);
         };
+        return self.delete_file_at(index);
+    }
+
+    /// Mark the file at `index` for deletion and move the first block marker away from it, if necessary
+    fn delete_file_at(&mut self, index: usize) -> Result<(), FilesystemDeleteError> {
         let file = &mut self.files[index];
         if !file.marked_for_deletion() {
-            file.mark_for_deletion().unwrap();
+            file.mark_for_deletion()?;
         }
 
         let file = &self.files[index];
-        let file_block = (file.address / T::BLOCK_SIZE) as u16;
+        let file_block = (file.address / self.block_size()) as u16;
         let first_block = self.get_first_block().unwrap_or(0);
         if file.deleted() {
             self.files.swap_remove(index);
@@ -568,6 +1117,61 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
         Ok(())
     }
 
+    /// Delete the oldest unimportant files until there is at least `target_bytes` of contiguous free space
+    ///
+    /// Important files and files that are currently open are never deleted. Nothing is deleted, if deleting all unimportant files would not free enough space.
+    ///
+    /// Returns the number of bytes that were freed, rounded up to whole blocks.
+    pub fn lru_evict(&mut self, target_bytes: u32) -> Result<u32, EvictError> {
+        self.cleanup_files();
+        let target_blocks = target_bytes.div_ceil(self.block_size()) as u16;
+        if self.longest_range(|importance| importance != Importance::Important)? < target_blocks {
+            return Err(EvictError::CannotFreeEnough);
+        }
+
+        let mut freed_bytes = 0;
+        while self.longest_range(|importance| importance == Importance::Free)? < target_blocks {
+            let Some(oldest_file) = self
+                .files
+                .iter()
+                .filter(|file| !file.deleted() && !file.marked_for_deletion())
+                .filter(|file| !file.important() && file.can_be_deleted())
+                .min_by_key(|file| file.age())
+            else {
+                return Err(EvictError::CannotFreeEnough);
+            };
+            let name = oldest_file.name.clone();
+            let length_in_blocks =
+                (oldest_file.length + size_of::<FileMetadata>() as u32).div_ceil(self.block_size());
+            self.delete_file(&name)?;
+            freed_bytes += length_in_blocks * self.block_size();
+        }
+        return Ok(freed_bytes);
+    }
+
+    /// Length in blocks of the longest contiguous range made up of ranges with a usable importance
+    fn longest_range(
+        &self,
+        usable: impl Fn(Importance) -> bool,
+    ) -> Result<u16, FindFreeSpaceError> {
+        let ranges = self.analyze_free_space()?;
+        let mut longest_length = 0;
+        let mut current_length = 0;
+        let mut next_start = 0;
+        for (start, range) in ranges.iter() {
+            if !usable(range.importance) || *start != next_start {
+                current_length = 0;
+            }
+            if usable(range.importance) {
+                current_length += range.length;
+            }
+            next_start = start + range.length;
+            longest_length = core::cmp::max(longest_length, current_length);
+        }
+        // The ranges are duplicated to handle the wraparound
+        return Ok(core::cmp::min(longest_length, self.block_count() as u16));
+    }
+
     fn find_new_first_block(&self) -> u16 {
         let good_file = self
             .files
@@ -575,7 +1179,7 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
             .find(|file| file.valid() && !file.deleted() && !file.marked_for_deletion());
 
         if let Some(file) = good_file {
-            return (file.address / T::BLOCK_SIZE) as u16;
+            return (file.address / self.block_size()) as u16;
         }
 
         let acceptable_file = self
@@ -584,13 +1188,13 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
             .find(|file| file.valid() && !file.deleted());
 
         if let Some(file) = acceptable_file {
-            return (file.address / T::BLOCK_SIZE) as u16;
+            return (file.address / self.block_size()) as u16;
         }
 
         let any_file = self.files.iter().find(|file| file.valid());
 
         if let Some(file) = any_file {
-            return (file.address / T::BLOCK_SIZE) as u16;
+            return (file.address / self.block_size()) as u16;
         }
 
         return 0;
@@ -610,9 +1214,68 @@ impl<T: Storage + 'static + Send + Sync> Filesystem<T> {
     }
 }
 
+/// Formats a hash as a hex string
+struct HexHash<'a>(&'a [u8; 32]);
+
+impl core::fmt::Debug for HexHash<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the files and how much of the storage they use
+impl<T: Storage + 'static + Send + Sync> core::fmt::Debug for Filesystem<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        /// The parts of a file that are interesting when debugging
+        struct FileSummary<'a, T: Storage + 'static + Send + Sync>(&'a FileInformation<T>);
+
+        impl<T: Storage + 'static + Send + Sync> core::fmt::Debug for FileSummary<'_, T> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.debug_struct("File")
+                    .field("name", &self.0.name)
+                    .field("length", &self.0.length)
+                    .field("hash", &HexHash(self.0.hash()))
+                    .field("valid", &self.0.valid())
+                    .finish()
+            }
+        }
+
+        let used_blocks: u32 = self
+            .files
+            .iter()
+            .filter(|file| !file.deleted())
+            .map(|file| self.blocks_of(file))
+            .sum();
+        f.debug_struct("Filesystem")
+            .field("block_size", &self.block_size())
+            .field("block_count", &self.block_count())
+            .field("used_blocks", &used_blocks)
+            .field(
+                "files",
+                &self.files.iter().map(FileSummary).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl<T: Storage + Clone + 'static + Send + Sync> Filesystem<T> {
+    /// Create an independent snapshot of the filesystem
+    ///
+    /// The files are found by scanning a copy of the storage with [Filesystem::new]. Files that are still being written are not finished in the snapshot.
+    ///
+    /// The copy of the storage is leaked to get a `'static` reference, so its memory is never freed. Only use this in tests and tools that take few snapshots.
+    pub fn snapshot(&self) -> Result<Filesystem<T>, FilesystemInitError> {
+        let storage: &'static T = Box::leak(Box::new(self.storage.clone()));
+        Filesystem::new(storage)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::storage::simulated::SimulatedStorage;
+    use crate::storage::{dynamic::DynamicStorage, simulated::SimulatedStorage};
 
     use super::*;
 
@@ -621,7 +1284,7 @@ mod tests {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         let file = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
         filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
         let result = filesystem.read_file("fancy").unwrap();
@@ -634,9 +1297,9 @@ mod tests {
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
         let file = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
-        let filesystem = Filesystem::new(storage);
+        let filesystem = Filesystem::new(storage).unwrap();
         let result = filesystem.read_file("fancy").unwrap();
         assert_eq!(result.upgrade().unwrap().as_ref(), file);
     }
@@ -647,7 +1310,7 @@ mod tests {
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
         let file = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
         filesystem.write_file("fancy2", &file, &[5u8; 32]).unwrap();
         filesystem.read_file_by_hash(&[0u8; 32]).unwrap();
@@ -656,26 +1319,157 @@ mod tests {
     }
 
     #[test]
-    fn writing_multiple_files() {
+    fn validated_writes_reject_a_wrong_hash() {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
-        let file = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
-        filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
-        filesystem.write_file("fancy2", &file, &[0u8; 32]).unwrap();
-        let result = filesystem.read_file("fancy").unwrap();
-        assert_eq!(result.upgrade().unwrap().as_ref(), file);
-        let result = filesystem.read_file("fancy2").unwrap();
-        assert_eq!(result.upgrade().unwrap().as_ref(), file);
-    }
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        let file = [1, 2, 3];
+        let hash: [u8; 32] = blake3::hash(&file).into();
+        let Err(FilesystemWriteError::HashMismatch) =
+            filesystem.write_file_validated("fancy", &file, &[0u8; 32])
+        else {
+            panic!("A file with a wrong hash was written");
+        };
+        assert!(filesystem.read_file("fancy").is_none());
+        filesystem
+            .write_file_validated("fancy", &file, &hash)
+            .unwrap();
+        filesystem
+            .write_or_replace_validated("fancy", &[4], &hash)
+            .unwrap_err();
+        let result = filesystem.read_file("fancy").unwrap();
+        assert_eq!(result.upgrade().unwrap().as_ref(), file);
+    }
+
+    #[test]
+    fn files_remember_their_hash_algorithm() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        let file = [1, 2, 3];
+        let blake3_hash = HashAlgorithm::Blake3.hash(&file);
+        let sha256_hash = HashAlgorithm::Sha256.hash(&file);
+        filesystem
+            .write_file("blake3", &file, &blake3_hash)
+            .unwrap();
+        filesystem
+            .write_file_with_hash_algorithm("sha256", &file, &sha256_hash, HashAlgorithm::Sha256)
+            .unwrap();
+        drop(filesystem);
+
+        let filesystem = Filesystem::new(storage).unwrap();
+        let blake3 = filesystem.read_file("blake3").unwrap().upgrade().unwrap();
+        assert_eq!(blake3.hash_algorithm(), HashAlgorithm::Blake3);
+        assert!(blake3.verify_hash());
+        let sha256 = filesystem.read_file("sha256").unwrap().upgrade().unwrap();
+        assert_eq!(sha256.hash_algorithm(), HashAlgorithm::Sha256);
+        assert!(sha256.verify_hash());
+        let sha256_hex: String = sha256_hash
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(
+            sha256_hex,
+            "039058c6f2c0cb492c533b0a4d14ef77cc0f78abccced5287d84a1a2011cfb81"
+        );
+    }
+
+    #[test]
+    fn snapshots_are_independent_of_the_filesystem() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        filesystem
+            .write_file("before", &[1, 2, 3], &[1u8; 32])
+            .unwrap();
+        let snapshot = filesystem.snapshot().unwrap();
+        filesystem
+            .write_file("after", &[4, 5, 6], &[2u8; 32])
+            .unwrap();
+        filesystem.delete_file("before").unwrap();
+
+        assert!(filesystem.read_file("before").is_none());
+        assert!(snapshot.read_file("after").is_none());
+        let before = snapshot.read_file("before").unwrap().upgrade().unwrap();
+        assert_eq!(before.as_ref(), &[1, 2, 3]);
+
+        let debug = format!("{:?}", snapshot);
+        assert!(debug.contains("\"before\""));
+        assert!(debug.contains(&"01".repeat(32)));
+        assert!(!debug.contains("\"after\""));
+    }
+
+    #[test]
+    fn unfinished_files_can_not_be_read_by_hash() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        let mut writer = filesystem
+            .get_file_writer("fancy", 3, &[7u8; 32])
+            .unwrap();
+        writer.write_content(&[1, 2, 3]).unwrap();
+        assert!(filesystem.read_file_by_hash(&[7u8; 32]).is_none());
+        writer.commit().unwrap();
+        filesystem.read_file_by_hash(&[7u8; 32]).unwrap();
+    }
+
+    #[test]
+    fn files_can_be_read_from_multiple_threads_at_once() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        // Two threads read each file, as the filesystem only holds BLOCKS / 2 files
+        for index in 0..5u8 {
+            filesystem
+                .write_file(&format!("file{}", index), &[index; 100], &[index; 32])
+                .unwrap();
+        }
+        let filesystem = std::sync::RwLock::new(filesystem);
+        let barrier = std::sync::Barrier::new(10);
+        std::thread::scope(|scope| {
+            for index in 0..10u8 {
+                let filesystem = &filesystem;
+                let barrier = &barrier;
+                scope.spawn(move || {
+                    let filesystem = filesystem.read().unwrap();
+                    // All threads hold the read lock at the same time
+                    barrier.wait();
+                    let index = index % 5;
+                    for _ in 0..100 {
+                        let file = filesystem.read_file(&format!("file{}", index)).unwrap();
+                        assert_eq!(file.upgrade().unwrap().as_ref(), [index; 100]);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn writing_multiple_files() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        let file = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
+        filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
+        filesystem.write_file("fancy2", &file, &[0u8; 32]).unwrap();
+        let result = filesystem.read_file("fancy").unwrap();
+        assert_eq!(result.upgrade().unwrap().as_ref(), file);
+        let result = filesystem.read_file("fancy2").unwrap();
+        assert_eq!(result.upgrade().unwrap().as_ref(), file);
+    }
 
     #[test]
     fn unimportant_files_get_deleted() {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         // A bit bigger than half the storage size
         let file = vec![0u8; SimulatedStorage::SIZE as usize / 2 + 1 - size_of::<FileMetadata>()];
         filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
@@ -690,7 +1484,7 @@ mod tests {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         // A bit bigger than half the storage size
         let file = vec![0u8; SimulatedStorage::SIZE as usize / 2 + 1 - size_of::<FileMetadata>()];
         filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
@@ -702,12 +1496,67 @@ mod tests {
             .unwrap_err();
     }
 
+    #[test]
+    fn lru_evict_deletes_the_oldest_file_first() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        // A bit smaller than half the storage size
+        let file = vec![0u8; SimulatedStorage::SIZE as usize / 2 - size_of::<FileMetadata>()];
+        filesystem.write_file("old", &file, &[0u8; 32]).unwrap();
+        filesystem.write_file("new", &file, &[1u8; 32]).unwrap();
+        filesystem
+            .read_file("old")
+            .unwrap()
+            .upgrade()
+            .unwrap()
+            .increase_age()
+            .unwrap();
+
+        let freed = filesystem.lru_evict(SimulatedStorage::BLOCK_SIZE).unwrap();
+        assert_eq!(freed, SimulatedStorage::SIZE / 2);
+        assert!(filesystem.read_file("old").is_none());
+        assert!(filesystem.read_file("new").is_some());
+    }
+
+    #[test]
+    fn lru_evict_does_not_delete_important_files() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        let file = vec![0u8; SimulatedStorage::SIZE as usize / 2 - size_of::<FileMetadata>()];
+        filesystem
+            .write_file("important", &file, &[0u8; 32])
+            .unwrap();
+        filesystem
+            .write_file("unimportant", &file, &[1u8; 32])
+            .unwrap();
+        filesystem
+            .read_file("important")
+            .unwrap()
+            .set_important()
+            .unwrap();
+
+        let Err(EvictError::CannotFreeEnough) =
+            filesystem.lru_evict(SimulatedStorage::SIZE / 2 + 1)
+        else {
+            panic!("Should fail when only important files could be deleted");
+        };
+        assert!(filesystem.read_file("unimportant").is_some());
+
+        filesystem.lru_evict(SimulatedStorage::SIZE / 2).unwrap();
+        assert!(filesystem.read_file("important").is_some());
+        assert!(filesystem.read_file("unimportant").is_none());
+    }
+
     #[test]
     fn open_reader_protects_files_from_being_deleted() {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         // A bit bigger than half the storage size
         let file = vec![0u8; SimulatedStorage::SIZE as usize / 2 + 1 - size_of::<FileMetadata>()];
         filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
@@ -724,7 +1573,7 @@ mod tests {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         let file = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
         filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
         filesystem.delete_file("fancy").unwrap();
@@ -738,7 +1587,7 @@ mod tests {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         let file = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
         filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
         filesystem.delete_file("fancy").unwrap();
@@ -746,7 +1595,7 @@ mod tests {
             panic!("Should not be able to read a deleted file");
         };
 
-        let filesystem = Filesystem::new(storage);
+        let filesystem = Filesystem::new(storage).unwrap();
         let None = filesystem.read_file("fancy") else {
             panic!("Should not be able to read a deleted file");
         };
@@ -757,7 +1606,7 @@ mod tests {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         let content = vec![0; SimulatedStorage::SIZE as usize - size_of::<FileMetadata>()];
         filesystem
             .write_file("fancy", &content, &[0u8; 32])
@@ -778,7 +1627,7 @@ mod tests {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         let content = vec![0; SimulatedStorage::SIZE as usize - size_of::<FileMetadata>()];
         filesystem
             .write_file("fancy", &content, &[0u8; 32])
@@ -795,12 +1644,331 @@ mod tests {
         assert_eq!(strong_ref.as_ref(), content);
     }
 
+    #[test]
+    fn listing_files_only_returns_readable_files() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        filesystem
+            .write_file("first", &[1, 2, 3], &[0u8; 32])
+            .unwrap();
+        filesystem
+            .write_file("second", &[4, 5], &[0u8; 32])
+            .unwrap();
+        filesystem.write_file("deleted", &[6], &[0u8; 32]).unwrap();
+        let strong_ref = filesystem.read_file("deleted").unwrap().upgrade().unwrap();
+        filesystem.delete_file("deleted").unwrap();
+        let _writer = filesystem
+            .get_file_writer("unfinished", 3, &[0u8; 32])
+            .unwrap();
+
+        let files = filesystem.list_files();
+        let names: Vec<&str> = files.iter().map(|file| file.name()).collect();
+        assert_eq!(names, ["first", "second"]);
+        assert_eq!(files[0].upgrade().unwrap().as_ref(), [1, 2, 3]);
+        drop(strong_ref);
+    }
+
+    #[test]
+    fn writing_a_file_reduces_the_free_space() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        assert_eq!(
+            filesystem.get_total_space_bytes(),
+            SimulatedStorage::SIZE as usize
+        );
+        let free_before = filesystem.get_free_space_bytes();
+        assert_eq!(free_before, filesystem.get_total_space_bytes());
+
+        let content = [7u8; 5000];
+        filesystem
+            .write_file("fancy", &content, &[0u8; 32])
+            .unwrap();
+        let free_after = filesystem.get_free_space_bytes();
+        assert!(free_before - free_after >= content.len() + size_of::<FileMetadata>());
+    }
+
+    #[test]
+    fn reading_a_corrupted_file_verified_fails() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        let content = [0xffu8; 100];
+        let hash: [u8; 32] = blake3::hash(&content).into();
+        filesystem.write_file("fancy", &content, &hash).unwrap();
+        assert!(filesystem.read_file_verified("fancy").is_ok());
+
+        // Flash can only clear bits, so this changes the first byte of the content
+        storage
+            .write(size_of::<FileMetadata>() as u32, &[0x00])
+            .unwrap();
+        let Err(ReadVerifyError::HashMismatch { expected, got }) =
+            filesystem.read_file_verified("fancy")
+        else {
+            panic!("Reading a corrupted file should fail");
+        };
+        assert_eq!(expected, hash);
+        assert_ne!(got, hash);
+        // Reading without verification still works
+        assert!(filesystem.read_file("fancy").is_some());
+    }
+
+    #[test]
+    fn formatting_removes_all_files() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        filesystem
+            .write_file("fancy", &[1, 2, 3], &[0u8; 32])
+            .unwrap();
+        filesystem
+            .write_file("other", &[4, 5, 6], &[0u8; 32])
+            .unwrap();
+        drop(filesystem);
+
+        let filesystem = unsafe { Filesystem::format(storage) }.unwrap();
+        assert!(filesystem.list_files().is_empty());
+        assert_eq!(
+            filesystem.get_free_space_bytes(),
+            filesystem.get_total_space_bytes()
+        );
+        drop(filesystem);
+
+        let filesystem = Filesystem::new(storage).unwrap();
+        assert!(filesystem.read_file("fancy").is_none());
+    }
+
+    #[test]
+    fn renamed_files_can_be_read_with_the_new_name() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        let content = [1, 2, 3, 4];
+        let hash: [u8; 32] = blake3::hash(&content).into();
+        filesystem.write_file("old", &content, &hash).unwrap();
+        filesystem.rename_file("old", "new").unwrap();
+        assert!(filesystem.read_file("old").is_none());
+        let renamed = filesystem.read_file("new").unwrap().upgrade().unwrap();
+        assert_eq!(renamed.as_ref(), content);
+        assert_eq!(renamed.hash(), &hash);
+        drop(renamed);
+        drop(filesystem);
+
+        let filesystem = Filesystem::new(storage).unwrap();
+        assert!(filesystem.read_file("old").is_none());
+        let renamed = filesystem.read_file("new").unwrap();
+        assert_eq!(renamed.upgrade().unwrap().as_ref(), content);
+    }
+
+    #[test]
+    fn renaming_to_an_existing_name_fails() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        filesystem.write_file("first", &[1], &[0u8; 32]).unwrap();
+        filesystem.write_file("second", &[2], &[0u8; 32]).unwrap();
+        let Err(RenameFileError::AlreadyExists) = filesystem.rename_file("first", "second") else {
+            panic!("Renaming to an existing name should fail");
+        };
+        let first = filesystem.read_file("first").unwrap();
+        assert_eq!(first.upgrade().unwrap().as_ref(), [1]);
+        let second = filesystem.read_file("second").unwrap();
+        assert_eq!(second.upgrade().unwrap().as_ref(), [2]);
+        let Err(RenameFileError::FileNotFound) = filesystem.rename_file("missing", "third") else {
+            panic!("Renaming a missing file should fail");
+        };
+    }
+
+    #[test]
+    fn renaming_works_at_the_maximum_number_of_files() {
+        let owned_storage = LimitedStorage(SimulatedStorage::new());
+        let storage = unsafe { std::mem::transmute::<_, &'static LimitedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        filesystem.write_file("first", &[1], &[0u8; 32]).unwrap();
+        filesystem.write_file("second", &[2], &[0u8; 32]).unwrap();
+
+        filesystem.rename_file("first", "third").unwrap();
+        assert!(filesystem.read_file("first").is_none());
+        let third = filesystem.read_file("third").unwrap();
+        assert_eq!(third.upgrade().unwrap().as_ref(), [1]);
+        assert_eq!(filesystem.file_count(), 2);
+    }
+
+    #[test]
+    fn iterating_the_blocks_yields_every_block_once() {
+        let storage = SimulatedStorage::new();
+        storage
+            .write(SimulatedStorage::BLOCK_SIZE, &[0x12])
+            .unwrap();
+        storage.inject_read_failure(2 * SimulatedStorage::BLOCK_SIZE);
+        let blocks: Vec<_> = storage.blocks().collect();
+        assert_eq!(blocks.len(), SimulatedStorage::BLOCKS as usize);
+        for (expected_index, (index, block)) in blocks.iter().enumerate() {
+            assert_eq!(*index, expected_index as u32);
+            if expected_index == 2 {
+                assert!(block.is_none());
+                continue;
+            }
+            assert_eq!(block.unwrap().len(), SimulatedStorage::BLOCK_SIZE as usize);
+        }
+        assert_eq!(blocks[1].1.unwrap()[0], 0x12);
+        assert!(blocks[0].1.unwrap().iter().all(|byte| *byte == 0xff));
+    }
+
+    #[test]
+    fn a_read_failure_inside_a_file_does_not_lose_the_following_files() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        // Occupies the first three blocks
+        let multi = vec![7u8; 2 * SimulatedStorage::BLOCK_SIZE as usize + 100];
+        filesystem.write_file("multi", &multi, &[0u8; 32]).unwrap();
+        filesystem.write_file("second", &[2], &[0u8; 32]).unwrap();
+        drop(filesystem);
+
+        storage.inject_read_failure(SimulatedStorage::BLOCK_SIZE + 10);
+        let filesystem = Filesystem::new(storage).unwrap();
+        assert!(filesystem.read_file("multi").is_none());
+        let second = filesystem.read_file("second").unwrap();
+        assert_eq!(second.upgrade().unwrap().as_ref(), [2]);
+        drop(second);
+        drop(filesystem);
+
+        // The blocks of the unreadable file were not erased
+        let filesystem = Filesystem::new(storage).unwrap();
+        let file = filesystem.read_file("multi").unwrap();
+        assert_eq!(file.upgrade().unwrap().as_ref(), multi.as_slice());
+        assert!(filesystem.read_file("second").is_some());
+    }
+
+    #[test]
+    fn a_read_failure_before_the_first_block_does_not_shift_the_scan() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        let multi = vec![7u8; 2 * SimulatedStorage::BLOCK_SIZE as usize + 100];
+        filesystem.write_file("multi", &multi, &[0u8; 32]).unwrap();
+        filesystem.write_file("second", &[2], &[0u8; 32]).unwrap();
+        drop(filesystem);
+
+        // Start the scan at the second file, so the blocks of the first file are read while skipping to it
+        storage
+            .write_metadata("first_block", &3u16.to_le_bytes())
+            .unwrap();
+        storage.inject_read_failure(SimulatedStorage::BLOCK_SIZE);
+        let filesystem = Filesystem::new(storage).unwrap();
+        let second = filesystem.read_file("second").unwrap();
+        assert_eq!(second.upgrade().unwrap().as_ref(), [2]);
+        let file = filesystem.read_file("multi").unwrap();
+        assert_eq!(file.upgrade().unwrap().as_ref(), multi.as_slice());
+    }
+
+    #[test]
+    fn a_failed_write_does_not_leave_a_file_behind() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        // The first file is placed at the start of the storage
+        storage.inject_write_failure(size_of::<FileMetadata>() as u32 + 10);
+        let Err(FilesystemWriteError::StorageError(StorageError::Context { source, .. })) =
+            filesystem.write_file("fancy", &[1; 100], &[0u8; 32])
+        else {
+            panic!("The injected write failure should be returned");
+        };
+        assert!(matches!(*source, StorageError::WriteFailure));
+        assert!(filesystem.list_files().is_empty());
+
+        // The failure only happens once
+        filesystem
+            .write_file("fancy", &[2; 100], &[0u8; 32])
+            .unwrap();
+        let file = filesystem.read_file("fancy").unwrap();
+        assert_eq!(file.upgrade().unwrap().as_ref(), [2; 100]);
+    }
+
+    #[test]
+    fn blocks_that_can_not_be_read_are_skipped_without_erasing_them() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        filesystem
+            .write_file("fancy", &[1, 2, 3], &[0u8; 32])
+            .unwrap();
+        drop(filesystem);
+
+        storage.inject_read_failure(0);
+        let filesystem = Filesystem::new(storage).unwrap();
+        assert!(filesystem.read_file("fancy").is_none());
+        drop(filesystem);
+
+        let filesystem = Filesystem::new(storage).unwrap();
+        let file = filesystem.read_file("fancy").unwrap();
+        assert_eq!(file.upgrade().unwrap().as_ref(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn writing_more_than_the_maximum_number_of_files_fails() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        for index in 0..SimulatedStorage::MAX_FILES {
+            filesystem
+                .write_file(&format!("file{}", index), &[index as u8], &[0u8; 32])
+                .unwrap();
+        }
+        assert_eq!(filesystem.file_count(), SimulatedStorage::MAX_FILES);
+        let Err(FilesystemWriteError::TooManyFiles) =
+            filesystem.write_file("one too many", &[0], &[0u8; 32])
+        else {
+            panic!("Writing more than the maximum number of files should fail");
+        };
+
+        // Deleting a file makes room for a new one
+        filesystem.delete_file("file0").unwrap();
+        filesystem
+            .write_file("one too many", &[0], &[0u8; 32])
+            .unwrap();
+    }
+
+    #[test]
+    fn replacing_a_file_works_at_the_maximum_number_of_files() {
+        let owned_storage = LimitedStorage(SimulatedStorage::new());
+        let storage = unsafe { std::mem::transmute::<_, &'static LimitedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        filesystem.write_file("first", &[1], &[0u8; 32]).unwrap();
+        filesystem.write_file("second", &[2], &[0u8; 32]).unwrap();
+
+        filesystem
+            .write_or_replace("first", &[3], &[0u8; 32])
+            .unwrap();
+        let file = filesystem.read_file("first").unwrap();
+        assert_eq!(file.upgrade().unwrap().as_ref(), [3]);
+        drop(file);
+        let Err(FilesystemWriteError::TooManyFiles) =
+            filesystem.write_or_replace("third", &[4], &[0u8; 32])
+        else {
+            panic!("Writing a new file at the maximum number of files should fail");
+        };
+    }
+
     #[test]
     fn writing_a_maximum_size_file_works() {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         let file = [0u8; SimulatedStorage::SIZE as usize - size_of::<FileMetadata>()];
         filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
         let result = filesystem.read_file("fancy").unwrap();
@@ -812,7 +1980,7 @@ mod tests {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         let file = [0u8; SimulatedStorage::SIZE as usize - size_of::<FileMetadata>()];
         filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
         filesystem.delete_file("fancy").unwrap();
@@ -827,7 +1995,7 @@ mod tests {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         let file = [0u8; SimulatedStorage::SIZE as usize - size_of::<FileMetadata>()];
         filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
         let fancy_file = filesystem.read_file("fancy").unwrap();
@@ -847,7 +2015,7 @@ mod tests {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         let file = [0u8; SimulatedStorage::SIZE as usize + 1];
         let Err(_) = filesystem.write_file("fancy", &file, &[0u8; 32]) else {
             panic!("Should fail when there is not enough space");
@@ -859,7 +2027,7 @@ mod tests {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         let file = [0u8; SimulatedStorage::SIZE as usize
             - size_of::<FileMetadata>()
             - SimulatedStorage::BLOCK_SIZE as usize * 2];
@@ -874,16 +2042,589 @@ mod tests {
         // let mut filesystem = Filesystem::new(storage);
     }
 
+    #[test]
+    fn files_filling_whole_blocks_are_found_after_a_restart() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        let file = [0u8; SimulatedStorage::BLOCK_SIZE as usize - size_of::<FileMetadata>()];
+        filesystem.write_file("first", &file, &[0u8; 32]).unwrap();
+        filesystem.write_file("second", &file, &[1u8; 32]).unwrap();
+        drop(filesystem);
+        let filesystem = Filesystem::new(storage).unwrap();
+        assert!(filesystem.read_file("first").is_some());
+        assert!(filesystem.read_file("second").is_some());
+    }
+
+    #[test]
+    fn copy_between_copies_all_files_to_a_different_storage() {
+        let owned_source = SimulatedStorage::new();
+        let source_storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_source) };
+        let mut source = Filesystem::new(source_storage).unwrap();
+        let small_file = vec![1u8; 100];
+        let large_file = vec![2u8; 10000];
+        source.write_file("small", &small_file, &[1u8; 32]).unwrap();
+        source.write_file("large", &large_file, &[2u8; 32]).unwrap();
+        source.read_file("large").unwrap().set_important().unwrap();
+        let mut unfinished = source.get_file_writer("unfinished", 3, &[3u8; 32]).unwrap();
+        unfinished.write_content(&[1, 2, 3]).unwrap();
+
+        let owned_destination = DynamicStorage::<65536>::new(1024, 64).unwrap();
+        let destination_storage =
+            unsafe { std::mem::transmute::<_, &'static DynamicStorage<65536>>(&owned_destination) };
+        let mut destination: DynamicFilesystem<65536> =
+            Filesystem::new(destination_storage).unwrap();
+        let stats = Filesystem::copy_between(&source, &mut destination).unwrap();
+        assert_eq!(
+            stats,
+            CopyStats {
+                files_copied: 2,
+                bytes_copied: 10100
+            }
+        );
+
+        let small = destination.read_file_by_hash(&[1u8; 32]).unwrap();
+        assert_eq!(small.upgrade().unwrap().name_str(), "small");
+        assert_eq!(small.upgrade().unwrap().as_ref(), small_file);
+        let large = destination.read_file("large").unwrap();
+        assert_eq!(large.upgrade().unwrap().as_ref(), large_file);
+        assert!(large.important());
+        assert!(!small.important());
+        assert!(destination.read_file("unfinished").is_none());
+    }
+
+    #[test]
+    fn works_with_different_block_sizes() {
+        for (block_size, block_count) in [(1024, 64), (4096, 16), (8192, 8), (16384, 4)] {
+            let owned_storage = DynamicStorage::<65536>::new(block_size, block_count).unwrap();
+            let storage =
+                unsafe { std::mem::transmute::<_, &'static DynamicStorage<65536>>(&owned_storage) };
+            let mut filesystem: DynamicFilesystem<65536> = Filesystem::new(storage).unwrap();
+            let small_file = vec![1u8; 100];
+            let large_file = vec![2u8; block_size as usize * 2];
+            filesystem
+                .write_file("small", &small_file, &[0u8; 32])
+                .unwrap();
+            filesystem
+                .write_file("large", &large_file, &[1u8; 32])
+                .unwrap();
+            drop(filesystem);
+
+            let filesystem: DynamicFilesystem<65536> = Filesystem::new(storage).unwrap();
+            let small = filesystem.read_file("small").unwrap();
+            assert_eq!(small.upgrade().unwrap().as_ref(), small_file);
+            let large = filesystem.read_file("large").unwrap();
+            assert_eq!(large.upgrade().unwrap().as_ref(), large_file);
+        }
+    }
+
+    #[test]
+    fn write_or_replace_replaces_the_content() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        filesystem
+            .write_or_replace("fancy", &[1, 2, 3], &[1u8; 32])
+            .unwrap();
+        let old_file = filesystem.read_file("fancy").unwrap().upgrade().unwrap();
+        filesystem
+            .write_or_replace("fancy", &[4, 5, 6, 7], &[2u8; 32])
+            .unwrap();
+        // Open readers keep the old content
+        assert_eq!(old_file.as_ref(), [1, 2, 3]);
+        let result = filesystem.read_file("fancy").unwrap();
+        assert_eq!(result.upgrade().unwrap().as_ref(), [4, 5, 6, 7]);
+        assert!(filesystem.read_file_by_hash(&[1u8; 32]).is_none());
+        drop(old_file);
+        drop(filesystem);
+        let filesystem = Filesystem::new(storage).unwrap();
+        let result = filesystem.read_file("fancy").unwrap();
+        assert_eq!(result.upgrade().unwrap().as_ref(), [4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn write_or_replace_keeps_the_old_file_if_there_is_not_enough_space() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        let file =
+            [1u8; (SimulatedStorage::BLOCK_SIZE * (SimulatedStorage::BLOCKS / 2 + 1)) as usize];
+        filesystem.write_file("fancy", &file, &[0u8; 32]).unwrap();
+        filesystem
+            .write_or_replace("fancy", &file, &[0u8; 32])
+            .unwrap_err();
+        let result = filesystem.read_file("fancy").unwrap();
+        assert_eq!(result.upgrade().unwrap().as_ref(), file);
+    }
+
+    #[test]
+    fn interrupted_replacements_are_resolved_after_a_restart() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        filesystem.write_file("complete", &[1], &[0u8; 32]).unwrap();
+        filesystem
+            .write_file("incomplete", &[2], &[0u8; 32])
+            .unwrap();
+        for (name, commit) in [("complete", true), ("incomplete", false)] {
+            let location = filesystem.find_free_space(1 + size_of::<FileMetadata>() as u32);
+            let (file, mut writer) =
+                FileInformation::to_storage(storage, location.unwrap(), 1, name, &[0u8; 32])
+                    .unwrap();
+            filesystem.files.push(file);
+            writer.set_replacement().unwrap();
+            writer.write_content(&[3]).unwrap();
+            if commit {
+                writer.commit().unwrap();
+            }
+        }
+        // The replacements are not visible before they are finished
+        let result = filesystem.read_file("complete").unwrap();
+        assert_eq!(result.upgrade().unwrap().as_ref(), [1]);
+        drop(filesystem);
+
+        let filesystem = Filesystem::new(storage).unwrap();
+        let result = filesystem.read_file("complete").unwrap();
+        assert_eq!(result.upgrade().unwrap().as_ref(), [3]);
+        let result = filesystem.read_file("incomplete").unwrap();
+        assert_eq!(result.upgrade().unwrap().as_ref(), [2]);
+    }
+
+    #[test]
+    fn dropping_a_writer_without_committing_deletes_the_file() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        let mut writer = filesystem
+            .get_file_writer("unfinished", 3, &[0u8; 32])
+            .unwrap();
+        writer.write_content(&[1, 2]).unwrap();
+        drop(writer);
+        // The name can be used again right away
+        filesystem
+            .write_file("unfinished", &[3], &[0u8; 32])
+            .unwrap();
+        drop(filesystem);
+
+        let filesystem = Filesystem::new(storage).unwrap();
+        assert_eq!(filesystem.files.len(), 1);
+        let result = filesystem.read_file("unfinished").unwrap();
+        assert_eq!(result.upgrade().unwrap().as_ref(), [3]);
+    }
+
+    #[test]
+    fn annotated_dump_marks_the_files() {
+        let owned_storage = SimulatedStorage::new();
+        let storage =
+            unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+        let mut filesystem = Filesystem::new(storage).unwrap();
+        filesystem
+            .write_file("fancy", b"hello", &[0u8; 32])
+            .unwrap();
+        let dump = SimulatedStorage::annotated_dump(&filesystem);
+        assert!(dump.contains("== \"fancy\" at 0x00000000: 5 bytes of content"));
+        assert!(dump.contains("== end of \"fancy\" at 0x00000045"));
+        assert!(dump.contains("|hello"));
+        // Erased flash is collapsed
+        assert!(dump.lines().count() < 32);
+        assert!(storage.hexdump_block(1).starts_with(
+            "00001000  ff ff ff ff ff ff ff ff  ff ff ff ff ff ff ff ff  |................|\n*\n"
+        ));
+    }
+
     #[test]
     fn can_not_create_two_files_with_the_same_name() {
         let owned_storage = SimulatedStorage::new();
         let storage =
             unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
-        let mut filesystem = Filesystem::new(storage);
+        let mut filesystem = Filesystem::new(storage).unwrap();
         let file = [0u8; SimulatedStorage::BLOCK_SIZE as usize - size_of::<FileMetadata>()];
         filesystem.write_file("cool", &file, &[0u8; 32]).unwrap();
         filesystem
             .write_file("cool", &file, &[0u8; 32])
             .unwrap_err();
     }
+
+    #[test]
+    fn accesses_outside_of_the_storage_are_out_of_bounds() {
+        let storage = SimulatedStorage::new();
+        let size = SimulatedStorage::SIZE;
+        assert!(matches!(
+            storage.read(size, 1),
+            Err(StorageError::OutOfBounds { address, length: 1, max }) if address == size && max == size
+        ));
+        assert!(matches!(
+            storage.write(0, &vec![0u8; size as usize]),
+            Err(StorageError::OutOfBounds { address: 0, length, max }) if length == size && max == size
+        ));
+        // Accesses that wrap around the end are still allowed
+        storage.read(size - 1, 2).unwrap();
+    }
+
+    /// A storage that only allows two files
+    struct LimitedStorage(SimulatedStorage);
+
+    impl Storage for LimitedStorage {
+        const BLOCK_SIZE: u32 = SimulatedStorage::BLOCK_SIZE;
+        const BLOCKS: u32 = SimulatedStorage::BLOCKS;
+        const MAX_FILES: usize = 2;
+
+        fn read(&self, address: u32, length: u32) -> Result<&'static [u8], StorageError> {
+            self.0.read(address, length)
+        }
+
+        fn write(&self, address: u32, data: &[u8]) -> Result<(), StorageError> {
+            self.0.write(address, data)
+        }
+
+        fn erase(&self, address: u32, length: u32) -> Result<(), EraseStorageError> {
+            self.0.erase(address, length)
+        }
+
+        fn read_metadata(&self, key: &str) -> Result<Box<[u8]>, MetadataError> {
+            self.0.read_metadata(key)
+        }
+
+        fn write_metadata(&self, key: &str, value: &[u8]) -> Result<(), MetadataError> {
+            self.0.write_metadata(key, value)
+        }
+    }
+
+    /// A storage that can be read, but not modified
+    struct ReadOnlyStorage(SimulatedStorage);
+
+    impl Storage for ReadOnlyStorage {
+        const BLOCK_SIZE: u32 = SimulatedStorage::BLOCK_SIZE;
+        const BLOCKS: u32 = SimulatedStorage::BLOCKS;
+
+        fn read(&self, address: u32, length: u32) -> Result<&'static [u8], StorageError> {
+            self.0.read(address, length)
+        }
+
+        fn write(&self, _address: u32, _data: &[u8]) -> Result<(), StorageError> {
+            Err(StorageError::IoError(std::io::Error::other("read only")))
+        }
+
+        fn erase(&self, _address: u32, _length: u32) -> Result<(), EraseStorageError> {
+            Err(StorageError::IoError(std::io::Error::other("read only")).into())
+        }
+
+        fn read_metadata(&self, key: &str) -> Result<Box<[u8]>, MetadataError> {
+            self.0.read_metadata(key)
+        }
+
+        fn write_metadata(&self, _key: &str, _value: &[u8]) -> Result<(), MetadataError> {
+            Err(std::io::Error::other("read only"))
+        }
+    }
+
+    #[test]
+    fn opening_a_storage_that_can_not_be_fixed_fails() {
+        // A new storage needs the first block to be written
+        let owned_storage = ReadOnlyStorage(SimulatedStorage::new());
+        let storage = unsafe { std::mem::transmute::<_, &'static ReadOnlyStorage>(&owned_storage) };
+        assert!(matches!(
+            Filesystem::new(storage),
+            Err(FilesystemInitError::MetadataWriteFailed(_))
+        ));
+
+        // Blocks with garbage need to be erased
+        let simulated = SimulatedStorage::new();
+        simulated.write_metadata("first_block", &[0, 0]).unwrap();
+        simulated.write(0, &[0x12, 0x34]).unwrap();
+        let owned_storage = ReadOnlyStorage(simulated);
+        let storage = unsafe { std::mem::transmute::<_, &'static ReadOnlyStorage>(&owned_storage) };
+        assert!(matches!(
+            Filesystem::new(storage),
+            Err(FilesystemInitError::EraseStorageError(_))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use crate::storage::simulated::SimulatedStorage;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+
+    const MAX_CONTENT_LENGTH: u32 = SimulatedStorage::SIZE - size_of::<FileMetadata>() as u32;
+
+    #[derive(Debug, Clone)]
+    enum Operation {
+        Write { name: u8, length: u32 },
+        Delete { name: u8 },
+    }
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        prop_oneof![
+            3 => (0u8..8, 0..MAX_CONTENT_LENGTH)
+                .prop_map(|(name, length)| Operation::Write { name, length }),
+            3 => (0u8..8, 0..(SimulatedStorage::BLOCK_SIZE * 3))
+                .prop_map(|(name, length)| Operation::Write { name, length }),
+            1 => (0u8..8).prop_map(|name| Operation::Delete { name }),
+        ]
+    }
+
+    #[derive(Debug, Clone)]
+    enum FaultyOperation {
+        Write { name: u8, length: u32 },
+        Delete { name: u8 },
+        InjectReadFailure { address: u32 },
+        InjectWriteFailure { address: u32 },
+        Remount,
+    }
+
+    fn faulty_operation() -> impl Strategy<Value = FaultyOperation> {
+        prop_oneof![
+            3 => (0u8..8, 0..(SimulatedStorage::BLOCK_SIZE * 3))
+                .prop_map(|(name, length)| FaultyOperation::Write { name, length }),
+            1 => (0u8..8).prop_map(|name| FaultyOperation::Delete { name }),
+            1 => (0..SimulatedStorage::SIZE)
+                .prop_map(|address| FaultyOperation::InjectReadFailure { address }),
+            1 => (0..SimulatedStorage::SIZE)
+                .prop_map(|address| FaultyOperation::InjectWriteFailure { address }),
+            1 => Just(FaultyOperation::Remount),
+        ]
+    }
+
+    fn file_name(name: u8) -> String {
+        format!("file{}", name)
+    }
+
+    fn file_content(name: u8, length: u32) -> Vec<u8> {
+        (0..length)
+            .map(|index| (index as u8).wrapping_add(name.wrapping_mul(31)))
+            .collect()
+    }
+
+    /// Apply the operations and return the expected content of every file that was not deleted explicitly
+    fn apply(
+        filesystem: &mut Filesystem<SimulatedStorage>,
+        operations: &[Operation],
+    ) -> HashMap<String, Vec<u8>> {
+        let mut expected: HashMap<String, Vec<u8>> = HashMap::new();
+        for operation in operations {
+            match operation {
+                Operation::Write { name, length } => {
+                    let name_str = file_name(*name);
+                    if filesystem.read_file(&name_str).is_some() {
+                        filesystem.delete_file(&name_str).unwrap();
+                    }
+                    let content = file_content(*name, *length);
+                    filesystem
+                        .write_file(&name_str, &content, &[*name; 32])
+                        .unwrap();
+                    expected.insert(name_str, content);
+                }
+                Operation::Delete { name } => {
+                    let name_str = file_name(*name);
+                    let _ = filesystem.delete_file(&name_str);
+                    expected.remove(&name_str);
+                }
+            }
+        }
+        expected
+    }
+
+    /// Mount the storage until it works. Injected failures only happen once, so this does not take long
+    fn mount(storage: &'static SimulatedStorage) -> Filesystem<SimulatedStorage> {
+        loop {
+            if let Ok(filesystem) = Filesystem::new(storage) {
+                return filesystem;
+            }
+        }
+    }
+
+    /// Check that exactly the expected files are readable and have the expected content
+    fn check_files(
+        filesystem: &Filesystem<SimulatedStorage>,
+        expected: &HashMap<String, Vec<u8>>,
+    ) -> Result<(), TestCaseError> {
+        let mut expected_names: Vec<String> = expected.keys().cloned().collect();
+        expected_names.sort();
+        prop_assert_eq!(live_file_names(filesystem), expected_names);
+        for (name, content) in expected {
+            let reader = filesystem.read_file(name).unwrap().upgrade().unwrap();
+            prop_assert!(
+                reader.as_ref() == content.as_slice(),
+                "Content of {} differs",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    /// Mount the storage until all injected read failures are used up and check the files
+    ///
+    /// Mounts with read failures may skip files that can not be read, but must not erase them. Every mount reads every block, so it uses up at least one injected read failure. The last mount has to find exactly the expected files.
+    fn remount(
+        storage: &'static SimulatedStorage,
+        injected_read_failures: usize,
+        expected: &HashMap<String, Vec<u8>>,
+    ) -> Result<Filesystem<SimulatedStorage>, TestCaseError> {
+        for _ in 0..injected_read_failures {
+            let faulty = mount(storage);
+            for name in live_file_names(&faulty) {
+                let reader = faulty.read_file(&name).unwrap().upgrade().unwrap();
+                prop_assert!(
+                    expected
+                        .get(&name)
+                        .is_some_and(|content| reader.as_ref() == content.as_slice()),
+                    "Content of {} differs",
+                    name
+                );
+            }
+        }
+        let filesystem = mount(storage);
+        check_files(&filesystem, expected)?;
+        Ok(filesystem)
+    }
+
+    fn live_file_names(filesystem: &Filesystem<SimulatedStorage>) -> Vec<String> {
+        let mut names: Vec<String> = filesystem
+            .files
+            .iter()
+            .filter(|file| file.valid() && !file.deleted() && !file.marked_for_deletion())
+            .map(|file| file.name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn reading_returns_the_written_content(operations in prop::collection::vec(operation(), 1..24)) {
+            let owned_storage = SimulatedStorage::new();
+            let storage =
+                unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+            let mut filesystem = Filesystem::new(storage).unwrap();
+            let expected = apply(&mut filesystem, &operations);
+
+            // Unimportant files may have been evicted, but existing files must have the correct content
+            for (name, content) in &expected {
+                if let Some(file) = filesystem.read_file(name) {
+                    let reader = file.upgrade().unwrap();
+                    prop_assert!(reader.as_ref() == content.as_slice(), "Content of {} differs", name);
+                }
+            }
+            // The last written file can never be evicted
+            if let Some(Operation::Write { name, length }) = operations.last() {
+                let reader = filesystem.read_file(&file_name(*name)).unwrap().upgrade().unwrap();
+                let content = file_content(*name, *length);
+                prop_assert!(reader.as_ref() == content.as_slice(), "Content of the last file differs");
+            }
+        }
+
+        #[test]
+        fn writing_a_file_that_fits_always_succeeds(
+            operations in prop::collection::vec(operation(), 0..24),
+            length in 0..MAX_CONTENT_LENGTH,
+        ) {
+            let owned_storage = SimulatedStorage::new();
+            let storage =
+                unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+            let mut filesystem = Filesystem::new(storage).unwrap();
+            apply(&mut filesystem, &operations);
+
+            // All files are unimportant and unreferenced, so they can be evicted to make space
+            let content = file_content(200, length);
+            prop_assert!(filesystem.write_file("final", &content, &[0u8; 32]).is_ok());
+        }
+
+        #[test]
+        fn reconstructing_the_filesystem_yields_the_same_files(operations in prop::collection::vec(operation(), 1..24)) {
+            let owned_storage = SimulatedStorage::new();
+            let storage =
+                unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+            let mut filesystem = Filesystem::new(storage).unwrap();
+            apply(&mut filesystem, &operations);
+            let names_before = live_file_names(&filesystem);
+
+            let reconstructed = Filesystem::new(storage).unwrap();
+            prop_assert_eq!(live_file_names(&reconstructed), names_before);
+        }
+
+        #[test]
+        fn remounting_never_loses_committed_files(operations in prop::collection::vec(faulty_operation(), 1..32)) {
+            let owned_storage = SimulatedStorage::new();
+            let storage =
+                unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+            let mut filesystem = mount(storage);
+            // Content of every committed file that was not deleted or evicted
+            let mut expected: HashMap<String, Vec<u8>> = HashMap::new();
+            let mut injected_read_failures = 0;
+            for operation in &operations {
+                match operation {
+                    FaultyOperation::Write { name, length } => {
+                        let name_str = file_name(*name);
+                        let content = file_content(*name, *length);
+                        if filesystem.write_or_replace(&name_str, &content, &[*name; 32]).is_ok() {
+                            expected.insert(name_str, content);
+                        }
+                    }
+                    FaultyOperation::Delete { name } => {
+                        let name_str = file_name(*name);
+                        if filesystem.delete_file(&name_str).is_ok() {
+                            expected.remove(&name_str);
+                        }
+                    }
+                    FaultyOperation::InjectReadFailure { address } => {
+                        storage.inject_read_failure(*address);
+                        injected_read_failures += 1;
+                    }
+                    FaultyOperation::InjectWriteFailure { address } => {
+                        storage.inject_write_failure(*address);
+                    }
+                    FaultyOperation::Remount => {
+                        drop(filesystem);
+                        filesystem = remount(storage, injected_read_failures, &expected)?;
+                        injected_read_failures = 0;
+                        continue;
+                    }
+                }
+                // Unimportant files may be evicted to make space for new ones
+                expected.retain(|name, _| filesystem.read_file(name).is_some());
+            }
+            drop(filesystem);
+            remount(storage, injected_read_failures, &expected)?;
+        }
+
+        #[test]
+        fn files_never_overlap(operations in prop::collection::vec(operation(), 1..24)) {
+            let owned_storage = SimulatedStorage::new();
+            let storage =
+                unsafe { std::mem::transmute::<_, &'static SimulatedStorage>(&owned_storage) };
+            let mut filesystem = Filesystem::new(storage).unwrap();
+            apply(&mut filesystem, &operations);
+
+            let blocks: Vec<(u32, u32)> = filesystem
+                .files
+                .iter()
+                .filter(|file| !file.deleted())
+                .map(|file| {
+                    let start = file.address / SimulatedStorage::BLOCK_SIZE;
+                    let length = (file.length + size_of::<FileMetadata>() as u32)
+                        .div_ceil(SimulatedStorage::BLOCK_SIZE);
+                    (start, length)
+                })
+                .collect();
+            let mut used = [false; SimulatedStorage::BLOCKS as usize];
+            for (start, length) in blocks {
+                for block in start..(start + length) {
+                    let block = (block % SimulatedStorage::BLOCKS) as usize;
+                    prop_assert!(!used[block], "Block {} is used by two files", block);
+                    used[block] = true;
+                }
+            }
+        }
+    }
 }
@@ -2,34 +2,80 @@
 //! storage backends used in the application. Implementations of this trait
 //! are responsible for handling theuse crate::storage::Storage;
 
+use alloc::{boxed::Box, string::String};
 use thiserror::Error;
 
 #[cfg(any(test, feature = "simulated"))]
 #[cfg_attr(docsrs, doc(cfg(feature = "simulated")))]
 pub mod simulated;
 
+#[cfg(any(test, feature = "simulated"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "simulated")))]
+pub mod dynamic;
+
 #[cfg(feature = "esp")]
 #[cfg_attr(docsrs, doc(cfg(feature = "esp")))]
 pub mod esp;
 
+/// Error returned by the metadata functions of a [Storage]
+#[cfg(feature = "std")]
+pub type MetadataError = std::io::Error;
+
+/// Error returned by the metadata functions of a [Storage]
+#[cfg(not(feature = "std"))]
+#[derive(Error, Debug)]
+#[error("{0}")]
+pub struct MetadataError(pub String);
+
 /// Some kind of error that can occur during a storage operation
 #[derive(Error, Debug)]
 pub enum StorageError {
     /// Failed to write to flash. Maybe the pages are not erased.
     #[error("Failed to write to flash. Maybe the pages are not erased.")]
-    IoError(#[from] std::io::Error),
-    /// Address is bigger than the storage size
-    #[error("Address is bigger than the storage size")]
-    AddressTooBig,
-    /// Size is bigger than the storage size
-    #[error("Size is bigger than the storage size")]
-    SizeTooBig,
+    IoError(#[from] MetadataError),
+    /// The accessed range does not fit into the storage
+    #[error("Accessing {length} bytes at address {address:#x} is out of bounds for a storage of {max} bytes")]
+    OutOfBounds {
+        /// Start of the access
+        address: u32,
+        /// Number of bytes that were accessed
+        length: u32,
+        /// Size of the storage
+        max: u32,
+    },
     /// Only returned by write_checked
     #[error("Read data does not match written data")]
     ReadDataDoesNotMatchWrittenData,
+    /// The storage failed to read the data
+    #[error("Failed to read from the storage")]
+    ReadFailure,
+    /// The storage failed to write the data
+    #[error("Failed to write to the storage")]
+    WriteFailure,
     /// Other error occurred during a storage operation
     #[error("{0}")]
     Other(String),
+    /// A storage operation failed at a known address
+    #[error("Storage operation failed while {operation} at address {address:#x}: {source}")]
+    Context {
+        /// What the filesystem was doing, for example `writing file metadata`
+        operation: &'static str,
+        /// The address the operation was working on
+        address: u32,
+        /// The underlying error
+        source: Box<StorageError>,
+    },
+}
+
+impl StorageError {
+    /// Add the operation that failed and its address to the error
+    pub fn with_context(self, operation: &'static str, address: u32) -> StorageError {
+        return StorageError::Context {
+            operation,
+            address,
+            source: Box::new(self),
+        };
+    }
 }
 
 #[derive(Error, Debug)]
@@ -58,11 +104,34 @@ pub enum EraseStorageError {
 /// Filesystem metadata is not stored in the main storage block
 ///
 /// Storage must provide these functions to store metadata.
+///
+/// Addresses and lengths in the storage are always `u32`, as flash is a lot smaller than 4 GiB.
 pub trait Storage {
     /// Size in which blocks can be erased
+    ///
+    /// Storages that only know their size at runtime set this to 0 and override [Storage::block_size] instead.
     const BLOCK_SIZE: u32;
     /// Total number of blocks
+    ///
+    /// Storages that only know their size at runtime set this to 0 and override [Storage::block_count] instead.
     const BLOCKS: u32;
+    /// Maximum number of files in a filesystem on this storage
+    ///
+    /// Storages that only know their size at runtime override [Storage::max_files] instead.
+    const MAX_FILES: usize = Self::BLOCKS as usize / 2;
+
+    /// Size in which blocks can be erased
+    fn block_size(&self) -> u32 {
+        return Self::BLOCK_SIZE;
+    }
+    /// Total number of blocks
+    fn block_count(&self) -> u32 {
+        return Self::BLOCKS;
+    }
+    /// Maximum number of files in a filesystem on this storage
+    fn max_files(&self) -> usize {
+        return Self::MAX_FILES;
+    }
 
     /// Read at a specific location.
     ///
@@ -82,9 +151,18 @@ pub trait Storage {
     fn erase(&self, address: u32, length: u32) -> Result<(), EraseStorageError>;
 
     /// Read a metadata key from persistent storage
-    fn read_metadata(&self, key: &str) -> std::io::Result<Box<[u8]>>;
+    fn read_metadata(&self, key: &str) -> Result<Box<[u8]>, MetadataError>;
     /// Write a metadata key from persistent storage
-    fn write_metadata(&self, key: &str, value: &[u8]) -> std::io::Result<()>;
+    fn write_metadata(&self, key: &str, value: &[u8]) -> Result<(), MetadataError>;
+
+    /// Iterate over all blocks in storage order, together with their index
+    ///
+    /// The blocks are memory mapped slices, so nothing is copied. Blocks that can not be read are `None`, so every index is yielded exactly once.
+    fn blocks(&self) -> impl Iterator<Item = (u32, Option<&'static [u8]>)> + '_ {
+        let block_size = self.block_size();
+        return (0..self.block_count())
+            .map(move |index| (index, self.read(index * block_size, block_size).ok()));
+    }
 
     /// Write metadata and return a memorymapped slice to the metadata
     fn write_readback(&self, address: u32, data: &[u8]) -> Result<&'static [u8], StorageError> {
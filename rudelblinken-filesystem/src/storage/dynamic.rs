@@ -0,0 +1,183 @@
+//! A Storage for testing purposes with a block size and block count that are chosen at runtime
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use super::{EraseStorageError, Storage, StorageError};
+
+/// Reads need to return slices that are aligned to 64 bytes
+const ALIGNMENT: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C, align(64))]
+struct AlignedChunk([u8; ALIGNMENT]);
+
+/// A storage with a block size and block count that are chosen at runtime and backed by a heap allocated buffer
+///
+/// The total size can be at most `MAX_SIZE` bytes.
+///
+/// ```
+/// use rudelblinken_filesystem::storage::dynamic::DynamicStorage;
+/// use rudelblinken_filesystem::storage::Storage;
+/// let storage = DynamicStorage::<65536>::new(1024, 32).unwrap();
+/// assert_eq!(storage.block_size(), 1024);
+/// ```
+#[derive(Debug)]
+pub struct DynamicStorage<const MAX_SIZE: usize> {
+    block_size: u32,
+    block_count: u32,
+    /// Twice the size of the storage, so reads that wrap around can return a contiguous slice
+    pool: Box<[AlignedChunk]>,
+    pool_ptr: *mut u8,
+    key_value: Arc<Mutex<HashMap<String, Box<[u8]>>>>,
+}
+
+unsafe impl<const MAX_SIZE: usize> Send for DynamicStorage<MAX_SIZE> {}
+unsafe impl<const MAX_SIZE: usize> Sync for DynamicStorage<MAX_SIZE> {}
+
+impl<const MAX_SIZE: usize> DynamicStorage<MAX_SIZE> {
+    /// Create a new storage with the given geometry
+    ///
+    /// Returns `None` if the block size is not a nonzero multiple of 64 bytes, if there are no blocks or if the storage would be bigger than `MAX_SIZE`.
+    pub fn new(block_size: u32, block_count: u32) -> Option<Self> {
+        if block_size == 0 || block_size as usize % ALIGNMENT != 0 || block_count == 0 {
+            return None;
+        }
+        let size = (block_size as usize).checked_mul(block_count as usize)?;
+        if size > MAX_SIZE || size > u32::MAX as usize / 2 {
+            return None;
+        }
+        let mut pool: Box<[AlignedChunk]> =
+            vec![AlignedChunk([0b11111111u8; ALIGNMENT]); size * 2 / ALIGNMENT].into_boxed_slice();
+        return Some(DynamicStorage {
+            block_size,
+            block_count,
+            pool_ptr: pool.as_mut_ptr() as *mut u8,
+            pool,
+            key_value: Default::default(),
+        });
+    }
+
+    /// Size of the storage in bytes
+    pub fn size(&self) -> u32 {
+        return self.block_size * self.block_count;
+    }
+
+    /// Get the whole pool including the mirrored copy
+    #[allow(clippy::mut_from_ref)]
+    fn pool(&self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.pool_ptr, self.pool.len() * ALIGNMENT) }
+    }
+}
+
+/// Copies zeroes from src to dest and ignores ones in src.
+fn copy_zeroes_from_slice(dest: &mut [u8], src: &[u8]) {
+    dest.iter_mut()
+        .zip(src.iter())
+        .for_each(|(dest, src)| *dest &= src);
+}
+
+impl<const MAX_SIZE: usize> Storage for DynamicStorage<MAX_SIZE> {
+    /// Unknown at compile time, use [Storage::block_count] instead
+    const BLOCKS: u32 = 0;
+    /// Unknown at compile time, use [Storage::block_size] instead
+    const BLOCK_SIZE: u32 = 0;
+
+    fn block_size(&self) -> u32 {
+        return self.block_size;
+    }
+
+    fn block_count(&self) -> u32 {
+        return self.block_count;
+    }
+
+    fn max_files(&self) -> usize {
+        return self.block_count as usize / 2;
+    }
+
+    fn read(&self, address: u32, length: u32) -> Result<&'static [u8], StorageError> {
+        let size = self.size();
+        if address >= size || length >= size {
+            return Err(StorageError::OutOfBounds {
+                address,
+                length,
+                max: size,
+            });
+        }
+        let static_slice = unsafe {
+            std::mem::transmute::<&[u8], &'static [u8]>(
+                &self.pool()[address as usize..(address + length) as usize],
+            )
+        };
+
+        Ok(static_slice)
+    }
+
+    fn write(&self, address: u32, data: &[u8]) -> Result<(), StorageError> {
+        let size = self.size();
+        if address >= size || data.len() >= size as usize {
+            return Err(StorageError::OutOfBounds {
+                address,
+                length: data.len() as u32,
+                max: size,
+            });
+        }
+        let pool = self.pool();
+
+        copy_zeroes_from_slice(
+            &mut pool[address as usize..address as usize + data.len()],
+            data,
+        );
+        // The part of the data that is overlapping
+        let overlapping_length = (address + data.len() as u32).saturating_sub(size);
+        let nonoverlapping_length = data.len() as u32 - overlapping_length;
+
+        copy_zeroes_from_slice(
+            &mut pool
+                [(size + address) as usize..((size + address) + nonoverlapping_length) as usize],
+            &data[0..nonoverlapping_length as usize],
+        );
+        copy_zeroes_from_slice(
+            &mut pool[0..overlapping_length as usize],
+            &data[nonoverlapping_length as usize..],
+        );
+
+        Ok(())
+    }
+
+    fn erase(&self, address: u32, length: u32) -> Result<(), EraseStorageError> {
+        if address % self.block_size != 0 || length % self.block_size != 0 {
+            return Err(EraseStorageError::SizeNotAMultipleOfPageSize);
+        }
+        if (address + length) > self.size() {
+            return Err(EraseStorageError::SizeNotAMultipleOfPageSize);
+        }
+        let pool = self.pool();
+
+        // Also erase the mirrored copy that is used for reads that wrap around
+        for start in [address, self.size() + address] {
+            pool[start as usize..(start + length) as usize].fill(0b11111111u8);
+        }
+        Ok(())
+    }
+
+    fn read_metadata(&self, key: &str) -> Result<Box<[u8]>, std::io::Error> {
+        return self
+            .key_value
+            .lock()
+            .map_err(|_| std::io::Error::other("Failed to lock mutex"))?
+            .get(key)
+            .cloned()
+            .ok_or(std::io::Error::other("Failed to get a key for that value"));
+    }
+
+    fn write_metadata(&self, key: &str, value: &[u8]) -> Result<(), std::io::Error> {
+        self.key_value
+            .lock()
+            .map_err(|_| std::io::Error::other("Failed to lock mutex"))?
+            .insert(key.into(), value.into());
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@
 // TODO: Write better module level docs
 use crate::{
     storage::{EraseStorageError, Storage, StorageError},
-    Filesystem,
+    Filesystem, FilesystemInitError,
 };
 use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, EspNvsPartition, NvsDefault};
 use esp_idf_sys::{
@@ -183,12 +183,14 @@ impl Storage for FlashStorage {
 
     fn read(&self, address: u32, length: u32) -> Result<&'static [u8], StorageError> {
         // TODO: Make this actually safe
-        if (address) > Self::BLOCKS * Self::BLOCK_SIZE {
-            return Err(StorageError::AddressTooBig.into());
-        }
-        if (address + length) > Self::BLOCKS * Self::BLOCK_SIZE * 2 {
-            // TODO: Support erase with wraparound
-            return Err(StorageError::SizeTooBig.into());
+        let size = Self::BLOCKS * Self::BLOCK_SIZE;
+        // The partition is mapped twice, so reads can wrap around
+        if address >= size || length >= size {
+            return Err(StorageError::OutOfBounds {
+                address,
+                length,
+                max: size,
+            });
         }
         let thing: &[u8];
         unsafe {
@@ -203,6 +205,14 @@ impl Storage for FlashStorage {
     }
 
     fn write(&self, address: u32, data: &[u8]) -> Result<(), StorageError> {
+        let size = Self::BLOCKS * Self::BLOCK_SIZE;
+        if address >= size || data.len() >= size as usize {
+            return Err(StorageError::OutOfBounds {
+                address,
+                length: data.len() as u32,
+                max: size,
+            });
+        }
         // TODO: Make this actually safe
         let data_ptr = data.as_ptr() as *const c_void;
         // println!(
@@ -239,12 +249,15 @@ impl Storage for FlashStorage {
         if length % Self::BLOCK_SIZE != 0 {
             return Err(EraseStorageError::CanOnlyEraseInBlockSizedChunks);
         }
-        if (address) > Self::BLOCKS * Self::BLOCK_SIZE {
-            return Err(StorageError::AddressTooBig.into());
-        }
-        if (address + length) > Self::BLOCKS * Self::BLOCK_SIZE {
+        let size = Self::BLOCKS * Self::BLOCK_SIZE;
+        if address >= size || length > size - address {
             // TODO: Support erase with wraparound
-            return Err(StorageError::SizeTooBig.into());
+            return Err(StorageError::OutOfBounds {
+                address,
+                length,
+                max: size,
+            }
+            .into());
         }
 
         unsafe {
@@ -292,7 +305,7 @@ static mut STORAGE_SINGLETON: Option<FlashStorage> = None;
 static mut FILESYSTEM_SINGLETON: Option<RwLock<Filesystem<FlashStorage>>> = None;
 
 /// An error occurred while initializing the global storage singleton
-#[derive(Error, Debug, Clone)]
+#[derive(Error, Debug)]
 pub enum SetupStorageError {
     /// Storage is already initialized
     #[error("Storage is already initialized.")]
@@ -300,6 +313,9 @@ pub enum SetupStorageError {
     /// An error while creating the storage
     #[error(transparent)]
     CreateStorageError(#[from] CreateStorageError),
+    /// An error while opening the filesystem on the storage
+    #[error(transparent)]
+    FilesystemInitError(#[from] FilesystemInitError),
 }
 
 /// Setup the global storage singleton
@@ -308,7 +324,7 @@ pub fn setup_storage() -> Result<(), SetupStorageError> {
         STORAGE_SINGLETON = Some(FlashStorage::new()?);
         FILESYSTEM_SINGLETON = Some(RwLock::new(Filesystem::new(
             STORAGE_SINGLETON.as_ref().unwrap(),
-        )));
+        )?));
         dbg!(&FILESYSTEM_SINGLETON.as_ref().unwrap().read().unwrap().files);
     }
     return Ok(());
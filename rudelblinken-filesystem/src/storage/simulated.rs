@@ -1,51 +1,272 @@
 //! A Storage for testing purposes that is backed by a heap allocated buffer
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
     sync::{Arc, Mutex},
 };
 
 use super::{EraseStorageError, Storage, StorageError};
+use crate::{file_metadata::FileMetadata, Filesystem};
 
-#[derive(Debug)]
+const SIMULATED_BLOCK_SIZE: usize = 4096;
+
+/// Number of bytes in each line of a hexdump
+const HEXDUMP_LINE_LENGTH: usize = 16;
+
+#[derive(Debug, Clone)]
 #[repr(C, align(4096))]
-struct AlignedBuffer<const SIZE: usize>([u8; SIZE]);
+struct AlignedBlock([u8; SIMULATED_BLOCK_SIZE]);
 
-#[derive(Debug)]
-/// A storage that is backed by a heap allocated buffer
+/// A storage with 16 blocks that is backed by a heap allocated buffer
 ///
 /// ```
 /// use rudelblinken_filesystem::storage::simulated::SimulatedStorage;
 /// let storage = SimulatedStorage::new();
 /// ```
-pub struct SimulatedStorage {
-    pool: Box<AlignedBuffer<{ Self::SIZE as usize * 2 }>>,
-    pool_ptr: *mut [u8; Self::SIZE as usize * 2],
+pub type SimulatedStorage = SizedSimulatedStorage<16>;
+
+#[derive(Debug)]
+/// A storage with a configurable number of blocks that is backed by a heap allocated buffer
+///
+/// ```
+/// use rudelblinken_filesystem::storage::simulated::SizedSimulatedStorage;
+/// let storage = SizedSimulatedStorage::<256>::new();
+/// ```
+pub struct SizedSimulatedStorage<const BLOCKS: u32> {
+    /// Twice the size of the storage, so reads that wrap around can return a contiguous slice
+    pool: Box<[AlignedBlock]>,
+    pool_ptr: *mut u8,
     key_value: Arc<Mutex<HashMap<String, Box<[u8]>>>>,
+    /// Addresses at which the next read fails
+    read_failures: Mutex<Vec<u32>>,
+    /// Addresses at which the next write fails
+    write_failures: Mutex<Vec<u32>>,
 }
 
-unsafe impl Send for SimulatedStorage {}
-unsafe impl Sync for SimulatedStorage {}
+unsafe impl<const BLOCKS: u32> Send for SizedSimulatedStorage<BLOCKS> {}
+unsafe impl<const BLOCKS: u32> Sync for SizedSimulatedStorage<BLOCKS> {}
 
-impl Default for SimulatedStorage {
+/// Copies the content and the metadata into a new, independent storage
+///
+/// Injected failures are not copied.
+impl<const BLOCKS: u32> Clone for SizedSimulatedStorage<BLOCKS> {
+    fn clone(&self) -> Self {
+        let mut pool = self.pool.clone();
+        let key_value = self.key_value.lock().unwrap().clone();
+        SizedSimulatedStorage {
+            pool_ptr: pool.as_mut_ptr() as *mut u8,
+            pool,
+            key_value: Arc::new(Mutex::new(key_value)),
+            read_failures: Default::default(),
+            write_failures: Default::default(),
+        }
+    }
+}
+
+impl<const BLOCKS: u32> Default for SizedSimulatedStorage<BLOCKS> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl SimulatedStorage {
+impl<const BLOCKS: u32> SizedSimulatedStorage<BLOCKS> {
     /// Size of the storage
     pub const SIZE: u32 = Self::BLOCKS * Self::BLOCK_SIZE;
 
     /// Create a new storage for testing purposes
-    pub fn new() -> SimulatedStorage {
-        let mut pool = Box::new(AlignedBuffer([0b11111111u8; Self::SIZE as usize * 2]));
-        SimulatedStorage {
-            pool_ptr: &mut (pool.0),
+    pub fn new() -> Self {
+        let mut pool: Box<[AlignedBlock]> = (0..(BLOCKS * 2))
+            .map(|_| AlignedBlock([0b11111111u8; SIMULATED_BLOCK_SIZE]))
+            .collect();
+        SizedSimulatedStorage {
+            pool_ptr: pool.as_mut_ptr() as *mut u8,
             pool,
             key_value: Default::default(),
+            read_failures: Default::default(),
+            write_failures: Default::default(),
         }
     }
+
+    /// Make the next read that includes `address` fail with [StorageError::ReadFailure]
+    ///
+    /// The failure only happens once.
+    pub fn inject_read_failure(&self, address: u32) {
+        self.read_failures
+            .lock()
+            .unwrap()
+            .push(address % Self::SIZE);
+    }
+
+    /// Make the next write that includes `address` fail with [StorageError::WriteFailure]
+    ///
+    /// The failure only happens once. Nothing is written by the failed write.
+    pub fn inject_write_failure(&self, address: u32) {
+        self.write_failures
+            .lock()
+            .unwrap()
+            .push(address % Self::SIZE);
+    }
+
+    /// Remove an injected failure that is inside the accessed range. Returns whether there was one
+    fn take_failure(failures: &Mutex<Vec<u32>>, address: u32, length: u32) -> bool {
+        let mut failures = failures.lock().unwrap();
+        // Accesses can wrap around the end of the storage
+        let Some(index) = failures
+            .iter()
+            .position(|failure| (failure + Self::SIZE - address) % Self::SIZE < length)
+        else {
+            return false;
+        };
+        failures.remove(index);
+        return true;
+    }
+
+    /// Format the whole storage in the classic hexdump format
+    ///
+    /// Repeated lines are collapsed into a single `*`, like `hexdump -C` does.
+    pub fn hexdump(&self) -> String {
+        return hexdump(&self.pool()[0..Self::SIZE as usize], 0, &BTreeMap::new());
+    }
+
+    /// Format a single block in the classic hexdump format
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block does not exist.
+    pub fn hexdump_block(&self, block: usize) -> String {
+        assert!(block < BLOCKS as usize, "Block {} does not exist", block);
+        let start = block * SIMULATED_BLOCK_SIZE;
+        return hexdump(
+            &self.pool()[start..start + SIMULATED_BLOCK_SIZE],
+            start as u32,
+            &BTreeMap::new(),
+        );
+    }
+
+    /// Format the whole storage as a hexdump with the files of `filesystem` marked
+    ///
+    /// The start and end of every file are marked and the fields of its metadata are listed with their values.
+    pub fn annotated_dump(filesystem: &Filesystem<Self>) -> String {
+        let mut annotations: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+        for file in &filesystem.files {
+            let metadata_length = size_of::<FileMetadata>() as u32;
+            let end = (file.address + metadata_length + file.length) % Self::SIZE;
+            let mut state = Vec::new();
+            if !file.valid() {
+                state.push("not ready");
+            }
+            if file.marked_for_deletion() {
+                state.push("marked for deletion");
+            }
+            if file.deleted() {
+                state.push("deleted");
+            }
+            if file.important() {
+                state.push("important");
+            }
+            let metadata = &filesystem.storage.pool()
+                [file.address as usize..(file.address + metadata_length) as usize];
+            annotations.entry(file.address).or_default().extend([
+                format!(
+                    "== {:?} at 0x{:08x}: {} bytes of content, age {}{}",
+                    file.name,
+                    file.address,
+                    file.length,
+                    file.age(),
+                    state
+                        .iter()
+                        .map(|state| format!(", {}", state))
+                        .collect::<String>()
+                ),
+                format!(
+                    "   +0x00 flags:  0x{:04x}",
+                    u16::from_le_bytes([metadata[0], metadata[1]])
+                ),
+                format!(
+                    "   +0x02 age:    0x{:04x}",
+                    u16::from_le_bytes([metadata[2], metadata[3]])
+                ),
+                format!("   +0x04 length: {}", file.length),
+                format!("   +0x08 hash:   {}", hex_string(&metadata[8..40])),
+                format!("   +0x28 name:   {}", hex_string(&metadata[40..56])),
+                "   +0x40 content".into(),
+            ]);
+            annotations
+                .entry(end)
+                .or_default()
+                .push(format!("== end of {:?} at 0x{:08x}", file.name, end));
+        }
+        return hexdump(
+            &filesystem.storage.pool()[0..Self::SIZE as usize],
+            0,
+            &annotations,
+        );
+    }
+
+    /// Get the whole pool including the mirrored copy
+    #[allow(clippy::mut_from_ref)]
+    fn pool(&self) -> &mut [u8] {
+        unsafe {
+            std::slice::from_raw_parts_mut(self.pool_ptr, self.pool.len() * SIMULATED_BLOCK_SIZE)
+        }
+    }
+}
+
+/// Format bytes as lowercase hex without separators
+fn hex_string(data: &[u8]) -> String {
+    return data.iter().map(|byte| format!("{:02x}", byte)).collect();
+}
+
+/// Format `data` in the classic hexdump format with addresses starting at `base_address`
+///
+/// Lines that are the same as the previous line are collapsed into a single `*`. Annotations are printed before the line that contains their address.
+fn hexdump(data: &[u8], base_address: u32, annotations: &BTreeMap<u32, Vec<String>>) -> String {
+    let mut output = String::new();
+    let mut previous_line: Option<&[u8]> = None;
+    let mut collapsed = false;
+    for (index, line) in data.chunks(HEXDUMP_LINE_LENGTH).enumerate() {
+        let address = base_address + (index * HEXDUMP_LINE_LENGTH) as u32;
+        let mut annotated = false;
+        for (_, line_annotations) in
+            annotations.range(address..address + HEXDUMP_LINE_LENGTH as u32)
+        {
+            for annotation in line_annotations {
+                writeln!(output, "{}", annotation).unwrap();
+                annotated = true;
+            }
+        }
+        if !annotated && previous_line == Some(line) {
+            if !collapsed {
+                output.push_str("*\n");
+                collapsed = true;
+            }
+            continue;
+        }
+        previous_line = Some(line);
+        collapsed = false;
+
+        write!(output, "{:08x} ", address).unwrap();
+        for column in 0..HEXDUMP_LINE_LENGTH {
+            if column % 8 == 0 {
+                output.push(' ');
+            }
+            match line.get(column) {
+                Some(byte) => write!(output, "{:02x} ", byte).unwrap(),
+                None => output.push_str("   "),
+            }
+        }
+        let ascii: String = line
+            .iter()
+            .map(|byte| match byte {
+                0x20..=0x7e => *byte as char,
+                _ => '.',
+            })
+            .collect();
+        writeln!(output, " |{}|", ascii).unwrap();
+    }
+    writeln!(output, "{:08x}", base_address + data.len() as u32).unwrap();
+    return output;
 }
 
 /// Copies zeroes from src to dest and ignores ones in src.
@@ -58,20 +279,24 @@ fn copy_zeroes_from_slice(dest: &mut [u8], src: &[u8]) {
     dest.copy_from_slice(&new_data);
 }
 
-impl Storage for SimulatedStorage {
-    const BLOCKS: u32 = 16;
-    const BLOCK_SIZE: u32 = 4096;
+impl<const BLOCKS: u32> Storage for SizedSimulatedStorage<BLOCKS> {
+    const BLOCKS: u32 = BLOCKS;
+    const BLOCK_SIZE: u32 = SIMULATED_BLOCK_SIZE as u32;
 
     fn read(&self, address: u32, length: u32) -> Result<&'static [u8], StorageError> {
-        if address >= Self::SIZE {
-            return Err(StorageError::AddressTooBig);
+        if address >= Self::SIZE || length >= Self::SIZE {
+            return Err(StorageError::OutOfBounds {
+                address,
+                length,
+                max: Self::SIZE,
+            });
         }
-        if length >= Self::SIZE {
-            return Err(StorageError::SizeTooBig);
+        if Self::take_failure(&self.read_failures, address, length) {
+            return Err(StorageError::ReadFailure);
         }
         let static_slice = unsafe {
             std::mem::transmute::<&[u8], &'static [u8]>(
-                &self.pool.0[address as usize..(address + length) as usize],
+                &self.pool()[address as usize..(address + length) as usize],
             )
         };
 
@@ -79,13 +304,17 @@ impl Storage for SimulatedStorage {
     }
 
     fn write(&self, address: u32, data: &[u8]) -> Result<(), StorageError> {
-        if address >= Self::SIZE {
-            return Err(StorageError::AddressTooBig);
+        if address >= Self::SIZE || data.len() >= Self::SIZE as usize {
+            return Err(StorageError::OutOfBounds {
+                address,
+                length: data.len() as u32,
+                max: Self::SIZE,
+            });
         }
-        if data.len() as u32 >= Self::SIZE {
-            return Err(StorageError::SizeTooBig);
+        if Self::take_failure(&self.write_failures, address, data.len() as u32) {
+            return Err(StorageError::WriteFailure);
         }
-        let pool = unsafe { &mut *self.pool_ptr };
+        let pool = self.pool();
 
         copy_zeroes_from_slice(
             &mut pool[address as usize..address as usize + data.len()],
@@ -116,23 +345,47 @@ impl Storage for SimulatedStorage {
         if (address + length) > Self::BLOCKS * Self::BLOCK_SIZE {
             return Err(EraseStorageError::SizeNotAMultipleOfPageSize);
         }
-        let pool = unsafe { &mut *self.pool_ptr };
+        let pool = self.pool();
 
         let number_of_blocks = length.div_ceil(Self::BLOCK_SIZE);
         for block in 0..number_of_blocks {
             let base_address = address + block * Self::BLOCK_SIZE;
             pool[base_address as usize..(base_address + Self::BLOCK_SIZE) as usize]
-                .copy_from_slice(&[0b11111111u8; Self::BLOCK_SIZE as usize]);
+                .fill(0b11111111u8);
+            // Also erase the mirrored copy that is used for reads that wrap around
+            let mirrored_address = Self::SIZE + base_address;
+            pool[mirrored_address as usize..(mirrored_address + Self::BLOCK_SIZE) as usize]
+                .fill(0b11111111u8);
         }
         Ok(())
     }
 
+    fn blocks(&self) -> impl Iterator<Item = (u32, Option<&'static [u8]>)> + '_ {
+        // The pool is followed by a mirrored copy for wraparound reads, which is not part of the blocks
+        let static_pool = unsafe {
+            std::mem::transmute::<&[u8], &'static [u8]>(&self.pool()[..Self::SIZE as usize])
+        };
+        return static_pool
+            .chunks(Self::BLOCK_SIZE as usize)
+            .enumerate()
+            .map(|(index, block)| {
+                let index = index as u32;
+                let failed = Self::take_failure(
+                    &self.read_failures,
+                    index * Self::BLOCK_SIZE,
+                    Self::BLOCK_SIZE,
+                );
+                return (index, (!failed).then_some(block));
+            });
+    }
+
     fn read_metadata(&self, key: &str) -> Result<Box<[u8]>, std::io::Error> {
         return self
             .key_value
             .lock()
             .map_err(|_| std::io::Error::other("Failed to lock mutex"))?
-            .get(key).cloned()
+            .get(key)
+            .cloned()
             .ok_or(std::io::Error::other("Failed to get a key for that value"));
     }
 
@@ -0,0 +1,30 @@
+//! Synchronization primitives that work with and without `std`
+//!
+//! Without `std` the lock is a spinlock from the `spin` crate. It is wrapped to provide the same
+//! interface as [std::sync::RwLock], except that it can never be poisoned.
+
+#[cfg(feature = "std")]
+pub(crate) use std::sync::RwLock;
+
+#[cfg(not(feature = "std"))]
+#[derive(Debug)]
+pub(crate) struct RwLock<T>(spin::RwLock<T>);
+
+#[cfg(not(feature = "std"))]
+impl<T> RwLock<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        return RwLock(spin::RwLock::new(value));
+    }
+
+    pub(crate) fn read(
+        &self,
+    ) -> Result<spin::RwLockReadGuard<'_, T>, core::convert::Infallible> {
+        return Ok(self.0.read());
+    }
+
+    pub(crate) fn write(
+        &self,
+    ) -> Result<spin::RwLockWriteGuard<'_, T>, core::convert::Infallible> {
+        return Ok(self.0.write());
+    }
+}
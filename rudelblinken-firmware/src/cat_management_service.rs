@@ -1,14 +1,18 @@
 use crate::config::main_program::{get_main_program, set_main_program};
-use crate::config::{get_config, set_config, DeviceName, LedStripColor, WasmGuestConfig};
+use crate::config::{
+    decode_program_schedule, encode_program_schedule, get_config, set_config, DeviceName, GroupId,
+    LedStripColor, ProgramSchedule, WasmGuestConfig, WasmHostSettings,
+};
 use crate::{
     file_upload_service::{FileUploadService},
     service_helpers::DocumentableCharacteristic,
     storage::FlashStorage,
-    wasm_service::wasm_host::WasmHost,
+    wasm_service::wasm_host::{AmbientLightCalibration, WasmHost, LIGHT_SENSOR_ADC},
 };
 use esp32_nimble::{
     utilities::{mutex::Mutex, BleUuid}, BLEDevice, NimbleProperties,
 };
+use esp_idf_hal::task::thread::ThreadSpawnConfiguration;
 use esp_idf_sys::{self as _, BLE_GATT_CHR_UNIT_UNITLESS};
 use rudelblinken_filesystem::file::{File, FileState};
 use rudelblinken_runtime::host::LedColor;
@@ -23,6 +27,11 @@ const CAT_MANAGEMENT_SERVICE_PROGRAM_HASH: u16 = 0x7893;
 const CAT_MANAGEMENT_SERVICE_NAME: u16 = 0x7894;
 const CAT_MANAGEMENT_SERVICE_STRIP_COLOR: u16 = 0x7895;
 const CAT_MANAGEMENT_SERVICE_WASM_GUEST_CONFIG: u16 = 0x7896;
+const CAT_MANAGEMENT_SERVICE_GROUP_ID: u16 = 0x789B;
+const CAT_MANAGEMENT_SERVICE_PROGRAM_SCHEDULE: u16 = 0x789C;
+const CAT_MANAGEMENT_SERVICE_LED_COLOR_PROFILE: u16 = 0x789D;
+const CAT_MANAGEMENT_SERVICE_DROPPED_EVENTS: u16 = 0x789E;
+const CAT_MANAGEMENT_SERVICE_AMBIENT_LIGHT_CALIBRATION: u16 = 0x789F;
 
 const CAT_MANAGEMENT_SERVICE_UUID: BleUuid = BleUuid::from_uuid16(CAT_MANAGEMENT_SERVICE);
 const CAT_MANAGEMENT_SERVICE_PROGRAM_HASH_UUID: BleUuid =
@@ -32,10 +41,22 @@ const CAT_MANAGEMENT_SERVICE_STRIP_COLOR_UUID: BleUuid =
     BleUuid::from_uuid16(CAT_MANAGEMENT_SERVICE_STRIP_COLOR);
 const CAT_MANAGEMENT_SERVICE_WASM_GUEST_CONFIG_UUID: BleUuid =
     BleUuid::from_uuid16(CAT_MANAGEMENT_SERVICE_WASM_GUEST_CONFIG);
+const CAT_MANAGEMENT_SERVICE_GROUP_ID_UUID: BleUuid =
+    BleUuid::from_uuid16(CAT_MANAGEMENT_SERVICE_GROUP_ID);
+const CAT_MANAGEMENT_SERVICE_PROGRAM_SCHEDULE_UUID: BleUuid =
+    BleUuid::from_uuid16(CAT_MANAGEMENT_SERVICE_PROGRAM_SCHEDULE);
+const CAT_MANAGEMENT_SERVICE_LED_COLOR_PROFILE_UUID: BleUuid =
+    BleUuid::from_uuid16(CAT_MANAGEMENT_SERVICE_LED_COLOR_PROFILE);
+const CAT_MANAGEMENT_SERVICE_DROPPED_EVENTS_UUID: BleUuid =
+    BleUuid::from_uuid16(CAT_MANAGEMENT_SERVICE_DROPPED_EVENTS);
+const CAT_MANAGEMENT_SERVICE_AMBIENT_LIGHT_CALIBRATION_UUID: BleUuid =
+    BleUuid::from_uuid16(CAT_MANAGEMENT_SERVICE_AMBIENT_LIGHT_CALIBRATION);
 
 pub struct CatManagementService {
     pub wasm_runner: mpsc::Sender<File<FlashStorage, { FileState::Reader }>>,
     file_upload_service: Arc<Mutex<FileUploadService>>,
+    /// Used to stop the running program when the schedule or the main program changes
+    wasm_host: WasmHost,
 }
 
 fn log_heap_stats() {
@@ -52,21 +73,58 @@ fn log_heap_stats() {
     )
 }
 
+/// Get the content of an uploaded program
+fn load_program(
+    files: &Mutex<FileUploadService>,
+    hash: &[u8; 32],
+) -> Option<File<FlashStorage, { FileState::Reader }>> {
+    let file_upload_service = files.lock();
+    let file = file_upload_service.get_file(hash)?;
+    return file.content.upgrade().ok();
+}
+
+/// Runs the programs sent through `receiver` indefinitely. If no program was sent, the programs of the [ProgramSchedule] are run in sequence.
 fn wasm_runner(
     host: WasmHost,
     receiver: mpsc::Receiver<File<FlashStorage, { FileState::Reader }>>,
+    files: Arc<Mutex<FileUploadService>>,
 ) {
+    let mut schedule: Vec<([u8; 32], u32)> = Vec::new();
+    let mut schedule_position = 0;
     loop {
         std::thread::sleep(Duration::from_millis(200));
 
-        let Ok(file) = receiver.try_recv() else {
-            continue;
+        let (file, max_execution_time) = match receiver.try_recv() {
+            Ok(file) => (file, None),
+            Err(_) => {
+                let current_schedule = get_config::<ProgramSchedule>();
+                if current_schedule != schedule {
+                    schedule = current_schedule;
+                    schedule_position = 0;
+                }
+                if schedule.is_empty() {
+                    continue;
+                }
+                let (hash, duration_ms) = schedule[schedule_position];
+                schedule_position = (schedule_position + 1) % schedule.len();
+                let Some(file) = load_program(&files, &hash) else {
+                    error!("Scheduled program is not available");
+                    continue;
+                };
+                (file, Some(Duration::from_millis(duration_ms as u64)))
+            }
         };
+        host.set_max_execution_time(max_execution_time);
 
         info!("before creating and linking instance");
         log_heap_stats();
 
-        let mut instance = match rudelblinken_runtime::linker::setup(&file, host.clone()) {
+        let runtime_config = host.configuration().runtime_config();
+        let mut instance = match rudelblinken_runtime::linker::setup_with_config(
+            &file,
+            host.clone(),
+            runtime_config,
+        ) {
             Ok(instance) => instance,
             Err(error) => {
                 error!("Linker Error:\n {}", error);
@@ -80,6 +138,7 @@ fn wasm_runner(
         let result = instance.run();
         match result {
             Ok(_) => info!("Wasm module finished execution"),
+            Err(_) if host.deadline_passed() => info!("Wasm module reached its execution time"),
             Err(err) => {
                 error!("Wasm module failed to execute:\n{}", err);
             }
@@ -96,16 +155,28 @@ impl CatManagementService {
         let wasm_send = {
             let (send, recv) = mpsc::channel::<File<FlashStorage, { FileState::Reader }>>();
 
-            // let files = files.clone();
+            let files = files.clone();
+            let host = host.clone();
             // let name = name.clone();
 
+            // Threads spawned by std use the current spawn configuration, so pin the runner and restore the previous configuration afterwards
+            let previous_configuration = ThreadSpawnConfiguration::get().unwrap_or_default();
+            ThreadSpawnConfiguration {
+                pin_to_core: host.configuration().cpu_affinity.core(),
+                ..Default::default()
+            }
+            .set()
+            .expect("failed to configure the wasm runner thread");
             std::thread::Builder::new()
                 .name("wasm-runner".to_owned())
                 .stack_size(0x2000)
                 .spawn(move || {
-                    wasm_runner(host, recv);
+                    wasm_runner(host, recv, files);
                 })
                 .expect("failed to spawn wasm runner thread");
+            previous_configuration
+                .set()
+                .expect("failed to restore the thread configuration");
 
             send
         };
@@ -113,6 +184,7 @@ impl CatManagementService {
         let cat_management_service = Arc::new(Mutex::new(CatManagementService {
             wasm_runner: wasm_send,
             file_upload_service: files,
+            wasm_host: host,
         }));
 
         let service = ble_device
@@ -150,6 +222,16 @@ impl CatManagementService {
             0,
             BLE_GATT_CHR_UNIT_UNITLESS,
         );
+        let led_color_profile_characteristic = service.lock().create_characteristic(
+            CAT_MANAGEMENT_SERVICE_LED_COLOR_PROFILE_UUID,
+            NimbleProperties::WRITE | NimbleProperties::READ,
+        );
+        led_color_profile_characteristic.document(
+            "LED color profile (postcard encoded)",
+            esp32_nimble::BLE2904Format::OPAQUE,
+            0,
+            BLE_GATT_CHR_UNIT_UNITLESS,
+        );
         let wasm_guest_config_characteristic = service.lock().create_characteristic(
             CAT_MANAGEMENT_SERVICE_WASM_GUEST_CONFIG_UUID,
             NimbleProperties::WRITE | NimbleProperties::READ,
@@ -160,6 +242,46 @@ impl CatManagementService {
             0,
             BLE_GATT_CHR_UNIT_UNITLESS,
         );
+        let group_id_characteristic = service.lock().create_characteristic(
+            CAT_MANAGEMENT_SERVICE_GROUP_ID_UUID,
+            NimbleProperties::WRITE | NimbleProperties::READ,
+        );
+        group_id_characteristic.document(
+            "Group ID",
+            esp32_nimble::BLE2904Format::UINT8,
+            0,
+            BLE_GATT_CHR_UNIT_UNITLESS,
+        );
+        let program_schedule_characteristic = service.lock().create_characteristic(
+            CAT_MANAGEMENT_SERVICE_PROGRAM_SCHEDULE_UUID,
+            NimbleProperties::WRITE | NimbleProperties::READ,
+        );
+        program_schedule_characteristic.document(
+            "Program schedule (32 byte hash and u32 duration in ms per program)",
+            esp32_nimble::BLE2904Format::OPAQUE,
+            0,
+            BLE_GATT_CHR_UNIT_UNITLESS,
+        );
+        let dropped_events_characteristic = service.lock().create_characteristic(
+            CAT_MANAGEMENT_SERVICE_DROPPED_EVENTS_UUID,
+            NimbleProperties::READ,
+        );
+        dropped_events_characteristic.document(
+            "Number of BLE events that were dropped, because the guest did not handle them fast enough",
+            esp32_nimble::BLE2904Format::UINT64,
+            0,
+            BLE_GATT_CHR_UNIT_UNITLESS,
+        );
+        let ambient_light_calibration_characteristic = service.lock().create_characteristic(
+            CAT_MANAGEMENT_SERVICE_AMBIENT_LIGHT_CALIBRATION_UUID,
+            NimbleProperties::WRITE | NimbleProperties::READ,
+        );
+        ambient_light_calibration_characteristic.document(
+            "Ambient light calibration (f32 slope and intercept, reads append the u32 raw sensor value)",
+            esp32_nimble::BLE2904Format::OPAQUE,
+            0,
+            BLE_GATT_CHR_UNIT_UNITLESS,
+        );
 
         let cat_management_service_clone = cat_management_service.clone();
         program_hash_characteristic.lock().on_read(move |value, _| {
@@ -174,43 +296,33 @@ impl CatManagementService {
                 return;
             };
 
-            set_main_program(&Some(hash));
-            let file_upload_service = service.file_upload_service.lock();
-            let file = file_upload_service
-                .get_file(&hash)
-                .expect("failed to get file");
-            let content = file.content.upgrade().unwrap();
-
-            service
-                .wasm_runner
-                .send(content)
-                .expect("failed to send new wasm module to runner");
+            if !service.run_program(&hash) {
+                error!("There is no uploaded program with that hash");
+            }
         });
 
         name_characteristic.lock().on_read(move |value, _| {
-            value.set_value(get_config::<DeviceName>().as_bytes());
+            value.set_value(get_config::<DeviceName>().as_str().as_bytes());
         });
         name_characteristic.lock().on_write(move |args| {
             let data = args.recv_data();
-            if data.len() <= 3 {
-                error!("Name too short");
-                return;
-            }
-            if data.len() > 16 {
-                error!("Name too long");
-                return;
-            }
-
-            let Ok(new_name) = String::from_utf8(data.into()) else {
+            let Ok(new_name) = std::str::from_utf8(data) else {
                 error!("Name not UTF 8");
                 return;
             };
+            let new_name = match DeviceName::try_from_str(new_name) {
+                Ok(new_name) => new_name,
+                Err(err) => {
+                    error!("Invalid name: {}", err);
+                    return;
+                }
+            };
 
             set_config::<DeviceName>(new_name);
         });
 
         strip_color_characteristic.lock().on_read(move |value, _| {
-            value.set_value(&get_config::<LedStripColor>().to_array());
+            value.set_value(&get_config::<LedStripColor>().color.to_array());
         });
         strip_color_characteristic.lock().on_write(move |args| {
             let data = args.recv_data();
@@ -222,9 +334,26 @@ impl CatManagementService {
                 return;
             }
 
-            set_config::<LedStripColor>(LedColor::new(data[0], data[1], data[2]));
+            let mut profile = get_config::<LedStripColor>();
+            profile.color = LedColor::new(data[0], data[1], data[2]);
+            set_config::<LedStripColor>(profile);
         });
 
+        led_color_profile_characteristic
+            .lock()
+            .on_read(move |value, _| {
+                value.set_value(&get_config::<LedStripColor>().encode_profile());
+            });
+        led_color_profile_characteristic
+            .lock()
+            .on_write(move |args| {
+                let Some(profile) = LedStripColor::decode_profile(args.recv_data()) else {
+                    error!("Invalid LED color profile");
+                    return;
+                };
+                set_config::<LedStripColor>(profile);
+            });
+
         wasm_guest_config_characteristic
             .lock()
             .on_read(move |value, _| {
@@ -235,12 +364,133 @@ impl CatManagementService {
             .on_write(move |args| {
                 set_config::<WasmGuestConfig>(args.recv_data().to_vec());
             });
+
+        group_id_characteristic.lock().on_read(move |value, _| {
+            value.set_value(&[get_config::<GroupId>()]);
+        });
+        group_id_characteristic.lock().on_write(move |args| {
+            let data = args.recv_data();
+            if data.len() != 1 {
+                error!(
+                    len = data.len(),
+                    "group id write with length different from 1"
+                );
+                return;
+            }
+
+            set_config::<GroupId>(data[0]);
+        });
+
+        program_schedule_characteristic
+            .lock()
+            .on_read(move |value, _| {
+                value.set_value(&encode_program_schedule(&get_config::<ProgramSchedule>()));
+            });
+        let cat_management_service_clone = cat_management_service.clone();
+        program_schedule_characteristic
+            .lock()
+            .on_write(move |args| {
+                let service = cat_management_service_clone.lock();
+                let data = args.recv_data();
+                let Some(schedule) = decode_program_schedule(data) else {
+                    error!(
+                        len = data.len(),
+                        "program schedule write with length that is not a multiple of 36"
+                    );
+                    return;
+                };
+                let file_upload_service = service.file_upload_service.lock();
+                if let Some((hash, _)) = schedule
+                    .iter()
+                    .find(|(hash, _)| file_upload_service.get_file(hash).is_none())
+                {
+                    error!(
+                        ?hash,
+                        "program schedule contains a program that was not uploaded"
+                    );
+                    return;
+                }
+                drop(file_upload_service);
+
+                set_config::<ProgramSchedule>(schedule);
+                // Switch to the new schedule right away
+                service.wasm_host.stop();
+            });
+
+        let cat_management_service_clone = cat_management_service.clone();
+        dropped_events_characteristic
+            .lock()
+            .on_read(move |value, _| {
+                let service = cat_management_service_clone.lock();
+                value.set_value(&service.wasm_host.dropped_events().to_le_bytes());
+            });
+
+        ambient_light_calibration_characteristic
+            .lock()
+            .on_read(move |value, _| {
+                let calibration = get_config::<WasmHostSettings>().ambient_light_calibration;
+                let raw = match LIGHT_SENSOR_ADC.lock().read() {
+                    Ok(raw) => raw as u32,
+                    Err(err) => {
+                        error!(?err, "reading ambient light failed");
+                        u32::MAX
+                    }
+                };
+                let mut encoded = Vec::with_capacity(12);
+                encoded.extend(calibration.slope.to_le_bytes());
+                encoded.extend(calibration.intercept.to_le_bytes());
+                encoded.extend(raw.to_le_bytes());
+                value.set_value(&encoded);
+            });
+        ambient_light_calibration_characteristic
+            .lock()
+            .on_write(move |args| {
+                let data = args.recv_data();
+                let Ok(data): Result<[u8; 8], _> = data.try_into() else {
+                    error!(
+                        len = data.len(),
+                        "ambient light calibration write with length different from 8"
+                    );
+                    return;
+                };
+                let (slope, intercept) = data.split_at(4);
+                let mut settings = get_config::<WasmHostSettings>();
+                // Takes effect after the next restart, like the other host settings
+                settings.ambient_light_calibration = AmbientLightCalibration {
+                    slope: f32::from_le_bytes(slope.try_into().unwrap()),
+                    intercept: f32::from_le_bytes(intercept.try_into().unwrap()),
+                };
+                set_config::<WasmHostSettings>(settings);
+            });
         cat_management_service.lock().on_boot();
 
         cat_management_service
     }
 
+    /// Make the uploaded program with the given hash the main program and run it
+    ///
+    /// Returns false if there is no uploaded program with that hash.
+    pub fn run_program(&self, hash: &[u8; 32]) -> bool {
+        let Some(content) = load_program(&self.file_upload_service, hash) else {
+            return false;
+        };
+
+        set_main_program(&Some(*hash));
+        // The program runs indefinitely, so it replaces the schedule
+        set_config::<ProgramSchedule>(Vec::new());
+        // Stop the current program before the new one is queued. Stopping afterwards could stop the new program if the runner was idle
+        self.wasm_host.stop();
+        self.wasm_runner
+            .send(content)
+            .expect("failed to send new wasm module to runner");
+        return true;
+    }
+
     fn on_boot(&mut self) {
+        if !get_config::<ProgramSchedule>().is_empty() {
+            // The runner starts the schedule by itself
+            return;
+        }
         let Some(hash) = get_main_program() else {
             return;
         };
@@ -1,9 +1,16 @@
 use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, EspNvsPartition, NvsDefault};
-use rudelblinken_runtime::host::LedColor;
+use rudelblinken_runtime::host::{LedColor, LedInfo};
+use serde::{Deserialize, Serialize};
 use std::sync::{LazyLock, RwLock};
+use thiserror::Error;
 
+use crate::wasm_service::wasm_host::WasmHostConfiguration;
+
+pub mod device_name;
 pub mod main_program;
 
+pub use device_name::DeviceName;
+
 pub static NVS_PARTITION: LazyLock<EspNvsPartition<NvsDefault>> = LazyLock::new(|| {
     let nvs_default_partition: EspNvsPartition<NvsDefault> =
         EspDefaultNvsPartition::take().unwrap();
@@ -93,75 +100,251 @@ pub fn set_config<V: ConfigValue>(val: V::V) {
     }
 }
 
-#[derive(Clone)]
-pub struct DeviceName {
-    name: String,
+/// Color profile of the LED strip
+#[derive(Clone, Debug)]
+pub struct LedStripColor {
+    pub color: LedColor,
+    /// Color temperature of white LEDs in kelvin. 0 if unknown
+    pub color_temperature_k: u16,
+    /// Dominant wavelength of single color LEDs in nanometers. 0 if unknown
+    pub wavelength_nm: u16,
+    /// Row-major matrix that maps requested colors to calibrated colors
+    pub white_balance: [[f32; 3]; 3],
+}
+
+/// Binary representation of [LedStripColor] in NVS
+#[derive(Serialize, Deserialize)]
+struct StoredLedStripColor {
+    color: [u8; 3],
+    color_temperature_k: u16,
+    wavelength_nm: u16,
+    white_balance: [[f32; 3]; 3],
 }
 
-static DEVICE_NAME: LazyLock<RwLock<DeviceName>> = setup_config_storage();
+static LED_STRIP_COLOR: LazyLock<RwLock<LedStripColor>> = setup_config_storage();
 
-impl StorableValue for DeviceName {
+impl LedStripColor {
+    pub fn decode_profile(encoded: &[u8]) -> Option<Self> {
+        let stored: StoredLedStripColor = postcard::from_bytes(encoded).ok()?;
+        Some(Self {
+            color: LedColor::new(stored.color[0], stored.color[1], stored.color[2]),
+            color_temperature_k: stored.color_temperature_k,
+            wavelength_nm: stored.wavelength_nm,
+            white_balance: stored.white_balance,
+        })
+    }
+
+    pub fn encode_profile(&self) -> Vec<u8> {
+        let stored = StoredLedStripColor {
+            color: self.color.to_array(),
+            color_temperature_k: self.color_temperature_k,
+            wavelength_nm: self.wavelength_nm,
+            white_balance: self.white_balance,
+        };
+        postcard::to_allocvec(&stored).expect("the color profile can always be serialized")
+    }
+}
+
+impl StorableValue for LedStripColor {
     fn initial_value() -> Self {
-        let name = unsafe {
-            let mut mac = [0u8; 6];
-            esp_idf_sys::esp_base_mac_addr_get(mac.as_mut_ptr());
-            format!(
-                "{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
-                mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
-            )
+        Self {
+            color: LedColor::new(0xff, 0xff, 0xff),
+            color_temperature_k: 0,
+            wavelength_nm: 0,
+            white_balance: LedInfo::IDENTITY_WHITE_BALANCE,
+        }
+    }
+
+    fn decode(encoded: &[u8]) -> Option<Self> {
+        // Older firmware versions only stored the color
+        if encoded.len() == 3 {
+            return Some(Self {
+                color: LedColor::new(encoded[0], encoded[1], encoded[2]),
+                ..Self::initial_value()
+            });
+        }
+        Self::decode_profile(encoded)
+    }
+
+    fn encode(&self) -> impl AsRef<[u8]> {
+        self.encode_profile()
+    }
+}
+
+impl InnerConfig for LedStripColor {
+    type V = LedStripColor;
+}
+
+impl ConfigValue for LedStripColor {
+    const IDENTIFIER: &'static str = "led_strip_color";
+
+    fn storage() -> &'static LazyLock<RwLock<Self>> {
+        &LED_STRIP_COLOR
+    }
+
+    fn from_inner(inner: Self::V) -> Self {
+        inner
+    }
+
+    fn to_inner(self) -> Self::V {
+        self
+    }
+}
+
+#[derive(Clone)]
+pub struct WasmGuestConfig {
+    config: Vec<u8>,
+}
+
+/// Version of the format of [WasmGuestConfig]
+///
+/// Version 1 was a flat byte array that was interpreted by each guest. Version 2 is a sequence of length-prefixed key-value pairs, as described in the `config` module of the SDK.
+pub const WASM_GUEST_CONFIG_VERSION: u8 = 2;
+/// NVS key of the format version of the stored guest config. NVS keys can be at most 15 characters long
+const WASM_GUEST_CONFIG_VERSION_KEY: &str = "wasm_cfg_ver";
+/// Key of the entry that holds a migrated version 1 config
+pub const LEGACY_CONFIG_KEY: &str = "legacy";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MigrationError {
+    #[error("Version {0} of the guest config is not known")]
+    UnknownVersion(u8),
+    #[error("The version 1 config is {0} bytes long, but a value can be at most 255 bytes long")]
+    ValueTooLong(usize),
+}
+
+impl WasmGuestConfig {
+    /// Upgrade an encoded config from `old_version` to [WASM_GUEST_CONFIG_VERSION]
+    ///
+    /// A version 1 config is stored as the value of a single [LEGACY_CONFIG_KEY] entry, so guests can still read the old bytes.
+    pub fn migrate(old_bytes: &[u8], old_version: u8) -> Result<Vec<u8>, MigrationError> {
+        match old_version {
+            1 => {
+                if old_bytes.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let length: u8 = old_bytes
+                    .len()
+                    .try_into()
+                    .map_err(|_| MigrationError::ValueTooLong(old_bytes.len()))?;
+                let mut migrated = vec![LEGACY_CONFIG_KEY.len() as u8];
+                migrated.extend_from_slice(LEGACY_CONFIG_KEY.as_bytes());
+                migrated.push(length);
+                migrated.extend_from_slice(old_bytes);
+                Ok(migrated)
+            }
+            WASM_GUEST_CONFIG_VERSION => Ok(old_bytes.to_vec()),
+            version => Err(MigrationError::UnknownVersion(version)),
+        }
+    }
+}
+
+/// Check if the config consists of complete length-prefixed key-value pairs
+fn is_key_value_config(mut config: &[u8]) -> bool {
+    let mut fields = 0;
+    while let Some((length, rest)) = config.split_first() {
+        let Some(remaining) = rest.get(*length as usize..) else {
+            return false;
         };
-        Self { name }
+        config = remaining;
+        fields += 1;
+    }
+    fields % 2 == 0
+}
+
+/// Upgrade the stored guest config to the current format
+///
+/// Configs that were stored before the version was tracked are treated as version 1, unless they already consist of key-value pairs. Call this on boot, before the config is passed to a guest.
+pub fn migrate_wasm_guest_config() {
+    let stored_version = CONFIG_NVS
+        .read()
+        .unwrap()
+        .get_u8(WASM_GUEST_CONFIG_VERSION_KEY)
+        .ok()
+        .flatten();
+    if stored_version == Some(WASM_GUEST_CONFIG_VERSION) {
+        return;
+    }
+    let config = get_config::<WasmGuestConfig>();
+    let old_version = match stored_version {
+        Some(version) => version,
+        None if is_key_value_config(&config) => WASM_GUEST_CONFIG_VERSION,
+        None => 1,
+    };
+    match WasmGuestConfig::migrate(&config, old_version) {
+        Ok(migrated) => {
+            if migrated != config {
+                tracing::info!(old_version, "migrated the wasm guest config");
+                set_config::<WasmGuestConfig>(migrated);
+            }
+        }
+        Err(err) => {
+            tracing::error!(old_version, %err, "failed to migrate the wasm guest config");
+            return;
+        }
+    }
+    CONFIG_NVS
+        .write()
+        .unwrap()
+        .set_u8(WASM_GUEST_CONFIG_VERSION_KEY, WASM_GUEST_CONFIG_VERSION)
+        .unwrap();
+}
+
+static WASM_GUEST_CONFIG: LazyLock<RwLock<WasmGuestConfig>> = setup_config_storage();
+
+impl StorableValue for WasmGuestConfig {
+    fn initial_value() -> Self {
+        Self { config: vec![] }
     }
 
     fn decode(encoded: &[u8]) -> Option<Self> {
-        String::from_utf8(encoded.to_vec())
-            .ok()
-            .map(|name| Self { name })
+        Some(Self {
+            config: encoded.to_vec(),
+        })
     }
 
     fn encode(&self) -> impl AsRef<[u8]> {
-        self.name.as_bytes()
+        &self.config
     }
 }
 
-impl InnerConfig for DeviceName {
-    type V = String;
+impl InnerConfig for WasmGuestConfig {
+    type V = Vec<u8>;
 }
 
-impl ConfigValue for DeviceName {
-    const IDENTIFIER: &'static str = "device_name";
+impl ConfigValue for WasmGuestConfig {
+    const IDENTIFIER: &'static str = "wasm_guest_cfg";
 
     fn storage() -> &'static LazyLock<RwLock<Self>> {
-        &DEVICE_NAME
+        &WASM_GUEST_CONFIG
     }
 
     fn from_inner(inner: Self::V) -> Self {
-        Self { name: inner }
+        Self { config: inner }
     }
 
     fn to_inner(self) -> Self::V {
-        self.name
+        self.config
     }
 }
 
+/// Devices only synchronize with other devices in the same group
 #[derive(Clone)]
-pub struct LedStripColor {
-    color: LedColor,
+pub struct GroupId {
+    group_id: u8,
 }
 
-static LED_STRIP_COLOR: LazyLock<RwLock<LedStripColor>> = setup_config_storage();
+static GROUP_ID: LazyLock<RwLock<GroupId>> = setup_config_storage();
 
-impl StorableValue for LedStripColor {
+impl StorableValue for GroupId {
     fn initial_value() -> Self {
-        Self {
-            color: LedColor::new(0xff, 0xff, 0xff),
-        }
+        Self { group_id: 0 }
     }
 
     fn decode(encoded: &[u8]) -> Option<Self> {
-        if encoded.len() == 3 {
+        if encoded.len() == 1 {
             Some(Self {
-                color: LedColor::new(encoded[0], encoded[1], encoded[2]),
+                group_id: encoded[0],
             })
         } else {
             None
@@ -169,69 +352,145 @@ impl StorableValue for LedStripColor {
     }
 
     fn encode(&self) -> impl AsRef<[u8]> {
-        self.color.to_array()
+        [self.group_id]
     }
 }
 
-impl InnerConfig for LedStripColor {
-    type V = LedColor;
+impl InnerConfig for GroupId {
+    type V = u8;
 }
 
-impl ConfigValue for LedStripColor {
-    const IDENTIFIER: &'static str = "led_strip_color";
+impl ConfigValue for GroupId {
+    const IDENTIFIER: &'static str = "group_id";
 
     fn storage() -> &'static LazyLock<RwLock<Self>> {
-        &LED_STRIP_COLOR
+        &GROUP_ID
     }
 
     fn from_inner(inner: Self::V) -> Self {
-        Self { color: inner }
+        Self { group_id: inner }
     }
 
     fn to_inner(self) -> Self::V {
-        self.color
+        self.group_id
     }
 }
 
+/// Programs that are run in sequence, each for the given duration in milliseconds
 #[derive(Clone)]
-pub struct WasmGuestConfig {
-    config: Vec<u8>,
+pub struct ProgramSchedule {
+    entries: Vec<([u8; 32], u32)>,
 }
 
-static WASM_GUEST_CONFIG: LazyLock<RwLock<WasmGuestConfig>> = setup_config_storage();
+/// Length of an encoded schedule entry: a 32 byte hash followed by a u32 duration
+const PROGRAM_SCHEDULE_ENTRY_LENGTH: usize = 36;
 
-impl StorableValue for WasmGuestConfig {
+/// Decode a program schedule. Returns `None` if the length is not a multiple of the entry length
+pub fn decode_program_schedule(encoded: &[u8]) -> Option<Vec<([u8; 32], u32)>> {
+    if encoded.len() % PROGRAM_SCHEDULE_ENTRY_LENGTH != 0 {
+        return None;
+    }
+    let entries = encoded
+        .chunks_exact(PROGRAM_SCHEDULE_ENTRY_LENGTH)
+        .map(|entry| {
+            let (hash, duration) = entry.split_first_chunk::<32>().unwrap();
+            (*hash, u32::from_le_bytes(duration.try_into().unwrap()))
+        })
+        .collect();
+    Some(entries)
+}
+
+/// Encode a program schedule
+pub fn encode_program_schedule(entries: &[([u8; 32], u32)]) -> Vec<u8> {
+    entries
+        .iter()
+        .flat_map(|(hash, duration)| hash.iter().copied().chain(duration.to_le_bytes()))
+        .collect()
+}
+
+static PROGRAM_SCHEDULE: LazyLock<RwLock<ProgramSchedule>> = setup_config_storage();
+
+impl StorableValue for ProgramSchedule {
     fn initial_value() -> Self {
-        Self { config: vec![] }
+        Self { entries: vec![] }
     }
 
     fn decode(encoded: &[u8]) -> Option<Self> {
         Some(Self {
-            config: encoded.to_vec(),
+            entries: decode_program_schedule(encoded)?,
         })
     }
 
     fn encode(&self) -> impl AsRef<[u8]> {
-        &self.config
+        encode_program_schedule(&self.entries)
     }
 }
 
-impl InnerConfig for WasmGuestConfig {
-    type V = Vec<u8>;
+impl InnerConfig for ProgramSchedule {
+    type V = Vec<([u8; 32], u32)>;
 }
 
-impl ConfigValue for WasmGuestConfig {
-    const IDENTIFIER: &'static str = "wasm_guest_cfg";
+impl ConfigValue for ProgramSchedule {
+    const IDENTIFIER: &'static str = "program_sched";
 
     fn storage() -> &'static LazyLock<RwLock<Self>> {
-        &WASM_GUEST_CONFIG
+        &PROGRAM_SCHEDULE
     }
 
     fn from_inner(inner: Self::V) -> Self {
-        Self { config: inner }
+        Self { entries: inner }
     }
 
     fn to_inner(self) -> Self::V {
-        self.config
+        self.entries
+    }
+}
+
+/// Limits and tuning parameters of the WASM host
+#[derive(Clone)]
+pub struct WasmHostSettings {
+    configuration: WasmHostConfiguration,
+}
+
+static WASM_HOST_SETTINGS: LazyLock<RwLock<WasmHostSettings>> = setup_config_storage();
+
+impl StorableValue for WasmHostSettings {
+    fn initial_value() -> Self {
+        Self {
+            configuration: WasmHostConfiguration::default(),
+        }
+    }
+
+    fn decode(encoded: &[u8]) -> Option<Self> {
+        Some(Self {
+            configuration: postcard::from_bytes(encoded).ok()?,
+        })
+    }
+
+    fn encode(&self) -> impl AsRef<[u8]> {
+        postcard::to_allocvec(&self.configuration)
+            .expect("the host configuration can always be serialized")
+    }
+}
+
+impl InnerConfig for WasmHostSettings {
+    type V = WasmHostConfiguration;
+}
+
+impl ConfigValue for WasmHostSettings {
+    const IDENTIFIER: &'static str = "wasm_host_cfg";
+
+    fn storage() -> &'static LazyLock<RwLock<Self>> {
+        &WASM_HOST_SETTINGS
+    }
+
+    fn from_inner(inner: Self::V) -> Self {
+        Self {
+            configuration: inner,
+        }
+    }
+
+    fn to_inner(self) -> Self::V {
+        self.configuration
     }
 }
@@ -0,0 +1,103 @@
+use std::sync::{LazyLock, RwLock};
+use thiserror::Error;
+
+use super::{setup_config_storage, ConfigValue, InnerConfig, StorableValue};
+
+/// Minimum length of a device name in bytes
+pub const MIN_DEVICE_NAME_LENGTH: usize = 3;
+/// Maximum length of a device name in bytes
+pub const MAX_DEVICE_NAME_LENGTH: usize = 16;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DeviceNameError {
+    #[error("The name needs to be at least {MIN_DEVICE_NAME_LENGTH} characters long")]
+    TooShort,
+    #[error("The name can be at most {MAX_DEVICE_NAME_LENGTH} characters long")]
+    TooLong,
+    #[error("The name can only contain letters, digits, '-' and '_'")]
+    InvalidCharacters,
+}
+
+/// The name of this device
+///
+/// A name is 3 to 16 characters long and only contains `[-_a-zA-Z0-9]`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceName {
+    name: String,
+}
+
+impl DeviceName {
+    pub fn try_from_str(name: &str) -> Result<Self, DeviceNameError> {
+        if name.len() < MIN_DEVICE_NAME_LENGTH {
+            return Err(DeviceNameError::TooShort);
+        }
+        if name.len() > MAX_DEVICE_NAME_LENGTH {
+            return Err(DeviceNameError::TooLong);
+        }
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(DeviceNameError::InvalidCharacters);
+        }
+        return Ok(Self {
+            name: name.to_string(),
+        });
+    }
+
+    pub fn as_str(&self) -> &str {
+        return &self.name;
+    }
+}
+
+impl std::fmt::Display for DeviceName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+static DEVICE_NAME: LazyLock<RwLock<DeviceName>> = setup_config_storage();
+
+impl StorableValue for DeviceName {
+    fn initial_value() -> Self {
+        let name = unsafe {
+            let mut mac = [0u8; 6];
+            esp_idf_sys::esp_base_mac_addr_get(mac.as_mut_ptr());
+            format!(
+                "{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+                mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+            )
+        };
+        Self { name }
+    }
+
+    fn decode(encoded: &[u8]) -> Option<Self> {
+        // Names stored by older firmware versions may be invalid, those are replaced by the initial value
+        let name = std::str::from_utf8(encoded).ok()?;
+        Self::try_from_str(name).ok()
+    }
+
+    fn encode(&self) -> impl AsRef<[u8]> {
+        self.name.as_bytes()
+    }
+}
+
+impl InnerConfig for DeviceName {
+    type V = DeviceName;
+}
+
+impl ConfigValue for DeviceName {
+    const IDENTIFIER: &'static str = "device_name";
+
+    fn storage() -> &'static LazyLock<RwLock<Self>> {
+        &DEVICE_NAME
+    }
+
+    fn from_inner(inner: Self::V) -> Self {
+        inner
+    }
+
+    fn to_inner(self) -> Self::V {
+        self
+    }
+}
@@ -0,0 +1,206 @@
+//! A small shell on the USB serial port for debugging devices without BLE
+//!
+//! Connect with any serial terminal and type `help` to get a list of commands.
+use crate::{
+    cat_management_service::CatManagementService, file_upload_service::FileUploadService,
+    nrf_logging_service::set_log_level,
+};
+use esp32_nimble::utilities::mutex::Mutex;
+use esp_idf_hal::{
+    delay::{TickType, BLOCK},
+    gpio,
+    usb_serial::{UsbSerialConfig, UsbSerialDriver, USB_SERIAL},
+};
+use std::{fmt::Write, str::FromStr, sync::Arc};
+use tracing::{error, level_filters::LevelFilter};
+
+/// Longer lines are cut off
+const MAX_LINE_LENGTH: usize = 128;
+/// Output is dropped if no host reads it for this long
+const WRITE_TIMEOUT_MS: u64 = 100;
+
+const HELP: &str = "\
+Commands:
+  help                Show this help
+  ls                  List the uploaded files
+  info                Show heap statistics
+  reset               Restart the device
+  run <hash>          Run the uploaded program with the given hex encoded hash
+  log-level <level>   Set the log level (off, error, warn, info, debug, trace)
+";
+
+pub struct DiagnosticsService {
+    serial: UsbSerialDriver<'static>,
+    cat_management_service: Arc<Mutex<CatManagementService>>,
+    file_upload_service: Arc<Mutex<FileUploadService>>,
+}
+
+impl DiagnosticsService {
+    /// Start the shell in its own thread
+    pub fn start(
+        cat_management_service: Arc<Mutex<CatManagementService>>,
+        file_upload_service: Arc<Mutex<FileUploadService>>,
+    ) {
+        std::thread::Builder::new()
+            .name("diagnostics".into())
+            .stack_size(0x2000)
+            .spawn(move || {
+                let serial = match UsbSerialDriver::new(
+                    unsafe { USB_SERIAL::new() },
+                    unsafe { gpio::Gpio18::new() },
+                    unsafe { gpio::Gpio19::new() },
+                    &UsbSerialConfig::new(),
+                ) {
+                    Ok(serial) => serial,
+                    Err(err) => {
+                        error!(?err, "Failed to set up the USB serial port");
+                        return;
+                    }
+                };
+                let mut service = DiagnosticsService {
+                    serial,
+                    cat_management_service,
+                    file_upload_service,
+                };
+                service.run();
+            })
+            .expect("failed to spawn the diagnostics thread");
+    }
+
+    fn run(&mut self) {
+        self.write("\nrudelblinken diagnostics, type `help` for a list of commands\n");
+        loop {
+            self.write("> ");
+            let line = self.read_line();
+            let output = self.execute(line.trim());
+            self.write(&output);
+        }
+    }
+
+    /// Write to the serial port. `\n` is sent as `\r\n`
+    fn write(&mut self, text: &str) {
+        let text = text.replace('\n', "\r\n");
+        let mut remaining = text.as_bytes();
+        while !remaining.is_empty() {
+            match self
+                .serial
+                .write(remaining, TickType::new_millis(WRITE_TIMEOUT_MS).ticks())
+            {
+                Ok(written) if written > 0 => remaining = &remaining[written..],
+                // Nobody is listening, so there is no point in waiting
+                _ => return,
+            }
+        }
+    }
+
+    /// Read a line and echo it back
+    fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        let mut buffer = [0u8; 1];
+        loop {
+            match self.serial.read(&mut buffer, BLOCK) {
+                Ok(1) => {}
+                _ => continue,
+            }
+            match buffer[0] {
+                b'\r' | b'\n' => {
+                    self.write("\n");
+                    return line;
+                }
+                // Backspace and delete
+                0x08 | 0x7f => {
+                    if line.pop().is_some() {
+                        self.write("\x08 \x08");
+                    }
+                }
+                byte if byte.is_ascii() && !byte.is_ascii_control() => {
+                    if line.len() < MAX_LINE_LENGTH {
+                        line.push(byte as char);
+                        self.write(&line[line.len() - 1..]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Run a command and return its output
+    fn execute(&self, command: &str) -> String {
+        let mut arguments = command.split_whitespace();
+        let Some(name) = arguments.next() else {
+            return String::new();
+        };
+        let argument = arguments.next();
+        return match (name, argument) {
+            ("help", None) => HELP.to_string(),
+            ("ls", None) => self.list_files(),
+            ("info", None) => heap_info(),
+            ("reset", None) => unsafe { esp_idf_sys::esp_restart() },
+            ("run", Some(hash)) => {
+                let Some(hash) = parse_hash(hash) else {
+                    return "The hash needs to be 64 hex characters\n".to_string();
+                };
+                if !self.cat_management_service.lock().run_program(&hash) {
+                    return "There is no uploaded program with that hash\n".to_string();
+                }
+                "Started the program\n".to_string()
+            }
+            ("log-level", Some(level)) => {
+                let Ok(level) = LevelFilter::from_str(level) else {
+                    return "Unknown log level\n".to_string();
+                };
+                if !set_log_level(level) {
+                    return "Logging is not set up\n".to_string();
+                }
+                format!("Log level set to {}\n", level)
+            }
+            _ => format!(
+                "Unknown command `{}`, type `help` for a list of commands\n",
+                command
+            ),
+        };
+    }
+
+    fn list_files(&self) -> String {
+        let file_upload_service = self.file_upload_service.lock();
+        let files = file_upload_service.files();
+        if files.is_empty() {
+            return "No files\n".to_string();
+        }
+        let mut output = String::new();
+        for file in files {
+            let hash = file.hash().iter().fold(String::new(), |mut hash, byte| {
+                let _ = write!(hash, "{:02x}", byte);
+                hash
+            });
+            let _ = writeln!(output, "{} {}", hash, file.name());
+        }
+        return output;
+    }
+}
+
+fn heap_info() -> String {
+    let (free, total, largest_block) = unsafe {
+        (
+            esp_idf_sys::heap_caps_get_free_size(esp_idf_sys::MALLOC_CAP_DEFAULT),
+            esp_idf_sys::heap_caps_get_total_size(esp_idf_sys::MALLOC_CAP_DEFAULT),
+            esp_idf_sys::heap_caps_get_largest_free_block(esp_idf_sys::MALLOC_CAP_DEFAULT),
+        )
+    };
+    return format!(
+        "Free heap: {} of {} bytes\nLargest free block: {} bytes\n",
+        free, total, largest_block
+    );
+}
+
+/// Parse a hex encoded 32 byte hash
+fn parse_hash(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut hash = [0u8; 32];
+    for (byte, chunk) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(chunk).ok()?, 16).ok()?;
+    }
+    return Some(hash);
+}
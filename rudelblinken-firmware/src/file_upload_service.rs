@@ -1,17 +1,20 @@
 use std::{
+    fmt::Write as _,
     io::{Seek, Write},
     sync::Arc,
 };
 
 use esp32_nimble::{
     utilities::{mutex::Mutex, BleUuid},
-    BLE2904Format, BLEServer, NimbleProperties,
+    BLE2904Format, BLECharacteristic, BLEServer, NimbleProperties,
 };
 use esp_idf_sys::{self as _, BLE_GATT_CHR_UNIT_UNITLESS};
 use rudelblinken_filesystem::{
     file::{File as FileContent, FileState},
-    Filesystem,
+    file_metadata::FileMetadata,
+    EvictError, Filesystem,
 };
+use rudelblinken_protocol::upload::{DataChunk, ZstdFrameHeader};
 use thiserror::Error;
 
 use crate::{
@@ -25,6 +28,12 @@ const FILE_UPLOAD_SERVICE_HASH: u16 = 0x7894;
 const FILE_UPLOAD_SERVICE_CHECKSUMS: u16 = 0x7895;
 const FILE_UPLOAD_SERVICE_LENGTH: u16 = 0x7896;
 const FILE_UPLOAD_SERVICE_CHUNK_LENGTH: u16 = 0x7897;
+const FILE_UPLOAD_SERVICE_CHECKSUM_ALGORITHM: u16 = 0x7898;
+const FILE_UPLOAD_SERVICE_COMPRESSION: u16 = 0x7899;
+const FILE_UPLOAD_SERVICE_UPLOAD_PROGRESS: u16 = 0x789A;
+const FILE_UPLOAD_SERVICE_PROTOCOL_VERSION: u16 = 0x789B;
+const FILE_UPLOAD_SERVICE_MAX_PROTOCOL_VERSION: u16 = 0x789C;
+const FILE_UPLOAD_SERVICE_MISSING_CHUNKS: u16 = 0x789E;
 
 const FILE_UPLOAD_SERVICE_UUID: BleUuid = BleUuid::from_uuid16(FILE_UPLOAD_SERVICE);
 const FILE_UPLOAD_SERVICE_DATA_UUID: BleUuid = BleUuid::from_uuid16(FILE_UPLOAD_SERVICE_DATA);
@@ -34,6 +43,102 @@ const FILE_UPLOAD_SERVICE_CHECKSUMS_UUID: BleUuid =
 const FILE_UPLOAD_SERVICE_LENGTH_UUID: BleUuid = BleUuid::from_uuid16(FILE_UPLOAD_SERVICE_LENGTH);
 const FILE_UPLOAD_SERVICE_CHUNK_LENGTH_UUID: BleUuid =
     BleUuid::from_uuid16(FILE_UPLOAD_SERVICE_CHUNK_LENGTH);
+const FILE_UPLOAD_SERVICE_CHECKSUM_ALGORITHM_UUID: BleUuid =
+    BleUuid::from_uuid16(FILE_UPLOAD_SERVICE_CHECKSUM_ALGORITHM);
+const FILE_UPLOAD_SERVICE_COMPRESSION_UUID: BleUuid =
+    BleUuid::from_uuid16(FILE_UPLOAD_SERVICE_COMPRESSION);
+const FILE_UPLOAD_SERVICE_UPLOAD_PROGRESS_UUID: BleUuid =
+    BleUuid::from_uuid16(FILE_UPLOAD_SERVICE_UPLOAD_PROGRESS);
+const FILE_UPLOAD_SERVICE_PROTOCOL_VERSION_UUID: BleUuid =
+    BleUuid::from_uuid16(FILE_UPLOAD_SERVICE_PROTOCOL_VERSION);
+const FILE_UPLOAD_SERVICE_MAX_PROTOCOL_VERSION_UUID: BleUuid =
+    BleUuid::from_uuid16(FILE_UPLOAD_SERVICE_MAX_PROTOCOL_VERSION);
+const FILE_UPLOAD_SERVICE_MISSING_CHUNKS_UUID: BleUuid =
+    BleUuid::from_uuid16(FILE_UPLOAD_SERVICE_MISSING_CHUNKS);
+
+/// The newest version of the upload protocol that is supported
+///
+/// Version 1 is the current protocol. Clients that do not write a protocol version are assumed to use version 1.
+pub const MAX_PROTOCOL_VERSION: u8 = 1;
+
+/// Number of bytes that are fed into the hasher at once when verifying a file
+const HASH_CHUNK_SIZE: usize = 4096;
+
+/// Maximum number of chunk indices reported by the missing chunks characteristic
+const MAX_REPORTED_MISSING_CHUNKS: usize = 64;
+
+/// The algorithm used for the per-chunk checksums
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ChecksumAlgorithm {
+    /// One CRC-8/LTE byte per chunk
+    #[default]
+    Crc8 = 0,
+    /// One little-endian CRC-32/ISO-HDLC value per chunk
+    Crc32 = 1,
+}
+
+impl ChecksumAlgorithm {
+    /// Length of a single checksum in bytes
+    pub const fn checksum_length(&self) -> usize {
+        match self {
+            ChecksumAlgorithm::Crc8 => 1,
+            ChecksumAlgorithm::Crc32 => 4,
+        }
+    }
+
+    /// Calculate the checksum of a chunk as little-endian bytes
+    fn checksum(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            ChecksumAlgorithm::Crc8 => {
+                // TODO: Find out if generating a new crc8 generator costs anything
+                let crc8_generator = crc::Crc::<u8>::new(&crc::CRC_8_LTE);
+                vec![crc8_generator.checksum(data)]
+            }
+            ChecksumAlgorithm::Crc32 => {
+                let crc32_generator = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+                crc32_generator.checksum(data).to_le_bytes().to_vec()
+            }
+        }
+    }
+}
+
+impl TryFrom<u8> for ChecksumAlgorithm {
+    type Error = FileUploadError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ChecksumAlgorithm::Crc8),
+            1 => Ok(ChecksumAlgorithm::Crc32),
+            other => Err(FileUploadError::UnknownChecksumAlgorithm(other)),
+        }
+    }
+}
+
+/// The compression applied to the uploaded data
+///
+/// The hash and the length always refer to the uncompressed content. The checksums always refer to the transferred data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Compression {
+    /// The data is transferred as is
+    #[default]
+    None = 0,
+    /// The data is a single zstd frame
+    Zstd = 1,
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = FileUploadError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Zstd),
+            other => Err(FileUploadError::UnknownCompression(other)),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct File {
@@ -42,23 +147,190 @@ pub struct File {
     pub content: FileContent<FlashStorage, { FileState::Weak }>,
 }
 
+impl File {
+    pub fn hash(&self) -> &[u8; 32] {
+        return &self.hash;
+    }
+
+    pub fn name(&self) -> &str {
+        return &self.name;
+    }
+}
+
+#[derive(Debug)]
+enum IncompleteContent {
+    /// Chunks are written directly to flash
+    Uncompressed(FileContent<FlashStorage, { FileState::Writer }>),
+    /// Chunks are decompressed in order and the content is written to flash while the upload is running
+    Zstd(ZstdUpload),
+}
+
+/// Largest zstd window that is accepted for compressed uploads
+///
+/// The decoder keeps one window of decompressed content in memory. Blocks are never larger than the window, so this also limits the compressed data that is buffered until a block is complete.
+const MAX_ZSTD_WINDOW_SIZE: u64 = 32 * 1024;
+/// Decompresses a single zstd frame while its chunks arrive and writes the content to a file
+struct ZstdUpload {
+    writer: FileContent<FlashStorage, { FileState::Writer }>,
+    decoder: ruzstd::FrameDecoder,
+    /// Set once the frame header was read
+    started: bool,
+    /// Compressed data that was received but not decoded yet
+    pending: Vec<u8>,
+    /// Index of the next chunk that can be decompressed
+    next_chunk: u16,
+    /// Length of the decompressed content, as specified for the upload
+    length: u32,
+    /// Number of decompressed bytes that were written to the file
+    written: u32,
+}
+
+impl std::fmt::Debug for ZstdUpload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ZstdUpload")
+            .field("started", &self.started)
+            .field("pending", &self.pending.len())
+            .field("next_chunk", &self.next_chunk)
+            .field("length", &self.length)
+            .field("written", &self.written)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ZstdUpload {
+    fn new(writer: FileContent<FlashStorage, { FileState::Writer }>, length: u32) -> Self {
+        ZstdUpload {
+            writer,
+            decoder: ruzstd::FrameDecoder::new(),
+            started: false,
+            pending: Vec::new(),
+            next_chunk: 0,
+            length,
+            written: 0,
+        }
+    }
+
+    /// Decompress the next chunk and write everything that can be decoded to the file
+    fn decompress_chunk(&mut self, data: &[u8]) -> Result<(), ReceiveChunkError> {
+        self.pending.extend_from_slice(data);
+        if !self.started {
+            let header = ZstdFrameHeader::parse(&self.pending).map_err(|_| {
+                ::tracing::warn!(target: "file-upload", "Compressed data does not start with a zstd frame");
+                ReceiveChunkError::DecompressionFailed
+            })?;
+            let Some(header) = header else {
+                return Ok(());
+            };
+            if header.window_size > MAX_ZSTD_WINDOW_SIZE {
+                ::tracing::warn!(target: "file-upload", "The zstd window of {} bytes is larger than the supported {} bytes", header.window_size, MAX_ZSTD_WINDOW_SIZE);
+                return Err(ReceiveChunkError::DecompressionFailed);
+            }
+            if header
+                .content_size
+                .is_some_and(|content_size| content_size != self.length as u64)
+            {
+                ::tracing::warn!(target: "file-upload", "The zstd frame contains {:?} bytes, but {} bytes were specified", header.content_size, self.length);
+                return Err(ReceiveChunkError::DecompressionFailed);
+            }
+            self.decoder
+                .init(&self.pending[..header.length])
+                .map_err(|error| {
+                    ::tracing::warn!(target: "file-upload", "Invalid zstd frame: {}", error);
+                    ReceiveChunkError::DecompressionFailed
+                })?;
+            self.pending.drain(..header.length);
+            self.started = true;
+        }
+
+        let mut buffer = [0u8; 512];
+        loop {
+            let (read, decompressed) = self
+                .decoder
+                .decode_from_to(&self.pending, &mut buffer)
+                .map_err(|error| {
+                    ::tracing::warn!(target: "file-upload", "Failed to decompress: {}", error);
+                    ReceiveChunkError::DecompressionFailed
+                })?;
+            self.pending.drain(..read);
+            if self.written as usize + decompressed > self.length as usize {
+                ::tracing::warn!(target: "file-upload", "The decompressed content is longer than the specified {} bytes", self.length);
+                return Err(ReceiveChunkError::DecompressionFailed);
+            }
+            self.writer.write_all(&buffer[..decompressed]).map_err(|error| {
+                ::tracing::warn!(target: "file-upload", "Failed to write decompressed content: {}", error);
+                ReceiveChunkError::DecompressionFailed
+            })?;
+            self.written += decompressed as u32;
+            if read == 0 && decompressed == 0 {
+                break;
+            }
+        }
+        // Incomplete blocks are kept until the rest arrives, but a block is never larger than the window
+        if self.pending.len() as u64 > 2 * MAX_ZSTD_WINDOW_SIZE {
+            ::tracing::warn!(target: "file-upload", "Buffered {} bytes without completing a zstd block", self.pending.len());
+            return Err(ReceiveChunkError::DecompressionFailed);
+        }
+        self.next_chunk += 1;
+        Ok(())
+    }
+
+    /// Check that the frame was decompressed completely and commit the file
+    fn finish(self) -> Result<(), VerifyFileError> {
+        if !self.decoder.is_finished() || !self.pending.is_empty() {
+            ::tracing::warn!(target: "file-upload", "The zstd frame is incomplete");
+            return Err(VerifyFileError::DecompressionFailed);
+        }
+        if self.written != self.length {
+            ::tracing::warn!(target: "file-upload", "Expected {} bytes but decompressed {} bytes", self.length, self.written);
+            return Err(VerifyFileError::SizeMismatch {
+                expected: self.length,
+                got: self.written,
+            });
+        }
+        self.writer.commit().map_err(|error| {
+            ::tracing::warn!(target: "file-upload", "Failed to store file: {}", error);
+            VerifyFileError::FailedToStoreDecompressedFile
+        })?;
+        Ok(())
+    }
+}
+
+/// Format a hash as lowercase hex
+fn format_hash(hash: &[u8; 32]) -> String {
+    return hash.iter().fold(String::new(), |mut formatted, byte| {
+        let _ = write!(formatted, "{:02x}", byte);
+        formatted
+    });
+}
+
 #[derive(Debug)]
 struct IncompleteFile {
-    incomplete_file: FileContent<FlashStorage, { FileState::Writer }>,
+    incomplete_file: IncompleteContent,
     checksums: Vec<u8>,
+    checksum_algorithm: ChecksumAlgorithm,
     received_chunks: Vec<bool>,
     chunk_length: u16,
+    /// Length of the last chunk as it was received
+    last_chunk_length: u16,
     length: u32,
     name: String,
     hash: [u8; 32],
+    /// Covers everything that happens to this upload, until it is finished or aborted
+    span: ::tracing::Span,
 }
 
 #[derive(Error, Debug, Clone)]
 pub enum ReceiveChunkError {
     #[error("Chunk has an invalid length")]
     InvalidLength,
+    #[error("Chunk index is larger than the number of chunks")]
+    InvalidIndex,
     #[error("Chunk has the wrong checksum")]
     WrongChecksum,
+    #[error("Compressed chunks need to be received in order")]
+    OutOfOrder,
+    #[error("Failed to decompress the received data")]
+    DecompressionFailed,
 }
 
 #[derive(Error, Debug, Clone)]
@@ -67,88 +339,166 @@ pub enum VerifyFileError {
     NotComplete,
     #[error("Hashes do not match")]
     HashMismatch,
+    #[error("Expected {expected} bytes but received {got} bytes")]
+    SizeMismatch { expected: u32, got: u32 },
+    #[error("Failed to decompress the received data")]
+    DecompressionFailed,
+    #[error("Failed to store the decompressed file")]
+    FailedToStoreDecompressedFile,
 }
 
 impl IncompleteFile {
     pub fn new(
         hash: [u8; 32],
         checksums: Vec<u8>,
+        checksum_algorithm: ChecksumAlgorithm,
         chunk_length: u16,
         length: u32,
-        writer: FileContent<FlashStorage, { FileState::Writer }>,
+        writer: IncompleteContent,
         name: String,
     ) -> Self {
+        let span = ::tracing::info_span!(target: "file-upload", "file_upload", hash = %format_hash(&hash), name = %name);
+        span.in_scope(|| {
+            ::tracing::info!(target: "file-upload", "Started upload of {} bytes", length);
+        });
         Self {
             incomplete_file: writer,
-            received_chunks: vec![false; checksums.len()],
+            received_chunks: vec![false; checksums.len() / checksum_algorithm.checksum_length()],
             checksums,
+            checksum_algorithm,
             chunk_length,
+            last_chunk_length: 0,
             length,
             name,
             hash,
+            span,
         }
     }
+    /// The number of chunks in this file
+    fn chunk_count(&self) -> usize {
+        self.received_chunks.len()
+    }
     pub fn receive_chunk(&mut self, data: &[u8], index: u16) -> Result<(), ReceiveChunkError> {
+        let span = self.span.clone();
+        let _entered = span.enter();
+        if index as usize >= self.chunk_count() {
+            return Err(ReceiveChunkError::InvalidIndex);
+        }
         // Verify length for all but the last chunk
-        if (index as usize != self.checksums.len() - 1)
+        if (index as usize != self.chunk_count() - 1)
             && (data.len() != self.chunk_length as usize)
         {
             return Err(ReceiveChunkError::InvalidLength);
         }
-        // Verify length for the last chunk
-        if (index as usize == self.checksums.len() - 1)
-            && (data.len() != (self.length as usize % self.chunk_length as usize))
-        {
-            return Err(ReceiveChunkError::InvalidLength);
+        // Verify length for the last chunk. The length of compressed data is not known in advance
+        let is_last_chunk = index as usize == self.chunk_count() - 1;
+        match &self.incomplete_file {
+            IncompleteContent::Uncompressed(_)
+                if is_last_chunk
+                    && (data.len() != (self.length as usize % self.chunk_length as usize)) =>
+            {
+                return Err(ReceiveChunkError::InvalidLength);
+            }
+            IncompleteContent::Zstd(_)
+                if is_last_chunk
+                    && (data.is_empty() || data.len() > self.chunk_length as usize) =>
+            {
+                return Err(ReceiveChunkError::InvalidLength);
+            }
+            _ => {}
+        }
+        if let IncompleteContent::Zstd(upload) = &self.incomplete_file {
+            if index < upload.next_chunk {
+                // Already decompressed, the client resent it
+                return Ok(());
+            }
+            if index > upload.next_chunk {
+                return Err(ReceiveChunkError::OutOfOrder);
+            }
         }
 
-        // TODO: Find out if generating a new crc8 generator costs anything
-        let crc8_generator = crc::Crc::<u8>::new(&crc::CRC_8_LTE);
-        let checksum = crc8_generator.checksum(data);
+        let checksum = self.checksum_algorithm.checksum(data);
+        let checksum_length = self.checksum_algorithm.checksum_length();
+        let expected_checksum = &self.checksums
+            [(index as usize * checksum_length)..((index as usize + 1) * checksum_length)];
 
-        if self.checksums[index as usize] != checksum {
+        if expected_checksum != checksum.as_slice() {
             ::tracing::error!(target: "file-upload", "Received chunk with invalid checksum");
             return Err(ReceiveChunkError::WrongChecksum);
         }
 
-        let offset = (self.chunk_length * index) as usize;
-        self.incomplete_file
-            .seek(std::io::SeekFrom::Start(offset as u64))
-            .unwrap();
-        self.incomplete_file.write(data).unwrap();
-        // self.incomplete_file.content[offset..(data.len() + offset)].copy_from_slice(data);
+        let offset = self.chunk_length as usize * index as usize;
+        match &mut self.incomplete_file {
+            IncompleteContent::Uncompressed(writer) => {
+                writer
+                    .seek(std::io::SeekFrom::Start(offset as u64))
+                    .unwrap();
+                writer.write(data).unwrap();
+            }
+            IncompleteContent::Zstd(upload) => {
+                upload.decompress_chunk(data)?;
+            }
+        }
+        if index as usize == self.chunk_count() - 1 {
+            self.last_chunk_length = data.len() as u16;
+        }
         self.received_chunks[index as usize] = true;
 
         Ok(())
     }
-    // /// Get the ID of the next missing chunk. Returns [None], if all chunks were already received.
-    // pub fn get_next_missing_chunk(&self) -> Option<usize> {
-    //     self.received_chunks
-    //         .iter()
-    //         .enumerate()
-    //         .find(|(_, received)| received == &&false)
-    //         .map(|(index, _)| index)
-    // }
+    /// Get the indices of the first `limit` chunks that were not received yet
+    fn missing_chunks(&self, limit: usize) -> Vec<u16> {
+        self.received_chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, received)| !**received)
+            .map(|(index, _)| index as u16)
+            .take(limit)
+            .collect()
+    }
+    /// The number of chunks that were already received
+    fn received_chunk_count(&self) -> usize {
+        self.received_chunks
+            .iter()
+            .filter(|received| **received)
+            .count()
+    }
     /// Check if the file is complete
     pub fn is_complete(&self) -> bool {
         self.received_chunks.iter().all(|received| *received)
     }
+    /// The number of bytes in all chunks, assuming the file is complete
+    pub fn written_bytes(&self) -> u32 {
+        let full_chunks = self.chunk_count().saturating_sub(1) as u32;
+        full_chunks * self.chunk_length as u32 + self.last_chunk_length as u32
+    }
     /// Verify that the received file is complete and has the correct hash
     pub fn verify_hash(
         self,
-        filesystem: &Filesystem<FlashStorage>,
+        filesystem: &mut Filesystem<FlashStorage>,
     ) -> Result<FileContent<FlashStorage, { FileState::Weak }>, VerifyFileError> {
+        let span = self.span.clone();
+        let _entered = span.enter();
         if !self.is_complete() {
             return Err(VerifyFileError::NotComplete);
         }
-        self.incomplete_file.commit().unwrap();
+        match self.incomplete_file {
+            IncompleteContent::Uncompressed(writer) => {
+                writer.commit().unwrap();
+            }
+            IncompleteContent::Zstd(upload) => {
+                upload.finish()?;
+            }
+        }
         let file = filesystem.read_file(&self.name).unwrap();
-        let mut hasher = blake3::Hasher::new();
-        hasher.update(file.upgrade().unwrap().as_ref());
-
-        // TODO: I am sure there is a better way to convert this into an array but I didnt find it after 10 minutes.
-        let mut hash: [u8; 32] = [0; 32];
-        hash.copy_from_slice(hasher.finalize().as_bytes());
+        let content = file.upgrade().unwrap();
+        // Uploaded files are written with the default algorithm, so this is the blake3 hash of the content
+        let mut hasher = content.hash_algorithm().hasher();
+        for chunk in content.read_chunks(HASH_CHUNK_SIZE) {
+            hasher.update(chunk);
+        }
+        let hash = hasher.finalize();
+        drop(content);
 
         if hash != self.hash {
             ::tracing::warn!(target: "file-upload", "Hashes dont match.\nExpected: {:?}\nGot     : {:?}", self.hash, hash);
@@ -161,8 +511,24 @@ impl IncompleteFile {
     /// Get the uploaded file, if the upload is finished, otherwise this return None and you just destroyed your incomplete file for no reason
     pub fn into_file(
         self,
-        filesystem: &Filesystem<FlashStorage>,
+        filesystem: &mut Filesystem<FlashStorage>,
     ) -> Result<FileContent<FlashStorage, { FileState::Weak }>, VerifyFileError> {
+        let span = self.span.clone();
+        let _entered = span.enter();
+        if !self.is_complete() {
+            return Err(VerifyFileError::NotComplete);
+        }
+        // Compressed uploads check the decompressed length when they are finished
+        let written_bytes = self.written_bytes();
+        if matches!(self.incomplete_file, IncompleteContent::Uncompressed(_))
+            && written_bytes != self.length
+        {
+            ::tracing::warn!(target: "file-upload", "Expected {} bytes but received {} bytes", self.length, written_bytes);
+            return Err(VerifyFileError::SizeMismatch {
+                expected: self.length,
+                got: written_bytes,
+            });
+        }
         let file = self.verify_hash(filesystem)?;
         Ok(file)
     }
@@ -176,6 +542,13 @@ pub struct FileUploadService {
     latest_checksums: Option<Vec<u8>>,
     latest_length: Option<u32>,
     latest_chunk_length: Option<u16>,
+    latest_checksum_algorithm: ChecksumAlgorithm,
+    latest_compression: Compression,
+    latest_protocol_version: u8,
+
+    /// Set once the last upload was verified and stored
+    upload_complete: bool,
+    upload_progress_characteristic: Option<Arc<Mutex<BLECharacteristic>>>,
 
     last_error: Option<FileUploadError>,
 }
@@ -192,8 +565,16 @@ pub enum FileUploadError {
     NoUploadActive,
     #[error("Received chunk is way too short")]
     ReceivedChunkWayTooShort,
+    #[error("Expected {expected} bytes for the parameter, but received {received}")]
+    InvalidParameterLength { expected: usize, received: usize },
     #[error("There is no checksum file with the supplied hash")]
     ChecksumFileDoesNotExist,
+    #[error("Unknown checksum algorithm {0}")]
+    UnknownChecksumAlgorithm(u8),
+    #[error("Unknown compression {0}")]
+    UnknownCompression(u8),
+    #[error("Unsupported protocol version {0}")]
+    UnsupportedProtocolVersion(u8),
 }
 
 #[derive(Error, Debug, Clone)]
@@ -208,6 +589,10 @@ pub enum StartUploadError {
     ChecksumsMissing,
     #[error("Content length seems incorrect, as it does not match chunk length multiplied by chunk size")]
     LengthIncorrect,
+    #[error("Checksums length is not a multiple of the checksum length of the selected algorithm")]
+    ChecksumsLengthIncorrect,
+    #[error("There is not enough space for the file, even after deleting all unimportant files")]
+    NotEnoughSpace,
 }
 
 impl FileUploadService {
@@ -225,22 +610,43 @@ impl FileUploadService {
         let Some(checksums) = &self.latest_checksums else {
             return Err(StartUploadError::ChecksumsMissing);
         };
-        let min_length =
-            ((chunk_length as usize) * checksums.len() - (chunk_length as usize - 1)) as u32;
-        let max_length = (chunk_length as usize * checksums.len()) as u32;
-        if (length < min_length) || (length > max_length) {
-            return Err(StartUploadError::LengthIncorrect);
-        }
-        let mut filesystem = get_filesystem().unwrap().write().unwrap();
-        // Delete previous file
-        let _ = filesystem.delete_file("firmware");
-        let writer = filesystem
-            .get_file_writer("firmware", length, hash)
-            .unwrap();
+        let checksum_algorithm = self.latest_checksum_algorithm;
+        if checksums.len() % checksum_algorithm.checksum_length() != 0 {
+            return Err(StartUploadError::ChecksumsLengthIncorrect);
+        }
+        let chunk_count = checksums.len() / checksum_algorithm.checksum_length();
+        // The length of compressed uploads refers to the decompressed content
+        if self.latest_compression == Compression::None {
+            let min_length =
+                ((chunk_length as usize) * chunk_count - (chunk_length as usize - 1)) as u32;
+            let max_length = (chunk_length as usize * chunk_count) as u32;
+            if (length < min_length) || (length > max_length) {
+                return Err(StartUploadError::LengthIncorrect);
+            }
+        }
+        let writer = {
+            let mut filesystem = get_filesystem().unwrap().write().unwrap();
+            // Delete previous file
+            let _ = filesystem.delete_file("firmware");
+            if let Err(EvictError::CannotFreeEnough) =
+                filesystem.lru_evict(length + size_of::<FileMetadata>() as u32)
+            {
+                return Err(StartUploadError::NotEnoughSpace);
+            }
+            filesystem
+                .get_file_writer("firmware", length, hash)
+                .map_err(|_| StartUploadError::NotEnoughSpace)?
+        };
+        let writer = match self.latest_compression {
+            Compression::None => IncompleteContent::Uncompressed(writer),
+            Compression::Zstd => IncompleteContent::Zstd(ZstdUpload::new(writer, length)),
+        };
 
+        self.upload_complete = false;
         self.currently_receiving = Some(IncompleteFile::new(
             *hash,
             checksums.clone(),
+            checksum_algorithm,
             chunk_length,
             length,
             writer,
@@ -261,6 +667,41 @@ impl FileUploadService {
         Ok(())
     }
 
+    /// Progress of the current upload in percent. Reports 100 once the upload was verified and stored
+    fn upload_progress(&self) -> u8 {
+        if self.upload_complete {
+            return 100;
+        }
+        let Some(current_upload) = &self.currently_receiving else {
+            return 0;
+        };
+        if current_upload.chunk_count() == 0 {
+            return 0;
+        }
+        // The upload is only complete once the file was stored, so this never reports 100
+        (current_upload.received_chunk_count() * 99 / current_upload.chunk_count()) as u8
+    }
+
+    /// Notify subscribed clients about the current upload progress
+    fn notify_upload_progress(&self) {
+        let Some(characteristic) = &self.upload_progress_characteristic else {
+            return;
+        };
+        characteristic
+            .lock()
+            .set_value(&[self.upload_progress()])
+            .notify();
+    }
+
+    /// Drop the current upload, because its parameters changed
+    fn abort_upload(&mut self) {
+        if let Some(upload) = self.currently_receiving.take() {
+            upload.span.in_scope(|| {
+                ::tracing::info!(target: "file-upload", "Aborted upload");
+            });
+        }
+    }
+
     fn log_error(&mut self, error: FileUploadError) {
         ::tracing::error!(target: "file-upload", "{}", error);
         self.last_error = Some(error);
@@ -281,14 +722,11 @@ impl FileUploadService {
         let received_data = args.recv_data();
         ::tracing::info!(target: "file-upload", "chunk length {}", received_data.len());
 
-        if received_data.len() < 3 {
+        let Some(DataChunk { index, data }) = DataChunk::parse(received_data) else {
             ::tracing::info!(target: "file-upload", "data length is too short {}", received_data.len());
 
             return Err(FileUploadError::ReceivedChunkWayTooShort);
-        }
-
-        let index = u16::from_le_bytes([received_data[0], received_data[1]]);
-        let data = &received_data[2..];
+        };
 
         ::tracing::info!(target: "file-upload", "Received data chunk {}", index);
         self.ensure_upload()?;
@@ -297,7 +735,13 @@ impl FileUploadService {
             // Should never happen, because we called ensure_upload above
             return Err(FileUploadError::NoUploadActive);
         };
-        current_upload.receive_chunk(data, index)?;
+        if let Err(error) = current_upload.receive_chunk(data, index) {
+            // A compressed upload can not continue after the decoder failed
+            if let ReceiveChunkError::DecompressionFailed = error {
+                self.abort_upload();
+            }
+            return Err(error.into());
+        }
         if current_upload.is_complete() {
             let incomplete_file = self
                 .currently_receiving
@@ -305,16 +749,32 @@ impl FileUploadService {
                 .ok_or(FileUploadError::NoUploadActive)?;
             let hash = incomplete_file.hash.clone();
             let name = incomplete_file.name.clone();
-            let file = incomplete_file.into_file(&get_filesystem().unwrap().read().unwrap())?;
+            let file = incomplete_file.into_file(&mut get_filesystem().unwrap().write().unwrap())?;
             self.files.push(File {
                 hash,
                 name: name,
                 content: file,
             });
+            self.upload_complete = true;
+            self.notify_upload_progress();
         }
         Ok(())
     }
 
+    /// Indices of the first missing chunks of the current upload as little-endian u16 values
+    ///
+    /// Empty if there is no active upload.
+    fn missing_chunks(&self) -> Vec<u8> {
+        let Some(current_upload) = &self.currently_receiving else {
+            return Vec::new();
+        };
+        current_upload
+            .missing_chunks(MAX_REPORTED_MISSING_CHUNKS)
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect()
+    }
+
     /// This will be called on writes to the hash characteristic
     ///
     /// We use this wrapper to make error handling easier
@@ -335,7 +795,7 @@ impl FileUploadService {
             return Ok(());
         }
         self.latest_hash = Some(new_hash);
-        self.currently_receiving = None;
+        self.abort_upload();
         Ok(())
     }
 
@@ -343,6 +803,11 @@ impl FileUploadService {
         self.files.iter().find(|file| &file.hash == hash)
     }
 
+    /// All files that were uploaded since the device started
+    pub fn files(&self) -> &[File] {
+        &self.files
+    }
+
     /// This will be called on writes to the checksum characteristic
     ///
     /// We use this wrapper to make error handling easier
@@ -360,7 +825,7 @@ impl FileUploadService {
             }
             ::tracing::info!(target: "file-upload", "Directly set checksums");
             self.latest_checksums = Some(new_checksums);
-            self.currently_receiving = None;
+            self.abort_upload();
             return Ok(());
         }
 
@@ -375,7 +840,7 @@ impl FileUploadService {
             }
             ::tracing::info!(target: "file-upload", "Loaded checksums from file");
             self.latest_checksums = Some(new_checksums);
-            self.currently_receiving = None;
+            self.abort_upload();
             return Ok(());
         }
 
@@ -415,7 +880,7 @@ impl FileUploadService {
         }
 
         self.latest_length = Some(new_length);
-        self.currently_receiving = None;
+        self.abort_upload();
 
         Ok(())
     }
@@ -445,7 +910,105 @@ impl FileUploadService {
         }
 
         self.latest_chunk_length = Some(new_chunk_length);
-        self.currently_receiving = None;
+        self.abort_upload();
+
+        Ok(())
+    }
+
+    /// This will be called on writes to the checksum algorithm characteristic
+    ///
+    /// We use this wrapper to make error handling easier
+    fn checksum_algorithm_write(
+        &mut self,
+        args: &mut esp32_nimble::OnWriteArgs<'_>,
+    ) -> Result<(), FileUploadError> {
+        let received_data = args.recv_data();
+        if received_data.len() != 1 {
+            ::tracing::info!(target: "file-upload", "checksum algorithm has the wrong length {}", received_data.len());
+
+            return Err(FileUploadError::InvalidParameterLength {
+                expected: 1,
+                received: received_data.len(),
+            });
+        }
+
+        let new_checksum_algorithm = ChecksumAlgorithm::try_from(received_data[0])?;
+        ::tracing::info!(target: "file-upload", "Received checksum algorithm {:?}", new_checksum_algorithm);
+
+        if self.latest_checksum_algorithm == new_checksum_algorithm {
+            // Not changed, nothing to do
+            return Ok(());
+        }
+
+        self.latest_checksum_algorithm = new_checksum_algorithm;
+        self.abort_upload();
+
+        Ok(())
+    }
+
+    /// This will be called on writes to the compression characteristic
+    ///
+    /// We use this wrapper to make error handling easier
+    fn compression_write(
+        &mut self,
+        args: &mut esp32_nimble::OnWriteArgs<'_>,
+    ) -> Result<(), FileUploadError> {
+        let received_data = args.recv_data();
+        if received_data.len() != 1 {
+            ::tracing::info!(target: "file-upload", "compression has the wrong length {}", received_data.len());
+
+            return Err(FileUploadError::InvalidParameterLength {
+                expected: 1,
+                received: received_data.len(),
+            });
+        }
+
+        let new_compression = Compression::try_from(received_data[0])?;
+        ::tracing::info!(target: "file-upload", "Received compression {:?}", new_compression);
+
+        if self.latest_compression == new_compression {
+            // Not changed, nothing to do
+            return Ok(());
+        }
+
+        self.latest_compression = new_compression;
+        self.abort_upload();
+
+        Ok(())
+    }
+
+    /// This will be called on writes to the protocol version characteristic
+    ///
+    /// We use this wrapper to make error handling easier
+    fn protocol_version_write(
+        &mut self,
+        args: &mut esp32_nimble::OnWriteArgs<'_>,
+    ) -> Result<(), FileUploadError> {
+        let received_data = args.recv_data();
+        if received_data.len() != 1 {
+            ::tracing::info!(target: "file-upload", "protocol version has the wrong length {}", received_data.len());
+
+            return Err(FileUploadError::InvalidParameterLength {
+                expected: 1,
+                received: received_data.len(),
+            });
+        }
+
+        let new_protocol_version = received_data[0];
+        if new_protocol_version == 0 || new_protocol_version > MAX_PROTOCOL_VERSION {
+            return Err(FileUploadError::UnsupportedProtocolVersion(
+                new_protocol_version,
+            ));
+        }
+        ::tracing::info!(target: "file-upload", "Received protocol version {}", new_protocol_version);
+
+        if self.latest_protocol_version == new_protocol_version {
+            // Not changed, nothing to do
+            return Ok(());
+        }
+
+        self.latest_protocol_version = new_protocol_version;
+        self.abort_upload();
 
         Ok(())
     }
@@ -459,6 +1022,12 @@ impl FileUploadService {
             latest_chunk_length: None,
             latest_hash: None,
             latest_length: None,
+            latest_checksum_algorithm: ChecksumAlgorithm::default(),
+            latest_compression: Compression::default(),
+            latest_protocol_version: 1,
+
+            upload_complete: false,
+            upload_progress_characteristic: None,
 
             last_error: None,
         }));
@@ -519,6 +1088,74 @@ impl FileUploadService {
             BLE_GATT_CHR_UNIT_UNITLESS,
         );
 
+        let checksum_algorithm_characteristic = service.lock().create_characteristic(
+            FILE_UPLOAD_SERVICE_CHECKSUM_ALGORITHM_UUID,
+            NimbleProperties::READ | NimbleProperties::WRITE,
+        );
+        checksum_algorithm_characteristic.document(
+            "Checksum Algorithm",
+            BLE2904Format::UINT8,
+            0,
+            BLE_GATT_CHR_UNIT_UNITLESS,
+        );
+
+        let compression_characteristic = service.lock().create_characteristic(
+            FILE_UPLOAD_SERVICE_COMPRESSION_UUID,
+            NimbleProperties::READ | NimbleProperties::WRITE,
+        );
+        compression_characteristic.document(
+            "Compression",
+            BLE2904Format::UINT8,
+            0,
+            BLE_GATT_CHR_UNIT_UNITLESS,
+        );
+
+        let protocol_version_characteristic = service.lock().create_characteristic(
+            FILE_UPLOAD_SERVICE_PROTOCOL_VERSION_UUID,
+            NimbleProperties::READ | NimbleProperties::WRITE,
+        );
+        protocol_version_characteristic.document(
+            "Protocol Version",
+            BLE2904Format::UINT8,
+            0,
+            BLE_GATT_CHR_UNIT_UNITLESS,
+        );
+
+        let max_protocol_version_characteristic = service.lock().create_characteristic(
+            FILE_UPLOAD_SERVICE_MAX_PROTOCOL_VERSION_UUID,
+            NimbleProperties::READ,
+        );
+        max_protocol_version_characteristic.document(
+            "Max Protocol Version",
+            BLE2904Format::UINT8,
+            0,
+            BLE_GATT_CHR_UNIT_UNITLESS,
+        );
+
+        let upload_progress_characteristic = service.lock().create_characteristic(
+            FILE_UPLOAD_SERVICE_UPLOAD_PROGRESS_UUID,
+            NimbleProperties::READ | NimbleProperties::NOTIFY,
+        );
+        upload_progress_characteristic.document(
+            "Upload Progress (percent)",
+            BLE2904Format::UINT8,
+            0,
+            BLE_GATT_CHR_UNIT_UNITLESS,
+        );
+        file_upload_service.lock().upload_progress_characteristic =
+            Some(upload_progress_characteristic.clone());
+
+        let missing_chunks_characteristic = service.lock().create_characteristic(
+            FILE_UPLOAD_SERVICE_MISSING_CHUNKS_UUID,
+            NimbleProperties::READ,
+        );
+        missing_chunks_characteristic.document(
+            "Missing Chunks",
+            BLE2904Format::OPAQUE,
+            0,
+            BLE_GATT_CHR_UNIT_UNITLESS,
+        );
+
         let file_upload_service_clone = file_upload_service.clone();
         data_characteristic.lock().on_write(move |args| {
             let mut service = file_upload_service_clone.lock();
@@ -577,6 +1214,75 @@ impl FileUploadService {
             value.set_value(&chunk_length);
         });
 
+        let file_upload_service_clone = file_upload_service.clone();
+        checksum_algorithm_characteristic
+            .lock()
+            .on_write(move |args| {
+                let mut service = file_upload_service_clone.lock();
+                if let Err(e) = service.checksum_algorithm_write(args) {
+                    service.log_error(e);
+                }
+            });
+        let file_upload_service_clone = file_upload_service.clone();
+        checksum_algorithm_characteristic
+            .lock()
+            .on_read(move |value, _| {
+                let service = file_upload_service_clone.lock();
+                value.set_value(&[service.latest_checksum_algorithm as u8]);
+            });
+
+        let file_upload_service_clone = file_upload_service.clone();
+        compression_characteristic.lock().on_write(move |args| {
+            let mut service = file_upload_service_clone.lock();
+            if let Err(e) = service.compression_write(args) {
+                service.log_error(e);
+            }
+        });
+        let file_upload_service_clone = file_upload_service.clone();
+        compression_characteristic.lock().on_read(move |value, _| {
+            let service = file_upload_service_clone.lock();
+            value.set_value(&[service.latest_compression as u8]);
+        });
+
+        let file_upload_service_clone = file_upload_service.clone();
+        protocol_version_characteristic
+            .lock()
+            .on_write(move |args| {
+                let mut service = file_upload_service_clone.lock();
+                if let Err(e) = service.protocol_version_write(args) {
+                    service.log_error(e);
+                }
+            });
+        let file_upload_service_clone = file_upload_service.clone();
+        protocol_version_characteristic
+            .lock()
+            .on_read(move |value, _| {
+                let service = file_upload_service_clone.lock();
+                value.set_value(&[service.latest_protocol_version]);
+            });
+
+        max_protocol_version_characteristic
+            .lock()
+            .on_read(move |value, _| {
+                value.set_value(&[MAX_PROTOCOL_VERSION]);
+            });
+
+        let file_upload_service_clone = file_upload_service.clone();
+        upload_progress_characteristic
+            .lock()
+            .on_read(move |value, _| {
+                let service = file_upload_service_clone.lock();
+                value.set_value(&[service.upload_progress()]);
+            });
+
+        let file_upload_service_clone = file_upload_service.clone();
+        missing_chunks_characteristic
+            .lock()
+            .on_read(move |value, _| {
+                let service = file_upload_service_clone.lock();
+                value.set_value(&service.missing_chunks());
+            });
+
         file_upload_service
     }
 }
@@ -3,6 +3,8 @@
 use std::sync::{LazyLock, OnceLock};
 
 use cat_management_service::CatManagementService;
+use config::{get_config, migrate_wasm_guest_config, DeviceName, GroupId, WasmHostSettings};
+use diagnostics::DiagnosticsService;
 use esp32_nimble::{
     enums::{ConnMode, DiscMode, PowerLevel, PowerType},
     utilities::mutex::Mutex,
@@ -15,11 +17,14 @@ use esp_idf_hal::{
 use esp_idf_sys::{self as _, heap_caps_print_heap_info, MALLOC_CAP_DEFAULT};
 use file_upload_service::FileUploadService;
 use nrf_logging_service::SerialLoggingService;
-use rudelblinken_runtime::host::{Advertisement, Event};
+use rudelblinken_protocol::encode_manufacturer_data;
+use rudelblinken_runtime::host::{Advertisement, Event, ServiceData};
 use storage::setup_storage;
+use wasm_service::wasm_host::WasmEvent;
 
 mod cat_management_service;
 mod config;
+mod diagnostics;
 mod file_upload_service;
 mod nrf_logging_service;
 pub mod service_helpers;
@@ -45,6 +50,38 @@ fn fix_mac_address() {
     };
 }
 
+/// AD type of service data with a 16 bit UUID
+const AD_TYPE_SERVICE_DATA_16BIT_UUID: u8 = 0x16;
+
+/// Collect the service data with 16 bit UUIDs from a raw advertisement payload
+///
+/// The payload is a sequence of AD structures, each starting with its length and type
+fn parse_service_data(payload: &[u8]) -> Vec<ServiceData> {
+    let mut service_data = Vec::new();
+    let mut remaining = payload;
+    while let Some((&length, rest)) = remaining.split_first() {
+        let length = length as usize;
+        // A zero length marks the end of the significant part
+        if length == 0 || length > rest.len() {
+            break;
+        }
+        let (structure, rest) = rest.split_at(length);
+        remaining = rest;
+        let (&ad_type, content) = structure.split_first().unwrap();
+        if ad_type != AD_TYPE_SERVICE_DATA_16BIT_UUID {
+            continue;
+        }
+        let Some((uuid, data)) = content.split_first_chunk::<2>() else {
+            continue;
+        };
+        service_data.push(ServiceData {
+            uuid: u16::from_le_bytes(*uuid),
+            data: data.to_vec(),
+        });
+    }
+    service_data
+}
+
 fn setup_ble_server() -> &'static mut BLEServer {
     let ble_device = BLEDevice::take();
     BLEDevice::take();
@@ -164,9 +201,13 @@ fn main() {
 
     let file_upload_service = FileUploadService::new(ble_device.get_server());
     LazyLock::force(&LED_PIN);
-    let (sender, receiver, host) = wasm_service::wasm_host::WasmHost::new();
+    migrate_wasm_guest_config();
+    // Operators can tune the host limits in NVS without reflashing
+    let (sender, receiver, host) =
+        wasm_service::wasm_host::WasmHost::new(get_config::<WasmHostSettings>());
     let cat_management_service =
         CatManagementService::new(ble_device, file_upload_service.clone(), host);
+    DiagnosticsService::start(cat_management_service.clone(), file_upload_service.clone());
 
     {
         let ble_advertising = ble_device.get_advertising();
@@ -174,9 +215,18 @@ fn main() {
             .lock()
             .set_data(
                 BLEAdvertisementData::new()
-                    .name("Rudelblinken")
                     .add_service_uuid(FileUploadService::uuid())
-                    .manufacturer_data(&[0, 0]),
+                    .manufacturer_data(
+                        &encode_manufacturer_data(get_config::<GroupId>() as u16, 0, &[0, 0])
+                            .unwrap(),
+                    ),
+            )
+            .unwrap();
+        // The name does not fit into the advertisement next to the guest data, so it is sent in the scan response
+        ble_advertising
+            .lock()
+            .set_scan_response_data(
+                BLEAdvertisementData::new().name(get_config::<DeviceName>().as_str()),
             )
             .unwrap();
         // Configure Advertiser with Specified Data
@@ -209,26 +259,50 @@ fn main() {
     ble_scan.active_scan(false).interval(100).window(99);
 
     loop {
+        // Apply the scan parameters requested by the guest, only the latest ones matter
+        while let Ok(event) = receiver.try_recv() {
+            if let WasmEvent::ConfigureScan {
+                window,
+                interval,
+                active,
+            } = event
+            {
+                ble_scan
+                    .active_scan(active)
+                    .interval(interval)
+                    .window(window);
+            }
+        }
         tracing::info!("Scanning for BLE devices");
         task::block_on(async {
             ble_scan
                 .start(ble_device, 1000, |dev, data| {
-                    if let Some(md) = data.manufacture_data() {
-                        let now = unsafe { esp_idf_sys::esp_timer_get_time() as u64 };
-
-                        let mut padded_mac = [0u8; 8];
-                        padded_mac[0..6].copy_from_slice(&dev.addr().as_le_bytes());
-                        let mut data = [0u8; 32];
-                        let data_length = std::cmp::min(md.payload.len(), 32);
-                        data[..data_length].copy_from_slice(&md.payload[..data_length]);
-                        sender.send(Event::AdvertisementReceived(Advertisement {
-                            company: md.company_identifier,
-                            address: padded_mac,
-                            data,
-                            data_length: data_length as u8,
-                            received_at: now,
-                        }));
+                    let manufacturer_data = data.manufacture_data();
+                    let service_data = parse_service_data(data.payload());
+                    // Advertisements without anything a guest could read are not forwarded
+                    if manufacturer_data.is_none() && service_data.is_empty() {
+                        return None::<()>;
                     }
+                    let now = unsafe { esp_idf_sys::esp_timer_get_time() as u64 };
+                    let (company, payload) = manufacturer_data
+                        .map_or((0, &[][..]), |md| (md.company_identifier, md.payload));
+
+                    let mut padded_mac = [0u8; 8];
+                    padded_mac[0..6].copy_from_slice(&dev.addr().as_le_bytes());
+                    let mut data = [0u8; 32];
+                    let data_length = std::cmp::min(payload.len(), 32);
+                    data[..data_length].copy_from_slice(&payload[..data_length]);
+                    let advertisement = Advertisement {
+                        company,
+                        address: padded_mac,
+                        data,
+                        data_length: data_length as u8,
+                        rssi: dev.rssi() as i8,
+                        received_at: now,
+                        service_data,
+                    };
+                    sender.push_scan_result(advertisement.clone());
+                    sender.send(Event::AdvertisementReceived(advertisement));
                     None::<()>
                 })
                 .await
@@ -7,9 +7,11 @@ use esp_idf_sys::BLE_GATT_CHR_UNIT_UNITLESS;
 use std::{
     ffi::CStr,
     io::{self, BufRead, Read},
-    sync::Arc,
+    sync::{Arc, OnceLock},
     u8,
 };
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, Registry};
 
 // https://docs.nordicsemi.com/bundle/ncs-latest/page/nrf/libraries/bluetooth/services/nus.html#nus-service-readme
 const SERIAL_LOGGING_TIO_SERVICE: BleUuid = uuid128!("6E400001-B5A3-F393-E0A9-E50E24DCCA9E");
@@ -59,6 +61,19 @@ fn write_ble(content: &[u8]) -> usize {
     return sent_bytes;
 }
 
+/// Changes the maximum level of the tracing subscriber at runtime
+static LOG_LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+/// Set the maximum level of log messages
+///
+/// Returns false if logging was not set up yet.
+pub fn set_log_level(level: LevelFilter) -> bool {
+    let Some(handle) = LOG_LEVEL.get() else {
+        return false;
+    };
+    return handle.reload(level).is_ok();
+}
+
 static mut RX_CHARACTERISTIC: Option<Arc<Mutex<esp32_nimble::BLECharacteristic>>> = None;
 static mut TX_CHARACTERISTIC: Option<Arc<Mutex<esp32_nimble::BLECharacteristic>>> = None;
 
@@ -176,10 +191,12 @@ impl SerialLoggingService {
             ::tracing::error!(target: "panic", "{}", args);
         }));
 
-        tracing_subscriber::fmt()
-            .with_max_level(tracing::Level::INFO)
-            .with_writer(|| SerialWriter {})
+        let (level_filter, level_handle) = reload::Layer::new(LevelFilter::INFO);
+        tracing_subscriber::registry()
+            .with(level_filter)
+            .with(tracing_subscriber::fmt::layer().with_writer(|| SerialWriter {}))
             .init();
+        let _ = LOG_LEVEL.set(level_handle);
 
         serial_logging_service
     }
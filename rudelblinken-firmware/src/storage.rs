@@ -15,7 +15,7 @@ use esp_idf_sys::{
 };
 use rudelblinken_filesystem::{
     storage::{EraseStorageError, Storage, StorageError},
-    Filesystem,
+    Filesystem, FilesystemInitError,
 };
 use thiserror::Error;
 
@@ -176,12 +176,14 @@ impl Storage for FlashStorage {
 
     fn read(&self, address: u32, length: u32) -> Result<&'static [u8], StorageError> {
         // TODO: Make this actually safe
-        if (address) > Self::BLOCKS * Self::BLOCK_SIZE {
-            return Err(StorageError::AddressTooBig.into());
-        }
-        if (address + length) > Self::BLOCKS * Self::BLOCK_SIZE * 2 {
-            // TODO: Support erase with wraparound
-            return Err(StorageError::SizeTooBig.into());
+        let size = Self::BLOCKS * Self::BLOCK_SIZE;
+        // The partition is mapped twice, so reads can wrap around
+        if address >= size || length >= size {
+            return Err(StorageError::OutOfBounds {
+                address,
+                length,
+                max: size,
+            });
         }
         let thing: &[u8];
         unsafe {
@@ -196,6 +198,14 @@ impl Storage for FlashStorage {
     }
 
     fn write(&self, address: u32, data: &[u8]) -> Result<(), StorageError> {
+        let size = Self::BLOCKS * Self::BLOCK_SIZE;
+        if address >= size || data.len() >= size as usize {
+            return Err(StorageError::OutOfBounds {
+                address,
+                length: data.len() as u32,
+                max: size,
+            });
+        }
         // TODO: Make this actually safe
         let data_ptr = data.as_ptr() as *const c_void;
         ::tracing::info!(
@@ -233,12 +243,15 @@ impl Storage for FlashStorage {
         if length % Self::BLOCK_SIZE != 0 {
             return Err(EraseStorageError::CanOnlyEraseInBlockSizedChunks);
         }
-        if (address) > Self::BLOCKS * Self::BLOCK_SIZE {
-            return Err(StorageError::AddressTooBig.into());
-        }
-        if (address + length) > Self::BLOCKS * Self::BLOCK_SIZE {
+        let size = Self::BLOCKS * Self::BLOCK_SIZE;
+        if address >= size || length > size - address {
             // TODO: Support erase with wraparound
-            return Err(StorageError::SizeTooBig.into());
+            return Err(StorageError::OutOfBounds {
+                address,
+                length,
+                max: size,
+            }
+            .into());
         }
 
         unsafe {
@@ -285,12 +298,14 @@ impl Storage for FlashStorage {
 static mut STORAGE_SINGLETON: Option<FlashStorage> = None;
 static mut FILESYSTEM_SINGLETON: Option<RwLock<Filesystem<FlashStorage>>> = None;
 
-#[derive(Error, Debug, Clone)]
+#[derive(Error, Debug)]
 pub enum SetupStorageError {
     #[error("Storage is already initialized.")]
     AlreadyInitialized,
     #[error(transparent)]
     CreateStorageError(#[from] CreateStorageError),
+    #[error(transparent)]
+    FilesystemInitError(#[from] FilesystemInitError),
 }
 
 pub fn setup_storage() -> Result<(), SetupStorageError> {
@@ -301,7 +316,7 @@ pub fn setup_storage() -> Result<(), SetupStorageError> {
         STORAGE_SINGLETON = Some(FlashStorage::new()?);
         FILESYSTEM_SINGLETON = Some(RwLock::new(Filesystem::new(
             STORAGE_SINGLETON.as_ref().unwrap(),
-        )));
+        )?));
         // dbg!(&FILESYSTEM_SINGLETON.as_ref().unwrap().read().unwrap().files);
     }
     return Ok(());
@@ -4,26 +4,35 @@ use esp_idf_hal::{
         self,
         oneshot::{config::AdcChannelConfig, AdcChannelDriver, AdcDriver},
     },
+    cpu::Core,
     gpio::{self},
     ledc::{self, config::TimerConfig, LedcDriver, LedcTimerDriver},
     units::FromValueType,
 };
 use esp_idf_sys::adc_atten_t_ADC_ATTEN_DB_12;
+use rudelblinken_protocol::encode_manufacturer_data;
 use rudelblinken_runtime::{
     host::{
-        self, AdvertisementSettings, AmbientLightType, Event, Host, LedColor, LedInfo, LogLevel,
-        VibrationSensorType,
+        self, Advertisement, AdvertisementSettings, AmbientLightType, Event, Host, LedColor,
+        LedInfo, LogLevel, VibrationSensorType,
     },
-    linker::linker::WrappedCaller,
+    linker::{linker::WrappedCaller, RuntimeConfig},
+    pause::PauseHandle,
+    timer::Timers,
 };
+use serde::{Deserialize, Serialize};
 use std::{
-    sync::{Arc, LazyLock},
-    time::Duration,
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock,
+    },
+    time::{Duration, Instant},
 };
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TrySendError};
 
 use crate::{
-    config::{get_config, DeviceName, LedStripColor, WasmGuestConfig},
+    config::{get_config, DeviceName, GroupId, LedStripColor, WasmGuestConfig},
     BLE_DEVICE,
 };
 
@@ -77,22 +86,279 @@ pub static VIBRATION_SENSOR_ADC: LazyLock<
     Mutex::new(pin)
 });
 
-#[derive(Clone)]
+/// Handle of the internal temperature sensor
+struct TemperatureSensor(esp_idf_sys::temperature_sensor_handle_t);
+
+// The driver can be used from any thread, it is only accessed through the mutex
+unsafe impl Send for TemperatureSensor {}
+
+/// The internal temperature sensor of the chip. `None` if it could not be set up
+static TEMPERATURE_SENSOR: LazyLock<Mutex<Option<TemperatureSensor>>> = LazyLock::new(|| {
+    let config = esp_idf_sys::temperature_sensor_config_t {
+        range_min: -10,
+        range_max: 80,
+        clk_src:
+            esp_idf_sys::soc_periph_temperature_sensor_clk_src_t_TEMPERATURE_SENSOR_CLK_SRC_DEFAULT,
+        ..Default::default()
+    };
+    let mut handle: esp_idf_sys::temperature_sensor_handle_t = std::ptr::null_mut();
+    let result =
+        esp_idf_sys::esp!(unsafe { esp_idf_sys::temperature_sensor_install(&config, &mut handle) })
+            .and_then(|_| {
+                esp_idf_sys::esp!(unsafe { esp_idf_sys::temperature_sensor_enable(handle) })
+            });
+    if let Err(err) = result {
+        ::tracing::warn!(?err, "setting up the temperature sensor failed");
+        return Mutex::new(None);
+    }
+    Mutex::new(Some(TemperatureSensor(handle)))
+});
+
+/// The core the WASM guest runs on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CpuAffinity {
+    /// Let the scheduler pick a core
+    #[default]
+    Any,
+    /// The core that also processes BLE
+    Core0,
+    /// The second core of dual core chips like the ESP32-S3. Treated like [CpuAffinity::Any] on single core chips
+    Core1,
+}
+
+impl CpuAffinity {
+    /// The core to pin the WASM runner thread to, if any
+    pub fn core(&self) -> Option<Core> {
+        return match self {
+            CpuAffinity::Any => None,
+            CpuAffinity::Core0 => Some(Core::Core0),
+            #[cfg(any(esp32, esp32s3))]
+            CpuAffinity::Core1 => Some(Core::Core1),
+            #[cfg(not(any(esp32, esp32s3)))]
+            CpuAffinity::Core1 => {
+                ::tracing::warn!("This chip only has a single core, ignoring the CPU affinity");
+                None
+            }
+        };
+    }
+}
+
+/// Linear mapping from the ADC reading of the light sensor to lux
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AmbientLightCalibration {
+    /// Lux per ADC count
+    pub slope: f32,
+    /// Lux at an ADC reading of 0
+    pub intercept: f32,
+}
+
+impl AmbientLightCalibration {
+    /// Convert a raw ADC reading to lux. Negative values are clamped to 0
+    pub fn lux(&self, raw: u32) -> u32 {
+        return (self.slope * raw as f32 + self.intercept).max(0.0) as u32;
+    }
+}
+
+impl Default for AmbientLightCalibration {
+    /// Rough fit for the photoresistor on the rudelblinken boards. Use `rudelctl calibrate-light` for accurate values
+    fn default() -> Self {
+        Self {
+            slope: 0.25,
+            intercept: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WasmHostConfiguration {
-    reset_fuel: u32,
+    /// Fuel the guest gets every time it yields
+    pub reset_fuel: u32,
+    /// Fuel the guest gets when its main loop is started
+    pub main_loop_fuel: u32,
+    /// Fuel available to each event callback of the guest
+    pub event_callback_fuel: u32,
+    /// Maximum number of 64 KiB pages the guest memory may grow to. `None` means no limit
+    pub max_memory_pages: Option<u32>,
+    /// Default maximum execution time in milliseconds, if none is given when the guest is started. `None` lets the guest run indefinitely
+    pub max_execution_ms: Option<u64>,
+    /// Random variation of the advertisement interval in percent of the minimum interval
+    pub advertisement_jitter_percent: u8,
+    /// Time in milliseconds after which the guest is considered stuck if it did not yield. `None` disables the watchdog
+    pub watchdog_timeout_ms: Option<u64>,
+    /// Allow the host to enter light sleep while the guest is sleeping
+    pub enable_light_sleep: bool,
+    /// Minimum time in milliseconds between two restarts of advertising with new data
+    pub min_advertisement_update_interval_ms: u64,
+    /// The core the guest runs on. Only takes effect after a restart
+    pub cpu_affinity: CpuAffinity,
+    /// Converts the readings of the light sensor to lux. Only takes effect after a restart
+    pub ambient_light_calibration: AmbientLightCalibration,
+    /// Number of received BLE events that are queued for the guest. Further events are dropped until the guest handled some. Only takes effect after a restart
+    pub ble_event_queue_depth: usize,
+}
+
+impl WasmHostConfiguration {
+    pub fn builder() -> WasmHostConfigurationBuilder {
+        return WasmHostConfigurationBuilder::default();
+    }
+
+    /// The engine settings for a guest running on this host
+    pub fn runtime_config(&self) -> RuntimeConfig {
+        return RuntimeConfig {
+            max_memory_pages: self.max_memory_pages,
+            initial_fuel: self.main_loop_fuel as u64,
+            enable_fuel_metering: true,
+            enable_validation: true,
+        };
+    }
 }
 
 impl Default for WasmHostConfiguration {
     fn default() -> Self {
         Self {
             reset_fuel: 999_999,
+            main_loop_fuel: 99_999,
+            event_callback_fuel: 999_999,
+            max_memory_pages: None,
+            max_execution_ms: None,
+            advertisement_jitter_percent: 0,
+            watchdog_timeout_ms: None,
+            enable_light_sleep: false,
+            min_advertisement_update_interval_ms: 50,
+            cpu_affinity: CpuAffinity::Any,
+            ambient_light_calibration: AmbientLightCalibration::default(),
+            ble_event_queue_depth: 32,
         }
     }
 }
 
+/// Builder for a [WasmHostConfiguration]. Unset values keep their defaults
+#[derive(Clone, Debug, Default)]
+pub struct WasmHostConfigurationBuilder {
+    config: WasmHostConfiguration,
+}
+
+impl WasmHostConfigurationBuilder {
+    pub fn reset_fuel(mut self, reset_fuel: u32) -> Self {
+        self.config.reset_fuel = reset_fuel;
+        return self;
+    }
+
+    pub fn main_loop_fuel(mut self, main_loop_fuel: u32) -> Self {
+        self.config.main_loop_fuel = main_loop_fuel;
+        return self;
+    }
+
+    pub fn event_callback_fuel(mut self, event_callback_fuel: u32) -> Self {
+        self.config.event_callback_fuel = event_callback_fuel;
+        return self;
+    }
+
+    pub fn max_memory_pages(mut self, max_memory_pages: u32) -> Self {
+        self.config.max_memory_pages = Some(max_memory_pages);
+        return self;
+    }
+
+    pub fn max_execution_ms(mut self, max_execution_ms: u64) -> Self {
+        self.config.max_execution_ms = Some(max_execution_ms);
+        return self;
+    }
+
+    /// The jitter is capped at 100 percent
+    pub fn advertisement_jitter_percent(mut self, advertisement_jitter_percent: u8) -> Self {
+        self.config.advertisement_jitter_percent = advertisement_jitter_percent.min(100);
+        return self;
+    }
+
+    pub fn watchdog_timeout_ms(mut self, watchdog_timeout_ms: u64) -> Self {
+        self.config.watchdog_timeout_ms = Some(watchdog_timeout_ms);
+        return self;
+    }
+
+    pub fn enable_light_sleep(mut self, enable_light_sleep: bool) -> Self {
+        self.config.enable_light_sleep = enable_light_sleep;
+        return self;
+    }
+
+    pub fn min_advertisement_update_interval_ms(
+        mut self,
+        min_advertisement_update_interval_ms: u64,
+    ) -> Self {
+        self.config.min_advertisement_update_interval_ms = min_advertisement_update_interval_ms;
+        return self;
+    }
+
+    pub fn cpu_affinity(mut self, cpu_affinity: CpuAffinity) -> Self {
+        self.config.cpu_affinity = cpu_affinity;
+        return self;
+    }
+
+    pub fn ambient_light_calibration(
+        mut self,
+        ambient_light_calibration: AmbientLightCalibration,
+    ) -> Self {
+        self.config.ambient_light_calibration = ambient_light_calibration;
+        return self;
+    }
+
+    /// The queue holds at least one event
+    pub fn ble_event_queue_depth(mut self, ble_event_queue_depth: usize) -> Self {
+        self.config.ble_event_queue_depth = ble_event_queue_depth.max(1);
+        return self;
+    }
+
+    pub fn build(self) -> WasmHostConfiguration {
+        return self.config;
+    }
+}
+
 pub enum WasmEvent {
     SetAdvertismentSettings(AdvertisementSettings),
     SetAdvertismentData(Vec<u8>),
+    /// The guest changed the scan parameters. The scan loop applies them before the next scan. Times are in milliseconds
+    ConfigureScan {
+        window: u16,
+        interval: u16,
+        active: bool,
+    },
+}
+
+/// Number of received advertisements that are kept for `get-scan-results`. Older ones are dropped first
+const SCAN_RESULTS_CAPACITY: usize = 32;
+
+/// Queues events for the guest without blocking
+///
+/// Events are dropped if the guest does not keep up and the queue is full, so a busy BLE environment can not use up the heap.
+#[derive(Clone)]
+pub struct EventSender {
+    sender: SyncSender<Event>,
+    /// Number of events that were dropped because the queue was full. Shared with the [WasmHost]
+    dropped_events: Arc<AtomicU64>,
+    /// Received advertisements for `get-scan-results`. Shared with the [WasmHost]
+    scan_results: Arc<Mutex<VecDeque<Advertisement>>>,
+}
+
+impl EventSender {
+    /// Queue an event for the guest. Returns false if the event was dropped
+    pub fn send(&self, event: Event) -> bool {
+        return match self.sender.try_send(event) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped_events.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        };
+    }
+
+    /// Keep an advertisement until the guest fetches it, dropping the oldest one if the buffer is full
+    pub fn push_scan_result(&self, advertisement: Advertisement) {
+        let mut scan_results = self.scan_results.lock();
+        if scan_results.len() >= SCAN_RESULTS_CAPACITY {
+            scan_results.pop_front();
+        }
+        scan_results.push_back(advertisement);
+    }
 }
 
 #[derive(Clone)]
@@ -100,48 +366,148 @@ pub struct WasmHost {
     pub host_events: Arc<Mutex<Receiver<Event>>>,
     pub wasm_events: Sender<WasmEvent>,
     config: WasmHostConfiguration,
+    /// Time in microseconds since boot after which the guest is stopped at its next yield
+    ///
+    /// Shared between all clones, so the guest can be stopped from other threads
+    execution_deadline: Arc<AtomicU64>,
+    /// The advertisement data that was last passed to the BLE stack
+    last_advertisement_data: Arc<Mutex<Vec<u8>>>,
+    /// When advertising was last restarted with new data
+    last_set_time: Option<Instant>,
+    /// Sequence number of the rudelblinken advertisement that is currently sent. Incremented whenever the data changes
+    advertisement_sequence: u8,
+    /// The group ID set by the guest with `set-group-id`. The configured group ID is sent if this is `None`
+    group_id: Option<u16>,
+    /// The minimum and maximum advertisement interval that were last passed to the BLE stack
+    last_advertisement_interval: Arc<Mutex<Option<(u16, u16)>>>,
+    /// Pauses the guest at its next yield, shared between all clones
+    paused: PauseHandle,
+    /// Timers set by the guest. They fire during [Host::yield_now]
+    timers: Timers,
+    /// Number of events that were dropped, because the event queue was full
+    dropped_events: Arc<AtomicU64>,
+    /// Received advertisements that were not fetched with `get-scan-results` yet
+    scan_results: Arc<Mutex<VecDeque<Advertisement>>>,
 }
 
 impl WasmHost {
-    pub fn new() -> (Sender<Event>, Receiver<WasmEvent>, Self) {
+    pub fn new(config: WasmHostConfiguration) -> (EventSender, Receiver<WasmEvent>, Self) {
         LazyLock::force(&LED_PIN);
-        let (host_sender, host_receiver) = channel::<Event>();
+        let (host_sender, host_receiver) =
+            sync_channel::<Event>(config.ble_event_queue_depth.max(1));
         let (wasm_sender, wasm_receiver) = channel::<WasmEvent>();
+        let dropped_events = Arc::new(AtomicU64::new(0));
+        let scan_results = Arc::new(Mutex::new(VecDeque::with_capacity(SCAN_RESULTS_CAPACITY)));
         return (
-            host_sender,
+            EventSender {
+                sender: host_sender,
+                dropped_events: dropped_events.clone(),
+                scan_results: scan_results.clone(),
+            },
             wasm_receiver,
             WasmHost {
                 host_events: Arc::new(Mutex::new(host_receiver)),
                 wasm_events: wasm_sender,
-                config: WasmHostConfiguration::default(),
+                config,
+                execution_deadline: Arc::new(AtomicU64::new(u64::MAX)),
+                last_advertisement_data: Arc::new(Mutex::new(Vec::new())),
+                last_set_time: None,
+                advertisement_sequence: 0,
+                group_id: None,
+                last_advertisement_interval: Arc::new(Mutex::new(None)),
+                paused: PauseHandle::new(),
+                timers: Timers::new(),
+                dropped_events,
+                scan_results,
             },
         );
     }
+
+    /// Number of events that were dropped since boot, because the guest did not handle them fast enough
+    pub fn dropped_events(&self) -> u64 {
+        return self.dropped_events.load(Ordering::Relaxed);
+    }
+
+    pub fn configuration(&self) -> &WasmHostConfiguration {
+        return &self.config;
+    }
+
+    /// Stop the guest at its first yield after the given time has passed. `None` falls back to the configured maximum execution time
+    pub fn set_max_execution_time(&self, max_execution_time: Option<Duration>) {
+        let max_execution_time =
+            max_execution_time.or(self.config.max_execution_ms.map(Duration::from_millis));
+        let deadline = match max_execution_time {
+            Some(max_execution_time) => {
+                let now = unsafe { esp_idf_sys::esp_timer_get_time() } as u64;
+                now.saturating_add(max_execution_time.as_micros() as u64)
+            }
+            None => u64::MAX,
+        };
+        self.execution_deadline.store(deadline, Ordering::Relaxed);
+    }
+
+    /// Stop the guest at its next yield
+    pub fn stop(&self) {
+        self.execution_deadline.store(0, Ordering::Relaxed);
+    }
+
+    /// Pause the guest at its next yield, for example while flash operations lock the SPI bus
+    pub fn pause(&self) {
+        self.paused.pause();
+    }
+
+    /// Continue a paused guest
+    pub fn resume(&self) {
+        self.paused.resume();
+    }
+
+    /// Check if the guest exceeded its maximum execution time
+    pub fn deadline_passed(&self) -> bool {
+        let now = unsafe { esp_idf_sys::esp_timer_get_time() } as u64;
+        now >= self.execution_deadline.load(Ordering::Relaxed)
+    }
 }
 
 impl Host for WasmHost {
     fn yield_now(
         caller: &mut WrappedCaller<'_, Self>,
         micros: u64,
-    ) -> Result<u32, rudelblinken_runtime::Error> {
+    ) -> Result<u32, host::HostError> {
+        caller.data().paused.wait_while_paused();
         let yield_until = unsafe { esp_idf_sys::esp_timer_get_time() } as u64 + micros;
 
         loop {
             // Sleep for 1 freeRTOS tick to force yielding
             std::thread::sleep(Duration::from_millis(1));
 
+            let now = unsafe { esp_idf_sys::esp_timer_get_time() } as u64;
             loop {
-                let receiver = caller.data().host_events.lock();
-                let Ok(event) = receiver.try_recv() else {
-                    break;
+                let event = match caller.data_mut().timers.pop_expired(now) {
+                    Some(event) => event,
+                    None => {
+                        let receiver = caller.data().host_events.lock();
+                        let Ok(event) = receiver.try_recv() else {
+                            break;
+                        };
+                        event
+                    }
                 };
-                drop(receiver);
+                let event_callback_fuel = caller.data().config.event_callback_fuel;
+                caller.inner().set_fuel(event_callback_fuel as u64).unwrap();
                 match event {
                     Event::AdvertisementReceived(advertisement) => {
                         caller.on_advertisement(advertisement)?;
                     }
+                    Event::TimerFired { id, fired_at } => {
+                        caller.on_timer(id, fired_at)?;
+                    }
                 }
             }
+            if caller.data().deadline_passed() {
+                return Err(host::HostError::new(
+                    "the maximum execution time was exceeded",
+                ));
+            }
             if yield_until < unsafe { esp_idf_sys::esp_timer_get_time() } as u64 {
                 break;
             }
@@ -152,24 +518,39 @@ impl Host for WasmHost {
         Ok(reset_fuel)
     }
 
-    fn sleep(
-        _caller: &mut WrappedCaller<'_, Self>,
-        micros: u64,
-    ) -> Result<(), rudelblinken_runtime::Error> {
+    fn sleep(_caller: &mut WrappedCaller<'_, Self>, micros: u64) -> Result<(), host::HostError> {
         std::thread::sleep(Duration::from_micros(micros));
         Ok(())
     }
 
-    fn time(_caller: &mut WrappedCaller<'_, Self>) -> Result<u64, rudelblinken_runtime::Error> {
+    fn time(_caller: &mut WrappedCaller<'_, Self>) -> Result<u64, host::HostError> {
         let time = unsafe { esp_idf_sys::esp_timer_get_time() };
         Ok(time as u64)
     }
 
+    fn set_timer(
+        caller: &mut WrappedCaller<'_, Self>,
+        id: u8,
+        delay_us: u64,
+    ) -> Result<(), host::HostError> {
+        let now = unsafe { esp_idf_sys::esp_timer_get_time() } as u64;
+        caller
+            .data_mut()
+            .timers
+            .set(id, now.saturating_add(delay_us));
+        Ok(())
+    }
+
+    fn cancel_timer(caller: &mut WrappedCaller<'_, Self>, id: u8) -> Result<(), host::HostError> {
+        caller.data_mut().timers.cancel(id);
+        Ok(())
+    }
+
     fn log(
         _caller: &mut WrappedCaller<'_, Self>,
         level: LogLevel,
         message: &str,
-    ) -> Result<(), rudelblinken_runtime::Error> {
+    ) -> Result<(), host::HostError> {
         match level {
             LogLevel::Error => ::tracing::error!(target: "wasm-guest", msg = &message),
             LogLevel::Warn => ::tracing::warn!(target: "wasm-guest",msg = &message),
@@ -180,18 +561,12 @@ impl Host for WasmHost {
         Ok(())
     }
 
-    fn get_name(
-        _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<String, rudelblinken_runtime::Error> {
-        let mut name = get_config::<DeviceName>();
-        let closest = name.floor_char_boundary(16);
-        let name = name.split_off(closest);
-        Ok(name)
+    fn get_name(_caller: &mut WrappedCaller<'_, Self>) -> Result<String, host::HostError> {
+        // Device names are at most 16 bytes long
+        Ok(get_config::<DeviceName>().to_string())
     }
 
-    fn get_config(
-        _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<Vec<u8>, rudelblinken_runtime::Error> {
+    fn get_config(_caller: &mut WrappedCaller<'_, Self>) -> Result<Vec<u8>, host::HostError> {
         Ok(get_config::<WasmGuestConfig>())
     }
 
@@ -199,9 +574,10 @@ impl Host for WasmHost {
         _caller: &mut WrappedCaller<'_, Self>,
         first_id: u16,
         lux: &[u16],
-    ) -> Result<u32, rudelblinken_runtime::Error> {
+    ) -> Result<u32, host::HostError> {
         if first_id == 0 && 0 < lux.len() {
-            host::to_error_code(LED_PIN.lock().set_duty(lux[0] as u32), 1)
+            // There is only one LED, so at most one is set
+            Ok(LED_PIN.lock().set_duty(lux[0] as u32).is_ok() as u32)
         } else {
             Ok(0)
         }
@@ -211,42 +587,57 @@ impl Host for WasmHost {
         _caller: &mut WrappedCaller<'_, Self>,
         _color: &LedColor,
         lux: u32,
-    ) -> Result<u32, rudelblinken_runtime::Error> {
-        host::to_error_code(LED_PIN.lock().set_duty(lux), 1)
+    ) -> Result<u32, host::HostError> {
+        Ok(LED_PIN.lock().set_duty(lux).is_ok() as u32)
     }
 
-    fn led_count(
-        _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<u16, rudelblinken_runtime::Error> {
+    fn led_count(_caller: &mut WrappedCaller<'_, Self>) -> Result<u16, host::HostError> {
         Ok(1)
     }
 
     fn get_led_info(
         _caller: &mut WrappedCaller<'_, Self>,
         id: u16,
-    ) -> Result<LedInfo, rudelblinken_runtime::Error> {
+    ) -> Result<LedInfo, host::HostError> {
         if id == 0 {
+            let profile = get_config::<LedStripColor>();
             Ok(LedInfo {
-                color: get_config::<LedStripColor>(),
+                color: profile.color,
                 max_lux: LED_PIN.lock().get_max_duty() as u16,
+                color_temperature_k: profile.color_temperature_k,
+                wavelength_nm: profile.wavelength_nm,
+                white_balance: profile.white_balance,
             })
         } else {
             Ok(LedInfo {
                 color: LedColor::new(0, 0, 0),
                 max_lux: 0 as u16,
+                color_temperature_k: 0,
+                wavelength_nm: 0,
+                white_balance: [[0.0; 3]; 3],
             })
         }
     }
 
     fn get_ambient_light_type(
         _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<AmbientLightType, rudelblinken_runtime::Error> {
+    ) -> Result<AmbientLightType, host::HostError> {
         Ok(AmbientLightType::Basic)
     }
 
-    fn get_ambient_light(
+    fn get_ambient_light(caller: &mut WrappedCaller<'_, Self>) -> Result<u32, host::HostError> {
+        match LIGHT_SENSOR_ADC.lock().read() {
+            Ok(v) => Ok(caller.data().config.ambient_light_calibration.lux(v as u32)),
+            Err(err) => {
+                tracing::warn!(?err, "reading ambient light failed");
+                Ok(u32::MAX)
+            }
+        }
+    }
+
+    fn get_ambient_light_raw(
         _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<u32, rudelblinken_runtime::Error> {
+    ) -> Result<u32, host::HostError> {
         match LIGHT_SENSOR_ADC.lock().read() {
             Ok(v) => Ok(v as u32),
             Err(err) => {
@@ -258,13 +649,11 @@ impl Host for WasmHost {
 
     fn get_vibration_sensor_type(
         _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<VibrationSensorType, rudelblinken_runtime::Error> {
+    ) -> Result<VibrationSensorType, host::HostError> {
         Ok(VibrationSensorType::Ball)
     }
 
-    fn get_vibration(
-        _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<u32, rudelblinken_runtime::Error> {
+    fn get_vibration(_caller: &mut WrappedCaller<'_, Self>) -> Result<u32, host::HostError> {
         match VIBRATION_SENSOR_ADC.lock().read() {
             Ok(v) => Ok(v as u32),
             Err(err) => {
@@ -274,47 +663,154 @@ impl Host for WasmHost {
         }
     }
 
+    fn get_temperature(_caller: &mut WrappedCaller<'_, Self>) -> Result<i32, host::HostError> {
+        let temperature_sensor = TEMPERATURE_SENSOR.lock();
+        let Some(temperature_sensor) = temperature_sensor.as_ref() else {
+            return Ok(i32::MIN);
+        };
+        let mut celsius = 0f32;
+        match esp_idf_sys::esp!(unsafe {
+            esp_idf_sys::temperature_sensor_get_celsius(temperature_sensor.0, &mut celsius)
+        }) {
+            Ok(()) => Ok((celsius * 1000.0).round() as i32),
+            Err(err) => {
+                tracing::warn!(?err, "reading the temperature failed");
+                Ok(i32::MIN)
+            }
+        }
+    }
+
     fn configure_advertisement(
-        _caller: &mut WrappedCaller<'_, Self>,
+        caller: &mut WrappedCaller<'_, Self>,
         settings: AdvertisementSettings,
-    ) -> Result<u32, rudelblinken_runtime::Error> {
+    ) -> Result<u32, host::HostError> {
         let min_interval = settings.min_interval.clamp(400, 1000);
-        let max_interval = settings.max_interval.clamp(min_interval, 1500);
+        // Widen the interval so devices with the same settings drift apart
+        let jitter =
+            min_interval as u32 * caller.data().config.advertisement_jitter_percent as u32 / 100;
+        let max_interval = std::cmp::max(settings.max_interval, min_interval + jitter as u16)
+            .clamp(min_interval, 1500);
+        // Guests may call this every frame, only restart advertising if the interval changed
+        let interval = Some((min_interval, max_interval));
+        if *caller.data().last_advertisement_interval.lock() == interval {
+            return Ok(0);
+        }
 
         let ble_device = unsafe { BLE_DEVICE.get_mut().unwrap() };
         let mut ble_advertising = ble_device.get_advertising().lock();
         ble_advertising
             .stop()
-            .map_err(|err| rudelblinken_runtime::Error::new(format!("{:?}", err)))?;
+            .map_err(|err| host::HostError::new(format!("{:?}", err)))?;
         ble_advertising
             .min_interval(min_interval)
             .max_interval(max_interval);
         ble_advertising
             .start()
-            .map_err(|err| rudelblinken_runtime::Error::new(format!("{:?}", err)))?;
+            .map_err(|err| host::HostError::new(format!("{:?}", err)))?;
+        *caller.data().last_advertisement_interval.lock() = interval;
         Ok(0)
     }
 
     fn set_advertisement_data(
         caller: &mut WrappedCaller<'_, Self>,
         data: &[u8],
-    ) -> Result<u32, rudelblinken_runtime::Error> {
+    ) -> Result<u32, host::HostError> {
+        // Restarting advertising is expensive, so skip updates that do not change anything
+        if *caller.data().last_advertisement_data.lock() == data {
+            return Ok(0);
+        }
+        // Changes within the interval are dropped, guests that update their data periodically get it applied by a later call
+        let min_interval =
+            Duration::from_millis(caller.data().config.min_advertisement_update_interval_ms);
+        if caller
+            .data()
+            .last_set_time
+            .is_some_and(|last_set_time| last_set_time.elapsed() < min_interval)
+        {
+            return Ok(0);
+        }
+        caller.data_mut().last_set_time = Some(Instant::now());
+        let sequence = caller.data().advertisement_sequence.wrapping_add(1);
+        let group_id = Self::get_group_id(caller)?;
+        let Some(manufacturer_data) = encode_manufacturer_data(group_id, sequence, data) else {
+            return Ok(1);
+        };
+
         let ble_device = unsafe { BLE_DEVICE.get_mut().unwrap() };
         let mut ble_advertising = ble_device.get_advertising().lock();
         ble_advertising
             .stop()
-            .map_err(|err| rudelblinken_runtime::Error::new(format!("{:?}", err)))?;
-        if let Err(_) = ble_advertising.set_data(
-            BLEAdvertisementData::new()
-                .name(&Host::get_name(caller)?)
-                .manufacturer_data(&data),
-        ) {
+            .map_err(|err| host::HostError::new(format!("{:?}", err)))?;
+        // The advertisement only contains the guest data, the name is sent in the scan response
+        if let Err(_) = ble_advertising
+            .set_data(BLEAdvertisementData::new().manufacturer_data(&manufacturer_data))
+        {
+            return Ok(1);
+        }
+        if let Err(_) = ble_advertising
+            .set_scan_response_data(BLEAdvertisementData::new().name(&Host::get_name(caller)?))
+        {
             return Ok(1);
         }
         ble_advertising
             .start()
-            .map_err(|err| rudelblinken_runtime::Error::new(format!("{:?}", err)))?;
+            .map_err(|err| host::HostError::new(format!("{:?}", err)))?;
+        *caller.data().last_advertisement_data.lock() = data.to_vec();
+        caller.data_mut().advertisement_sequence = sequence;
+
+        Ok(0)
+    }
 
+    fn get_connected_devices(
+        _caller: &mut WrappedCaller<'_, Self>,
+    ) -> Result<Vec<u64>, host::HostError> {
+        let ble_device = unsafe { BLE_DEVICE.get_mut().unwrap() };
+        let server = ble_device.get_server();
+        let mut devices = Vec::with_capacity(server.connected_count());
+        for connection in server.connections() {
+            // Same layout as the address of received advertisements
+            let mut padded_mac = [0u8; 8];
+            padded_mac[0..6].copy_from_slice(&connection.address().as_le_bytes());
+            devices.push(u64::from_le_bytes(padded_mac));
+        }
+        Ok(devices)
+    }
+
+    fn configure_scan(
+        caller: &mut WrappedCaller<'_, Self>,
+        window: u16,
+        interval: u16,
+        active: bool,
+    ) -> Result<u32, host::HostError> {
+        // The scan is owned by the scan loop in main, which restarts it every second
+        let event = WasmEvent::ConfigureScan {
+            window: std::cmp::min(window, interval),
+            interval,
+            active,
+        };
+        if caller.data().wasm_events.send(event).is_err() {
+            return Ok(1);
+        }
         Ok(0)
     }
+
+    fn get_scan_results(
+        caller: &mut WrappedCaller<'_, Self>,
+    ) -> Result<Vec<Advertisement>, host::HostError> {
+        Ok(caller.data().scan_results.lock().drain(..).collect())
+    }
+
+    fn set_group_id(caller: &mut WrappedCaller<'_, Self>, id: u16) -> Result<(), host::HostError> {
+        caller.data_mut().group_id = Some(id);
+        // Makes the next call to set-advertisement-data restart advertising, even if the data did not change
+        caller.data().last_advertisement_data.lock().clear();
+        Ok(())
+    }
+
+    fn get_group_id(caller: &mut WrappedCaller<'_, Self>) -> Result<u16, host::HostError> {
+        Ok(caller
+            .data()
+            .group_id
+            .unwrap_or(get_config::<GroupId>() as u16))
+    }
 }
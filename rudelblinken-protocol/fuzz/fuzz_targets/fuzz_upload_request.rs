@@ -0,0 +1,14 @@
+//! Parse arbitrary bytes as writes to the file upload service
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rudelblinken_protocol::upload::{DataChunk, ZstdFrameHeader};
+
+fuzz_target!(|data: &[u8]| {
+    if let Some(chunk) = DataChunk::parse(data) {
+        assert_eq!(chunk.data.len() + 2, data.len());
+    }
+    if let Ok(Some(header)) = ZstdFrameHeader::parse(data) {
+        assert!(header.length <= data.len());
+    }
+});
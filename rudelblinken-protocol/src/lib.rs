@@ -0,0 +1,213 @@
+//! # Rudelblinken Protocol
+//!
+//! Wire format of the manufacturer data in the BLE advertisements of rudelblinken devices.
+//!
+//! Hosts put a [RudelblinkenAdvertisement] after the company ID of every advertisement they send. The data set by the guest becomes the user data, so guests do not need to mark their advertisements as rudelblinken advertisements themselves.
+//!
+//! | Offset | Length | Field                                |
+//! | ------ | ------ | ------------------------------------ |
+//! | 0      | 3      | Magic bytes `ca 7e a2`               |
+//! | 3      | 1      | Version of the format, currently `1` |
+//! | 4      | 2      | Group ID, little endian              |
+//! | 6      | 1      | Sequence number                      |
+//! | 7      | 1      | Length of the user data              |
+//! | 8      | 0-16   | User data                            |
+//!
+//! Only the used part of the user data is sent.
+//!
+//! The [upload] module describes the writes to the file upload service.
+//!
+//! ```rust
+//! use rudelblinken_protocol::RudelblinkenAdvertisement;
+//!
+//! let advertisement = RudelblinkenAdvertisement::new(7, 1, b"hello").unwrap();
+//! let parsed = RudelblinkenAdvertisement::parse(advertisement.encode()).unwrap();
+//! assert_eq!(parsed.group_id(), 7);
+//! assert_eq!(parsed.user_data(), b"hello");
+//! ```
+#![no_std]
+
+extern crate alloc;
+
+pub mod upload;
+
+use alloc::vec::Vec;
+use zerocopy::{little_endian, FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
+
+/// Marks an advertisement as a rudelblinken advertisement
+pub const MAGIC: [u8; 3] = [0xca, 0x7e, 0xa2];
+/// Version of the format that is described by [RudelblinkenAdvertisement]
+pub const VERSION: u8 = 1;
+/// Maximum length of the user data
+///
+/// A legacy advertisement has room for 24 bytes of manufacturer data after the company ID, the header takes 8 of them.
+pub const USER_DATA_CAPACITY: usize = 16;
+/// Length of the header before the user data
+pub const HEADER_LENGTH: usize = size_of::<RudelblinkenAdvertisement>() - USER_DATA_CAPACITY;
+
+/// The manufacturer data of a rudelblinken advertisement, without the company ID
+#[repr(C)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned,
+)]
+pub struct RudelblinkenAdvertisement {
+    /// Always [MAGIC]
+    pub magic: [u8; 3],
+    /// Always [VERSION]
+    pub version: u8,
+    /// Group of the sender
+    pub group_id: little_endian::U16,
+    /// Incremented by the sender whenever the user data changes, so receivers can tell new data from a repeated advertisement
+    pub sequence: u8,
+    /// How many of the user data bytes are used
+    pub user_data_length: u8,
+    /// Data set by the guest, zero padded
+    pub user_data: [u8; USER_DATA_CAPACITY],
+}
+
+impl RudelblinkenAdvertisement {
+    /// Create an advertisement. Returns `None` if the user data is longer than [USER_DATA_CAPACITY].
+    pub fn new(group_id: u16, sequence: u8, user_data: &[u8]) -> Option<Self> {
+        if user_data.len() > USER_DATA_CAPACITY {
+            return None;
+        }
+        let mut padded_user_data = [0u8; USER_DATA_CAPACITY];
+        padded_user_data[..user_data.len()].copy_from_slice(user_data);
+        Some(RudelblinkenAdvertisement {
+            magic: MAGIC,
+            version: VERSION,
+            group_id: group_id.into(),
+            sequence,
+            user_data_length: user_data.len() as u8,
+            user_data: padded_user_data,
+        })
+    }
+
+    /// Parse manufacturer data without the company ID. Returns `None` if it is not a rudelblinken advertisement of the current version.
+    ///
+    /// Bytes after the user data are ignored.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_LENGTH {
+            return None;
+        }
+        // The unused part of the user data is not sent
+        let mut bytes = [0u8; size_of::<RudelblinkenAdvertisement>()];
+        let length = core::cmp::min(data.len(), bytes.len());
+        bytes[..length].copy_from_slice(&data[..length]);
+        let mut advertisement = RudelblinkenAdvertisement::read_from_bytes(&bytes).ok()?;
+        if advertisement.magic != MAGIC || advertisement.version != VERSION {
+            return None;
+        }
+        let user_data_length = advertisement.user_data_length as usize;
+        if user_data_length > USER_DATA_CAPACITY || HEADER_LENGTH + user_data_length > data.len() {
+            return None;
+        }
+        // Bytes after the user data are not part of the advertisement
+        advertisement.user_data[user_data_length..].fill(0);
+        Some(advertisement)
+    }
+
+    /// Encode as manufacturer data without the company ID. The unused part of the user data is left out.
+    pub fn encode(&self) -> &[u8] {
+        let length = HEADER_LENGTH + self.user_data().len();
+        &self.as_bytes()[..length]
+    }
+
+    /// Group of the sender
+    pub fn group_id(&self) -> u16 {
+        self.group_id.get()
+    }
+
+    /// The used part of the user data
+    pub fn user_data(&self) -> &[u8] {
+        let length = core::cmp::min(self.user_data_length as usize, USER_DATA_CAPACITY);
+        &self.user_data[..length]
+    }
+}
+
+/// Wrap manufacturer data set by a guest in a rudelblinken advertisement. The company ID stays in front of the header
+///
+/// Data that is shorter than a company ID is treated as user data of company `0`. Returns `None` if the data does not fit into the user data.
+pub fn encode_manufacturer_data(group_id: u16, sequence: u8, data: &[u8]) -> Option<Vec<u8>> {
+    let (company, user_data) = data.split_first_chunk::<2>().unwrap_or((&[0, 0], data));
+    let advertisement = RudelblinkenAdvertisement::new(group_id, sequence, user_data)?;
+    let mut manufacturer_data = company.to_vec();
+    manufacturer_data.extend_from_slice(advertisement.encode());
+    Some(manufacturer_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_header_has_the_documented_layout() {
+        let advertisement = RudelblinkenAdvertisement::new(0x1234, 5, &[0xaa, 0xbb]).unwrap();
+        assert_eq!(
+            advertisement.encode(),
+            &[0xca, 0x7e, 0xa2, 0x01, 0x34, 0x12, 0x05, 0x02, 0xaa, 0xbb]
+        );
+        assert_eq!(HEADER_LENGTH, 8);
+    }
+
+    #[test]
+    fn encoded_advertisements_can_be_parsed() {
+        let advertisement = RudelblinkenAdvertisement::new(3, 200, b"0123456789abcdef").unwrap();
+        let parsed = RudelblinkenAdvertisement::parse(advertisement.encode()).unwrap();
+        assert_eq!(parsed, advertisement);
+        assert_eq!(parsed.sequence, 200);
+        assert_eq!(parsed.user_data(), b"0123456789abcdef");
+    }
+
+    #[test]
+    fn user_data_that_does_not_fit_is_rejected() {
+        assert!(RudelblinkenAdvertisement::new(0, 0, &[0; USER_DATA_CAPACITY + 1]).is_none());
+    }
+
+    #[test]
+    fn other_advertisements_are_not_parsed() {
+        assert!(RudelblinkenAdvertisement::parse(&[]).is_none());
+        assert!(RudelblinkenAdvertisement::parse(&[0xca, 0x7e, 0xa2]).is_none());
+        assert!(RudelblinkenAdvertisement::parse(&[0xca, 0x7e, 0xa3, 1, 0, 0, 0, 0]).is_none());
+        // Unknown version
+        assert!(RudelblinkenAdvertisement::parse(&[0xca, 0x7e, 0xa2, 2, 0, 0, 0, 0]).is_none());
+        // The user data is shorter than announced
+        assert!(
+            RudelblinkenAdvertisement::parse(&[0xca, 0x7e, 0xa2, 1, 0, 0, 0, 3, 1, 2]).is_none()
+        );
+    }
+
+    #[test]
+    fn trailing_bytes_are_ignored() {
+        let parsed =
+            RudelblinkenAdvertisement::parse(&[0xca, 0x7e, 0xa2, 1, 7, 0, 1, 1, 0x42, 0xff, 0xff])
+                .unwrap();
+        assert_eq!(parsed.group_id(), 7);
+        assert_eq!(parsed.user_data(), &[0x42]);
+        assert_eq!(parsed.encode().len(), HEADER_LENGTH + 1);
+    }
+
+    #[test]
+    fn encoded_manufacturer_data_keeps_the_company_in_front() {
+        let manufacturer_data = encode_manufacturer_data(0x0102, 9, &[0x34, 0x12, 0xaa]).unwrap();
+        assert_eq!(&manufacturer_data[..2], &[0x34, 0x12]);
+        let parsed = RudelblinkenAdvertisement::parse(&manufacturer_data[2..]).unwrap();
+        assert_eq!(parsed.group_id(), 0x0102);
+        assert_eq!(parsed.sequence, 9);
+        assert_eq!(parsed.user_data(), &[0xaa]);
+    }
+
+    #[test]
+    fn manufacturer_data_without_a_company_gets_company_zero() {
+        let manufacturer_data = encode_manufacturer_data(1, 0, &[0x42]).unwrap();
+        assert_eq!(&manufacturer_data[..2], &[0, 0]);
+        let parsed = RudelblinkenAdvertisement::parse(&manufacturer_data[2..]).unwrap();
+        assert_eq!(parsed.user_data(), &[0x42]);
+    }
+
+    #[test]
+    fn manufacturer_data_that_does_not_fit_is_rejected() {
+        assert!(encode_manufacturer_data(0, 0, &[0; 2 + USER_DATA_CAPACITY]).is_some());
+        assert!(encode_manufacturer_data(0, 0, &[0; 2 + USER_DATA_CAPACITY + 1]).is_none());
+    }
+}
@@ -0,0 +1,151 @@
+//! Wire format of the writes to the file upload service
+//!
+//! All values are little endian. Writes to the data characteristic start with the index of the chunk:
+//!
+//! | Offset | Length | Field                   |
+//! | ------ | ------ | ----------------------- |
+//! | 0      | 2      | Chunk index             |
+//! | 2      | 1-     | Content of the chunk    |
+//!
+//! Compressed uploads are a single zstd frame. The device parses the frame header with [ZstdFrameHeader] to reject frames that it can not decode before it starts decompressing.
+
+/// Magic number at the start of every zstd frame
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// A write to the data characteristic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataChunk<'a> {
+    /// Index of the chunk in the upload
+    pub index: u16,
+    /// Content of the chunk
+    pub data: &'a [u8],
+}
+
+impl<'a> DataChunk<'a> {
+    /// Parse a write to the data characteristic. Returns `None` if it does not contain at least one byte of content
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        let (index, data) = bytes.split_first_chunk::<2>()?;
+        if data.is_empty() {
+            return None;
+        }
+        Some(DataChunk {
+            index: u16::from_le_bytes(*index),
+            data,
+        })
+    }
+}
+
+/// The data does not start with [ZSTD_MAGIC]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotAZstdFrame;
+
+/// The parts of a zstd frame header that need to be checked before decoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZstdFrameHeader {
+    /// Length of the header, including the magic number
+    pub length: usize,
+    /// Memory the decoder needs to keep decompressed content around
+    pub window_size: u64,
+    /// Length of the decompressed content, if the frame specifies it
+    pub content_size: Option<u64>,
+}
+
+impl ZstdFrameHeader {
+    /// Parse the header at the start of a zstd frame. Returns `Ok(None)` if the header is not complete yet
+    pub fn parse(data: &[u8]) -> Result<Option<Self>, NotAZstdFrame> {
+        if data.len() < ZSTD_MAGIC.len() + 1 {
+            return Ok(None);
+        }
+        if data[..ZSTD_MAGIC.len()] != ZSTD_MAGIC {
+            return Err(NotAZstdFrame);
+        }
+        let descriptor = data[ZSTD_MAGIC.len()];
+        let single_segment = descriptor & 0b0010_0000 != 0;
+        let dictionary_id_length = [0, 1, 2, 4][(descriptor & 0b11) as usize];
+        let content_size_length = match descriptor >> 6 {
+            0 if single_segment => 1,
+            0 => 0,
+            1 => 2,
+            2 => 4,
+            _ => 8,
+        };
+        let window_descriptor_offset = ZSTD_MAGIC.len() + 1;
+        let content_size_offset =
+            window_descriptor_offset + (!single_segment) as usize + dictionary_id_length;
+        let length = content_size_offset + content_size_length;
+        if data.len() < length {
+            return Ok(None);
+        }
+
+        let content_size = (content_size_length != 0).then(|| {
+            let mut bytes = [0u8; 8];
+            bytes[..content_size_length].copy_from_slice(&data[content_size_offset..length]);
+            // Two byte sizes are stored with an offset of 256
+            u64::from_le_bytes(bytes) + if content_size_length == 2 { 256 } else { 0 }
+        });
+        let window_size = if single_segment {
+            content_size.unwrap_or(0)
+        } else {
+            let window_descriptor = data[window_descriptor_offset];
+            let window_base = 1u64 << (10 + (window_descriptor >> 3));
+            window_base + (window_base / 8) * (window_descriptor & 0b111) as u64
+        };
+        Ok(Some(ZstdFrameHeader {
+            length,
+            window_size,
+            content_size,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_chunks_start_with_the_index() {
+        let chunk = DataChunk::parse(&[0x34, 0x12, 1, 2, 3]).unwrap();
+        assert_eq!(chunk.index, 0x1234);
+        assert_eq!(chunk.data, &[1, 2, 3]);
+        assert!(DataChunk::parse(&[]).is_none());
+        assert!(DataChunk::parse(&[0, 0]).is_none());
+    }
+
+    #[test]
+    fn incomplete_zstd_headers_need_more_data() {
+        assert_eq!(ZstdFrameHeader::parse(&[]), Ok(None));
+        assert_eq!(ZstdFrameHeader::parse(&ZSTD_MAGIC), Ok(None));
+        // A four byte content size is announced, but only two bytes are there
+        assert_eq!(
+            ZstdFrameHeader::parse(&[0x28, 0xb5, 0x2f, 0xfd, 0b1010_0000, 1, 2]),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn other_data_is_not_a_zstd_frame() {
+        assert_eq!(ZstdFrameHeader::parse(&[0, 0, 0, 0, 0]), Err(NotAZstdFrame));
+    }
+
+    #[test]
+    fn single_segment_frames_use_the_content_size_as_window() {
+        let header =
+            ZstdFrameHeader::parse(&[0x28, 0xb5, 0x2f, 0xfd, 0b0110_0000, 0x00, 0x01, 0xff])
+                .unwrap()
+                .unwrap();
+        assert_eq!(header.length, 7);
+        assert_eq!(header.content_size, Some(256 + 256));
+        assert_eq!(header.window_size, 512);
+    }
+
+    #[test]
+    fn the_window_descriptor_is_decoded() {
+        // Exponent 5 and mantissa 2 without a content size
+        let header = ZstdFrameHeader::parse(&[0x28, 0xb5, 0x2f, 0xfd, 0, 0b0010_1010])
+            .unwrap()
+            .unwrap();
+        assert_eq!(header.length, 6);
+        assert_eq!(header.content_size, None);
+        assert_eq!(header.window_size, (1 << 15) + 2 * (1 << 15) / 8);
+    }
+}
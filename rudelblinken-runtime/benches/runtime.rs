@@ -0,0 +1,275 @@
+//! Benchmarks for the overhead of calls across the WASM/host boundary
+//!
+//! The guests are small hand written modules, so the measurements only contain the cost of the
+//! interpreter and the glue code. The host is a [MockHost] that does nothing besides counting calls.
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rudelblinken_runtime::{
+    host::{
+        Advertisement, AdvertisementSettings, AmbientLightType, Host, LedColor, LedInfo, LogLevel,
+        VibrationSensorType,
+    },
+    linker::{linker::WrappedCaller, setup},
+};
+
+/// Error message used by the [MockHost] to stop the guest after the requested number of calls
+const FINISHED: &str = "benchmark finished";
+
+/// Guest that calls `yield-now` in an endless loop
+///
+/// It also exports an empty `on-advertisement` handler.
+const YIELD_GUEST: &str = r#"
+(module
+  (import "rudel:base/base@0.0.1" "yield-now" (func $yield_now (param i64) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "rudel:base/run@0.0.1#run")
+    (loop $loop
+      (drop (call $yield_now (i64.const 0)))
+      (br $loop)))
+  (func (export "rudel:base/ble-guest@0.0.1#on-advertisement")
+    (param i64 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i64)))
+"#;
+
+/// Guest that sets 16 LEDs in an endless loop
+const SET_LEDS_GUEST: &str = r#"
+(module
+  (import "rudel:base/hardware@0.0.1" "set-leds" (func $set_leds (param i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\ff\00\ff\00\ff\00\ff\00\ff\00\ff\00\ff\00\ff\00\ff\00\ff\00\ff\00\ff\00\ff\00\ff\00\ff\00\ff\00")
+  (func (export "rudel:base/run@0.0.1#run")
+    (loop $loop
+      (drop (call $set_leds (i32.const 0) (i32.const 0) (i32.const 16)))
+      (br $loop))))
+"#;
+
+/// A host that stops the guest after a fixed number of calls
+struct MockHost {
+    remaining_calls: u64,
+    /// Advertisement that gets delivered on every yield
+    advertisement: Option<Advertisement>,
+}
+
+impl MockHost {
+    fn new(calls: u64, advertisement: Option<Advertisement>) -> Self {
+        return MockHost {
+            remaining_calls: calls,
+            advertisement,
+        };
+    }
+
+    /// Count a call and refill the fuel, so the guest does not get killed
+    fn count_call(caller: &mut WrappedCaller<'_, Self>) -> Result<(), wasmi::Error> {
+        if caller.data().remaining_calls == 0 {
+            return Err(wasmi::Error::new(FINISHED));
+        }
+        caller.data_mut().remaining_calls -= 1;
+        caller.inner().set_fuel(u64::MAX).unwrap();
+        return Ok(());
+    }
+}
+
+impl Host for MockHost {
+    fn yield_now(caller: &mut WrappedCaller<'_, Self>, _micros: u64) -> Result<u32, wasmi::Error> {
+        Self::count_call(caller)?;
+        if let Some(advertisement) = caller.data().advertisement.clone() {
+            caller.on_advertisement(advertisement)?;
+        }
+        return Ok(0);
+    }
+
+    fn sleep(_caller: &mut WrappedCaller<'_, Self>, _micros: u64) -> Result<(), wasmi::Error> {
+        return Ok(());
+    }
+
+    fn time(_caller: &mut WrappedCaller<'_, Self>) -> Result<u64, wasmi::Error> {
+        return Ok(0);
+    }
+
+    fn set_timer(
+        _caller: &mut WrappedCaller<'_, Self>,
+        _id: u8,
+        _delay_us: u64,
+    ) -> Result<(), wasmi::Error> {
+        return Ok(());
+    }
+
+    fn cancel_timer(_caller: &mut WrappedCaller<'_, Self>, _id: u8) -> Result<(), wasmi::Error> {
+        return Ok(());
+    }
+
+    fn log(
+        _caller: &mut WrappedCaller<'_, Self>,
+        _level: LogLevel,
+        _message: &str,
+    ) -> Result<(), wasmi::Error> {
+        return Ok(());
+    }
+
+    fn get_name(_caller: &mut WrappedCaller<'_, Self>) -> Result<String, wasmi::Error> {
+        return Ok("MockHost".to_string());
+    }
+
+    fn get_config(_caller: &mut WrappedCaller<'_, Self>) -> Result<Vec<u8>, wasmi::Error> {
+        return Ok(vec![]);
+    }
+
+    fn set_leds(
+        caller: &mut WrappedCaller<'_, Self>,
+        _first_id: u16,
+        _lux: &[u16],
+    ) -> Result<u32, wasmi::Error> {
+        Self::count_call(caller)?;
+        return Ok(0);
+    }
+
+    fn set_rgb(
+        caller: &mut WrappedCaller<'_, Self>,
+        _color: &LedColor,
+        _lux: u32,
+    ) -> Result<u32, wasmi::Error> {
+        return Self::led_count(caller).map(u32::from);
+    }
+
+    fn led_count(_caller: &mut WrappedCaller<'_, Self>) -> Result<u16, wasmi::Error> {
+        return Ok(16);
+    }
+
+    fn get_led_info(
+        _caller: &mut WrappedCaller<'_, Self>,
+        _id: u16,
+    ) -> Result<LedInfo, wasmi::Error> {
+        return Ok(LedInfo {
+            color: LedColor::new(0, 0, 0),
+            max_lux: 0,
+            color_temperature_k: 0,
+            wavelength_nm: 0,
+            white_balance: LedInfo::IDENTITY_WHITE_BALANCE,
+        });
+    }
+
+    fn get_ambient_light_type(
+        _caller: &mut WrappedCaller<'_, Self>,
+    ) -> Result<AmbientLightType, wasmi::Error> {
+        return Ok(AmbientLightType::None);
+    }
+
+    fn get_ambient_light(_caller: &mut WrappedCaller<'_, Self>) -> Result<u32, wasmi::Error> {
+        return Ok(0);
+    }
+
+    fn get_ambient_light_raw(_caller: &mut WrappedCaller<'_, Self>) -> Result<u32, wasmi::Error> {
+        return Ok(0);
+    }
+
+    fn get_vibration_sensor_type(
+        _caller: &mut WrappedCaller<'_, Self>,
+    ) -> Result<VibrationSensorType, wasmi::Error> {
+        return Ok(VibrationSensorType::None);
+    }
+
+    fn get_vibration(_caller: &mut WrappedCaller<'_, Self>) -> Result<u32, wasmi::Error> {
+        return Ok(0);
+    }
+
+    fn get_temperature(_caller: &mut WrappedCaller<'_, Self>) -> Result<i32, wasmi::Error> {
+        return Ok(0);
+    }
+
+    fn configure_advertisement(
+        _caller: &mut WrappedCaller<'_, Self>,
+        _settings: AdvertisementSettings,
+    ) -> Result<u32, wasmi::Error> {
+        return Ok(0);
+    }
+
+    fn set_advertisement_data(
+        _caller: &mut WrappedCaller<'_, Self>,
+        _data: &[u8],
+    ) -> Result<u32, wasmi::Error> {
+        return Ok(0);
+    }
+
+    fn get_connected_devices(
+        _caller: &mut WrappedCaller<'_, Self>,
+    ) -> Result<Vec<u64>, wasmi::Error> {
+        return Ok(Vec::new());
+    }
+
+    fn configure_scan(
+        _caller: &mut WrappedCaller<'_, Self>,
+        _window: u16,
+        _interval: u16,
+        _active: bool,
+    ) -> Result<u32, wasmi::Error> {
+        return Ok(0);
+    }
+
+    fn get_scan_results(
+        _caller: &mut WrappedCaller<'_, Self>,
+    ) -> Result<Vec<Advertisement>, wasmi::Error> {
+        return Ok(Vec::new());
+    }
+
+    fn set_group_id(_caller: &mut WrappedCaller<'_, Self>, _id: u16) -> Result<(), wasmi::Error> {
+        return Ok(());
+    }
+
+    fn get_group_id(_caller: &mut WrappedCaller<'_, Self>) -> Result<u16, wasmi::Error> {
+        return Ok(0);
+    }
+}
+
+/// Run the guest until the host has seen `calls` calls and return the time it took
+///
+/// Parsing and instantiating the module is not included in the measurement.
+fn run_guest(wasm: &[u8], calls: u64, advertisement: Option<Advertisement>) -> Duration {
+    let host = MockHost::new(calls, advertisement);
+    let mut instance = setup(wasm, host).unwrap();
+    let start = Instant::now();
+    let error = instance.run().unwrap_err();
+    let elapsed = start.elapsed();
+    assert!(
+        error.to_string().contains(FINISHED),
+        "Guest stopped unexpectedly: {}",
+        error
+    );
+    return elapsed;
+}
+
+fn yield_now(c: &mut Criterion) {
+    let wasm = wat::parse_str(YIELD_GUEST).unwrap();
+    c.bench_function("yield_now", |b| {
+        b.iter_custom(|iters| run_guest(&wasm, iters, None));
+    });
+}
+
+fn on_advertisement(c: &mut Criterion) {
+    let wasm = wat::parse_str(YIELD_GUEST).unwrap();
+    let advertisement = Advertisement {
+        company: 0x0059,
+        address: [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0, 0],
+        data: [0xaa; 32],
+        data_length: 32,
+        rssi: -60,
+        received_at: 0,
+        service_data: Vec::new(),
+    };
+    // Every iteration contains one yield and one dispatch, so subtract the yield_now result to get the dispatch overhead
+    c.bench_function("yield_now_with_advertisement", |b| {
+        b.iter_custom(|iters| run_guest(&wasm, iters, Some(advertisement.clone())));
+    });
+}
+
+fn set_leds(c: &mut Criterion) {
+    let wasm = wat::parse_str(SET_LEDS_GUEST).unwrap();
+    let mut group = c.benchmark_group("set_leds");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("16_leds", |b| {
+        b.iter_custom(|iters| run_guest(&wasm, iters, None));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, yield_now, on_advertisement, set_leds);
+criterion_main!(benches);
@@ -5,15 +5,27 @@ use std::{
 
 use crate::{
     host::{
-        AdvertisementSettings, AmbientLightType, Event, Host, LedColor, LedInfo, LogLevel,
-        VibrationSensorType,
+        Advertisement, AdvertisementSettings, AmbientLightType, Event, Host, LedColor, LedInfo,
+        LogLevel, VibrationSensorType,
     },
     linker::linker::WrappedCaller,
+    pause::PauseHandle,
+    timer::Timers,
 };
 
 pub struct EmulatedHost {
     pub start_time: Instant,
     pub events: Receiver<Event>,
+    /// Pauses the guest at its next yield
+    pub paused: PauseHandle,
+    /// Timers set by the guest
+    pub timers: Timers,
+    /// Advertisements that are returned by the next call to `get-scan-results`
+    pub scan_results: Vec<Advertisement>,
+    /// The group ID set by the guest with `set-group-id`
+    pub group_id: Option<u16>,
+    /// Configuration returned by `get-config`
+    pub config: Vec<u8>,
 }
 
 impl EmulatedHost {
@@ -24,6 +36,11 @@ impl EmulatedHost {
             EmulatedHost {
                 start_time: Instant::now(),
                 events: receiver,
+                paused: PauseHandle::new(),
+                timers: Timers::new(),
+                scan_results: Vec::new(),
+                group_id: None,
+                config: Vec::new(),
             },
         );
     }
@@ -31,6 +48,7 @@ impl EmulatedHost {
 
 impl Host for EmulatedHost {
     fn yield_now(caller: &mut WrappedCaller<'_, Self>, micros: u64) -> Result<u32, wasmi::Error> {
+        caller.data().paused.wait_while_paused();
         //YIELD here
         // callbacks = return Ok(());
         // todo!();
@@ -39,10 +57,20 @@ impl Host for EmulatedHost {
                 Event::AdvertisementReceived(advertisement) => {
                     caller.on_advertisement(advertisement)?;
                 }
+                Event::TimerFired { id, fired_at } => {
+                    caller.on_timer(id, fired_at)?;
+                }
             }
             // Self::log(caller, LogLevel::Warn, "Got something").unwrap();
         }
-        caller.inner().set_fuel(999_999).unwrap();
+        let now = caller.data().start_time.elapsed().as_micros() as u64;
+        while let Some(Event::TimerFired { id, fired_at }) =
+            caller.data_mut().timers.pop_expired(now)
+        {
+            caller.on_timer(id, fired_at)?;
+        }
+        // Fails if fuel metering is disabled, then there is nothing to refill
+        let _ = caller.inner().set_fuel(999_999);
         return Ok(999_999);
     }
 
@@ -55,6 +83,24 @@ impl Host for EmulatedHost {
         return Ok(caller.data().start_time.elapsed().as_micros() as u64);
     }
 
+    fn set_timer(
+        caller: &mut WrappedCaller<'_, Self>,
+        id: u8,
+        delay_us: u64,
+    ) -> Result<(), wasmi::Error> {
+        let now = caller.data().start_time.elapsed().as_micros() as u64;
+        caller
+            .data_mut()
+            .timers
+            .set(id, now.saturating_add(delay_us));
+        return Ok(());
+    }
+
+    fn cancel_timer(caller: &mut WrappedCaller<'_, Self>, id: u8) -> Result<(), wasmi::Error> {
+        caller.data_mut().timers.cancel(id);
+        return Ok(());
+    }
+
     fn log(
         _caller: &mut WrappedCaller<'_, Self>,
         level: LogLevel,
@@ -68,8 +114,8 @@ impl Host for EmulatedHost {
         return Ok("EmulatedHost".to_string());
     }
 
-    fn get_config(_caller: &mut WrappedCaller<'_, Self>) -> Result<Vec<u8>, wasmi::Error> {
-        return Ok(vec![]);
+    fn get_config(caller: &mut WrappedCaller<'_, Self>) -> Result<Vec<u8>, wasmi::Error> {
+        return Ok(caller.data().config.clone());
     }
 
     fn set_leds(
@@ -81,11 +127,12 @@ impl Host for EmulatedHost {
     }
 
     fn set_rgb(
-        _caller: &mut WrappedCaller<'_, Self>,
+        caller: &mut WrappedCaller<'_, Self>,
         _color: &crate::host::LedColor,
         _lux: u32,
     ) -> Result<u32, wasmi::Error> {
-        return Ok(0);
+        // All LEDs are set
+        return Self::led_count(caller).map(u32::from);
     }
 
     fn led_count(_caller: &mut WrappedCaller<'_, Self>) -> Result<u16, wasmi::Error> {
@@ -99,6 +146,9 @@ impl Host for EmulatedHost {
         return Ok(LedInfo {
             color: LedColor::new(0, 0, 0),
             max_lux: 0,
+            color_temperature_k: 0,
+            wavelength_nm: 0,
+            white_balance: LedInfo::IDENTITY_WHITE_BALANCE,
         });
     }
 
@@ -112,6 +162,10 @@ impl Host for EmulatedHost {
         return Ok(0);
     }
 
+    fn get_ambient_light_raw(_caller: &mut WrappedCaller<'_, Self>) -> Result<u32, wasmi::Error> {
+        return Ok(0);
+    }
+
     fn get_vibration_sensor_type(
         _caller: &mut WrappedCaller<'_, Self>,
     ) -> Result<VibrationSensorType, wasmi::Error> {
@@ -122,6 +176,10 @@ impl Host for EmulatedHost {
         return Ok(0);
     }
 
+    fn get_temperature(_caller: &mut WrappedCaller<'_, Self>) -> Result<i32, wasmi::Error> {
+        return Ok(25_000);
+    }
+
     fn configure_advertisement(
         _context: &mut WrappedCaller<'_, Self>,
         _settings: AdvertisementSettings,
@@ -135,4 +193,34 @@ impl Host for EmulatedHost {
     ) -> Result<u32, wasmi::Error> {
         return Ok(0);
     }
+
+    fn get_connected_devices(
+        _context: &mut WrappedCaller<'_, Self>,
+    ) -> Result<Vec<u64>, wasmi::Error> {
+        return Ok(Vec::new());
+    }
+
+    fn configure_scan(
+        _context: &mut WrappedCaller<'_, Self>,
+        _window: u16,
+        _interval: u16,
+        _active: bool,
+    ) -> Result<u32, wasmi::Error> {
+        return Ok(0);
+    }
+
+    fn get_scan_results(
+        context: &mut WrappedCaller<'_, Self>,
+    ) -> Result<Vec<Advertisement>, wasmi::Error> {
+        return Ok(std::mem::take(&mut context.data_mut().scan_results));
+    }
+
+    fn set_group_id(context: &mut WrappedCaller<'_, Self>, id: u16) -> Result<(), wasmi::Error> {
+        context.data_mut().group_id = Some(id);
+        return Ok(());
+    }
+
+    fn get_group_id(context: &mut WrappedCaller<'_, Self>) -> Result<u16, wasmi::Error> {
+        return Ok(context.data().group_id.unwrap_or(0));
+    }
 }
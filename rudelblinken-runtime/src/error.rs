@@ -0,0 +1,95 @@
+//! Errors returned by the runtime
+use crate::host::HostError;
+use std::fmt::Display;
+use wasmi::core::TrapCode;
+
+/// Error of a guest module
+#[derive(Debug)]
+pub enum Error {
+    /// The guest ran out of fuel without yielding
+    FuelExhausted,
+    /// The guest trapped
+    WasmTrap { trap_code: TrapCode },
+    /// A host function failed
+    HostError {
+        function: &'static str,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// The guest could not be linked with the host functions or does not export the expected functions
+    LinkError(String),
+    /// The guest is not a valid WASM module
+    ValidationError(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::FuelExhausted => write!(f, "the guest ran out of fuel"),
+            Error::WasmTrap { trap_code } => write!(f, "the guest trapped: {}", trap_code),
+            Error::HostError { function, source } => {
+                write!(f, "host function {} failed: {}", function, source)
+            }
+            Error::LinkError(message) => write!(f, "failed to link the guest: {}", message),
+            Error::ValidationError(message) => write!(f, "invalid guest module: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::HostError { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<HostError> for Error {
+    /// Classify an error that occurred while the guest was running
+    fn from(error: HostError) -> Self {
+        if let Some(trap_code) = error.as_trap_code() {
+            if trap_code == TrapCode::OutOfFuel {
+                return Error::FuelExhausted;
+            }
+            return Error::WasmTrap { trap_code };
+        }
+        if error.downcast_ref::<FailedHostCall>().is_none() {
+            // Errors raised by the glue code, for example because the guest passed an invalid pointer
+            return Error::HostError {
+                function: "unknown",
+                source: Box::new(error),
+            };
+        }
+        let FailedHostCall { function, source } = error
+            .downcast::<FailedHostCall>()
+            .expect("the error was checked to be a failed host call");
+        // Guest callbacks can be invoked by host functions, report their errors directly
+        if source.as_trap_code().is_some() || source.downcast_ref::<FailedHostCall>().is_some() {
+            return Error::from(source);
+        }
+        return Error::HostError {
+            function,
+            source: Box::new(source),
+        };
+    }
+}
+
+/// An error returned by the implementation of a host function, tagged with the name of the function
+#[derive(Debug)]
+pub(crate) struct FailedHostCall {
+    function: &'static str,
+    source: HostError,
+}
+
+impl Display for FailedHostCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.function, self.source)
+    }
+}
+
+impl wasmi::core::HostError for FailedHostCall {}
+
+/// Tag an error of a host function implementation with the name of the function
+pub(crate) fn failed_host_call(function: &'static str) -> impl FnOnce(HostError) -> HostError {
+    return move |source| HostError::host(FailedHostCall { function, source });
+}
@@ -1,5 +1,8 @@
 use crate::linker::linker::WrappedCaller;
 
+/// Error returned by the implementations of host functions. Returning it stops the guest
+pub use wasmi::Error as HostError;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(i32)]
 pub enum LogLevel {
@@ -36,6 +39,8 @@ impl core::fmt::Display for LogLevel {
 }
 
 /// The semantic version of a module
+///
+/// Versions are ordered by major, then minor, then patch version.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SemanticVersion {
     pub major: u8,
@@ -51,6 +56,18 @@ impl SemanticVersion {
             patch,
         }
     }
+
+    /// Check if this version is compatible with the required version
+    ///
+    /// The major version must match and this version must not be older than the required one.
+    pub fn satisfies(&self, required: &SemanticVersion) -> bool {
+        return self.major == required.major && self >= required;
+    }
+}
+impl core::fmt::Display for SemanticVersion {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
 }
 
 #[repr(C)]
@@ -75,6 +92,18 @@ impl LedColor {
 pub struct LedInfo {
     pub color: LedColor,
     pub max_lux: u16,
+    /// Color temperature of white LEDs in kelvin. 0 if unknown or not a white LED
+    pub color_temperature_k: u16,
+    /// Dominant wavelength of single color LEDs in nanometers. 0 if unknown or not a single color LED
+    pub wavelength_nm: u16,
+    /// Row-major matrix that maps requested colors to calibrated colors
+    pub white_balance: [[f32; 3]; 3],
+}
+
+impl LedInfo {
+    /// White balance that does not change the color
+    pub const IDENTITY_WHITE_BALANCE: [[f32; 3]; 3] =
+        [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
 }
 
 /// Information about the ambient light sensor.
@@ -119,8 +148,15 @@ impl VibrationSensorType {
     }
 }
 
+/// Service data of an advertisement with a 16 bit service UUID
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceData {
+    pub uuid: u16,
+    pub data: Vec<u8>,
+}
+
 #[repr(C, align(4))]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Advertisement {
     pub company: u16,
     pub address: [u8; 8],
@@ -128,7 +164,11 @@ pub struct Advertisement {
     pub data: [u8; 32],
     /// how many of the data bytes are actually used
     pub data_length: u8,
+    /// Signal strength in dBm. 0 if unknown
+    pub rssi: i8,
     pub received_at: u64,
+    /// Service data with 16 bit UUIDs contained in the advertisement
+    pub service_data: Vec<ServiceData>,
 }
 
 /// Configure the BLE advertisements
@@ -150,6 +190,11 @@ impl ::core::fmt::Debug for AdvertisementSettings {
 #[derive(Clone, Debug)]
 pub enum Event {
     AdvertisementReceived(Advertisement),
+    /// A timer set by the guest expired. `fired_at` is the time in microseconds it was noticed by the host
+    TimerFired {
+        id: u8,
+        fired_at: u64,
+    },
 }
 
 pub trait Host
@@ -157,74 +202,114 @@ where
     Self: Sized,
 {
     #[doc = "You need to yield periodically, as the watchdog will kill you if you dont"]
-    fn yield_now(context: &mut WrappedCaller<'_, Self>, micros: u64) -> Result<u32, wasmi::Error>;
+    fn yield_now(context: &mut WrappedCaller<'_, Self>, micros: u64) -> Result<u32, HostError>;
     #[doc = " Sleep for a given amount of time."]
-    fn sleep(context: &mut WrappedCaller<'_, Self>, micros: u64) -> Result<(), wasmi::Error>;
+    fn sleep(context: &mut WrappedCaller<'_, Self>, micros: u64) -> Result<(), HostError>;
 
     #[doc = " Returns the number of microseconds that have passed since boot"]
-    fn time(context: &mut WrappedCaller<'_, Self>) -> Result<u64, wasmi::Error>;
+    fn time(context: &mut WrappedCaller<'_, Self>) -> Result<u64, HostError>;
+
+    #[doc = " Fire the timer with the given id after the delay. Setting a pending timer again replaces it"]
+    fn set_timer(
+        context: &mut WrappedCaller<'_, Self>,
+        id: u8,
+        delay_us: u64,
+    ) -> Result<(), HostError>;
+    #[doc = " Cancel the timer with the given id"]
+    fn cancel_timer(context: &mut WrappedCaller<'_, Self>, id: u8) -> Result<(), HostError>;
 
     #[doc = " Log a message"]
     fn log(
         context: &mut WrappedCaller<'_, Self>,
         level: LogLevel,
         message: &str,
-    ) -> Result<(), wasmi::Error>;
+    ) -> Result<(), HostError>;
 
     /// The name for this host. You can assume that this is unique
     ///
     /// Gets truncated to the first 16 bytes
-    fn get_name(context: &mut WrappedCaller<'_, Self>) -> Result<String, wasmi::Error>;
+    fn get_name(context: &mut WrappedCaller<'_, Self>) -> Result<String, HostError>;
 
     /// The configuration set on the host via BLE; to be treaded as an opaque byte slice
-    fn get_config(context: &mut WrappedCaller<'_, Self>) -> Result<Vec<u8>, wasmi::Error>;
+    fn get_config(context: &mut WrappedCaller<'_, Self>) -> Result<Vec<u8>, HostError>;
 
+    /// Set the LEDs starting at `first_id`. Returns the number of LEDs that were set
     fn set_leds(
         context: &mut WrappedCaller<'_, Self>,
         first_id: u16,
         lux: &[u16],
-    ) -> Result<u32, wasmi::Error>;
+    ) -> Result<u32, HostError>;
+    /// Set all LEDs. Returns the number of LEDs that were set
     fn set_rgb(
         context: &mut WrappedCaller<'_, Self>,
         color: &LedColor,
         lux: u32,
-    ) -> Result<u32, wasmi::Error>;
-    fn led_count(context: &mut WrappedCaller<'_, Self>) -> Result<u16, wasmi::Error>;
-    fn get_led_info(
-        context: &mut WrappedCaller<'_, Self>,
-        id: u16,
-    ) -> Result<LedInfo, wasmi::Error>;
+    ) -> Result<u32, HostError>;
+    fn led_count(context: &mut WrappedCaller<'_, Self>) -> Result<u16, HostError>;
+    fn get_led_info(context: &mut WrappedCaller<'_, Self>, id: u16) -> Result<LedInfo, HostError>;
 
     /// Check if this board has an ambient light sensor
     fn get_ambient_light_type(
         context: &mut WrappedCaller<'_, Self>,
-    ) -> Result<AmbientLightType, wasmi::Error>;
+    ) -> Result<AmbientLightType, HostError>;
     /// Get the ambient light in lux
-    fn get_ambient_light(context: &mut WrappedCaller<'_, Self>) -> Result<u32, wasmi::Error>;
+    fn get_ambient_light(context: &mut WrappedCaller<'_, Self>) -> Result<u32, HostError>;
+    /// Get the uncalibrated reading of the ambient light sensor
+    fn get_ambient_light_raw(context: &mut WrappedCaller<'_, Self>) -> Result<u32, HostError>;
 
     fn get_vibration_sensor_type(
         context: &mut WrappedCaller<'_, Self>,
-    ) -> Result<VibrationSensorType, wasmi::Error>;
-    fn get_vibration(context: &mut WrappedCaller<'_, Self>) -> Result<u32, wasmi::Error>;
+    ) -> Result<VibrationSensorType, HostError>;
+    fn get_vibration(context: &mut WrappedCaller<'_, Self>) -> Result<u32, HostError>;
+
+    /// Get the temperature of the device in millidegrees Celsius
+    fn get_temperature(context: &mut WrappedCaller<'_, Self>) -> Result<i32, HostError>;
+    #[doc = " Get the battery level in percent. 255 means that the battery level is unknown"]
+    fn get_battery_level(_context: &mut WrappedCaller<'_, Self>) -> Result<u8, HostError> {
+        return Ok(255);
+    }
 
     fn configure_advertisement(
         context: &mut WrappedCaller<'_, Self>,
         settings: AdvertisementSettings,
-    ) -> Result<u32, wasmi::Error>;
+    ) -> Result<u32, HostError>;
     fn set_advertisement_data(
         context: &mut WrappedCaller<'_, Self>,
         data: &[u8],
-    ) -> Result<u32, wasmi::Error>;
+    ) -> Result<u32, HostError>;
+    #[doc = " Get the addresses of the devices that are currently connected over BLE"]
+    fn get_connected_devices(context: &mut WrappedCaller<'_, Self>) -> Result<Vec<u64>, HostError>;
+    /// Configure how the host scans for advertisements
+    ///
+    /// The host listens for `window` milliseconds every `interval` milliseconds. Active scans request scan responses.
+    fn configure_scan(
+        context: &mut WrappedCaller<'_, Self>,
+        window: u16,
+        interval: u16,
+        active: bool,
+    ) -> Result<u32, HostError>;
+    /// Take the advertisements that were received since the last call
+    fn get_scan_results(
+        context: &mut WrappedCaller<'_, Self>,
+    ) -> Result<Vec<Advertisement>, HostError>;
+    /// Send the given group ID in the header of the advertisements, instead of the configured one
+    ///
+    /// Applies to the next call to `set-advertisement-data`.
+    fn set_group_id(context: &mut WrappedCaller<'_, Self>, id: u16) -> Result<(), HostError>;
+    /// The group ID that is sent in the header of the advertisements
+    ///
+    /// This is the one set with `set-group-id`, or the configured one.
+    fn get_group_id(context: &mut WrappedCaller<'_, Self>) -> Result<u16, HostError>;
 }
 
-pub fn to_error_code<T, E>(result: Result<T, E>, code: u32) -> Result<u32, wasmi::Error> {
+pub fn to_error_code<T, E>(result: Result<T, E>, code: u32) -> Result<u32, HostError> {
     match result {
         Ok(_) => Ok(0),
         Err(_) => Ok(code),
     }
 }
 
-pub fn map_to_error_code<T, E, F>(result: Result<T, E>, f: F) -> Result<u32, wasmi::Error>
+pub fn map_to_error_code<T, E, F>(result: Result<T, E>, f: F) -> Result<u32, HostError>
 where
     F: FnOnce(E) -> u32,
 {
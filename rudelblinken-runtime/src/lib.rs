@@ -20,16 +20,34 @@
 //! ```
 
 pub mod emulated_host;
+mod error;
 pub mod host;
 pub mod linker;
+pub mod pause;
+pub mod timer;
 
-/// This crate uses wasmi::Error as its main error type.
-pub use wasmi::Error;
+pub use error::Error;
 
 #[cfg(test)]
 mod tests {
     use super::emulated_host::EmulatedHost;
-    use super::linker::setup;
+    use super::host::{Advertisement, Event, SemanticVersion, ServiceData};
+    use super::linker::{setup, setup_with_config, RuntimeConfig};
+    use super::Error;
+    use std::time::Duration;
+
+    /// A guest that imports a host function and exports run for the given API version
+    fn guest_for_api_version(version: &str) -> Vec<u8> {
+        return wat::parse_str(format!(
+            r#"
+            (module
+              (import "rudel:base/base@{version}" "time" (func $time (result i64)))
+              (func (export "rudel:base/run@{version}#run")
+                (drop (call $time))))
+            "#
+        ))
+        .unwrap();
+    }
 
     #[test]
     fn can_execute_helloworld() {
@@ -55,11 +73,456 @@ mod tests {
 
         let (_, host) = EmulatedHost::new();
         let mut instance = setup(&module_bytes, host).unwrap();
-        let error: wasmi::Error = instance.run().unwrap_err();
+        let error = instance.run().unwrap_err();
+        assert!(matches!(error, Error::FuelExhausted));
+    }
+
+    #[test]
+    fn invalid_module_fails_validation() {
+        let (_, host) = EmulatedHost::new();
+        let Err(error) = setup(b"not a wasm module", host) else {
+            panic!("an invalid module was accepted");
+        };
+        assert!(matches!(error, Error::ValidationError(_)));
+    }
+
+    #[test]
+    fn guest_for_older_api_version_runs() {
+        let (_, host) = EmulatedHost::new();
+        let mut instance = setup(&guest_for_api_version("0.0.1"), host).unwrap();
+        instance.run().unwrap();
+    }
+
+    #[test]
+    fn guest_for_current_api_version_runs() {
+        let (_, host) = EmulatedHost::new();
+        let mut instance = setup(&guest_for_api_version("0.0.2"), host).unwrap();
+        instance.run().unwrap();
+    }
+
+    #[test]
+    fn guest_for_unknown_api_version_fails_to_link() {
+        let (_, host) = EmulatedHost::new();
+        let Err(error) = setup(&guest_for_api_version("1.0.0"), host) else {
+            panic!("a guest for an unknown API version was linked");
+        };
+        assert!(matches!(error, Error::LinkError(_)));
+    }
+
+    #[test]
+    fn functions_added_in_newer_api_versions_are_not_linked_for_older_guests() {
+        let guest = wat::parse_str(
+            r#"
+            (module
+              (import "rudel:base/base@0.0.1" "set-timer" (func $set_timer (param i32 i64)))
+              (func (export "rudel:base/run@0.0.1#run")))
+            "#,
+        )
+        .unwrap();
+
+        let (_, host) = EmulatedHost::new();
+        let Err(error) = setup(&guest, host) else {
+            panic!("a function added in 0.0.2 was linked for a 0.0.1 guest");
+        };
+        assert!(matches!(error, Error::LinkError(_)));
+    }
+
+    #[test]
+    fn older_guests_get_the_led_info_without_calibration_fields() {
+        // The led-info record of 0.0.1 is 6 bytes long, the bytes after it belong to the guest
+        let guest = wat::parse_str(
+            r#"
+            (module
+              (import "rudel:base/hardware@0.0.1" "get-led-info" (func $get_led_info (param i32 i32)))
+              (memory (export "memory") 1)
+              (data (i32.const 256) "\ff\ff\ff\ff\ff\ff\ff\ff\ff\ff\ff\ff\ff\ff\ff\ff")
+              (func (export "rudel:base/run@0.0.1#run")
+                (call $get_led_info (i32.const 0) (i32.const 256))
+                (if (i32.ne (i32.load8_u offset=0 (i32.const 256)) (i32.const 0))
+                  (then unreachable))
+                (if (i32.ne (i32.load16_u offset=4 (i32.const 256)) (i32.const 0))
+                  (then unreachable))
+                (if (i32.ne (i32.load16_u offset=6 (i32.const 256)) (i32.const 0xffff))
+                  (then unreachable))
+                (if (i64.ne (i64.load offset=8 (i32.const 256)) (i64.const -1))
+                  (then unreachable))))
+            "#,
+        )
+        .unwrap();
+
+        let (_, host) = EmulatedHost::new();
+        let mut instance = setup(&guest, host).unwrap();
+        instance.run().unwrap();
+    }
+
+    #[test]
+    fn older_guests_can_read_the_config() {
+        let guest = wat::parse_str(
+            r#"
+            (module
+              (import "rudel:base/base@0.0.1" "get-config" (func $get_config (param i32)))
+              (memory (export "memory") 1)
+              (global $next (mut i32) (i32.const 1024))
+              ;; Bump allocator that never frees
+              (func (export "cabi_realloc") (param $ptr i32) (param $old_size i32) (param $align i32) (param $new_size i32) (result i32)
+                (local $result i32)
+                (local.set $result
+                  (i32.and
+                    (i32.add (global.get $next) (i32.sub (local.get $align) (i32.const 1)))
+                    (i32.sub (i32.const 0) (local.get $align))))
+                (global.set $next (i32.add (local.get $result) (local.get $new_size)))
+                (local.get $result))
+              (func (export "rudel:base/run@0.0.1#run")
+                (call $get_config (i32.const 0))
+                (if (i32.ne (i32.load (i32.const 4)) (i32.const 3))
+                  (then unreachable))
+                (if (i32.ne (i32.load8_u offset=2 (i32.load (i32.const 0))) (i32.const 7))
+                  (then unreachable))))
+            "#,
+        )
+        .unwrap();
+
+        let (_, mut host) = EmulatedHost::new();
+        host.config = vec![5, 6, 7];
+        let mut instance = setup(&guest, host).unwrap();
+        instance.run().unwrap();
+    }
+
+    #[test]
+    fn paused_guest_continues_after_resume() {
+        let guest = wat::parse_str(
+            r#"
+            (module
+              (import "rudel:base/base@0.0.2" "yield-now" (func $yield_now (param i64) (result i32)))
+              (func (export "rudel:base/run@0.0.2#run")
+                (drop (call $yield_now (i64.const 0)))))
+            "#,
+        )
+        .unwrap();
+
+        let (_, host) = EmulatedHost::new();
+        let pause_handle = host.paused.clone();
+        pause_handle.pause();
+        let mut instance = setup(&guest, host).unwrap();
+        let runner = std::thread::spawn(move || instance.run().unwrap());
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!runner.is_finished());
+        pause_handle.resume();
+        runner.join().unwrap();
+    }
+
+    #[test]
+    fn timer_fires_while_yielding() {
+        let guest = wat::parse_str(
+            r#"
+            (module
+              (import "rudel:base/base@0.0.2" "yield-now" (func $yield_now (param i64) (result i32)))
+              (import "rudel:base/base@0.0.2" "set-timer" (func $set_timer (param i32 i64)))
+              (global $fired (mut i32) (i32.const 0))
+              (func (export "rudel:base/timer-guest@0.0.2#on-timer") (param $id i32) (param $fired_at i64)
+                (global.set $fired (local.get $id)))
+              (func (export "rudel:base/run@0.0.2#run")
+                (call $set_timer (i32.const 7) (i64.const 0))
+                (drop (call $yield_now (i64.const 0)))
+                (if (i32.ne (global.get $fired) (i32.const 7))
+                  (then unreachable))))
+            "#,
+        )
+        .unwrap();
+
+        let (_, host) = EmulatedHost::new();
+        let mut instance = setup(&guest, host).unwrap();
+        instance.run().unwrap();
+    }
+
+    #[test]
+    fn guest_with_too_much_memory_fails_validation() {
+        let guest = wat::parse_str(
+            r#"
+            (module
+              (memory (export "memory") 2)
+              (func (export "rudel:base/run@0.0.2#run")))
+            "#,
+        )
+        .unwrap();
+
+        let (_, host) = EmulatedHost::new();
+        let config = RuntimeConfig {
+            max_memory_pages: Some(1),
+            ..RuntimeConfig::default()
+        };
+        let Err(error) = setup_with_config(&guest, host, config) else {
+            panic!("a guest with too much memory was accepted");
+        };
+        assert!(matches!(error, Error::ValidationError(_)));
+    }
+
+    #[test]
+    fn guest_runs_without_validation_and_fuel_metering() {
+        let (_, host) = EmulatedHost::new();
+        let config = RuntimeConfig {
+            enable_fuel_metering: false,
+            enable_validation: false,
+            ..RuntimeConfig::default()
+        };
+        let mut instance =
+            setup_with_config(&guest_for_api_version("0.0.2"), host, config).unwrap();
+        instance.run().unwrap();
+    }
+
+    /// An advertisement with the given RSSI
+    fn advertisement_with_rssi(rssi: i8) -> Advertisement {
+        return Advertisement {
+            company: 0x0059,
+            address: [1, 2, 3, 4, 5, 6, 0, 0],
+            data: [0; 32],
+            data_length: 0,
+            rssi,
+            received_at: 0,
+            service_data: Vec::new(),
+        };
+    }
+
+    #[test]
+    fn advertisements_are_passed_to_the_guest_with_their_rssi() {
+        let guest = wat::parse_str(
+            r#"
+            (module
+              (import "rudel:base/base@0.0.2" "yield-now" (func $yield_now (param i64) (result i32)))
+              (global $rssi (mut i32) (i32.const 0))
+              (func (export "rudel:base/ble-guest@0.0.2#on-advertisement")
+                (param i64 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i64)
+                (global.set $rssi (local.get 11)))
+              (func (export "rudel:base/run@0.0.2#run")
+                (drop (call $yield_now (i64.const 0)))
+                (if (i32.ne (global.get $rssi) (i32.const -70))
+                  (then unreachable))))
+            "#,
+        )
+        .unwrap();
+
+        let (sender, host) = EmulatedHost::new();
+        sender
+            .send(Event::AdvertisementReceived(advertisement_with_rssi(-70)))
+            .unwrap();
+        let mut instance = setup(&guest, host).unwrap();
+        instance.run().unwrap();
+    }
+
+    #[test]
+    fn advertisements_are_passed_to_the_guest_with_their_service_data() {
+        let guest = wat::parse_str(
+            r#"
+            (module
+              (import "rudel:base/base@0.0.2" "yield-now" (func $yield_now (param i64) (result i32)))
+              (memory (export "memory") 1)
+              (global $next (mut i32) (i32.const 1024))
+              (global $uuid (mut i32) (i32.const 0))
+              (global $last_byte (mut i32) (i32.const 0))
+              ;; Bump allocator that never frees
+              (func (export "cabi_realloc") (param $ptr i32) (param $old_size i32) (param $align i32) (param $new_size i32) (result i32)
+                (local $result i32)
+                (local.set $result
+                  (i32.and
+                    (i32.add (global.get $next) (i32.sub (local.get $align) (i32.const 1)))
+                    (i32.sub (i32.const 0) (local.get $align))))
+                (global.set $next (i32.add (local.get $result) (local.get $new_size)))
+                (local.get $result))
+              (func (export "rudel:base/ble-guest@0.0.2#on-advertisement")
+                (param i64 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i64 i32 i32)
+                (if (i32.ne (local.get 14) (i32.const 1))
+                  (then unreachable))
+                (global.set $uuid (i32.load16_u offset=0 (local.get 13)))
+                (global.set $last_byte
+                  (i32.load8_u offset=1 (i32.load offset=4 (local.get 13)))))
+              (func (export "rudel:base/run@0.0.2#run")
+                (drop (call $yield_now (i64.const 0)))
+                (if (i32.ne (global.get $uuid) (i32.const 0x181c))
+                  (then unreachable))
+                (if (i32.ne (global.get $last_byte) (i32.const 0xbb))
+                  (then unreachable))))
+            "#,
+        )
+        .unwrap();
+
+        let (sender, host) = EmulatedHost::new();
+        sender
+            .send(Event::AdvertisementReceived(Advertisement {
+                service_data: vec![ServiceData {
+                    uuid: 0x181c,
+                    data: vec![0xaa, 0xbb],
+                }],
+                ..advertisement_with_rssi(-70)
+            }))
+            .unwrap();
+        let mut instance = setup(&guest, host).unwrap();
+        instance.run().unwrap();
+    }
+
+    #[test]
+    fn guests_built_before_the_rssi_field_still_get_advertisements() {
+        let guest = wat::parse_str(
+            r#"
+            (module
+              (import "rudel:base/base@0.0.2" "yield-now" (func $yield_now (param i64) (result i32)))
+              (global $data_length (mut i32) (i32.const -1))
+              (func (export "rudel:base/ble-guest@0.0.2#on-advertisement")
+                (param i64 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i64)
+                (global.set $data_length (local.get 10)))
+              (func (export "rudel:base/run@0.0.2#run")
+                (drop (call $yield_now (i64.const 0)))
+                (if (i32.ne (global.get $data_length) (i32.const 0))
+                  (then unreachable))))
+            "#,
+        )
+        .unwrap();
+
+        let (sender, host) = EmulatedHost::new();
+        sender
+            .send(Event::AdvertisementReceived(advertisement_with_rssi(-70)))
+            .unwrap();
+        let mut instance = setup(&guest, host).unwrap();
+        instance.run().unwrap();
+    }
+
+    #[test]
+    fn scan_results_are_written_to_guest_memory_in_the_canonical_layout() {
+        // The guest reads the list like the generated bindings. Records are 64 bytes long and 8 byte aligned
+        let guest = wat::parse_str(
+            r#"
+            (module
+              (import "rudel:base/ble@0.0.2" "get-scan-results" (func $get_scan_results (param i32)))
+              (memory (export "memory") 1)
+              (global $next (mut i32) (i32.const 1024))
+              ;; Bump allocator that never frees
+              (func (export "cabi_realloc") (param $ptr i32) (param $old_size i32) (param $align i32) (param $new_size i32) (result i32)
+                (local $result i32)
+                (local.set $result
+                  (i32.and
+                    (i32.add (global.get $next) (i32.sub (local.get $align) (i32.const 1)))
+                    (i32.sub (i32.const 0) (local.get $align))))
+                (global.set $next (i32.add (local.get $result) (local.get $new_size)))
+                (local.get $result))
+              (func (export "rudel:base/run@0.0.2#run")
+                (local $first i32)
+                (local $second i32)
+                (local $service_data i32)
+                (call $get_scan_results (i32.const 0))
+                (if (i32.ne (i32.load (i32.const 4)) (i32.const 2))
+                  (then unreachable))
+                (local.set $first (i32.load (i32.const 0)))
+                (if (i32.and (local.get $first) (i32.const 7))
+                  (then unreachable))
+                (local.set $second (i32.add (local.get $first) (i32.const 64)))
+                (if (i64.ne (i64.load offset=0 (local.get $first)) (i64.const 0x060504030201))
+                  (then unreachable))
+                (if (i64.ne (i64.load offset=48 (local.get $first)) (i64.const 42))
+                  (then unreachable))
+                (if (i64.ne (i64.load offset=0 (local.get $second)) (i64.const 0x161514131211))
+                  (then unreachable))
+                (if (i32.ne (i32.load16_u offset=8 (local.get $second)) (i32.const 0xbeef))
+                  (then unreachable))
+                (if (i32.ne (i32.load offset=12 (local.get $second)) (i32.const 0x03020100))
+                  (then unreachable))
+                (if (i32.ne (i32.load offset=40 (local.get $second)) (i32.const 0x1f1e1d1c))
+                  (then unreachable))
+                (if (i32.ne (i32.load8_u offset=44 (local.get $second)) (i32.const 20))
+                  (then unreachable))
+                (if (i32.ne (i32.load8_s offset=45 (local.get $second)) (i32.const -70))
+                  (then unreachable))
+                (if (i64.ne (i64.load offset=48 (local.get $second)) (i64.const 1000000007))
+                  (then unreachable))
+                ;; The second advertisement has one entry of service data
+                (if (i32.ne (i32.load offset=60 (local.get $first)) (i32.const 0))
+                  (then unreachable))
+                (if (i32.ne (i32.load offset=60 (local.get $second)) (i32.const 1))
+                  (then unreachable))
+                (local.set $service_data (i32.load offset=56 (local.get $second)))
+                (if (i32.ne (i32.load16_u offset=0 (local.get $service_data)) (i32.const 0x181c))
+                  (then unreachable))
+                (if (i32.ne (i32.load offset=8 (local.get $service_data)) (i32.const 3))
+                  (then unreachable))
+                (if (i32.ne
+                      (i32.load8_u offset=2 (i32.load offset=4 (local.get $service_data)))
+                      (i32.const 0xcc))
+                  (then unreachable))
+                ;; The results were taken by the first call
+                (call $get_scan_results (i32.const 0))
+                (if (i32.ne (i32.load (i32.const 4)) (i32.const 0))
+                  (then unreachable))))
+            "#,
+        )
+        .unwrap();
+
+        let (_, mut host) = EmulatedHost::new();
+        host.scan_results = vec![
+            Advertisement {
+                company: 1,
+                address: [1, 2, 3, 4, 5, 6, 0, 0],
+                data: [0; 32],
+                data_length: 0,
+                rssi: 0,
+                received_at: 42,
+                service_data: Vec::new(),
+            },
+            Advertisement {
+                company: 0xbeef,
+                address: [0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0, 0],
+                data: std::array::from_fn(|index| index as u8),
+                data_length: 20,
+                rssi: -70,
+                received_at: 1_000_000_007,
+                service_data: vec![ServiceData {
+                    uuid: 0x181c,
+                    data: vec![0xaa, 0xbb, 0xcc],
+                }],
+            },
+        ];
+        let mut instance = setup(&guest, host).unwrap();
+        instance.run().unwrap();
+    }
+
+    #[test]
+    fn semantic_version_satisfies_requirement() {
+        let version = SemanticVersion::new(1, 2, 3);
+        assert!(version.satisfies(&SemanticVersion::new(1, 2, 3)));
+        assert!(version.satisfies(&SemanticVersion::new(1, 1, 9)));
+        assert!(!version.satisfies(&SemanticVersion::new(1, 2, 4)));
+        assert!(!version.satisfies(&SemanticVersion::new(1, 3, 0)));
+        assert!(!version.satisfies(&SemanticVersion::new(0, 2, 3)));
+        assert!(!version.satisfies(&SemanticVersion::new(2, 0, 0)));
+    }
+
+    #[test]
+    fn semantic_versions_are_ordered_by_major_minor_and_patch() {
+        let mut versions = vec![
+            SemanticVersion::new(1, 0, 0),
+            SemanticVersion::new(0, 1, 9),
+            SemanticVersion::new(0, 2, 0),
+            SemanticVersion::new(0, 0, 1),
+            SemanticVersion::new(0, 1, 10),
+        ];
+        versions.sort();
         assert_eq!(
-            error.as_trap_code().unwrap(),
-            wasmi::core::TrapCode::OutOfFuel
+            versions,
+            vec![
+                SemanticVersion::new(0, 0, 1),
+                SemanticVersion::new(0, 1, 9),
+                SemanticVersion::new(0, 1, 10),
+                SemanticVersion::new(0, 2, 0),
+                SemanticVersion::new(1, 0, 0),
+            ]
         );
+        assert!(SemanticVersion::new(0, 1, 0) >= SemanticVersion::new(0, 1, 0));
+        assert!(SemanticVersion::new(0, 9, 9) < SemanticVersion::new(1, 0, 0));
+        assert!(SemanticVersion::new(2, 0, 0) > SemanticVersion::new(1, 255, 255));
+    }
+
+    #[test]
+    fn semantic_versions_are_displayed_with_dots() {
+        assert_eq!(SemanticVersion::new(0, 1, 0).to_string(), "0.1.0");
+        assert_eq!(SemanticVersion::new(12, 3, 255).to_string(), "12.3.255");
     }
     // // How would I even test this?
     // #[test]
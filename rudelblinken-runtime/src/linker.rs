@@ -1,13 +1,23 @@
 pub mod glue;
 pub mod linker;
 
-use crate::host::Host;
+use crate::{host::Host, Error};
 use linker::{link_base, link_ble, link_hardware};
 use wasmi::{Config, Engine, Instance, Linker, Module, Store};
 
 const MAJOR: u8 = 0;
 const MINOR: u8 = 0;
-const PATCH: u8 = 1;
+const PATCH: u8 = 2;
+
+/// Versions of the API that are linked, starting with the current one
+///
+/// Guests built against any of these versions can be run by this host. Only add older versions that are backward compatible with the current one.
+pub const API_VERSIONS: &[&str] = &["0.0.2", "0.0.1"];
+
+/// Versions of the API that contain the functions added or changed in 0.0.2
+///
+/// Guests built against 0.0.1 do not import these, so they are only linked for newer versions.
+pub const SINCE_0_0_2: &[&str] = &["0.0.2"];
 
 pub struct LinkedHost<T: Host> {
     instance: Instance,
@@ -18,36 +28,115 @@ impl<T: Host> LinkedHost<T> {
     fn new(instance: Instance, store: Store<T>) -> Self {
         return LinkedHost { instance, store };
     }
-    pub fn run(&mut self) -> Result<(), wasmi::Error> {
-        let run = self
-            .instance
-            .get_typed_func::<(), ()>(&self.store, "rudel:base/run@0.0.1#run")?;
+    pub fn run(&mut self) -> Result<(), Error> {
+        let run = API_VERSIONS
+            .iter()
+            .find_map(|version| {
+                self.instance
+                    .get_export(&self.store, &format!("rudel:base/run@{}#run", version))
+            })
+            .and_then(|run| run.into_func())
+            .ok_or_else(|| Error::LinkError("run is not exported".to_string()))?
+            .typed::<(), ()>(&self.store)
+            .map_err(|error| Error::LinkError(error.to_string()))?;
         run.call(&mut self.store, ())?;
         return Ok(());
     }
 }
 
-pub fn setup<T: Host>(wasm: &[u8], host: T) -> Result<LinkedHost<T>, wasmi::Error> {
+/// Engine level settings for running a guest
+///
+/// Settings of the host itself, like the LED configuration, are not part of this.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    /// Reject guests whose memory starts with more 64 KiB pages. `None` means no limit
+    ///
+    /// Growing the memory at runtime is not limited yet.
+    pub max_memory_pages: Option<u32>,
+    /// Fuel the guest has until the host refills it for the first time
+    pub initial_fuel: u64,
+    /// Count the instructions of the guest, so it can be stopped if it does not yield. Should always be enabled on real devices
+    pub enable_fuel_metering: bool,
+    /// Validate the guest module before it is compiled. Only disable this for modules that are known to be valid
+    pub enable_validation: bool,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        return RuntimeConfig {
+            max_memory_pages: None,
+            initial_fuel: 99999,
+            enable_fuel_metering: true,
+            enable_validation: true,
+        };
+    }
+}
+
+/// Link a guest with the default [RuntimeConfig]
+pub fn setup<T: Host>(wasm: &[u8], host: T) -> Result<LinkedHost<T>, Error> {
+    return setup_with_config(wasm, host, RuntimeConfig::default());
+}
+
+/// Link a guest with the given engine settings
+pub fn setup_with_config<T: Host>(
+    wasm: &[u8],
+    host: T,
+    config: RuntimeConfig,
+) -> Result<LinkedHost<T>, Error> {
     let engine = Engine::new(
         Config::default()
-            .consume_fuel(true)
+            .consume_fuel(config.enable_fuel_metering)
             .ignore_custom_sections(true),
     );
-    let module = Module::new(&engine, wasm)?;
+    let module = if config.enable_validation {
+        Module::new(&engine, wasm)
+    } else {
+        // SAFETY: The caller disabled validation, because the module is known to be valid
+        unsafe { Module::new_unchecked(&engine, wasm) }
+    }
+    .map_err(|error| Error::ValidationError(error.to_string()))?;
+
+    if let Some(max_memory_pages) = config.max_memory_pages {
+        check_memory_pages(&module, max_memory_pages)?;
+    }
 
     let mut store = Store::new(&engine, host);
-    store.set_fuel(99999).unwrap();
+    if config.enable_fuel_metering {
+        store.set_fuel(config.initial_fuel).unwrap();
+    }
 
     let mut linker = <Linker<T>>::new(&engine);
 
-    setup_linker(&mut linker, &mut store)?;
+    setup_linker(&mut linker, &mut store).map_err(|error| Error::LinkError(error.to_string()))?;
 
-    let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .map_err(|error| Error::LinkError(error.to_string()))?
+        .start(&mut store)?;
 
     let linked_instance = LinkedHost::new(instance, store);
     return Ok(linked_instance);
 }
 
+/// Make sure the initial memory of the module does not exceed `max_memory_pages`
+fn check_memory_pages(module: &Module, max_memory_pages: u32) -> Result<(), Error> {
+    let memories = module
+        .exports()
+        .map(|export| export.ty().clone())
+        .chain(module.imports().map(|import| import.ty().clone()))
+        .filter_map(|ty| ty.memory().cloned());
+    for memory in memories {
+        if u64::from(memory.minimum()) > u64::from(max_memory_pages) {
+            return Err(Error::ValidationError(format!(
+                "the guest needs {} memory pages, but only {} are allowed",
+                memory.minimum(),
+                max_memory_pages
+            )));
+        }
+    }
+    return Ok(());
+}
+
 /// Link the host functions provided by T.
 ///
 /// This functions will provide the rudel-host functions to the linker by generating glue code for the functionality provided by the host implementation T
@@ -1,8 +1,9 @@
 /// Provides functions that glue the relatively raw host functions to the implementation of Host
 use super::{linker::WrappedCaller, MAJOR, MINOR, PATCH};
+use crate::error::failed_host_call;
 use crate::host::{
-    AdvertisementSettings, AmbientLightType, Host, LedColor, LedInfo, LogLevel, SemanticVersion,
-    VibrationSensorType,
+    Advertisement, AdvertisementSettings, AmbientLightType, Host, LedColor, LedInfo, LogLevel,
+    SemanticVersion, VibrationSensorType,
 };
 
 /// `get-base-version: func() -> semantic-version;`
@@ -13,23 +14,46 @@ pub(super) fn get_base_version<T: Host>(
     *version = SemanticVersion::new(MAJOR, MINOR, PATCH);
     return Ok(());
 }
+/// `version-satisfies: func(version: semantic-version, required: semantic-version) -> bool;`
+pub(super) fn version_satisfies<T: Host>(
+    mut _caller: WrappedCaller<'_, T>,
+    version: SemanticVersion,
+    required: SemanticVersion,
+) -> Result<bool, wasmi::Error> {
+    return Ok(version.satisfies(&required));
+}
 /// `yield-now: func();`
 pub(super) fn yield_now<T: Host>(
     mut caller: WrappedCaller<'_, T>,
     micros: u64,
 ) -> Result<u32, wasmi::Error> {
-    return T::yield_now(&mut caller, micros);
+    return T::yield_now(&mut caller, micros).map_err(failed_host_call("yield-now"));
 }
 /// `sleep: func(micros: u64);`
 pub(super) fn sleep<T: Host>(
     mut caller: WrappedCaller<'_, T>,
     micros: u64,
 ) -> Result<(), wasmi::Error> {
-    return T::sleep(&mut caller, micros);
+    return T::sleep(&mut caller, micros).map_err(failed_host_call("sleep"));
 }
 /// `time: func() -> u64;`
 pub(super) fn time<T: Host>(mut caller: WrappedCaller<'_, T>) -> Result<u64, wasmi::Error> {
-    return T::time(&mut caller);
+    return T::time(&mut caller).map_err(failed_host_call("time"));
+}
+/// `set-timer: func(id: u8, delay-us: u64);`
+pub(super) fn set_timer<T: Host>(
+    mut caller: WrappedCaller<'_, T>,
+    id: u8,
+    delay_us: u64,
+) -> Result<(), wasmi::Error> {
+    return T::set_timer(&mut caller, id, delay_us).map_err(failed_host_call("set-timer"));
+}
+/// `cancel-timer: func(id: u8);`
+pub(super) fn cancel_timer<T: Host>(
+    mut caller: WrappedCaller<'_, T>,
+    id: u8,
+) -> Result<(), wasmi::Error> {
+    return T::cancel_timer(&mut caller, id).map_err(failed_host_call("cancel-timer"));
 }
 /// `log: func(level: log-level, message: string)  -> ();`
 pub(super) fn log<T: Host>(
@@ -37,14 +61,14 @@ pub(super) fn log<T: Host>(
     level: LogLevel,
     message: &str,
 ) -> Result<(), wasmi::Error> {
-    return T::log(&mut caller, level, message);
+    return T::log(&mut caller, level, message).map_err(failed_host_call("log"));
 }
 /// `get-name: func(name: &mut [u8; 16]);`
 pub(super) fn get_name<T: Host>(
     mut caller: WrappedCaller<'_, T>,
     name: &mut [u8; 16],
 ) -> Result<(), wasmi::Error> {
-    let host_name = T::get_name(&mut caller)?;
+    let host_name = T::get_name(&mut caller).map_err(failed_host_call("get-name"))?;
     let name_bytes = host_name.as_bytes();
     let name_length = std::cmp::min(name_bytes.len(), name.len());
     name[..name_length].copy_from_slice(&name_bytes[..name_length]);
@@ -56,7 +80,7 @@ pub(super) fn get_name<T: Host>(
 pub(super) fn get_config<T: Host>(
     caller: &mut WrappedCaller<'_, T>,
 ) -> Result<Vec<u8>, wasmi::Error> {
-    T::get_config(caller)
+    T::get_config(caller).map_err(failed_host_call("get-config"))
 }
 
 /// `get-hardware-version: func() -> semantic-version;`
@@ -73,7 +97,7 @@ pub(super) fn set_leds<T: Host>(
     first_id: u16,
     leds: &[u16],
 ) -> Result<u32, wasmi::Error> {
-    T::set_leds(&mut caller, first_id, leds)
+    T::set_leds(&mut caller, first_id, leds).map_err(failed_host_call("set-leds"))
 }
 /// `set-rgb: func(color: led-color, lux: u32) -> ();`
 pub(super) fn set_rgb<T: Host>(
@@ -81,44 +105,60 @@ pub(super) fn set_rgb<T: Host>(
     color: &LedColor,
     lux: u32,
 ) -> Result<u32, wasmi::Error> {
-    T::set_rgb(&mut caller, color, lux)
+    T::set_rgb(&mut caller, color, lux).map_err(failed_host_call("set-rgb"))
 }
 /// `led-count: func() -> u32;`
 pub(super) fn led_count<T: Host>(mut caller: WrappedCaller<'_, T>) -> Result<u16, wasmi::Error> {
-    return T::led_count(&mut caller);
+    return T::led_count(&mut caller).map_err(failed_host_call("led-count"));
 }
 /// `get-led-info: func(id: u16) -> led-info;`
 pub(super) fn get_led_info<T: Host>(
     mut caller: WrappedCaller<'_, T>,
     id: u16,
-    info: &mut LedInfo,
-) -> Result<(), wasmi::Error> {
-    *info = T::get_led_info(&mut caller, id)?;
-    return Ok(());
+) -> Result<LedInfo, wasmi::Error> {
+    return T::get_led_info(&mut caller, id).map_err(failed_host_call("get-led-info"));
 }
 /// `get-ambient-light-type: func() -> ambient-light-type;`
 pub(super) fn get_ambient_light_type<T: Host>(
     mut caller: WrappedCaller<'_, T>,
 ) -> Result<AmbientLightType, wasmi::Error> {
-    T::get_ambient_light_type(&mut caller)
+    T::get_ambient_light_type(&mut caller).map_err(failed_host_call("get-ambient-light-type"))
 }
 /// `get-ambient-light: func() -> u32;`
 pub(super) fn get_ambient_light<T: Host>(
     mut caller: WrappedCaller<'_, T>,
 ) -> Result<u32, wasmi::Error> {
-    T::get_ambient_light(&mut caller)
+    T::get_ambient_light(&mut caller).map_err(failed_host_call("get-ambient-light"))
+}
+/// `get-ambient-light-raw: func() -> u32;`
+pub(super) fn get_ambient_light_raw<T: Host>(
+    mut caller: WrappedCaller<'_, T>,
+) -> Result<u32, wasmi::Error> {
+    T::get_ambient_light_raw(&mut caller).map_err(failed_host_call("get-ambient-light-raw"))
 }
 /// `get-vibration-sensor-type: func() -> vibration-sensor-type;`
 pub(super) fn get_vibration_sensor_type<T: Host>(
     mut caller: WrappedCaller<'_, T>,
 ) -> Result<VibrationSensorType, wasmi::Error> {
-    T::get_vibration_sensor_type(&mut caller)
+    T::get_vibration_sensor_type(&mut caller).map_err(failed_host_call("get-vibration-sensor-type"))
 }
 /// `get-vibration: func() -> u32;`
 pub(super) fn get_vibration<T: Host>(
     mut caller: WrappedCaller<'_, T>,
 ) -> Result<u32, wasmi::Error> {
-    T::get_vibration(&mut caller)
+    T::get_vibration(&mut caller).map_err(failed_host_call("get-vibration"))
+}
+/// `get-temperature: func() -> s32;`
+pub(super) fn get_temperature<T: Host>(
+    mut caller: WrappedCaller<'_, T>,
+) -> Result<i32, wasmi::Error> {
+    T::get_temperature(&mut caller).map_err(failed_host_call("get-temperature"))
+}
+/// `get-battery-level: func() -> u8;`
+pub(super) fn get_battery_level<T: Host>(
+    mut caller: WrappedCaller<'_, T>,
+) -> Result<u8, wasmi::Error> {
+    T::get_battery_level(&mut caller).map_err(failed_host_call("get-battery-level"))
 }
 
 /// `get-ble-version: func() -> semantic-version;`
@@ -136,6 +176,7 @@ pub(super) fn configure_advertisement<T: Host>(
     settings: AdvertisementSettings,
 ) -> Result<u32, wasmi::Error> {
     T::configure_advertisement(&mut caller, settings)
+        .map_err(failed_host_call("configure-advertisement"))
 }
 
 /// `set-advertisement-data: func(data: advertisement-data) -> ();`
@@ -143,5 +184,43 @@ pub(super) fn set_advertisement_data<T: Host>(
     mut caller: WrappedCaller<'_, T>,
     data: &[u8],
 ) -> Result<u32, wasmi::Error> {
-    T::set_advertisement_data(&mut caller, data)
+    T::set_advertisement_data(&mut caller, data).map_err(failed_host_call("set-advertisement-data"))
+}
+
+/// `get-connected-devices: func() -> list<u64>;`
+pub(super) fn get_connected_devices<T: Host>(
+    caller: &mut WrappedCaller<'_, T>,
+) -> Result<Vec<u64>, wasmi::Error> {
+    T::get_connected_devices(caller).map_err(failed_host_call("get-connected-devices"))
+}
+
+/// `configure-scan: func(window: u16, interval: u16, active: bool) -> u32;`
+pub(super) fn configure_scan<T: Host>(
+    mut caller: WrappedCaller<'_, T>,
+    window: u16,
+    interval: u16,
+    active: bool,
+) -> Result<u32, wasmi::Error> {
+    T::configure_scan(&mut caller, window, interval, active)
+        .map_err(failed_host_call("configure-scan"))
+}
+
+/// `get-scan-results: func() -> list<advertisement>;`
+pub(super) fn get_scan_results<T: Host>(
+    caller: &mut WrappedCaller<'_, T>,
+) -> Result<Vec<Advertisement>, wasmi::Error> {
+    T::get_scan_results(caller).map_err(failed_host_call("get-scan-results"))
+}
+
+/// `set-group-id: func(id: u16);`
+pub(super) fn set_group_id<T: Host>(
+    mut caller: WrappedCaller<'_, T>,
+    id: u16,
+) -> Result<(), wasmi::Error> {
+    T::set_group_id(&mut caller, id).map_err(failed_host_call("set-group-id"))
+}
+
+/// `get-group-id: func() -> u16;`
+pub(super) fn get_group_id<T: Host>(mut caller: WrappedCaller<'_, T>) -> Result<u16, wasmi::Error> {
+    T::get_group_id(&mut caller).map_err(failed_host_call("get-group-id"))
 }
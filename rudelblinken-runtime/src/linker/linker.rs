@@ -1,9 +1,97 @@
 use crate::host::{
     Advertisement, AdvertisementSettings, Host, LedColor, LedInfo, LogLevel, SemanticVersion,
+    ServiceData,
 };
 use wasmi::{Caller, Extern, Func, Linker, Memory, Store};
+use zerocopy::{Immutable, IntoBytes};
 
-use super::glue;
+use super::{glue, API_VERSIONS, SINCE_0_0_2};
+
+/// An advertisement in the layout of the `advertisement` record in the guest memory
+#[repr(C)]
+#[derive(IntoBytes, Immutable)]
+struct GuestAdvertisement {
+    address: u64,
+    company: u16,
+    _padding0: [u8; 2],
+    /// The tuple of eight u32 has the same layout as the bytes on little endian hosts
+    data: [u8; 32],
+    data_length: u8,
+    /// Placed in the padding before `received_at`, so the record did not change its size
+    rssi: i8,
+    _padding1: [u8; 2],
+    received_at: u64,
+    service_data_ptr: u32,
+    service_data_len: u32,
+}
+
+impl GuestAdvertisement {
+    /// Convert an advertisement whose service data was already written to the guest memory
+    fn new(
+        advertisement: &Advertisement,
+        (service_data_ptr, service_data_len): (u32, u32),
+    ) -> Self {
+        return GuestAdvertisement {
+            address: u64::from_le_bytes(advertisement.address),
+            company: advertisement.company,
+            _padding0: [0; 2],
+            data: advertisement.data,
+            data_length: advertisement.data_length,
+            rssi: advertisement.rssi,
+            _padding1: [0; 2],
+            received_at: advertisement.received_at,
+            service_data_ptr,
+            service_data_len,
+        };
+    }
+}
+
+/// Service data in the layout of the `service-data` record in the guest memory
+#[repr(C)]
+#[derive(IntoBytes, Immutable)]
+struct GuestServiceData {
+    uuid: u16,
+    _padding0: [u8; 2],
+    data_ptr: u32,
+    data_len: u32,
+}
+
+/// Flattened parameters of `on-advertisement`
+type OnAdvertisementParams = (
+    u64,
+    u32,
+    u32,
+    u32,
+    u32,
+    u32,
+    u32,
+    u32,
+    u32,
+    u32,
+    u32,
+    i32,
+    u64,
+    u32,
+    u32,
+);
+/// Flattened parameters of `on-advertisement` for guests built before the advertisement record had service data
+type OnAdvertisementWithoutServiceDataParams = (
+    u64,
+    u32,
+    u32,
+    u32,
+    u32,
+    u32,
+    u32,
+    u32,
+    u32,
+    u32,
+    u32,
+    i32,
+    u64,
+);
+/// Flattened parameters of `on-advertisement` for guests built before the advertisement record had an RSSI field
+type LegacyOnAdvertisementParams = (u64, u32, u32, u32, u32, u32, u32, u32, u32, u32, u32, u64);
 
 #[repr(transparent)]
 pub struct WrappedCaller<'a, T: Host + Sized>(Caller<'a, T>);
@@ -22,7 +110,18 @@ impl<'a, T: Host> WrappedCaller<'a, T> {
         return self.0.data_mut();
     }
 
-    fn realloc(
+    /// Get an export of the guest, that may belong to any of the supported API versions
+    fn get_versioned_export(&self, interface: &str, function: &str) -> Option<Extern> {
+        return API_VERSIONS.iter().find_map(|version| {
+            self.0
+                .get_export(&format!("{}@{}#{}", interface, version, function))
+        });
+    }
+
+    /// Call the `cabi_realloc` function of the guest
+    ///
+    /// Allocates `new_size` bytes with the given alignment, if `ptr` and `old_size` are 0.
+    pub(crate) fn realloc(
         &mut self,
         ptr: u32,
         old_size: u32,
@@ -44,8 +143,45 @@ impl<'a, T: Host> WrappedCaller<'a, T> {
         run.call(&mut self.0, (ptr, old_size, align, new_size))
     }
 
+    /// Copy a list into a new allocation in the guest memory
+    ///
+    /// Returns the pointer and the number of elements, as expected by the canonical ABI for lists. The allocation is owned by the guest, which is responsible for freeing it. The host must not access it after passing it to the guest.
+    pub fn write_list<D: IntoBytes + Immutable>(
+        &mut self,
+        data: &[D],
+    ) -> Result<(u32, u32), wasmi::Error> {
+        let bytes = data.as_bytes();
+        let ptr = self.realloc(0, 0, align_of::<D>() as u32, bytes.len() as u32)?;
+        let memory = get_memory(&self.0)?;
+        let destination = get_mut_slice(&memory, &mut self.0, ptr, bytes.len() as u32)?;
+        destination.copy_from_slice(bytes);
+        return Ok((ptr, data.len() as u32));
+    }
+
+    /// Copy the service data of an advertisement into the guest memory
+    ///
+    /// Returns the pointer and the number of elements of the `service-data` list. Like [WrappedCaller::write_list], the guest owns all allocations.
+    fn write_service_data(
+        &mut self,
+        service_data: &[ServiceData],
+    ) -> Result<(u32, u32), wasmi::Error> {
+        let service_data = service_data
+            .iter()
+            .map(|service_data| -> Result<GuestServiceData, wasmi::Error> {
+                let (data_ptr, data_len) = self.write_list(&service_data.data)?;
+                return Ok(GuestServiceData {
+                    uuid: service_data.uuid,
+                    _padding0: [0; 2],
+                    data_ptr,
+                    data_len,
+                });
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        return self.write_list(&service_data);
+    }
+
     pub fn run(&mut self) -> Result<(), wasmi::Error> {
-        let Some(run) = self.0.get_export("rudel:base/run@0.0.1#run") else {
+        let Some(run) = self.get_versioned_export("rudel:base/run", "run") else {
             return Err(wasmi::Error::new("run not found"));
         };
         let Extern::Func(run) = run else {
@@ -60,27 +196,72 @@ impl<'a, T: Host> WrappedCaller<'a, T> {
         return Ok(());
     }
 
+    /// Call the `on-advertisement` function of the guest
+    ///
+    /// Guests that were built before the advertisement record had service data or an RSSI field get the advertisement without them.
     pub fn on_advertisement(&mut self, advertisement: Advertisement) -> Result<(), wasmi::Error> {
-        let Some(run) = self
-            .0
-            .get_export("rudel:base/ble-guest@0.0.1#on-advertisement")
+        let Some(run) = self.get_versioned_export("rudel:base/ble-guest", "on-advertisement")
         else {
             return Err(wasmi::Error::new("on-advertisement not found"));
         };
         let Extern::Func(run) = run else {
             return Err(wasmi::Error::new("on-advertisement is not a function"));
         };
-        let Ok(run) =
-            run.typed::<(u64, u32, u32, u32, u32, u32, u32, u32, u32, u32, u32, u64), ()>(&self.0)
-        else {
-            return Err(wasmi::Error::new(
-                "on-advertisement does not have a matching function signature",
-            ));
-        };
 
         let address = u64::from_le_bytes(advertisement.address);
         let company = advertisement.company as u32;
         let data = unsafe { std::mem::transmute::<[u8; 32], [u32; 8]>(advertisement.data) };
+        if let Ok(run) = run.typed::<OnAdvertisementParams, ()>(&self.0) {
+            let (service_data_ptr, service_data_len) =
+                self.write_service_data(&advertisement.service_data)?;
+            run.call(
+                &mut self.0,
+                (
+                    address,
+                    company,
+                    data[0],
+                    data[1],
+                    data[2],
+                    data[3],
+                    data[4],
+                    data[5],
+                    data[6],
+                    data[7],
+                    advertisement.data_length as u32,
+                    advertisement.rssi as i32,
+                    advertisement.received_at,
+                    service_data_ptr,
+                    service_data_len,
+                ),
+            )?;
+            return Ok(());
+        }
+        if let Ok(run) = run.typed::<OnAdvertisementWithoutServiceDataParams, ()>(&self.0) {
+            run.call(
+                &mut self.0,
+                (
+                    address,
+                    company,
+                    data[0],
+                    data[1],
+                    data[2],
+                    data[3],
+                    data[4],
+                    data[5],
+                    data[6],
+                    data[7],
+                    advertisement.data_length as u32,
+                    advertisement.rssi as i32,
+                    advertisement.received_at,
+                ),
+            )?;
+            return Ok(());
+        }
+        let Ok(run) = run.typed::<LegacyOnAdvertisementParams, ()>(&self.0) else {
+            return Err(wasmi::Error::new(
+                "on-advertisement does not have a matching function signature",
+            ));
+        };
         run.call(
             &mut self.0,
             (
@@ -100,6 +281,25 @@ impl<'a, T: Host> WrappedCaller<'a, T> {
         )?;
         return Ok(());
     }
+
+    /// Call the `on-timer` function of the guest
+    ///
+    /// Guests that do not use timers do not need to export it, so a missing export is ignored.
+    pub fn on_timer(&mut self, id: u8, fired_at: u64) -> Result<(), wasmi::Error> {
+        let Some(run) = self.get_versioned_export("rudel:base/timer-guest", "on-timer") else {
+            return Ok(());
+        };
+        let Extern::Func(run) = run else {
+            return Err(wasmi::Error::new("on-timer is not a function"));
+        };
+        let Ok(run) = run.typed::<(u32, u64), ()>(&self.0) else {
+            return Err(wasmi::Error::new(
+                "on-timer does not have a matching function signature",
+            ));
+        };
+        run.call(&mut self.0, (id as u32, fired_at))?;
+        return Ok(());
+    }
 }
 
 impl<'a, T: Host> AsRef<Caller<'a, T>> for WrappedCaller<'a, T> {
@@ -177,16 +377,20 @@ fn get_mut_array<T: Host, const L: usize>(
     return Ok(static_result);
 }
 
-/// Link the host functions provided by T.
+/// Define a host function in the given module for each of the given API versions
 ///
-/// This functions will provide the rudel-host functions to the linker by generating glue code for the functionality provided by the host implementation T
+/// This allows guests that were built against older, compatible versions of the API to be linked as well.
 pub fn link_function<T: Host>(
     linker: &mut Linker<T>,
     module: &str,
     function: &str,
+    versions: &[&str],
     implementation: impl Into<Extern>,
 ) -> Result<(), wasmi::Error> {
-    linker.define(&format!("{}@0.0.1", module), function, implementation)?;
+    let implementation = implementation.into();
+    for version in versions {
+        linker.define(&format!("{}@{}", module, version), function, implementation)?;
+    }
     return Ok(());
 }
 
@@ -203,6 +407,7 @@ pub fn link_base<T: Host>(
         linker,
         "rudel:base/base",
         "get-base-version",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>, offset: i32| -> Result<(), wasmi::Error> {
@@ -221,12 +426,42 @@ pub fn link_base<T: Host>(
         ),
     )?;
 
+    // __attribute__((__import_module__("rudel:base/base@0.0.2"), __import_name__("version-satisfies")))
+    // extern int32_t __wasm_import_rudel_base_base_version_satisfies(int32_t, int32_t, int32_t, int32_t, int32_t, int32_t);
+    link_function(
+        linker,
+        "rudel:base/base",
+        "version-satisfies",
+        SINCE_0_0_2,
+        Func::wrap(
+            &mut store,
+            |caller: Caller<'_, T>,
+             major: i32,
+             minor: i32,
+             patch: i32,
+             required_major: i32,
+             required_minor: i32,
+             required_patch: i32|
+             -> Result<i32, wasmi::Error> {
+                let caller = WrappedCaller(caller);
+                let version = SemanticVersion::new(major as u8, minor as u8, patch as u8);
+                let required = SemanticVersion::new(
+                    required_major as u8,
+                    required_minor as u8,
+                    required_patch as u8,
+                );
+                return Ok(glue::version_satisfies(caller, version, required)? as i32);
+            },
+        ),
+    )?;
+
     // __attribute__((__import_module__("rudel:base/base@0.0.1"), __import_name__("yield-now")))
     // extern void __wasm_import_rudel_base_base_yield_now(void);
     link_function(
         linker,
         "rudel:base/base",
         "yield-now",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>, micros: u64| -> Result<u32, wasmi::Error> {
@@ -242,6 +477,7 @@ pub fn link_base<T: Host>(
         linker,
         "rudel:base/base",
         "sleep",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>, micros: u64| -> Result<(), wasmi::Error> {
@@ -257,6 +493,7 @@ pub fn link_base<T: Host>(
         linker,
         "rudel:base/base",
         "time",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>| -> Result<u64, wasmi::Error> {
@@ -266,12 +503,45 @@ pub fn link_base<T: Host>(
         ),
     )?;
 
+    // __attribute__((__import_module__("rudel:base/base@0.0.2"), __import_name__("set-timer")))
+    // extern void __wasm_import_rudel_base_base_set_timer(int32_t, int64_t);
+    link_function(
+        linker,
+        "rudel:base/base",
+        "set-timer",
+        SINCE_0_0_2,
+        Func::wrap(
+            &mut store,
+            |caller: Caller<'_, T>, id: i32, delay_us: u64| -> Result<(), wasmi::Error> {
+                let caller = WrappedCaller(caller);
+                return glue::set_timer(caller, id as u8, delay_us);
+            },
+        ),
+    )?;
+
+    // __attribute__((__import_module__("rudel:base/base@0.0.2"), __import_name__("cancel-timer")))
+    // extern void __wasm_import_rudel_base_base_cancel_timer(int32_t);
+    link_function(
+        linker,
+        "rudel:base/base",
+        "cancel-timer",
+        SINCE_0_0_2,
+        Func::wrap(
+            &mut store,
+            |caller: Caller<'_, T>, id: i32| -> Result<(), wasmi::Error> {
+                let caller = WrappedCaller(caller);
+                return glue::cancel_timer(caller, id as u8);
+            },
+        ),
+    )?;
+
     // __attribute__((__import_module__("rudel:base/base@0.0.1"), __import_name__("log")))
     // extern void __wasm_import_rudel_base_base_log(int32_t, uint8_t *, size_t);
     link_function(
         linker,
         "rudel:base/base",
         "log",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>,
@@ -300,6 +570,7 @@ pub fn link_base<T: Host>(
         linker,
         "rudel:base/base",
         "get-name",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>, offset: i32| -> Result<(), wasmi::Error> {
@@ -317,6 +588,7 @@ pub fn link_base<T: Host>(
         linker,
         "rudel:base/base",
         "get-config",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>, ret: i32| -> Result<(), wasmi::Error> {
@@ -369,6 +641,7 @@ pub fn link_hardware<T: Host>(
         linker,
         "rudel:base/hardware",
         "get-hardware-version",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>, offset: i32| -> Result<(), wasmi::Error> {
@@ -393,6 +666,7 @@ pub fn link_hardware<T: Host>(
         linker,
         "rudel:base/hardware",
         "set-leds",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>,
@@ -420,6 +694,7 @@ pub fn link_hardware<T: Host>(
         linker,
         "rudel:base/hardware",
         "set-rgb",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>,
@@ -446,6 +721,7 @@ pub fn link_hardware<T: Host>(
         linker,
         "rudel:base/hardware",
         "led-count",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>| -> Result<i32, wasmi::Error> {
@@ -455,30 +731,70 @@ pub fn link_hardware<T: Host>(
         ),
     )?;
 
-    // __attribute__((__import_module__("rudel:base/hardware@0.0.1"), __import_name__("get-led-info")))
+    // __attribute__((__import_module__("rudel:base/hardware@0.0.2"), __import_name__("get-led-info")))
     // extern void __wasm_import_rudel_base_hardware_get_led_info(int32_t, uint8_t *);
     link_function(
         linker,
         "rudel:base/hardware",
         "get-led-info",
+        SINCE_0_0_2,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>, id: i32, offset: i32| -> Result<(), wasmi::Error> {
                 let mut caller = WrappedCaller(caller);
                 let memory = get_memory(caller.as_ref())?;
-                let slice = get_mut_slice(&memory, caller.as_mut(), offset as u32, 6)?;
+                let slice = get_mut_slice(
+                    &memory,
+                    caller.as_mut(),
+                    offset as u32,
+                    std::mem::size_of::<LedInfo>() as u32,
+                )?;
                 // Layout in memory is
                 // 0: red
                 // 1: green
                 // 2: blue
-                // 4: -
-                // 5: lux_high
-                // 6: lux_low
+                // 3: -
+                // 4-5: max_lux
+                // 6-7: color_temperature_k
+                // 8-9: wavelength_nm
+                // 10-11: -
+                // 12-47: white_balance as 9 row-major f32 values
                 // SAFETY: Should be safe because the layout should match
                 let led_info_ptr =
                     unsafe { std::mem::transmute::<*mut u8, *mut LedInfo>(slice.as_mut_ptr()) };
                 let led_info = unsafe { &mut *led_info_ptr };
-                return glue::get_led_info(caller, id as u16, led_info);
+                *led_info = glue::get_led_info(caller, id as u16)?;
+                return Ok(());
+            },
+        ),
+    )?;
+
+    // The led-info record of 0.0.1 ends after max-lux, so the calibration fields must not be written
+    // __attribute__((__import_module__("rudel:base/hardware@0.0.1"), __import_name__("get-led-info")))
+    // extern void __wasm_import_rudel_base_hardware_get_led_info(int32_t, uint8_t *);
+    link_function(
+        linker,
+        "rudel:base/hardware",
+        "get-led-info",
+        &["0.0.1"],
+        Func::wrap(
+            &mut store,
+            |caller: Caller<'_, T>, id: i32, offset: i32| -> Result<(), wasmi::Error> {
+                let mut caller = WrappedCaller(caller);
+                let memory = get_memory(caller.as_ref())?;
+                let slice = get_mut_array::<T, 6>(&memory, caller.as_mut(), offset)?;
+                let led_info = glue::get_led_info(caller, id as u16)?;
+                // Layout in memory is
+                // 0: red
+                // 1: green
+                // 2: blue
+                // 3: -
+                // 4-5: max_lux
+                slice[0] = led_info.color.red;
+                slice[1] = led_info.color.green;
+                slice[2] = led_info.color.blue;
+                slice[4..6].copy_from_slice(&led_info.max_lux.to_le_bytes());
+                return Ok(());
             },
         ),
     )?;
@@ -489,6 +805,7 @@ pub fn link_hardware<T: Host>(
         linker,
         "rudel:base/hardware",
         "get-ambient-light-type",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>| -> Result<i32, wasmi::Error> {
@@ -504,6 +821,7 @@ pub fn link_hardware<T: Host>(
         linker,
         "rudel:base/hardware",
         "get-ambient-light",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>| -> Result<i32, wasmi::Error> {
@@ -513,12 +831,29 @@ pub fn link_hardware<T: Host>(
         ),
     )?;
 
+    // __attribute__((__import_module__("rudel:base/hardware@0.0.2"), __import_name__("get-ambient-light-raw")))
+    // extern int32_t __wasm_import_rudel_base_hardware_get_ambient_light_raw(void);
+    link_function(
+        linker,
+        "rudel:base/hardware",
+        "get-ambient-light-raw",
+        SINCE_0_0_2,
+        Func::wrap(
+            &mut store,
+            |caller: Caller<'_, T>| -> Result<i32, wasmi::Error> {
+                let caller = WrappedCaller(caller);
+                return glue::get_ambient_light_raw(caller).map(|result| result as i32);
+            },
+        ),
+    )?;
+
     // __attribute__((__import_module__("rudel:base/hardware@0.0.1"), __import_name__("get-vibration-sensor-type")))
     // extern int32_t __wasm_import_rudel_base_hardware_vibration_type(void);
     link_function(
         linker,
         "rudel:base/hardware",
         "get-vibration-sensor-type",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>| -> Result<i32, wasmi::Error> {
@@ -534,6 +869,7 @@ pub fn link_hardware<T: Host>(
         linker,
         "rudel:base/hardware",
         "get-vibration",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>| -> Result<i32, wasmi::Error> {
@@ -543,6 +879,38 @@ pub fn link_hardware<T: Host>(
         ),
     )?;
 
+    // __attribute__((__import_module__("rudel:base/hardware@0.0.2"), __import_name__("get-temperature")))
+    // extern int32_t __wasm_import_rudel_base_hardware_get_temperature(void);
+    link_function(
+        linker,
+        "rudel:base/hardware",
+        "get-temperature",
+        SINCE_0_0_2,
+        Func::wrap(
+            &mut store,
+            |caller: Caller<'_, T>| -> Result<i32, wasmi::Error> {
+                let caller = WrappedCaller(caller);
+                return glue::get_temperature(caller);
+            },
+        ),
+    )?;
+
+    // __attribute__((__import_module__("rudel:base/hardware@0.0.2"), __import_name__("get-battery-level")))
+    // extern int32_t __wasm_import_rudel_base_hardware_get_battery_level(void);
+    link_function(
+        linker,
+        "rudel:base/hardware",
+        "get-battery-level",
+        SINCE_0_0_2,
+        Func::wrap(
+            &mut store,
+            |caller: Caller<'_, T>| -> Result<u32, wasmi::Error> {
+                let caller = WrappedCaller(caller);
+                return glue::get_battery_level(caller).map(|level| level as u32);
+            },
+        ),
+    )?;
+
     return Ok(());
 }
 
@@ -559,6 +927,7 @@ pub fn link_ble<T: Host>(
         linker,
         "rudel:base/ble",
         "get-ble-version",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>, offset: i32| -> Result<(), wasmi::Error> {
@@ -583,6 +952,7 @@ pub fn link_ble<T: Host>(
         linker,
         "rudel:base/ble",
         "configure-advertisement",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>,
@@ -608,6 +978,7 @@ pub fn link_ble<T: Host>(
         linker,
         "rudel:base/ble",
         "set-advertisement-data",
+        API_VERSIONS,
         Func::wrap(
             &mut store,
             |caller: Caller<'_, T>, offset: i32, length: i32| -> Result<u32, wasmi::Error> {
@@ -622,5 +993,120 @@ pub fn link_ble<T: Host>(
         ),
     )?;
 
+    // __attribute__((__import_module__("rudel:base/ble@0.0.2"), __import_name__("get-connected-devices")))
+    // extern void __wasm_import_rudel_base_ble_get_connected_devices(uint8_t *);
+    link_function(
+        linker,
+        "rudel:base/ble",
+        "get-connected-devices",
+        SINCE_0_0_2,
+        Func::wrap(
+            &mut store,
+            |caller: Caller<'_, T>, ret: i32| -> Result<(), wasmi::Error> {
+                let mut caller = WrappedCaller(caller);
+                let devices = glue::get_connected_devices(&mut caller)?;
+                let (ptr, len) = caller.write_list(&devices)?;
+
+                // typedef struct {
+                //   uint64_t *ptr;
+                //   size_t len;
+                // } rudel_list_u64_t;
+                let memory = get_memory(caller.as_ref())?;
+                let list_header = get_mut_array::<T, 8>(&memory, caller.as_mut(), ret)?;
+                list_header[0..4].copy_from_slice(&ptr.to_le_bytes());
+                list_header[4..8].copy_from_slice(&len.to_le_bytes());
+                Ok(())
+            },
+        ),
+    )?;
+
+    // __attribute__((__import_module__("rudel:base/ble@0.0.2"), __import_name__("configure-scan")))
+    // extern int32_t __wasm_import_rudel_base_ble_configure_scan(int32_t, int32_t, int32_t);
+    link_function(
+        linker,
+        "rudel:base/ble",
+        "configure-scan",
+        SINCE_0_0_2,
+        Func::wrap(
+            &mut store,
+            |caller: Caller<'_, T>,
+             window: i32,
+             interval: i32,
+             active: i32|
+             -> Result<u32, wasmi::Error> {
+                let caller = WrappedCaller(caller);
+                glue::configure_scan(caller, window as u16, interval as u16, active != 0)
+            },
+        ),
+    )?;
+
+    // __attribute__((__import_module__("rudel:base/ble@0.0.2"), __import_name__("get-scan-results")))
+    // extern void __wasm_import_rudel_base_ble_get_scan_results(uint8_t *);
+    link_function(
+        linker,
+        "rudel:base/ble",
+        "get-scan-results",
+        SINCE_0_0_2,
+        Func::wrap(
+            &mut store,
+            |caller: Caller<'_, T>, ret: i32| -> Result<(), wasmi::Error> {
+                let mut caller = WrappedCaller(caller);
+                let advertisements = glue::get_scan_results(&mut caller)?
+                    .iter()
+                    .map(
+                        |advertisement| -> Result<GuestAdvertisement, wasmi::Error> {
+                            let service_data =
+                                caller.write_service_data(&advertisement.service_data)?;
+                            return Ok(GuestAdvertisement::new(advertisement, service_data));
+                        },
+                    )
+                    .collect::<Result<Vec<_>, _>>()?;
+                let (ptr, len) = caller.write_list(&advertisements)?;
+
+                // typedef struct {
+                //   rudel_advertisement_t *ptr;
+                //   size_t len;
+                // } rudel_list_advertisement_t;
+                let memory = get_memory(caller.as_ref())?;
+                let list_header = get_mut_array::<T, 8>(&memory, caller.as_mut(), ret)?;
+                list_header[0..4].copy_from_slice(&ptr.to_le_bytes());
+                list_header[4..8].copy_from_slice(&len.to_le_bytes());
+                Ok(())
+            },
+        ),
+    )?;
+
+    // __attribute__((__import_module__("rudel:base/ble@0.0.2"), __import_name__("set-group-id")))
+    // extern void __wasm_import_rudel_base_ble_set_group_id(int32_t);
+    link_function(
+        linker,
+        "rudel:base/ble",
+        "set-group-id",
+        SINCE_0_0_2,
+        Func::wrap(
+            &mut store,
+            |caller: Caller<'_, T>, id: i32| -> Result<(), wasmi::Error> {
+                let caller = WrappedCaller(caller);
+                glue::set_group_id(caller, id as u16)
+            },
+        ),
+    )?;
+
+    // __attribute__((__import_module__("rudel:base/ble@0.0.2"), __import_name__("get-group-id")))
+    // extern int32_t __wasm_import_rudel_base_ble_get_group_id(void);
+    link_function(
+        linker,
+        "rudel:base/ble",
+        "get-group-id",
+        SINCE_0_0_2,
+        Func::wrap(
+            &mut store,
+            |caller: Caller<'_, T>| -> Result<u32, wasmi::Error> {
+                let caller = WrappedCaller(caller);
+                return glue::get_group_id(caller).map(|id| id as u32);
+            },
+        ),
+    )?;
+
     return Ok(());
 }
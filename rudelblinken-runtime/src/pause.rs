@@ -0,0 +1,44 @@
+//! Pause a running guest from another thread
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Pauses a guest at its next yield until it is resumed
+///
+/// Clones share their state, so a host can keep one and hand out clones to the code that needs to pause the guest, for example while flash operations lock the SPI bus.
+#[derive(Clone, Debug, Default)]
+pub struct PauseHandle {
+    paused: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl PauseHandle {
+    pub fn new() -> Self {
+        return PauseHandle::default();
+    }
+
+    /// Block the guest at its next yield until [PauseHandle::resume] is called
+    pub fn pause(&self) {
+        let (paused, _) = &*self.paused;
+        *paused.lock().unwrap() = true;
+    }
+
+    /// Continue a paused guest
+    pub fn resume(&self) {
+        let (paused, resumed) = &*self.paused;
+        *paused.lock().unwrap() = false;
+        resumed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        let (paused, _) = &*self.paused;
+        return *paused.lock().unwrap();
+    }
+
+    /// Block while the guest is paused
+    ///
+    /// Hosts call this at the start of [crate::host::Host::yield_now].
+    pub fn wait_while_paused(&self) {
+        let (paused, resumed) = &*self.paused;
+        let _guard = resumed
+            .wait_while(paused.lock().unwrap(), |paused| *paused)
+            .unwrap();
+    }
+}
@@ -0,0 +1,55 @@
+//! Software timers that fire while the guest yields
+use crate::host::Event;
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+/// A timer that fires at a fixed time
+///
+/// Ordered by the time it fires, so the next timer is at the top of a min-heap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimerEntry {
+    /// Time in microseconds as returned by [crate::host::Host::time]
+    pub fire_at: u64,
+    pub id: u8,
+}
+
+/// The pending timers of a guest
+///
+/// Every id can only be pending once, setting a timer again replaces it. Hosts check for expired timers in [crate::host::Host::yield_now] and pass them to the guest as [Event::TimerFired].
+#[derive(Clone, Debug, Default)]
+pub struct Timers {
+    pending: BinaryHeap<Reverse<TimerEntry>>,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        return Timers::default();
+    }
+
+    /// Fire the timer with the given id at `fire_at`
+    pub fn set(&mut self, id: u8, fire_at: u64) {
+        self.cancel(id);
+        self.pending.push(Reverse(TimerEntry { fire_at, id }));
+    }
+
+    /// Remove the timer with the given id, if it is pending
+    pub fn cancel(&mut self, id: u8) {
+        self.pending.retain(|Reverse(entry)| entry.id != id);
+    }
+
+    /// The time the next timer fires
+    pub fn next_fire_at(&self) -> Option<u64> {
+        return self.pending.peek().map(|Reverse(entry)| entry.fire_at);
+    }
+
+    /// Remove the earliest timer that expired at `now` and turn it into an event
+    pub fn pop_expired(&mut self, now: u64) -> Option<Event> {
+        if self.next_fire_at()? > now {
+            return None;
+        }
+        let Reverse(entry) = self.pending.pop()?;
+        return Some(Event::TimerFired {
+            id: entry.id,
+            fired_at: now,
+        });
+    }
+}
@@ -0,0 +1,124 @@
+//! A received BLE advertisement with helpers to access its contents
+//!
+//! ```ignore
+//! fn on_advertisement(advertisement: Advertisement) {
+//!     let advertisement = BleAdvertisement::new(advertisement);
+//!     if let Some(received) = advertisement.try_parse_rudelblinken() {
+//!         log(LogLevel::Info, &format!("Device {} is in group {}", received.device_id, received.group_id));
+//!     }
+//! }
+//! ```
+use crate::{Advertisement, RudelAdvertisement};
+
+/// A received BLE advertisement
+#[derive(Debug, Clone)]
+pub struct BleAdvertisement {
+    advertisement: Advertisement,
+    rssi: Option<i8>,
+}
+
+impl BleAdvertisement {
+    /// Wrap an advertisement passed to [crate::BleGuest::on_advertisement]
+    ///
+    /// The RSSI is taken from the advertisement, unless the host reported it as unknown.
+    pub fn new(advertisement: Advertisement) -> Self {
+        let rssi = (advertisement.rssi != 0).then_some(advertisement.rssi);
+        return BleAdvertisement {
+            advertisement,
+            rssi,
+        };
+    }
+
+    /// Wrap an advertisement with a known RSSI in dBm, overriding the one reported by the host
+    pub fn with_rssi(advertisement: Advertisement, rssi: i8) -> Self {
+        return BleAdvertisement {
+            advertisement,
+            rssi: Some(rssi),
+        };
+    }
+
+    /// The wrapped advertisement
+    pub fn advertisement(&self) -> &Advertisement {
+        return &self.advertisement;
+    }
+
+    /// Get the manufacturer data, if it was sent with the given company ID
+    ///
+    /// The company ID itself is not included.
+    pub fn manufacturer_data_for(&self, id: u16) -> Option<&[u8]> {
+        if self.advertisement.company != id {
+            return None;
+        }
+        return Some(self.advertisement.get_data());
+    }
+
+    /// Get the service data for the given 16 bit service UUID
+    ///
+    /// The UUID itself is not included. If the advertisement contains multiple entries for the UUID, the first one is returned.
+    pub fn service_data_for(&self, uuid: u16) -> Option<&[u8]> {
+        return self
+            .advertisement
+            .service_data
+            .iter()
+            .find(|service_data| service_data.uuid == uuid)
+            .map(|service_data| service_data.data.as_slice());
+    }
+
+    /// Signal strength of the advertisement in dBm, if known
+    pub fn rssi(&self) -> Option<i8> {
+        return self.rssi;
+    }
+
+    /// MAC address of the sender. Only the lower 48 bits are used.
+    pub fn address(&self) -> u64 {
+        return self.advertisement.address;
+    }
+
+    /// Time at which the advertisement was received in microseconds, see [crate::time]
+    pub fn received_at_us(&self) -> u64 {
+        return self.advertisement.received_at;
+    }
+
+    /// Decode the advertisement as a rudelblinken sync advertisement. Returns `None` if it is not one.
+    pub fn try_parse_rudelblinken(&self) -> Option<RudelAdvertisement> {
+        return RudelAdvertisement::from_advertisement(&self.advertisement);
+    }
+}
+
+impl From<Advertisement> for BleAdvertisement {
+    fn from(advertisement: Advertisement) -> Self {
+        return BleAdvertisement::new(advertisement);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ServiceData;
+
+    #[test]
+    fn service_data_is_found_by_uuid() {
+        let advertisement = BleAdvertisement::new(Advertisement {
+            address: 0,
+            company: 0,
+            data: (0, 0, 0, 0, 0, 0, 0, 0),
+            data_length: 0,
+            rssi: 0,
+            received_at: 0,
+            service_data: vec![
+                ServiceData {
+                    uuid: 0x181a,
+                    data: vec![1, 2],
+                },
+                ServiceData {
+                    uuid: 0x181c,
+                    data: vec![3],
+                },
+            ],
+        });
+        assert_eq!(advertisement.service_data_for(0x181c), Some(&[3][..]));
+        assert_eq!(advertisement.service_data_for(0x181a), Some(&[1, 2][..]));
+        assert_eq!(advertisement.service_data_for(0x1800), None);
+        assert_eq!(advertisement.manufacturer_data_for(0x0059), None);
+    }
+}
@@ -0,0 +1,74 @@
+//! Key-value pairs in the guest configuration
+//!
+//! The configuration that is set over BLE (for example with `rudelctl set-wasm-config`) is a sequence of entries. Every entry is a key and a value, each prefixed with its length as a single byte:
+//!
+//! ```text
+//! [key length] [key] [value length] [value] [key length] [key] ...
+//! ```
+//!
+//! Keys are UTF-8 strings. Numbers are stored as little-endian bytes, booleans as a single `0` or `1` byte and strings as UTF-8 without a terminator.
+use crate::get_config;
+
+/// Iterate over the entries of an encoded configuration
+///
+/// Stops at the first entry that is not valid.
+pub fn config_entries(config: &[u8]) -> impl Iterator<Item = (&str, &[u8])> {
+    let mut remaining = config;
+    return std::iter::from_fn(move || {
+        let (key, rest) = split_entry(remaining)?;
+        let key = std::str::from_utf8(key).ok()?;
+        let (value, rest) = split_entry(rest)?;
+        remaining = rest;
+        return Some((key, value));
+    });
+}
+
+/// Split a length-prefixed field from the start of `data`
+fn split_entry(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (length, rest) = data.split_first()?;
+    if rest.len() < *length as usize {
+        return None;
+    }
+    return Some(rest.split_at(*length as usize));
+}
+
+/// Get the raw value of a key from the configuration of this guest
+pub fn get_config_value(key: &str) -> Option<Vec<u8>> {
+    let config = get_config();
+    return config_entries(&config)
+        .find(|(entry_key, _)| *entry_key == key)
+        .map(|(_, value)| value.to_vec());
+}
+
+/// Get a string from the configuration of this guest
+pub fn get_config_string(key: &str) -> Option<String> {
+    return String::from_utf8(get_config_value(key)?).ok();
+}
+
+/// Get a `u32` from the configuration of this guest
+///
+/// Shorter values are zero-extended, so values that were set as `u8` or `u16` can be read as well.
+pub fn get_config_u32(key: &str) -> Option<u32> {
+    let value = get_config_value(key)?;
+    if value.len() > 4 {
+        return None;
+    }
+    let mut bytes = [0u8; 4];
+    bytes[..value.len()].copy_from_slice(&value);
+    return Some(u32::from_le_bytes(bytes));
+}
+
+/// Get an `f32` from the configuration of this guest
+pub fn get_config_f32(key: &str) -> Option<f32> {
+    let value: [u8; 4] = get_config_value(key)?.try_into().ok()?;
+    return Some(f32::from_le_bytes(value));
+}
+
+/// Get a boolean from the configuration of this guest
+pub fn get_config_bool(key: &str) -> Option<bool> {
+    return match get_config_value(key)?.as_slice() {
+        [0] => Some(false),
+        [1] => Some(true),
+        _ => None,
+    };
+}
@@ -0,0 +1,694 @@
+//! Synchronize a cyclic animation between all devices in a group
+//!
+//! Every device advances a progress counter that wraps around every 256 steps. Devices broadcast their progress in their advertisements and nudge their own progress towards the progress of the other devices in the same group.
+//!
+//! One device in each group acts as the leader. The leader never nudges towards other devices, while followers nudge towards the leader with a higher weight than towards other followers. By default the device with the lowest device ID that was seen recently becomes the leader.
+//!
+//! The leader also broadcasts its group time, the milliseconds since the group epoch. Followers calibrate their own group time from the leader's advertisements, so all devices share a common time base that can be used for coordination based on wall-clock time.
+//!
+//! If the signal strength of received advertisements is known, nearby devices can be given more influence than distant ones with [GroupSync::set_rssi_range].
+//!
+//! Devices that are out of range of each other can still be synchronized by enabling relaying with [GroupSync::enable_relay]. Relaying devices rebroadcast the advertisements they receive, alternating with their own advertisement. Every advertisement carries the number of remaining hops, which is decremented on every relay. Advertisements are identified by the device ID and time of the original sender, so duplicates and advertisements that looped back are dropped.
+//!
+//! ```ignore
+//! let mut sync = GroupSync::new();
+//! loop {
+//!     let progress = sync.update((time() / 1000) as u32);
+//!     set_advertisement_data(&sync.advertisement_data());
+//!     yield_now(1_000);
+//! }
+//! ```
+use crate::{
+    get_device_id, get_group_id, time, Advertisement, BleAdvertisement, RudelblinkenAdvertisement,
+};
+use std::collections::VecDeque;
+
+/// Length of the user data of a sync advertisement
+const PAYLOAD_LENGTH: usize = 10;
+/// Flag that is set, if the sender is the leader of its group
+const FLAG_LEADER: u8 = 0b0000_0001;
+/// The remaining hops are stored in the upper four bits of the flags
+const HOPS_SHIFT: u8 = 4;
+/// Maximum number of hops that fits into the flags
+pub const MAX_HOPS: u8 = 0x0f;
+
+/// Duration of a progress step in milliseconds
+const MS_PER_STEP: u32 = 16;
+/// Higher values make the nudging towards other devices slower
+const NUDGE_STRENGTH: i32 = 20;
+/// Weight of the leader's progress for followers, if no weight was set
+const DEFAULT_LEADER_WEIGHT: u8 = 8;
+/// A device is no longer considered for the automatic leader election, if it was not seen for this long
+const LEADER_TIMEOUT_MS: u32 = 5_000;
+/// Higher values make the calibration of the group time slower, but less susceptible to jitter
+const CALIBRATION_SMOOTHING: i64 = 8;
+/// If the estimated group time differs from the current one by more than this, the estimate is used directly instead of smoothing
+const CALIBRATION_JUMP_MS: i64 = 1_000;
+/// Number of recently received advertisements that are remembered to detect duplicates
+const RECENT_CAPACITY: usize = 32;
+/// Maximum number of advertisements waiting to be relayed
+const RELAY_QUEUE_CAPACITY: usize = 8;
+/// Duration in milliseconds for which a relayed advertisement or the own advertisement is broadcast before switching to the other
+const RELAY_SLOT_MS: u32 = 200;
+/// Fixed point scale of the RSSI weight. A weight of `RSSI_WEIGHT_SCALE` is a factor of 1.0
+const RSSI_WEIGHT_SCALE: i32 = 100;
+/// Lowest RSSI weight, so distant devices still have some influence
+const MIN_RSSI_WEIGHT: i32 = 10;
+
+/// The role of this device in its group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupRole {
+    /// Broadcast the progress without nudging towards other devices
+    Leader,
+    /// Nudge towards other devices. Nudges towards the leader are multiplied by the given weight, while nudges towards other followers have a weight of 1.
+    ///
+    /// A weight of 20 or more moves straight to the progress of the leader.
+    Follower(u8),
+}
+
+/// A decoded rudelblinken sync advertisement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RudelAdvertisement {
+    /// Group of the sender. This is not part of the encoded advertisement, the host sends it in the rudelblinken header.
+    pub group_id: u16,
+    /// The sender is the leader of its group
+    pub leader: bool,
+    /// Progress of the sender
+    pub progress: u8,
+    /// Device ID of the original sender
+    pub device_id: u32,
+    /// How often this advertisement may still be relayed
+    pub hops: u8,
+    /// Group time of the original sender in milliseconds since the group epoch
+    pub time: u32,
+    /// Local time in milliseconds at which the advertisement was received. This is not part of the encoded advertisement.
+    pub received_at: u32,
+}
+
+impl RudelAdvertisement {
+    /// Decode the user data of a rudelblinken advertisement. Returns `None` if it is not a sync advertisement.
+    pub fn decode(advertisement: &RudelblinkenAdvertisement) -> Option<Self> {
+        let data = advertisement.user_data();
+        if data.len() != PAYLOAD_LENGTH {
+            return None;
+        }
+        return Some(RudelAdvertisement {
+            group_id: advertisement.group_id(),
+            leader: data[0] & FLAG_LEADER != 0,
+            progress: data[1],
+            hops: data[0] >> HOPS_SHIFT,
+            device_id: u32::from_le_bytes([data[2], data[3], data[4], data[5]]),
+            time: u32::from_le_bytes([data[6], data[7], data[8], data[9]]),
+            received_at: 0,
+        });
+    }
+
+    /// Decode a received advertisement. Returns `None` if it is not a rudelblinken sync advertisement.
+    pub fn from_advertisement(advertisement: &Advertisement) -> Option<Self> {
+        let mut decoded = Self::decode(&advertisement.parse_rudelblinken()?)?;
+        decoded.received_at = (advertisement.received_at / 1000) as u32;
+        return Some(decoded);
+    }
+
+    /// Encode as manufacturer data, including the company ID
+    ///
+    /// The host adds the rudelblinken header with the group ID before sending it.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = vec![0x00, 0x00];
+        let leader_flag = if self.leader { FLAG_LEADER } else { 0 };
+        data.push(leader_flag | self.hops.min(MAX_HOPS) << HOPS_SHIFT);
+        data.push(self.progress);
+        data.extend_from_slice(&self.device_id.to_le_bytes());
+        data.extend_from_slice(&self.time.to_le_bytes());
+        return data;
+    }
+}
+
+/// Synchronizes a progress counter with the other devices in the same group
+#[derive(Debug, Clone)]
+pub struct GroupSync {
+    /// Advertisements from other groups are ignored
+    group_id: u16,
+    device_id: u32,
+    /// The explicitly set role. The role is assigned automatically if this is `None`
+    role: Option<GroupRole>,
+    progress: u8,
+    /// Timestamp in milliseconds at which `progress` was last advanced
+    progress_time: u32,
+    /// Weighted sum of the progress differences since the last update
+    offset_sum: i32,
+    /// Number of advertisements since the last update, scaled by [RSSI_WEIGHT_SCALE]
+    offset_count: i32,
+    /// Remainder of the last nudge, so small offsets are not lost
+    nudge_remainder: i32,
+    /// Lowest device ID in the group that was seen recently and the time in milliseconds it was last seen
+    lowest_seen: Option<(u32, u32)>,
+    /// Difference between the group time and the local time in milliseconds
+    epoch_offset: i64,
+    /// The group time was calibrated from a leader at least once
+    calibrated: bool,
+    /// Hops for the own advertisements. Relaying is disabled if this is 0
+    max_hops: u8,
+    /// Device ID and time of recently received advertisements
+    recent: VecDeque<(u32, u32)>,
+    /// Advertisements waiting to be relayed. Contains at most one advertisement per original sender
+    relay_queue: VecDeque<RudelAdvertisement>,
+    /// The advertisement that is currently relayed instead of the own advertisement
+    relaying: Option<RudelAdvertisement>,
+    /// Timestamp in milliseconds at which the current relay slot started
+    slot_start: u32,
+    /// RSSI range in dBm used to weight the nudges. The RSSI is ignored if this is `None`
+    rssi_range: Option<(i8, i8)>,
+}
+
+impl GroupSync {
+    /// Create a new group sync for the group of this device, see [crate::get_group_id]
+    ///
+    /// Set the group ID with [crate::set_group_id] before creating the group sync.
+    pub fn new() -> Self {
+        return GroupSync {
+            group_id: get_group_id(),
+            device_id: get_device_id(),
+            role: None,
+            progress: 0,
+            progress_time: (time() / 1000) as u32,
+            offset_sum: 0,
+            offset_count: 0,
+            nudge_remainder: 0,
+            lowest_seen: None,
+            epoch_offset: 0,
+            calibrated: false,
+            max_hops: 0,
+            recent: VecDeque::with_capacity(RECENT_CAPACITY),
+            relay_queue: VecDeque::with_capacity(RELAY_QUEUE_CAPACITY),
+            relaying: None,
+            slot_start: 0,
+            rssi_range: None,
+        };
+    }
+
+    /// Set the role of this device
+    pub fn set_role(&mut self, role: GroupRole) {
+        self.role = Some(role);
+    }
+
+    /// Let the device with the lowest device ID become the leader. This is the default.
+    pub fn set_automatic_role(&mut self) {
+        self.role = None;
+    }
+
+    /// Rebroadcast received advertisements, so devices that are out of range of each other are synchronized. Own advertisements can be relayed up to `max_hops` times. `max_hops` is clamped to [MAX_HOPS].
+    ///
+    /// Only advertisements from devices that enabled relaying are relayed.
+    pub fn enable_relay(&mut self, max_hops: u8) {
+        self.max_hops = max_hops.min(MAX_HOPS);
+    }
+
+    /// Stop rebroadcasting received advertisements
+    pub fn disable_relay(&mut self) {
+        self.max_hops = 0;
+        self.relay_queue.clear();
+        self.relaying = None;
+    }
+
+    /// Weight the nudges by the RSSI of the received advertisements. Advertisements with an RSSI of `max` or higher have full influence, advertisements with an RSSI close to `min` have a tenth of it.
+    ///
+    /// Advertisements without an RSSI, for example from hosts that do not report it, have full influence.
+    pub fn set_rssi_range(&mut self, min: i8, max: i8) {
+        self.rssi_range = Some((min, max));
+    }
+
+    /// Weight of an advertisement with the given RSSI, scaled by [RSSI_WEIGHT_SCALE]
+    fn rssi_weight(&self, rssi: Option<i8>) -> i32 {
+        let (Some((min, max)), Some(rssi)) = (self.rssi_range, rssi) else {
+            return RSSI_WEIGHT_SCALE;
+        };
+        if max <= min {
+            return RSSI_WEIGHT_SCALE;
+        }
+        let weight = (rssi as i32 - min as i32) * RSSI_WEIGHT_SCALE / (max as i32 - min as i32);
+        return weight.clamp(MIN_RSSI_WEIGHT, RSSI_WEIGHT_SCALE);
+    }
+
+    /// The current role of this device
+    pub fn role(&self) -> GroupRole {
+        if let Some(role) = self.role {
+            return role;
+        }
+        match self.lowest_seen {
+            Some((lowest_id, _)) if lowest_id < self.device_id => {
+                return GroupRole::Follower(DEFAULT_LEADER_WEIGHT);
+            }
+            _ => return GroupRole::Leader,
+        }
+    }
+
+    /// The current progress
+    pub fn progress(&self) -> u8 {
+        return self.progress;
+    }
+
+    /// The current group time in milliseconds since the group epoch
+    pub fn group_time(&self) -> u32 {
+        return ((time() / 1000) as i64 + self.epoch_offset) as u32;
+    }
+
+    /// Difference between the group time and the local time in milliseconds
+    pub fn epoch_offset(&self) -> i64 {
+        return self.epoch_offset;
+    }
+
+    /// Adjust the group time based on an advertisement from the leader. Advertisements that are not from a leader of this group are ignored.
+    ///
+    /// The transmission delay is not known, so it is treated as part of the offset. It is small and roughly the same for all followers.
+    pub fn calibrate_from_leader_advertisement(&mut self, advertisement: &RudelAdvertisement) {
+        if !advertisement.leader || advertisement.group_id != self.group_id {
+            return;
+        }
+        let estimate = advertisement.time as i64 - advertisement.received_at as i64;
+        let difference = estimate - self.epoch_offset;
+        if !self.calibrated || difference.abs() > CALIBRATION_JUMP_MS {
+            self.epoch_offset = estimate;
+            self.calibrated = true;
+            return;
+        }
+        self.epoch_offset += difference / CALIBRATION_SMOOTHING;
+    }
+
+    /// Apply the collected nudges and advance the progress to the given time in milliseconds. Returns the new progress.
+    pub fn update(&mut self, timestamp: u32) -> u8 {
+        if let Some((_, last_seen)) = self.lowest_seen {
+            if timestamp.wrapping_sub(last_seen) > LEADER_TIMEOUT_MS {
+                self.lowest_seen = None;
+            }
+        }
+
+        if self.offset_count != 0 {
+            let divisor = self.offset_count * NUDGE_STRENGTH;
+            let nudge_base = self.offset_sum + self.nudge_remainder;
+            self.progress = self.progress.wrapping_add((nudge_base / divisor) as u8);
+            self.nudge_remainder = nudge_base % divisor;
+            self.offset_sum = 0;
+            self.offset_count = 0;
+        }
+
+        let elapsed = timestamp.wrapping_sub(self.progress_time);
+        if (elapsed as i32) < 0 {
+            // The timestamp is older than the last update
+            return self.progress;
+        }
+        self.progress_time = timestamp - elapsed % MS_PER_STEP;
+        self.progress = self.progress.wrapping_add((elapsed / MS_PER_STEP) as u8);
+
+        if timestamp.wrapping_sub(self.slot_start) >= RELAY_SLOT_MS {
+            self.slot_start = timestamp;
+            self.relaying = match self.relaying {
+                Some(_) => None,
+                None => self.relay_queue.pop_front(),
+            };
+        }
+        return self.progress;
+    }
+
+    /// The advertisement that announces the progress of this device
+    pub fn own_advertisement(&self) -> RudelAdvertisement {
+        return RudelAdvertisement {
+            group_id: self.group_id,
+            leader: self.role() == GroupRole::Leader,
+            progress: self.progress,
+            device_id: self.device_id,
+            hops: self.max_hops,
+            time: self.group_time(),
+            received_at: 0,
+        };
+    }
+
+    /// The advertisement that should currently be broadcast. This is either the own advertisement or a relayed one.
+    pub fn advertisement(&self) -> RudelAdvertisement {
+        let Some(mut relayed) = self.relaying else {
+            return self.own_advertisement();
+        };
+        // Account for the time the advertisement spent on this device
+        let delay = self.progress_time.wrapping_sub(relayed.received_at);
+        relayed.progress = relayed.progress.wrapping_add((delay / MS_PER_STEP) as u8);
+        return relayed;
+    }
+
+    /// Manufacturer data for [crate::set_advertisement_data]
+    pub fn advertisement_data(&self) -> Vec<u8> {
+        return self.advertisement().encode();
+    }
+
+    /// Process a received advertisement with the RSSI reported by the host. Advertisements from other groups are ignored.
+    pub fn on_advertisement(&mut self, advertisement: &Advertisement) {
+        self.on_ble_advertisement(&BleAdvertisement::new(advertisement.clone()));
+    }
+
+    /// Process a received advertisement with a known RSSI in dBm. See [GroupSync::set_rssi_range].
+    pub fn on_advertisement_with_rssi(&mut self, advertisement: &Advertisement, rssi: i8) {
+        self.on_ble_advertisement(&BleAdvertisement::with_rssi(advertisement.clone(), rssi));
+    }
+
+    /// Process a received advertisement. The RSSI is used if it is known.
+    pub fn on_ble_advertisement(&mut self, advertisement: &BleAdvertisement) {
+        let Some(received) = advertisement.try_parse_rudelblinken() else {
+            return;
+        };
+        let rssi = advertisement.rssi();
+        if received.group_id != self.group_id || received.device_id == self.device_id {
+            return;
+        }
+        let key = (received.device_id, received.time);
+        if self.recent.contains(&key) {
+            return;
+        }
+        if self.recent.len() == RECENT_CAPACITY {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(key);
+        let received_at = received.received_at;
+
+        if self.max_hops != 0 && received.hops != 0 {
+            let relayed = RudelAdvertisement {
+                hops: received.hops - 1,
+                ..received
+            };
+            self.relay_queue
+                .retain(|queued| queued.device_id != relayed.device_id);
+            if self.relay_queue.len() == RELAY_QUEUE_CAPACITY {
+                self.relay_queue.pop_front();
+            }
+            self.relay_queue.push_back(relayed);
+        }
+
+        if received.device_id <= self.lowest_seen.map_or(u32::MAX, |(id, _)| id) {
+            self.lowest_seen = Some((received.device_id, received_at));
+        }
+
+        let weight = match self.role() {
+            GroupRole::Leader => return,
+            GroupRole::Follower(leader_weight) if received.leader => {
+                self.calibrate_from_leader_advertisement(&received);
+                // Higher weights would overshoot the progress of the leader
+                (leader_weight as i32).min(NUDGE_STRENGTH)
+            }
+            GroupRole::Follower(_) => 1,
+        };
+        self.update(received_at);
+        let offset = received.progress.wrapping_sub(self.progress) as i8 as i32;
+        // The nudge is applied before the next advertisement is processed, so the weights scale it instead of averaging between advertisements
+        self.offset_sum += offset * weight * self.rssi_weight(rssi);
+        self.offset_count += RSSI_WEIGHT_SCALE;
+    }
+}
+
+impl Default for GroupSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+    use crate::testing::MockHost;
+    use rudelblinken_protocol::encode_manufacturer_data;
+
+    /// A sync advertisement with the header added by the sending host, received at the given time in milliseconds
+    fn received_advertisement(sent: RudelAdvertisement, received_at_ms: u64) -> Advertisement {
+        let manufacturer_data = encode_manufacturer_data(sent.group_id, 0, &sent.encode()).unwrap();
+        let mut advertisement = Advertisement {
+            address: 0,
+            company: u16::from_le_bytes([manufacturer_data[0], manufacturer_data[1]]),
+            data: (0, 0, 0, 0, 0, 0, 0, 0),
+            data_length: (manufacturer_data.len() - 2) as u8,
+            rssi: 0,
+            received_at: received_at_ms * 1000,
+            service_data: Vec::new(),
+        };
+        advertisement
+            .get_data_mut()
+            .copy_from_slice(&manufacturer_data[2..]);
+        return advertisement;
+    }
+
+    /// A sync advertisement sent by the given device in group 0
+    fn sent_by(device_id: u32, leader: bool, progress: u8) -> RudelAdvertisement {
+        return RudelAdvertisement {
+            group_id: 0,
+            leader,
+            progress,
+            device_id,
+            hops: 0,
+            time: 0,
+            received_at: 0,
+        };
+    }
+
+    /// An advertisement from the leader of the given group, with the header added by the sending host
+    fn leader_advertisement(group_id: u16, progress: u8) -> Advertisement {
+        let sent = RudelAdvertisement {
+            group_id,
+            ..sent_by(0, true, progress)
+        };
+        return received_advertisement(sent, 0);
+    }
+
+    #[test]
+    fn advertisements_from_other_groups_are_dropped() {
+        MockHost::new()
+            .with_name("follower")
+            .with_group_id(1)
+            .install();
+        let mut sync = GroupSync::new();
+        sync.on_advertisement(&leader_advertisement(2, 100));
+        assert_eq!(sync.update(0), 0);
+        assert_eq!(sync.role(), GroupRole::Leader);
+
+        sync.on_advertisement(&leader_advertisement(1, 100));
+        assert_ne!(sync.update(0), 0);
+        assert_ne!(sync.role(), GroupRole::Leader);
+    }
+
+    #[test]
+    fn the_group_id_set_by_the_guest_is_used_for_filtering() {
+        MockHost::new()
+            .with_name("follower")
+            .with_group_id(1)
+            .install();
+        crate::set_group_id(0x0102);
+        let mut sync = GroupSync::new();
+        sync.on_advertisement(&leader_advertisement(1, 100));
+        assert_eq!(sync.update(0), 0);
+
+        sync.on_advertisement(&leader_advertisement(0x0102, 100));
+        assert_ne!(sync.update(0), 0);
+    }
+
+    #[test]
+    fn the_device_with_the_lowest_id_becomes_the_leader() {
+        MockHost::new().with_name("follower").install();
+        let mut sync = GroupSync::new();
+        assert_eq!(sync.role(), GroupRole::Leader);
+
+        sync.on_advertisement(&received_advertisement(
+            sent_by(sync.device_id + 1, false, 0),
+            0,
+        ));
+        assert_eq!(sync.role(), GroupRole::Leader);
+
+        sync.on_advertisement(&received_advertisement(
+            sent_by(sync.device_id - 1, false, 0),
+            0,
+        ));
+        assert_eq!(sync.role(), GroupRole::Follower(DEFAULT_LEADER_WEIGHT));
+
+        sync.set_role(GroupRole::Leader);
+        assert_eq!(sync.role(), GroupRole::Leader);
+        sync.set_automatic_role();
+        assert_eq!(sync.role(), GroupRole::Follower(DEFAULT_LEADER_WEIGHT));
+    }
+
+    #[test]
+    fn leaders_that_were_not_seen_recently_are_forgotten() {
+        MockHost::new().with_name("follower").install();
+        let mut sync = GroupSync::new();
+        sync.on_advertisement(&received_advertisement(
+            sent_by(sync.device_id - 1, true, 0),
+            0,
+        ));
+
+        sync.update(LEADER_TIMEOUT_MS);
+        assert_eq!(sync.role(), GroupRole::Follower(DEFAULT_LEADER_WEIGHT));
+        sync.update(LEADER_TIMEOUT_MS + 1);
+        assert_eq!(sync.role(), GroupRole::Leader);
+    }
+
+    #[test]
+    fn leaders_do_not_nudge() {
+        MockHost::new().with_name("leader").install();
+        let mut sync = GroupSync::new();
+        sync.set_role(GroupRole::Leader);
+        sync.on_advertisement(&received_advertisement(
+            sent_by(sync.device_id - 1, true, 100),
+            0,
+        ));
+        assert_eq!(sync.update(0), 0);
+        assert!(sync.own_advertisement().leader);
+    }
+
+    #[test]
+    fn followers_nudge_harder_towards_the_leader() {
+        MockHost::new().with_name("follower").install();
+        let mut sync = GroupSync::new();
+        sync.set_role(GroupRole::Follower(8));
+        sync.on_advertisement(&received_advertisement(sent_by(1, false, 100), 0));
+        // Followers have a weight of 1, so the nudge is 100 / NUDGE_STRENGTH
+        assert_eq!(sync.update(0), 5);
+
+        let mut sync = GroupSync::new();
+        sync.set_role(GroupRole::Follower(8));
+        sync.on_advertisement(&received_advertisement(sent_by(1, true, 100), 0));
+        assert_eq!(sync.update(0), 40);
+        assert!(!sync.own_advertisement().leader);
+    }
+
+    #[test]
+    fn high_leader_weights_do_not_overshoot() {
+        MockHost::new().with_name("follower").install();
+        let mut sync = GroupSync::new();
+        sync.set_role(GroupRole::Follower(u8::MAX));
+        sync.on_advertisement(&received_advertisement(sent_by(1, true, 100), 0));
+        assert_eq!(sync.update(0), 100);
+    }
+
+    /// An advertisement from the leader of group 0 with the given group time, received at the given local time in milliseconds
+    fn leader_time(time: u32, received_at: u32) -> RudelAdvertisement {
+        return RudelAdvertisement {
+            time,
+            received_at,
+            ..sent_by(1, true, 0)
+        };
+    }
+
+    #[test]
+    fn the_first_leader_advertisement_sets_the_group_time() {
+        MockHost::new().with_time_ms(2_000).install();
+        let mut sync = GroupSync::new();
+        sync.calibrate_from_leader_advertisement(&leader_time(10_000, 1_500));
+        assert_eq!(sync.epoch_offset(), 8_500);
+        assert_eq!(sync.group_time(), 10_500);
+    }
+
+    #[test]
+    fn later_leader_advertisements_are_smoothed() {
+        MockHost::new().install();
+        let mut sync = GroupSync::new();
+        sync.calibrate_from_leader_advertisement(&leader_time(10_000, 1_500));
+        sync.calibrate_from_leader_advertisement(&leader_time(10_580, 2_000));
+        assert_eq!(sync.epoch_offset(), 8_500 + 80 / CALIBRATION_SMOOTHING);
+    }
+
+    #[test]
+    fn large_differences_jump_to_the_new_group_time() {
+        MockHost::new().install();
+        let mut sync = GroupSync::new();
+        sync.calibrate_from_leader_advertisement(&leader_time(10_000, 0));
+        sync.calibrate_from_leader_advertisement(&leader_time(
+            10_000 + CALIBRATION_JUMP_MS as u32,
+            0,
+        ));
+        assert_eq!(
+            sync.epoch_offset(),
+            10_000 + CALIBRATION_JUMP_MS / CALIBRATION_SMOOTHING
+        );
+
+        let offset = sync.epoch_offset();
+        sync.calibrate_from_leader_advertisement(&leader_time(
+            (offset + CALIBRATION_JUMP_MS + 1) as u32,
+            0,
+        ));
+        assert_eq!(sync.epoch_offset(), offset + CALIBRATION_JUMP_MS + 1);
+    }
+
+    #[test]
+    fn only_leaders_of_the_own_group_are_used_for_calibration() {
+        MockHost::new().with_group_id(1).install();
+        let mut sync = GroupSync::new();
+        sync.calibrate_from_leader_advertisement(&RudelAdvertisement {
+            group_id: 1,
+            leader: false,
+            ..leader_time(500, 0)
+        });
+        sync.calibrate_from_leader_advertisement(&RudelAdvertisement {
+            group_id: 2,
+            ..leader_time(500, 0)
+        });
+        assert_eq!(sync.epoch_offset(), 0);
+
+        // The ignored advertisements did not count as the first calibration, so this one is not smoothed
+        sync.calibrate_from_leader_advertisement(&RudelAdvertisement {
+            group_id: 1,
+            ..leader_time(500, 0)
+        });
+        assert_eq!(sync.epoch_offset(), 500);
+    }
+
+    #[test]
+    fn followers_calibrate_from_received_leader_advertisements() {
+        MockHost::new().with_name("follower").install();
+        let mut sync = GroupSync::new();
+        let sent = RudelAdvertisement {
+            device_id: sync.device_id - 1,
+            ..leader_time(10_000, 0)
+        };
+        sync.on_advertisement(&received_advertisement(sent, 1_500));
+        assert_eq!(sync.epoch_offset(), 8_500);
+    }
+
+    #[test]
+    fn the_rssi_weight_scales_linearly_between_min_and_max() {
+        MockHost::new().install();
+        let mut sync = GroupSync::new();
+        assert_eq!(sync.rssi_weight(Some(-80)), RSSI_WEIGHT_SCALE);
+
+        sync.set_rssi_range(-90, -50);
+        assert_eq!(sync.rssi_weight(Some(-50)), RSSI_WEIGHT_SCALE);
+        assert_eq!(sync.rssi_weight(Some(-70)), RSSI_WEIGHT_SCALE / 2);
+        assert_eq!(sync.rssi_weight(Some(-80)), RSSI_WEIGHT_SCALE / 4);
+        // Advertisements without an RSSI have full influence
+        assert_eq!(sync.rssi_weight(None), RSSI_WEIGHT_SCALE);
+    }
+
+    #[test]
+    fn the_rssi_weight_is_clamped_between_a_tenth_and_one() {
+        MockHost::new().install();
+        let mut sync = GroupSync::new();
+        sync.set_rssi_range(-90, -50);
+        assert_eq!(sync.rssi_weight(Some(-30)), RSSI_WEIGHT_SCALE);
+        assert_eq!(sync.rssi_weight(Some(-88)), MIN_RSSI_WEIGHT);
+        assert_eq!(sync.rssi_weight(Some(-120)), MIN_RSSI_WEIGHT);
+
+        // An empty range is ignored
+        sync.set_rssi_range(-50, -50);
+        assert_eq!(sync.rssi_weight(Some(-120)), RSSI_WEIGHT_SCALE);
+    }
+
+    #[test]
+    fn distant_devices_nudge_less() {
+        MockHost::new().with_name("follower").install();
+        let mut sync = GroupSync::new();
+        sync.set_role(GroupRole::Follower(1));
+        sync.set_rssi_range(-90, -50);
+        let advertisement = received_advertisement(sent_by(1, false, 100), 0);
+        sync.on_advertisement_with_rssi(&advertisement, -50);
+        assert_eq!(sync.update(0), 5);
+
+        let mut sync = GroupSync::new();
+        sync.set_role(GroupRole::Follower(1));
+        sync.set_rssi_range(-90, -50);
+        sync.on_advertisement_with_rssi(&advertisement, -70);
+        // Half of the nudge of a device at full strength. The remainder is kept for the next update
+        assert_eq!(sync.update(0), 2);
+    }
+}
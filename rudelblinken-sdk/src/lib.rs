@@ -3,25 +3,64 @@
 //! This is the SDK for the Rudelblinken platform. It provides a set of functions to interact with the connected hardware.
 #![feature(split_array)]
 
+mod ble_advertisement;
+pub mod config;
+pub mod group_sync;
 mod rudel;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod timers;
+pub use ble_advertisement::BleAdvertisement;
+pub use config::{
+    get_config_bool, get_config_f32, get_config_string, get_config_u32, get_config_value,
+};
+pub use group_sync::{GroupRole, GroupSync, RudelAdvertisement};
 pub use rudel::{
     export, exports,
     exports::rudel::base::ble_guest::{Advertisement, Guest as BleGuest},
     exports::rudel::base::run::Guest,
-    rudel::base::base::{get_base_version, log, sleep, time, yield_now, LogLevel, SemanticVersion},
+    exports::rudel::base::timer_guest::Guest as TimerGuest,
+    rudel::base::base::{get_base_version, version_satisfies, LogLevel, SemanticVersion},
     rudel::base::ble::{
-        configure_advertisement, get_ble_version, set_advertisement_data, AdvertisementData,
-        AdvertisementSettings,
+        get_ble_version, get_connected_devices, AdvertisementData, AdvertisementSettings,
+        ServiceData,
     },
     rudel::base::hardware::{
-        get_ambient_light, get_ambient_light_type, get_hardware_version, get_led_info,
-        get_vibration, get_vibration_sensor_type, led_count, set_leds, set_rgb, AmbientLightType,
-        LedColor, LedInfo, VibrationSensorType,
+        get_ambient_light_raw, get_ambient_light_type, get_hardware_version, get_led_info,
+        get_vibration_sensor_type, AmbientLightType, LedColor, LedInfo, VibrationSensorType,
+    },
+};
+pub use rudelblinken_protocol::RudelblinkenAdvertisement;
+// Provided by the mock host in native tests, see the testing module
+#[cfg(not(feature = "testing"))]
+pub use rudel::rudel::base::{
+    base::{cancel_timer, log, set_timer, sleep, time, yield_now},
+    ble::{configure_advertisement, configure_scan, get_scan_results, set_advertisement_data},
+    hardware::{
+        get_ambient_light, get_battery_level, get_temperature, get_vibration, led_count, set_leds,
+        set_rgb,
     },
 };
+#[cfg(not(feature = "testing"))]
+use rudel::rudel::base::{
+    base::{get_config as host_get_config, get_name as host_get_name},
+    ble::{get_group_id as host_get_group_id, set_group_id as host_set_group_id},
+};
+#[cfg(feature = "testing")]
+pub use testing::host::{
+    cancel_timer, configure_advertisement, configure_scan, get_ambient_light, get_battery_level,
+    get_scan_results, get_temperature, get_vibration, led_count, log, set_advertisement_data,
+    set_leds, set_rgb, set_timer, sleep, time, yield_now,
+};
+#[cfg(feature = "testing")]
+use testing::host::{
+    get_config as host_get_config, get_group_id as host_get_group_id, get_name as host_get_name,
+    set_group_id as host_set_group_id,
+};
+pub use timers::{cancel_on_timer, dispatch_timer, set_on_timer};
 
 pub fn get_name() -> String {
-    let tuple = rudel::rudel::base::base::get_name();
+    let tuple = host_get_name();
     let array: [u8; 16] = [
         tuple.0, tuple.1, tuple.2, tuple.3, tuple.4, tuple.5, tuple.6, tuple.7, tuple.8, tuple.9,
         tuple.10, tuple.11, tuple.12, tuple.13, tuple.14, tuple.15,
@@ -36,8 +75,35 @@ pub fn get_name() -> String {
     String::from_utf8_lossy(array).to_string()
 }
 
+/// Get an ID for this device that is derived from its name.
+///
+/// As the name is unique, the ID is unique with a high probability.
+pub fn get_device_id() -> u32 {
+    // 32 bit FNV-1a
+    return get_name().bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    });
+}
+
+/// Get the configuration for this guest, as configured over BLE.
+///
+/// The [config] module reads key-value pairs from the configuration.
 pub fn get_config() -> Vec<u8> {
-    rudel::rudel::base::base::get_config()
+    return host_get_config();
+}
+
+/// Get the group ID of this device.
+///
+/// This is the group ID set with [set_group_id], or the one from the configuration. Devices should only synchronize with devices in the same group.
+pub fn get_group_id() -> u16 {
+    return host_get_group_id();
+}
+
+/// Set the group ID of this device, overriding the one from the configuration.
+///
+/// The host sends it in the header of the advertisements. Call this at startup, before creating a [GroupSync] or setting the advertisement data, so independent light shows can run next to each other.
+pub fn set_group_id(id: u16) {
+    host_set_group_id(id);
 }
 
 impl exports::rudel::base::ble_guest::Advertisement {
@@ -93,4 +159,32 @@ impl exports::rudel::base::ble_guest::Advertisement {
                 .split_array_mut::<6>();
         return start;
     }
+    /// Parse the manufacturer data as a rudelblinken advertisement. Returns `None` if it is not one.
+    pub fn parse_rudelblinken(&self) -> Option<RudelblinkenAdvertisement> {
+        return RudelblinkenAdvertisement::parse(self.get_data());
+    }
+}
+
+// The bindings are generated, so the traits can not be derived
+impl PartialEq for SemanticVersion {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other) == std::cmp::Ordering::Equal;
+    }
+}
+impl Eq for SemanticVersion {}
+impl PartialOrd for SemanticVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+/// Versions are ordered by major, then minor, then patch version
+impl Ord for SemanticVersion {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        return (self.major, self.minor, self.patch).cmp(&(other.major, other.minor, other.patch));
+    }
+}
+impl std::fmt::Display for SemanticVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{}.{}.{}", self.major, self.minor, self.patch);
+    }
 }
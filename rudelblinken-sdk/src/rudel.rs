@@ -1,4 +1,4 @@
-// Generated by `wit-bindgen` 0.36.0. DO NOT EDIT!
+// Generated by `wit-bindgen` 0.41.0. DO NOT EDIT!
 // Options used:
 //   * pub-export-macro
 //   * disable_custom_section_link_helpers
@@ -7,7 +7,7 @@
 pub mod rudel {
     pub mod base {
         /// base is the interface for all basic functionality of the rudelblinken system
-        #[allow(dead_code, clippy::all)]
+        #[allow(dead_code, async_fn_in_trait, unused_imports, clippy::all)]
         pub mod base {
             use super::super::super::_rt;
             /// The semantic version of a module
@@ -85,24 +85,81 @@ pub mod rudel {
                     let mut ret_area = RetArea([::core::mem::MaybeUninit::uninit(); 3]);
                     let ptr0 = ret_area.0.as_mut_ptr().cast::<u8>();
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/base@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/base@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "get-base-version"]
-                        fn wit_import(_: *mut u8);
+                        fn wit_import1(_: *mut u8);
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: *mut u8) {
+                    unsafe extern "C" fn wit_import1(_: *mut u8) {
                         unreachable!()
                     }
-                    wit_import(ptr0);
-                    let l1 = i32::from(*ptr0.add(0).cast::<u8>());
-                    let l2 = i32::from(*ptr0.add(1).cast::<u8>());
-                    let l3 = i32::from(*ptr0.add(2).cast::<u8>());
-                    SemanticVersion {
-                        major: l1 as u8,
-                        minor: l2 as u8,
-                        patch: l3 as u8,
+                    unsafe { wit_import1(ptr0) };
+                    let l2 = i32::from(*ptr0.add(0).cast::<u8>());
+                    let l3 = i32::from(*ptr0.add(1).cast::<u8>());
+                    let l4 = i32::from(*ptr0.add(2).cast::<u8>());
+                    let result5 = SemanticVersion {
+                        major: l2 as u8,
+                        minor: l3 as u8,
+                        patch: l4 as u8,
+                    };
+                    result5
+                }
+            }
+            #[allow(unused_unsafe, clippy::all)]
+            /// Check if a version is compatible with a required version
+            ///
+            /// The major version must match and the version must not be older than the required version.
+            pub fn version_satisfies(
+                version: SemanticVersion,
+                required: SemanticVersion,
+            ) -> bool {
+                unsafe {
+                    let SemanticVersion {
+                        major: major0,
+                        minor: minor0,
+                        patch: patch0,
+                    } = version;
+                    let SemanticVersion {
+                        major: major1,
+                        minor: minor1,
+                        patch: patch1,
+                    } = required;
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "rudel:base/base@0.0.2")]
+                    unsafe extern "C" {
+                        #[link_name = "version-satisfies"]
+                        fn wit_import2(
+                            _: i32,
+                            _: i32,
+                            _: i32,
+                            _: i32,
+                            _: i32,
+                            _: i32,
+                        ) -> i32;
                     }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import2(
+                        _: i32,
+                        _: i32,
+                        _: i32,
+                        _: i32,
+                        _: i32,
+                        _: i32,
+                    ) -> i32 {
+                        unreachable!()
+                    }
+                    let ret = unsafe {
+                        wit_import2(
+                            _rt::as_i32(major0),
+                            _rt::as_i32(minor0),
+                            _rt::as_i32(patch0),
+                            _rt::as_i32(major1),
+                            _rt::as_i32(minor1),
+                            _rt::as_i32(patch1),
+                        )
+                    };
+                    _rt::bool_lift(ret as u8)
                 }
             }
             #[allow(unused_unsafe, clippy::all)]
@@ -116,16 +173,16 @@ pub mod rudel {
             pub fn yield_now(micros: u64) -> u32 {
                 unsafe {
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/base@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/base@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "yield-now"]
-                        fn wit_import(_: i64) -> i32;
+                        fn wit_import0(_: i64) -> i32;
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: i64) -> i32 {
+                    unsafe extern "C" fn wit_import0(_: i64) -> i32 {
                         unreachable!()
                     }
-                    let ret = wit_import(_rt::as_i64(&micros));
+                    let ret = unsafe { wit_import0(_rt::as_i64(&micros)) };
                     ret as u32
                 }
             }
@@ -134,34 +191,34 @@ pub mod rudel {
             pub fn get_remaining_fuel() -> u32 {
                 unsafe {
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/base@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/base@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "get-remaining-fuel"]
-                        fn wit_import() -> i32;
+                        fn wit_import0() -> i32;
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import() -> i32 {
+                    unsafe extern "C" fn wit_import0() -> i32 {
                         unreachable!()
                     }
-                    let ret = wit_import();
+                    let ret = unsafe { wit_import0() };
                     ret as u32
                 }
             }
             #[allow(unused_unsafe, clippy::all)]
             /// Sleep for a given amount of time without yielding
-            pub fn sleep(micros: u64) {
+            pub fn sleep(micros: u64) -> () {
                 unsafe {
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/base@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/base@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "sleep"]
-                        fn wit_import(_: i64);
+                        fn wit_import0(_: i64);
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: i64) {
+                    unsafe extern "C" fn wit_import0(_: i64) {
                         unreachable!()
                     }
-                    wit_import(_rt::as_i64(&micros));
+                    unsafe { wit_import0(_rt::as_i64(&micros)) };
                 }
             }
             #[allow(unused_unsafe, clippy::all)]
@@ -169,37 +226,73 @@ pub mod rudel {
             pub fn time() -> u64 {
                 unsafe {
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/base@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/base@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "time"]
-                        fn wit_import() -> i64;
+                        fn wit_import0() -> i64;
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import() -> i64 {
+                    unsafe extern "C" fn wit_import0() -> i64 {
                         unreachable!()
                     }
-                    let ret = wit_import();
+                    let ret = unsafe { wit_import0() };
                     ret as u64
                 }
             }
             #[allow(unused_unsafe, clippy::all)]
+            /// Fire a timer after the given number of microseconds
+            ///
+            /// The timer fires while you yield, by calling `on-timer` of the timer-guest interface. Setting a timer with the id of a pending timer replaces it.
+            pub fn set_timer(id: u8, delay_us: u64) -> () {
+                unsafe {
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "rudel:base/base@0.0.2")]
+                    unsafe extern "C" {
+                        #[link_name = "set-timer"]
+                        fn wit_import0(_: i32, _: i64);
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import0(_: i32, _: i64) {
+                        unreachable!()
+                    }
+                    unsafe { wit_import0(_rt::as_i32(&id), _rt::as_i64(&delay_us)) };
+                }
+            }
+            #[allow(unused_unsafe, clippy::all)]
+            /// Cancel a pending timer
+            pub fn cancel_timer(id: u8) -> () {
+                unsafe {
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "rudel:base/base@0.0.2")]
+                    unsafe extern "C" {
+                        #[link_name = "cancel-timer"]
+                        fn wit_import0(_: i32);
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import0(_: i32) {
+                        unreachable!()
+                    }
+                    unsafe { wit_import0(_rt::as_i32(&id)) };
+                }
+            }
+            #[allow(unused_unsafe, clippy::all)]
             /// Log a message
-            pub fn log(level: LogLevel, message: &str) {
+            pub fn log(level: LogLevel, message: &str) -> () {
                 unsafe {
                     let vec0 = message;
                     let ptr0 = vec0.as_ptr().cast::<u8>();
                     let len0 = vec0.len();
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/base@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/base@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "log"]
-                        fn wit_import(_: i32, _: *mut u8, _: usize);
+                        fn wit_import1(_: i32, _: *mut u8, _: usize);
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: i32, _: *mut u8, _: usize) {
+                    unsafe extern "C" fn wit_import1(_: i32, _: *mut u8, _: usize) {
                         unreachable!()
                     }
-                    wit_import(level.clone() as i32, ptr0.cast_mut(), len0);
+                    unsafe { wit_import1(level.clone() as i32, ptr0.cast_mut(), len0) };
                 }
             }
             #[allow(unused_unsafe, clippy::all)]
@@ -234,34 +327,33 @@ pub mod rudel {
                     let mut ret_area = RetArea([::core::mem::MaybeUninit::uninit(); 16]);
                     let ptr0 = ret_area.0.as_mut_ptr().cast::<u8>();
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/base@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/base@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "get-name"]
-                        fn wit_import(_: *mut u8);
+                        fn wit_import1(_: *mut u8);
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: *mut u8) {
+                    unsafe extern "C" fn wit_import1(_: *mut u8) {
                         unreachable!()
                     }
-                    wit_import(ptr0);
-                    let l1 = i32::from(*ptr0.add(0).cast::<u8>());
-                    let l2 = i32::from(*ptr0.add(1).cast::<u8>());
-                    let l3 = i32::from(*ptr0.add(2).cast::<u8>());
-                    let l4 = i32::from(*ptr0.add(3).cast::<u8>());
-                    let l5 = i32::from(*ptr0.add(4).cast::<u8>());
-                    let l6 = i32::from(*ptr0.add(5).cast::<u8>());
-                    let l7 = i32::from(*ptr0.add(6).cast::<u8>());
-                    let l8 = i32::from(*ptr0.add(7).cast::<u8>());
-                    let l9 = i32::from(*ptr0.add(8).cast::<u8>());
-                    let l10 = i32::from(*ptr0.add(9).cast::<u8>());
-                    let l11 = i32::from(*ptr0.add(10).cast::<u8>());
-                    let l12 = i32::from(*ptr0.add(11).cast::<u8>());
-                    let l13 = i32::from(*ptr0.add(12).cast::<u8>());
-                    let l14 = i32::from(*ptr0.add(13).cast::<u8>());
-                    let l15 = i32::from(*ptr0.add(14).cast::<u8>());
-                    let l16 = i32::from(*ptr0.add(15).cast::<u8>());
-                    (
-                        l1 as u8,
+                    unsafe { wit_import1(ptr0) };
+                    let l2 = i32::from(*ptr0.add(0).cast::<u8>());
+                    let l3 = i32::from(*ptr0.add(1).cast::<u8>());
+                    let l4 = i32::from(*ptr0.add(2).cast::<u8>());
+                    let l5 = i32::from(*ptr0.add(3).cast::<u8>());
+                    let l6 = i32::from(*ptr0.add(4).cast::<u8>());
+                    let l7 = i32::from(*ptr0.add(5).cast::<u8>());
+                    let l8 = i32::from(*ptr0.add(6).cast::<u8>());
+                    let l9 = i32::from(*ptr0.add(7).cast::<u8>());
+                    let l10 = i32::from(*ptr0.add(8).cast::<u8>());
+                    let l11 = i32::from(*ptr0.add(9).cast::<u8>());
+                    let l12 = i32::from(*ptr0.add(10).cast::<u8>());
+                    let l13 = i32::from(*ptr0.add(11).cast::<u8>());
+                    let l14 = i32::from(*ptr0.add(12).cast::<u8>());
+                    let l15 = i32::from(*ptr0.add(13).cast::<u8>());
+                    let l16 = i32::from(*ptr0.add(14).cast::<u8>());
+                    let l17 = i32::from(*ptr0.add(15).cast::<u8>());
+                    let result18 = (
                         l2 as u8,
                         l3 as u8,
                         l4 as u8,
@@ -277,7 +369,9 @@ pub mod rudel {
                         l14 as u8,
                         l15 as u8,
                         l16 as u8,
-                    )
+                        l17 as u8,
+                    );
+                    result18
                 }
             }
             #[allow(unused_unsafe, clippy::all)]
@@ -285,30 +379,41 @@ pub mod rudel {
             /// semantics of the configuration depend on the guest.
             pub fn get_config() -> _rt::Vec<u8> {
                 unsafe {
-                    #[repr(align(4))]
-                    struct RetArea([::core::mem::MaybeUninit<u8>; 8]);
-                    let mut ret_area = RetArea([::core::mem::MaybeUninit::uninit(); 8]);
+                    #[cfg_attr(target_pointer_width = "64", repr(align(8)))]
+                    #[cfg_attr(target_pointer_width = "32", repr(align(4)))]
+                    struct RetArea(
+                        [::core::mem::MaybeUninit<
+                            u8,
+                        >; 2 * ::core::mem::size_of::<*const u8>()],
+                    );
+                    let mut ret_area = RetArea(
+                        [::core::mem::MaybeUninit::uninit(); 2
+                            * ::core::mem::size_of::<*const u8>()],
+                    );
                     let ptr0 = ret_area.0.as_mut_ptr().cast::<u8>();
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/base@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/base@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "get-config"]
-                        fn wit_import(_: *mut u8);
+                        fn wit_import1(_: *mut u8);
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: *mut u8) {
+                    unsafe extern "C" fn wit_import1(_: *mut u8) {
                         unreachable!()
                     }
-                    wit_import(ptr0);
-                    let l1 = *ptr0.add(0).cast::<*mut u8>();
-                    let l2 = *ptr0.add(4).cast::<usize>();
-                    let len3 = l2;
-                    _rt::Vec::from_raw_parts(l1.cast(), len3, len3)
+                    unsafe { wit_import1(ptr0) };
+                    let l2 = *ptr0.add(0).cast::<*mut u8>();
+                    let l3 = *ptr0
+                        .add(::core::mem::size_of::<*const u8>())
+                        .cast::<usize>();
+                    let len4 = l3;
+                    let result5 = _rt::Vec::from_raw_parts(l2.cast(), len4, len4);
+                    result5
                 }
             }
         }
         /// Use this interface to control the hardware
-        #[allow(dead_code, clippy::all)]
+        #[allow(dead_code, async_fn_in_trait, unused_imports, clippy::all)]
         pub mod hardware {
             use super::super::super::_rt;
             pub type SemanticVersion = super::super::super::rudel::base::base::SemanticVersion;
@@ -336,6 +441,12 @@ pub mod rudel {
             pub struct LedInfo {
                 pub color: LedColor,
                 pub max_lux: u16,
+                /// Color temperature of white LEDs in kelvin. 0 if unknown or not a white LED
+                pub color_temperature_k: u16,
+                /// Dominant wavelength of single color LEDs in nanometers. 0 if unknown or not a single color LED
+                pub wavelength_nm: u16,
+                /// Row-major matrix that maps requested colors to calibrated colors
+                pub white_balance: ((f32, f32, f32), (f32, f32, f32), (f32, f32, f32)),
             }
             impl ::core::fmt::Debug for LedInfo {
                 fn fmt(
@@ -345,6 +456,9 @@ pub mod rudel {
                     f.debug_struct("LedInfo")
                         .field("color", &self.color)
                         .field("max-lux", &self.max_lux)
+                        .field("color-temperature-k", &self.color_temperature_k)
+                        .field("wavelength-nm", &self.wavelength_nm)
+                        .field("white-balance", &self.white_balance)
                         .finish()
                 }
             }
@@ -433,24 +547,25 @@ pub mod rudel {
                     let mut ret_area = RetArea([::core::mem::MaybeUninit::uninit(); 3]);
                     let ptr0 = ret_area.0.as_mut_ptr().cast::<u8>();
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/hardware@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/hardware@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "get-hardware-version"]
-                        fn wit_import(_: *mut u8);
+                        fn wit_import1(_: *mut u8);
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: *mut u8) {
+                    unsafe extern "C" fn wit_import1(_: *mut u8) {
                         unreachable!()
                     }
-                    wit_import(ptr0);
-                    let l1 = i32::from(*ptr0.add(0).cast::<u8>());
-                    let l2 = i32::from(*ptr0.add(1).cast::<u8>());
-                    let l3 = i32::from(*ptr0.add(2).cast::<u8>());
-                    super::super::super::rudel::base::base::SemanticVersion {
-                        major: l1 as u8,
-                        minor: l2 as u8,
-                        patch: l3 as u8,
-                    }
+                    unsafe { wit_import1(ptr0) };
+                    let l2 = i32::from(*ptr0.add(0).cast::<u8>());
+                    let l3 = i32::from(*ptr0.add(1).cast::<u8>());
+                    let l4 = i32::from(*ptr0.add(2).cast::<u8>());
+                    let result5 = super::super::super::rudel::base::base::SemanticVersion {
+                        major: l2 as u8,
+                        minor: l3 as u8,
+                        patch: l4 as u8,
+                    };
+                    result5
                 }
             }
             #[allow(unused_unsafe, clippy::all)]
@@ -461,46 +576,63 @@ pub mod rudel {
             /// You should probably not use this function directly, but use a higher level abstraction instead
             ///
             /// The first-id is the index of the first LED to set. If the lux list is shorter than the number of LEDs, the remaining LEDs will not be modified. If the lux list is longer than the number of LEDs, the remaining values will be ignored.
+            ///
+            /// Returns the number of LEDs that were set.
             pub fn set_leds(first_id: u16, lux: &[u16]) -> u32 {
                 unsafe {
                     let vec0 = lux;
                     let ptr0 = vec0.as_ptr().cast::<u8>();
                     let len0 = vec0.len();
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/hardware@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/hardware@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "set-leds"]
-                        fn wit_import(_: i32, _: *mut u8, _: usize) -> i32;
+                        fn wit_import1(_: i32, _: *mut u8, _: usize) -> i32;
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: i32, _: *mut u8, _: usize) -> i32 {
+                    unsafe extern "C" fn wit_import1(
+                        _: i32,
+                        _: *mut u8,
+                        _: usize,
+                    ) -> i32 {
                         unreachable!()
                     }
-                    let ret = wit_import(_rt::as_i32(&first_id), ptr0.cast_mut(), len0);
+                    let ret = unsafe {
+                        wit_import1(_rt::as_i32(&first_id), ptr0.cast_mut(), len0)
+                    };
                     ret as u32
                 }
             }
             #[allow(unused_unsafe, clippy::all)]
             /// Convenience function to set all LEDs
+            ///
+            /// Returns the number of LEDs that were set.
             pub fn set_rgb(color: LedColor, lux: u32) -> u32 {
                 unsafe {
                     let LedColor { red: red0, green: green0, blue: blue0 } = color;
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/hardware@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/hardware@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "set-rgb"]
-                        fn wit_import(_: i32, _: i32, _: i32, _: i32) -> i32;
+                        fn wit_import1(_: i32, _: i32, _: i32, _: i32) -> i32;
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: i32, _: i32, _: i32, _: i32) -> i32 {
+                    unsafe extern "C" fn wit_import1(
+                        _: i32,
+                        _: i32,
+                        _: i32,
+                        _: i32,
+                    ) -> i32 {
                         unreachable!()
                     }
-                    let ret = wit_import(
-                        _rt::as_i32(red0),
-                        _rt::as_i32(green0),
-                        _rt::as_i32(blue0),
-                        _rt::as_i32(&lux),
-                    );
+                    let ret = unsafe {
+                        wit_import1(
+                            _rt::as_i32(red0),
+                            _rt::as_i32(green0),
+                            _rt::as_i32(blue0),
+                            _rt::as_i32(&lux),
+                        )
+                    };
                     ret as u32
                 }
             }
@@ -509,16 +641,16 @@ pub mod rudel {
             pub fn led_count() -> u32 {
                 unsafe {
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/hardware@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/hardware@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "led-count"]
-                        fn wit_import() -> i32;
+                        fn wit_import0() -> i32;
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import() -> i32 {
+                    unsafe extern "C" fn wit_import0() -> i32 {
                         unreachable!()
                     }
-                    let ret = wit_import();
+                    let ret = unsafe { wit_import0() };
                     ret as u32
                 }
             }
@@ -528,33 +660,48 @@ pub mod rudel {
             /// If the id does not exist, the function will return a led-info with all values set to 0
             pub fn get_led_info(id: u16) -> LedInfo {
                 unsafe {
-                    #[repr(align(2))]
-                    struct RetArea([::core::mem::MaybeUninit<u8>; 6]);
-                    let mut ret_area = RetArea([::core::mem::MaybeUninit::uninit(); 6]);
+                    #[repr(align(4))]
+                    struct RetArea([::core::mem::MaybeUninit<u8>; 48]);
+                    let mut ret_area = RetArea([::core::mem::MaybeUninit::uninit(); 48]);
                     let ptr0 = ret_area.0.as_mut_ptr().cast::<u8>();
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/hardware@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/hardware@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "get-led-info"]
-                        fn wit_import(_: i32, _: *mut u8);
+                        fn wit_import1(_: i32, _: *mut u8);
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: i32, _: *mut u8) {
+                    unsafe extern "C" fn wit_import1(_: i32, _: *mut u8) {
                         unreachable!()
                     }
-                    wit_import(_rt::as_i32(&id), ptr0);
-                    let l1 = i32::from(*ptr0.add(0).cast::<u8>());
-                    let l2 = i32::from(*ptr0.add(1).cast::<u8>());
-                    let l3 = i32::from(*ptr0.add(2).cast::<u8>());
-                    let l4 = i32::from(*ptr0.add(4).cast::<u16>());
-                    LedInfo {
+                    unsafe { wit_import1(_rt::as_i32(&id), ptr0) };
+                    let l2 = i32::from(*ptr0.add(0).cast::<u8>());
+                    let l3 = i32::from(*ptr0.add(1).cast::<u8>());
+                    let l4 = i32::from(*ptr0.add(2).cast::<u8>());
+                    let l5 = i32::from(*ptr0.add(4).cast::<u16>());
+                    let l6 = i32::from(*ptr0.add(6).cast::<u16>());
+                    let l7 = i32::from(*ptr0.add(8).cast::<u16>());
+                    let l8 = *ptr0.add(12).cast::<f32>();
+                    let l9 = *ptr0.add(16).cast::<f32>();
+                    let l10 = *ptr0.add(20).cast::<f32>();
+                    let l11 = *ptr0.add(24).cast::<f32>();
+                    let l12 = *ptr0.add(28).cast::<f32>();
+                    let l13 = *ptr0.add(32).cast::<f32>();
+                    let l14 = *ptr0.add(36).cast::<f32>();
+                    let l15 = *ptr0.add(40).cast::<f32>();
+                    let l16 = *ptr0.add(44).cast::<f32>();
+                    let result17 = LedInfo {
                         color: LedColor {
-                            red: l1 as u8,
-                            green: l2 as u8,
-                            blue: l3 as u8,
+                            red: l2 as u8,
+                            green: l3 as u8,
+                            blue: l4 as u8,
                         },
-                        max_lux: l4 as u16,
-                    }
+                        max_lux: l5 as u16,
+                        color_temperature_k: l6 as u16,
+                        wavelength_nm: l7 as u16,
+                        white_balance: ((l8, l9, l10), (l11, l12, l13), (l14, l15, l16)),
+                    };
+                    result17
                 }
             }
             #[allow(unused_unsafe, clippy::all)]
@@ -564,16 +711,16 @@ pub mod rudel {
             pub fn get_ambient_light_type() -> AmbientLightType {
                 unsafe {
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/hardware@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/hardware@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "get-ambient-light-type"]
-                        fn wit_import() -> i32;
+                        fn wit_import0() -> i32;
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import() -> i32 {
+                    unsafe extern "C" fn wit_import0() -> i32 {
                         unreachable!()
                     }
-                    let ret = wit_import();
+                    let ret = unsafe { wit_import0() };
                     AmbientLightType::_lift(ret as u8)
                 }
             }
@@ -584,16 +731,36 @@ pub mod rudel {
             pub fn get_ambient_light() -> u32 {
                 unsafe {
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/hardware@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/hardware@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "get-ambient-light"]
-                        fn wit_import() -> i32;
+                        fn wit_import0() -> i32;
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import() -> i32 {
+                    unsafe extern "C" fn wit_import0() -> i32 {
                         unreachable!()
                     }
-                    let ret = wit_import();
+                    let ret = unsafe { wit_import0() };
+                    ret as u32
+                }
+            }
+            #[allow(unused_unsafe, clippy::all)]
+            /// Get the uncalibrated reading of the ambient light sensor
+            ///
+            /// Use this if you want to do your own calibration. The unit depends on the hardware
+            pub fn get_ambient_light_raw() -> u32 {
+                unsafe {
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "rudel:base/hardware@0.0.2")]
+                    unsafe extern "C" {
+                        #[link_name = "get-ambient-light-raw"]
+                        fn wit_import0() -> i32;
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import0() -> i32 {
+                        unreachable!()
+                    }
+                    let ret = unsafe { wit_import0() };
                     ret as u32
                 }
             }
@@ -602,16 +769,16 @@ pub mod rudel {
             pub fn get_vibration_sensor_type() -> VibrationSensorType {
                 unsafe {
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/hardware@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/hardware@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "get-vibration-sensor-type"]
-                        fn wit_import() -> i32;
+                        fn wit_import0() -> i32;
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import() -> i32 {
+                    unsafe extern "C" fn wit_import0() -> i32 {
                         unreachable!()
                     }
-                    let ret = wit_import();
+                    let ret = unsafe { wit_import0() };
                     VibrationSensorType::_lift(ret as u8)
                 }
             }
@@ -622,22 +789,62 @@ pub mod rudel {
             pub fn get_vibration() -> u32 {
                 unsafe {
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/hardware@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/hardware@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "get-vibration"]
-                        fn wit_import() -> i32;
+                        fn wit_import0() -> i32;
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import() -> i32 {
+                    unsafe extern "C" fn wit_import0() -> i32 {
                         unreachable!()
                     }
-                    let ret = wit_import();
+                    let ret = unsafe { wit_import0() };
                     ret as u32
                 }
             }
+            #[allow(unused_unsafe, clippy::all)]
+            /// Get the temperature of the device in millidegrees Celsius
+            ///
+            /// Returns the minimum value of s32 if the temperature could not be read
+            pub fn get_temperature() -> i32 {
+                unsafe {
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "rudel:base/hardware@0.0.2")]
+                    unsafe extern "C" {
+                        #[link_name = "get-temperature"]
+                        fn wit_import0() -> i32;
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import0() -> i32 {
+                        unreachable!()
+                    }
+                    let ret = unsafe { wit_import0() };
+                    ret
+                }
+            }
+            #[allow(unused_unsafe, clippy::all)]
+            /// Get the battery level in percent
+            ///
+            /// Returns 255 if the battery level is unknown, for example because the host can not measure it. Do not base any behaviour on the battery level in that case.
+            pub fn get_battery_level() -> u8 {
+                unsafe {
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "rudel:base/hardware@0.0.2")]
+                    unsafe extern "C" {
+                        #[link_name = "get-battery-level"]
+                        fn wit_import0() -> i32;
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import0() -> i32 {
+                        unreachable!()
+                    }
+                    let ret = unsafe { wit_import0() };
+                    ret as u8
+                }
+            }
         }
         /// Control ble stuff
-        #[allow(dead_code, clippy::all)]
+        #[allow(dead_code, async_fn_in_trait, unused_imports, clippy::all)]
         pub mod ble {
             use super::super::super::_rt;
             pub type SemanticVersion = super::super::super::rudel::base::base::SemanticVersion;
@@ -659,10 +866,59 @@ pub mod rudel {
                         .finish()
                 }
             }
-            /// The data to be sent in the advertisement
+            /// The manufacturer data to be sent in the advertisement, starting with the company ID
             ///
-            /// Up to 32 bytes of data
+            /// The host wraps everything after the company ID in a rudelblinken advertisement header, so up to 16 bytes of data fit after it
             pub type AdvertisementData = _rt::Vec<u8>;
+            /// Service data with a 16 bit service UUID
+            #[derive(Clone)]
+            pub struct ServiceData {
+                pub uuid: u16,
+                pub data: _rt::Vec<u8>,
+            }
+            impl ::core::fmt::Debug for ServiceData {
+                fn fmt(
+                    &self,
+                    f: &mut ::core::fmt::Formatter<'_>,
+                ) -> ::core::fmt::Result {
+                    f.debug_struct("ServiceData")
+                        .field("uuid", &self.uuid)
+                        .field("data", &self.data)
+                        .finish()
+                }
+            }
+            #[derive(Clone)]
+            pub struct Advertisement {
+                pub address: u64,
+                /// Company identifier, 0 if the advertisement has no manufacturer data
+                pub company: u16,
+                /// 32 byte of data
+                /// TODO: Figure out the limit
+                pub data: (u32, u32, u32, u32, u32, u32, u32, u32),
+                /// how many of the data bytes are actually used
+                pub data_length: u8,
+                /// Signal strength in dBm, 0 if unknown
+                pub rssi: i8,
+                pub received_at: u64,
+                /// Service data with 16 bit UUIDs contained in the advertisement
+                pub service_data: _rt::Vec<ServiceData>,
+            }
+            impl ::core::fmt::Debug for Advertisement {
+                fn fmt(
+                    &self,
+                    f: &mut ::core::fmt::Formatter<'_>,
+                ) -> ::core::fmt::Result {
+                    f.debug_struct("Advertisement")
+                        .field("address", &self.address)
+                        .field("company", &self.company)
+                        .field("data", &self.data)
+                        .field("data-length", &self.data_length)
+                        .field("rssi", &self.rssi)
+                        .field("received-at", &self.received_at)
+                        .field("service-data", &self.service_data)
+                        .finish()
+                }
+            }
             #[allow(unused_unsafe, clippy::all)]
             /// Get the version of the hardware interface provided by the runtime.
             ///
@@ -674,24 +930,25 @@ pub mod rudel {
                     let mut ret_area = RetArea([::core::mem::MaybeUninit::uninit(); 3]);
                     let ptr0 = ret_area.0.as_mut_ptr().cast::<u8>();
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/ble@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/ble@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "get-ble-version"]
-                        fn wit_import(_: *mut u8);
+                        fn wit_import1(_: *mut u8);
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: *mut u8) {
+                    unsafe extern "C" fn wit_import1(_: *mut u8) {
                         unreachable!()
                     }
-                    wit_import(ptr0);
-                    let l1 = i32::from(*ptr0.add(0).cast::<u8>());
-                    let l2 = i32::from(*ptr0.add(1).cast::<u8>());
-                    let l3 = i32::from(*ptr0.add(2).cast::<u8>());
-                    super::super::super::rudel::base::base::SemanticVersion {
-                        major: l1 as u8,
-                        minor: l2 as u8,
-                        patch: l3 as u8,
-                    }
+                    unsafe { wit_import1(ptr0) };
+                    let l2 = i32::from(*ptr0.add(0).cast::<u8>());
+                    let l3 = i32::from(*ptr0.add(1).cast::<u8>());
+                    let l4 = i32::from(*ptr0.add(2).cast::<u8>());
+                    let result5 = super::super::super::rudel::base::base::SemanticVersion {
+                        major: l2 as u8,
+                        minor: l3 as u8,
+                        patch: l4 as u8,
+                    };
+                    result5
                 }
             }
             #[allow(unused_unsafe, clippy::all)]
@@ -702,42 +959,264 @@ pub mod rudel {
                         max_interval: max_interval0,
                     } = settings;
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/ble@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/ble@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "configure-advertisement"]
-                        fn wit_import(_: i32, _: i32) -> i32;
+                        fn wit_import1(_: i32, _: i32) -> i32;
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: i32, _: i32) -> i32 {
+                    unsafe extern "C" fn wit_import1(_: i32, _: i32) -> i32 {
                         unreachable!()
                     }
-                    let ret = wit_import(
-                        _rt::as_i32(min_interval0),
-                        _rt::as_i32(max_interval0),
-                    );
+                    let ret = unsafe {
+                        wit_import1(
+                            _rt::as_i32(min_interval0),
+                            _rt::as_i32(max_interval0),
+                        )
+                    };
                     ret as u32
                 }
             }
             #[allow(unused_unsafe, clippy::all)]
-            pub fn set_advertisement_data(data: &AdvertisementData) -> u32 {
+            pub fn set_advertisement_data(data: &[u8]) -> u32 {
                 unsafe {
                     let vec0 = data;
                     let ptr0 = vec0.as_ptr().cast::<u8>();
                     let len0 = vec0.len();
                     #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "rudel:base/ble@0.0.1")]
-                    extern "C" {
+                    #[link(wasm_import_module = "rudel:base/ble@0.0.2")]
+                    unsafe extern "C" {
                         #[link_name = "set-advertisement-data"]
-                        fn wit_import(_: *mut u8, _: usize) -> i32;
+                        fn wit_import1(_: *mut u8, _: usize) -> i32;
                     }
                     #[cfg(not(target_arch = "wasm32"))]
-                    fn wit_import(_: *mut u8, _: usize) -> i32 {
+                    unsafe extern "C" fn wit_import1(_: *mut u8, _: usize) -> i32 {
                         unreachable!()
                     }
-                    let ret = wit_import(ptr0.cast_mut(), len0);
+                    let ret = unsafe { wit_import1(ptr0.cast_mut(), len0) };
                     ret as u32
                 }
             }
+            #[allow(unused_unsafe, clippy::all)]
+            /// Get the addresses of the devices that are currently connected over BLE
+            ///
+            /// Connections change the duty cycle of the radio, so you may want to send fewer advertisements while someone is connected.
+            pub fn get_connected_devices() -> _rt::Vec<u64> {
+                unsafe {
+                    #[cfg_attr(target_pointer_width = "64", repr(align(8)))]
+                    #[cfg_attr(target_pointer_width = "32", repr(align(4)))]
+                    struct RetArea(
+                        [::core::mem::MaybeUninit<
+                            u8,
+                        >; 2 * ::core::mem::size_of::<*const u8>()],
+                    );
+                    let mut ret_area = RetArea(
+                        [::core::mem::MaybeUninit::uninit(); 2
+                            * ::core::mem::size_of::<*const u8>()],
+                    );
+                    let ptr0 = ret_area.0.as_mut_ptr().cast::<u8>();
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "rudel:base/ble@0.0.2")]
+                    unsafe extern "C" {
+                        #[link_name = "get-connected-devices"]
+                        fn wit_import1(_: *mut u8);
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import1(_: *mut u8) {
+                        unreachable!()
+                    }
+                    unsafe { wit_import1(ptr0) };
+                    let l2 = *ptr0.add(0).cast::<*mut u8>();
+                    let l3 = *ptr0
+                        .add(::core::mem::size_of::<*const u8>())
+                        .cast::<usize>();
+                    let len4 = l3;
+                    let result5 = _rt::Vec::from_raw_parts(l2.cast(), len4, len4);
+                    result5
+                }
+            }
+            #[allow(unused_unsafe, clippy::all)]
+            /// Configure how the host scans for advertisements
+            ///
+            /// `window` and `interval` are in milliseconds. The host listens for `window` milliseconds every `interval` milliseconds, so the window is at most as long as the interval. Active scans request scan responses from the advertising devices. Returns 0 on success.
+            pub fn configure_scan(window: u16, interval: u16, active: bool) -> u32 {
+                unsafe {
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "rudel:base/ble@0.0.2")]
+                    unsafe extern "C" {
+                        #[link_name = "configure-scan"]
+                        fn wit_import0(_: i32, _: i32, _: i32) -> i32;
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import0(_: i32, _: i32, _: i32) -> i32 {
+                        unreachable!()
+                    }
+                    let ret = unsafe {
+                        wit_import0(
+                            _rt::as_i32(&window),
+                            _rt::as_i32(&interval),
+                            match &active {
+                                true => 1,
+                                false => 0,
+                            },
+                        )
+                    };
+                    ret as u32
+                }
+            }
+            #[allow(unused_unsafe, clippy::all)]
+            /// Get the advertisements that were received since the last call
+            ///
+            /// The host only keeps the most recent advertisements, older ones are dropped if you do not call this often enough. Advertisements are also passed to `on-advertisement` of the `ble-guest` interface, use whichever fits your program better.
+            pub fn get_scan_results() -> _rt::Vec<Advertisement> {
+                unsafe {
+                    #[cfg_attr(target_pointer_width = "64", repr(align(8)))]
+                    #[cfg_attr(target_pointer_width = "32", repr(align(4)))]
+                    struct RetArea(
+                        [::core::mem::MaybeUninit<
+                            u8,
+                        >; 2 * ::core::mem::size_of::<*const u8>()],
+                    );
+                    let mut ret_area = RetArea(
+                        [::core::mem::MaybeUninit::uninit(); 2
+                            * ::core::mem::size_of::<*const u8>()],
+                    );
+                    let ptr0 = ret_area.0.as_mut_ptr().cast::<u8>();
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "rudel:base/ble@0.0.2")]
+                    unsafe extern "C" {
+                        #[link_name = "get-scan-results"]
+                        fn wit_import1(_: *mut u8);
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import1(_: *mut u8) {
+                        unreachable!()
+                    }
+                    unsafe { wit_import1(ptr0) };
+                    let l2 = *ptr0.add(0).cast::<*mut u8>();
+                    let l3 = *ptr0
+                        .add(::core::mem::size_of::<*const u8>())
+                        .cast::<usize>();
+                    let base24 = l2;
+                    let len24 = l3;
+                    let mut result24 = _rt::Vec::with_capacity(len24);
+                    for i in 0..len24 {
+                        let base = base24
+                            .add(i * (56 + 2 * ::core::mem::size_of::<*const u8>()));
+                        let e24 = {
+                            let l4 = *base.add(0).cast::<i64>();
+                            let l5 = i32::from(*base.add(8).cast::<u16>());
+                            let l6 = *base.add(12).cast::<i32>();
+                            let l7 = *base.add(16).cast::<i32>();
+                            let l8 = *base.add(20).cast::<i32>();
+                            let l9 = *base.add(24).cast::<i32>();
+                            let l10 = *base.add(28).cast::<i32>();
+                            let l11 = *base.add(32).cast::<i32>();
+                            let l12 = *base.add(36).cast::<i32>();
+                            let l13 = *base.add(40).cast::<i32>();
+                            let l14 = i32::from(*base.add(44).cast::<u8>());
+                            let l15 = i32::from(*base.add(45).cast::<i8>());
+                            let l16 = *base.add(48).cast::<i64>();
+                            let l17 = *base.add(56).cast::<*mut u8>();
+                            let l18 = *base
+                                .add(56 + 1 * ::core::mem::size_of::<*const u8>())
+                                .cast::<usize>();
+                            let base23 = l17;
+                            let len23 = l18;
+                            let mut result23 = _rt::Vec::with_capacity(len23);
+                            for i in 0..len23 {
+                                let base = base23
+                                    .add(i * (3 * ::core::mem::size_of::<*const u8>()));
+                                let e23 = {
+                                    let l19 = i32::from(*base.add(0).cast::<u16>());
+                                    let l20 = *base
+                                        .add(::core::mem::size_of::<*const u8>())
+                                        .cast::<*mut u8>();
+                                    let l21 = *base
+                                        .add(2 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<usize>();
+                                    let len22 = l21;
+                                    ServiceData {
+                                        uuid: l19 as u16,
+                                        data: _rt::Vec::from_raw_parts(l20.cast(), len22, len22),
+                                    }
+                                };
+                                result23.push(e23);
+                            }
+                            _rt::cabi_dealloc(
+                                base23,
+                                len23 * (3 * ::core::mem::size_of::<*const u8>()),
+                                ::core::mem::size_of::<*const u8>(),
+                            );
+                            Advertisement {
+                                address: l4 as u64,
+                                company: l5 as u16,
+                                data: (
+                                    l6 as u32,
+                                    l7 as u32,
+                                    l8 as u32,
+                                    l9 as u32,
+                                    l10 as u32,
+                                    l11 as u32,
+                                    l12 as u32,
+                                    l13 as u32,
+                                ),
+                                data_length: l14 as u8,
+                                rssi: l15 as i8,
+                                received_at: l16 as u64,
+                                service_data: result23,
+                            }
+                        };
+                        result24.push(e24);
+                    }
+                    _rt::cabi_dealloc(
+                        base24,
+                        len24 * (56 + 2 * ::core::mem::size_of::<*const u8>()),
+                        8,
+                    );
+                    let result25 = result24;
+                    result25
+                }
+            }
+            #[allow(unused_unsafe, clippy::all)]
+            /// Set the group ID that the host sends in the header of the advertisements
+            ///
+            /// By default the host sends the group ID from the configuration. Applies to the next call to `set-advertisement-data`.
+            pub fn set_group_id(id: u16) -> () {
+                unsafe {
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "rudel:base/ble@0.0.2")]
+                    unsafe extern "C" {
+                        #[link_name = "set-group-id"]
+                        fn wit_import0(_: i32);
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import0(_: i32) {
+                        unreachable!()
+                    }
+                    unsafe { wit_import0(_rt::as_i32(&id)) };
+                }
+            }
+            #[allow(unused_unsafe, clippy::all)]
+            /// Get the group ID that the host sends in the header of the advertisements
+            ///
+            /// This is the group ID set with `set-group-id`, or the group ID from the configuration. Devices should only synchronize with devices in the same group.
+            pub fn get_group_id() -> u16 {
+                unsafe {
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "rudel:base/ble@0.0.2")]
+                    unsafe extern "C" {
+                        #[link_name = "get-group-id"]
+                        fn wit_import0() -> i32;
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import0() -> i32 {
+                        unreachable!()
+                    }
+                    let ret = unsafe { wit_import0() };
+                    ret as u16
+                }
+            }
         }
     }
 }
@@ -746,36 +1225,10 @@ pub mod rudel {
 pub mod exports {
     pub mod rudel {
         pub mod base {
-            #[allow(dead_code, clippy::all)]
+            #[allow(dead_code, async_fn_in_trait, unused_imports, clippy::all)]
             pub mod ble_guest {
                 use super::super::super::super::_rt;
-                #[repr(C)]
-                #[derive(Clone, Copy)]
-                pub struct Advertisement {
-                    pub address: u64,
-                    /// Company identifier
-                    pub company: u16,
-                    /// 32 byte of data
-                    /// TODO: Figure out the limit
-                    pub data: (u32, u32, u32, u32, u32, u32, u32, u32),
-                    /// how many of the data bytes are actually used
-                    pub data_length: u8,
-                    pub received_at: u64,
-                }
-                impl ::core::fmt::Debug for Advertisement {
-                    fn fmt(
-                        &self,
-                        f: &mut ::core::fmt::Formatter<'_>,
-                    ) -> ::core::fmt::Result {
-                        f.debug_struct("Advertisement")
-                            .field("address", &self.address)
-                            .field("company", &self.company)
-                            .field("data", &self.data)
-                            .field("data-length", &self.data_length)
-                            .field("received-at", &self.received_at)
-                            .finish()
-                    }
-                }
+                pub type Advertisement = super::super::super::super::rudel::base::ble::Advertisement;
                 #[doc(hidden)]
                 #[allow(non_snake_case)]
                 pub unsafe fn _export_on_advertisement_cabi<T: Guest>(
@@ -790,10 +1243,40 @@ pub mod exports {
                     arg8: i32,
                     arg9: i32,
                     arg10: i32,
-                    arg11: i64,
+                    arg11: i32,
+                    arg12: i64,
+                    arg13: *mut u8,
+                    arg14: usize,
                 ) {
                     #[cfg(target_arch = "wasm32")] _rt::run_ctors_once();
-                    T::on_advertisement(Advertisement {
+                    let base4 = arg13;
+                    let len4 = arg14;
+                    let mut result4 = _rt::Vec::with_capacity(len4);
+                    for i in 0..len4 {
+                        let base = base4
+                            .add(i * (3 * ::core::mem::size_of::<*const u8>()));
+                        let e4 = {
+                            let l0 = i32::from(*base.add(0).cast::<u16>());
+                            let l1 = *base
+                                .add(::core::mem::size_of::<*const u8>())
+                                .cast::<*mut u8>();
+                            let l2 = *base
+                                .add(2 * ::core::mem::size_of::<*const u8>())
+                                .cast::<usize>();
+                            let len3 = l2;
+                            super::super::super::super::rudel::base::ble::ServiceData {
+                                uuid: l0 as u16,
+                                data: _rt::Vec::from_raw_parts(l1.cast(), len3, len3),
+                            }
+                        };
+                        result4.push(e4);
+                    }
+                    _rt::cabi_dealloc(
+                        base4,
+                        len4 * (3 * ::core::mem::size_of::<*const u8>()),
+                        ::core::mem::size_of::<*const u8>(),
+                    );
+                    T::on_advertisement(super::super::super::super::rudel::base::ble::Advertisement {
                         address: arg0 as u64,
                         company: arg1 as u16,
                         data: (
@@ -807,7 +1290,9 @@ pub mod exports {
                             arg9 as u32,
                         ),
                         data_length: arg10 as u8,
-                        received_at: arg11 as u64,
+                        rssi: arg11 as i8,
+                        received_at: arg12 as u64,
+                        service_data: result4,
                     });
                 }
                 pub trait Guest {
@@ -815,26 +1300,57 @@ pub mod exports {
                     ///
                     /// The rudelblinken runtime will mock out all functions the it can not link.
                     /// If this function returns false you should not use any of the other functions
-                    fn on_advertisement(advertisement: Advertisement);
+                    fn on_advertisement(advertisement: Advertisement) -> ();
                 }
                 #[doc(hidden)]
                 #[macro_export]
-                macro_rules! __export_rudel_base_ble_guest_0_0_1_cabi {
+                macro_rules! __export_rudel_base_ble_guest_0_0_2_cabi {
                     ($ty:ident with_types_in $($path_to_types:tt)*) => {
-                        const _ : () = { #[export_name =
-                        "rudel:base/ble-guest@0.0.1#on-advertisement"] unsafe extern "C"
+                        const _ : () = { #[unsafe (export_name =
+                        "rudel:base/ble-guest@0.0.2#on-advertisement")] unsafe extern "C"
                         fn export_on_advertisement(arg0 : i64, arg1 : i32, arg2 : i32,
                         arg3 : i32, arg4 : i32, arg5 : i32, arg6 : i32, arg7 : i32, arg8
-                        : i32, arg9 : i32, arg10 : i32, arg11 : i64,) {
-                        $($path_to_types)*:: _export_on_advertisement_cabi::<$ty > (arg0,
-                        arg1, arg2, arg3, arg4, arg5, arg6, arg7, arg8, arg9, arg10,
-                        arg11) } };
+                        : i32, arg9 : i32, arg10 : i32, arg11 : i32, arg12 : i64, arg13 :
+                        * mut u8, arg14 : usize,) { unsafe { $($path_to_types)*::
+                        _export_on_advertisement_cabi::<$ty > (arg0, arg1, arg2, arg3,
+                        arg4, arg5, arg6, arg7, arg8, arg9, arg10, arg11, arg12, arg13,
+                        arg14) } } };
+                    };
+                }
+                #[doc(hidden)]
+                pub use __export_rudel_base_ble_guest_0_0_2_cabi;
+            }
+            /// Callbacks for timers set with `set-timer`
+            #[allow(dead_code, async_fn_in_trait, unused_imports, clippy::all)]
+            pub mod timer_guest {
+                use super::super::super::super::_rt;
+                #[doc(hidden)]
+                #[allow(non_snake_case)]
+                pub unsafe fn _export_on_timer_cabi<T: Guest>(arg0: i32, arg1: i64) {
+                    #[cfg(target_arch = "wasm32")] _rt::run_ctors_once();
+                    T::on_timer(arg0 as u8, arg1 as u64);
+                }
+                pub trait Guest {
+                    /// Called while yielding after a timer expired
+                    ///
+                    /// `fired-at` is the time in microseconds since boot when the host noticed the expired timer.
+                    fn on_timer(id: u8, fired_at: u64) -> ();
+                }
+                #[doc(hidden)]
+                #[macro_export]
+                macro_rules! __export_rudel_base_timer_guest_0_0_2_cabi {
+                    ($ty:ident with_types_in $($path_to_types:tt)*) => {
+                        const _ : () = { #[unsafe (export_name =
+                        "rudel:base/timer-guest@0.0.2#on-timer")] unsafe extern "C" fn
+                        export_on_timer(arg0 : i32, arg1 : i64,) { unsafe {
+                        $($path_to_types)*:: _export_on_timer_cabi::<$ty > (arg0, arg1) }
+                        } };
                     };
                 }
                 #[doc(hidden)]
-                pub use __export_rudel_base_ble_guest_0_0_1_cabi;
+                pub use __export_rudel_base_timer_guest_0_0_2_cabi;
             }
-            #[allow(dead_code, clippy::all)]
+            #[allow(dead_code, async_fn_in_trait, unused_imports, clippy::all)]
             pub mod run {
                 use super::super::super::super::_rt;
                 #[doc(hidden)]
@@ -845,49 +1361,26 @@ pub mod exports {
                 }
                 pub trait Guest {
                     /// Run the program.
-                    fn run();
+                    fn run() -> ();
                 }
                 #[doc(hidden)]
                 #[macro_export]
-                macro_rules! __export_rudel_base_run_0_0_1_cabi {
+                macro_rules! __export_rudel_base_run_0_0_2_cabi {
                     ($ty:ident with_types_in $($path_to_types:tt)*) => {
-                        const _ : () = { #[export_name = "rudel:base/run@0.0.1#run"]
-                        unsafe extern "C" fn export_run() { $($path_to_types)*::
-                        _export_run_cabi::<$ty > () } };
+                        const _ : () = { #[unsafe (export_name =
+                        "rudel:base/run@0.0.2#run")] unsafe extern "C" fn export_run() {
+                        unsafe { $($path_to_types)*:: _export_run_cabi::<$ty > () } } };
                     };
                 }
                 #[doc(hidden)]
-                pub use __export_rudel_base_run_0_0_1_cabi;
+                pub use __export_rudel_base_run_0_0_2_cabi;
             }
         }
     }
 }
 #[rustfmt::skip]
 mod _rt {
-    pub fn as_i64<T: AsI64>(t: T) -> i64 {
-        t.as_i64()
-    }
-    pub trait AsI64 {
-        fn as_i64(self) -> i64;
-    }
-    impl<'a, T: Copy + AsI64> AsI64 for &'a T {
-        fn as_i64(self) -> i64 {
-            (*self).as_i64()
-        }
-    }
-    impl AsI64 for i64 {
-        #[inline]
-        fn as_i64(self) -> i64 {
-            self as i64
-        }
-    }
-    impl AsI64 for u64 {
-        #[inline]
-        fn as_i64(self) -> i64 {
-            self as i64
-        }
-    }
-    pub use alloc_crate::vec::Vec;
+    #![allow(dead_code, clippy::all)]
     pub fn as_i32<T: AsI32>(t: T) -> i32 {
         t.as_i32()
     }
@@ -947,14 +1440,57 @@ mod _rt {
             self as i32
         }
     }
+    pub unsafe fn bool_lift(val: u8) -> bool {
+        if cfg!(debug_assertions) {
+            match val {
+                0 => false,
+                1 => true,
+                _ => panic!("invalid bool discriminant"),
+            }
+        } else {
+            val != 0
+        }
+    }
+    pub fn as_i64<T: AsI64>(t: T) -> i64 {
+        t.as_i64()
+    }
+    pub trait AsI64 {
+        fn as_i64(self) -> i64;
+    }
+    impl<'a, T: Copy + AsI64> AsI64 for &'a T {
+        fn as_i64(self) -> i64 {
+            (*self).as_i64()
+        }
+    }
+    impl AsI64 for i64 {
+        #[inline]
+        fn as_i64(self) -> i64 {
+            self as i64
+        }
+    }
+    impl AsI64 for u64 {
+        #[inline]
+        fn as_i64(self) -> i64 {
+            self as i64
+        }
+    }
+    pub use alloc_crate::vec::Vec;
+    pub unsafe fn cabi_dealloc(ptr: *mut u8, size: usize, align: usize) {
+        if size == 0 {
+            return;
+        }
+        let layout = alloc::Layout::from_size_align_unchecked(size, align);
+        alloc::dealloc(ptr, layout);
+    }
     #[cfg(target_arch = "wasm32")]
     pub fn run_ctors_once() {
         wit_bindgen::rt::run_ctors_once();
     }
     extern crate alloc as alloc_crate;
+    pub use alloc_crate::alloc;
 }
-/// Generates `#[no_mangle]` functions to export the specified type as the
-/// root implementation of all generated traits.
+/// Generates `#[unsafe(no_mangle)]` functions to export the specified type as
+/// the root implementation of all generated traits.
 ///
 /// For more information see the documentation of `wit_bindgen::generate!`.
 ///
@@ -978,78 +1514,110 @@ macro_rules! __export_rudel_impl {
     };
     ($ty:ident with_types_in $($path_to_types_root:tt)*) => {
         $($path_to_types_root)*::
-        exports::rudel::base::ble_guest::__export_rudel_base_ble_guest_0_0_1_cabi!($ty
+        exports::rudel::base::ble_guest::__export_rudel_base_ble_guest_0_0_2_cabi!($ty
         with_types_in $($path_to_types_root)*:: exports::rudel::base::ble_guest);
         $($path_to_types_root)*::
-        exports::rudel::base::run::__export_rudel_base_run_0_0_1_cabi!($ty with_types_in
+        exports::rudel::base::timer_guest::__export_rudel_base_timer_guest_0_0_2_cabi!($ty
+        with_types_in $($path_to_types_root)*:: exports::rudel::base::timer_guest);
+        $($path_to_types_root)*::
+        exports::rudel::base::run::__export_rudel_base_run_0_0_2_cabi!($ty with_types_in
         $($path_to_types_root)*:: exports::rudel::base::run); const _ : () = {
-        #[cfg(target_arch = "wasm32")] #[link_section =
-        "component-type:wit-bindgen:0.36.0:rudel:base@0.0.1:rudel:imports and exports"]
-        #[doc(hidden)] pub static __WIT_BINDGEN_COMPONENT_TYPE : [u8; 1409] = *
+        #[cfg(target_arch = "wasm32")] #[unsafe (link_section =
+        "component-type:wit-bindgen:0.41.0:rudel:base@0.0.2:rudel:imports and exports")]
+        #[doc(hidden)] #[allow(clippy::octal_escapes)] pub static
+        __WIT_BINDGEN_COMPONENT_TYPE : [u8; 1985] = *
         b"\
-\0asm\x0d\0\x01\0\0\x19\x16wit-component-encoding\x04\0\x07\x85\x0a\x01A\x02\x01\
-A\x0b\x01B\x16\x01r\x03\x05major}\x05minor}\x05patch}\x04\0\x10semantic-version\x03\
+\0asm\x0d\0\x01\0\0\x19\x16wit-component-encoding\x04\0\x07\xc5\x0e\x01A\x02\x01\
+A\x0e\x01B\x1c\x01r\x03\x05major}\x05minor}\x05patch}\x04\0\x10semantic-version\x03\
 \0\0\x01m\x05\x05error\x07warning\x04info\x05debug\x05trace\x04\0\x09log-level\x03\
-\0\x02\x01@\0\0\x01\x04\0\x10get-base-version\x01\x04\x01@\x01\x06microsw\0y\x04\
-\0\x09yield-now\x01\x05\x01@\0\0y\x04\0\x12get-remaining-fuel\x01\x06\x01@\x01\x06\
-microsw\x01\0\x04\0\x05sleep\x01\x07\x01@\0\0w\x04\0\x04time\x01\x08\x01@\x02\x05\
-level\x03\x07messages\x01\0\x04\0\x03log\x01\x09\x01o\x10}}}}}}}}}}}}}}}}\x01@\0\
-\0\x0a\x04\0\x08get-name\x01\x0b\x01p}\x01@\0\0\x0c\x04\0\x0aget-config\x01\x0d\x03\
-\0\x15rudel:base/base@0.0.1\x05\0\x02\x03\0\0\x10semantic-version\x01B\x1b\x02\x03\
-\x02\x01\x01\x04\0\x10semantic-version\x03\0\0\x01r\x03\x03red}\x05green}\x04blu\
-e}\x04\0\x09led-color\x03\0\x02\x01r\x02\x05color\x03\x07max-lux{\x04\0\x08led-i\
-nfo\x03\0\x04\x01m\x02\x04none\x05basic\x04\0\x12ambient-light-type\x03\0\x06\x01\
-m\x02\x04none\x04ball\x04\0\x15vibration-sensor-type\x03\0\x08\x01@\0\0\x01\x04\0\
-\x14get-hardware-version\x01\x0a\x01p{\x01@\x02\x08first-id{\x03lux\x0b\0y\x04\0\
-\x08set-leds\x01\x0c\x01@\x02\x05color\x03\x03luxy\0y\x04\0\x07set-rgb\x01\x0d\x01\
-@\0\0y\x04\0\x09led-count\x01\x0e\x01@\x01\x02id{\0\x05\x04\0\x0cget-led-info\x01\
-\x0f\x01@\0\0\x07\x04\0\x16get-ambient-light-type\x01\x10\x04\0\x11get-ambient-l\
-ight\x01\x0e\x01@\0\0\x09\x04\0\x19get-vibration-sensor-type\x01\x11\x04\0\x0dge\
-t-vibration\x01\x0e\x03\0\x19rudel:base/hardware@0.0.1\x05\x02\x01B\x0c\x02\x03\x02\
-\x01\x01\x04\0\x10semantic-version\x03\0\0\x01r\x02\x0cmin-interval{\x0cmax-inte\
-rval{\x04\0\x16advertisement-settings\x03\0\x02\x01p}\x04\0\x12advertisement-dat\
-a\x03\0\x04\x01@\0\0\x01\x04\0\x0fget-ble-version\x01\x06\x01@\x01\x08settings\x03\
-\0y\x04\0\x17configure-advertisement\x01\x07\x01@\x01\x04data\x05\0y\x04\0\x16se\
-t-advertisement-data\x01\x08\x03\0\x14rudel:base/ble@0.0.1\x05\x03\x01B\x05\x01o\
-\x08yyyyyyyy\x01r\x05\x07addressw\x07company{\x04data\0\x0bdata-length}\x0brecei\
-ved-atw\x04\0\x0dadvertisement\x03\0\x01\x01@\x01\x0dadvertisement\x02\x01\0\x04\
-\0\x10on-advertisement\x01\x03\x04\0\x1arudel:base/ble-guest@0.0.1\x05\x04\x01B\x02\
-\x01@\0\x01\0\x04\0\x03run\x01\0\x04\0\x14rudel:base/run@0.0.1\x05\x05\x04\0\x16\
-rudel:base/rudel@0.0.1\x04\0\x0b\x0b\x01\0\x05rudel\x03\0\0\0G\x09producers\x01\x0c\
-processed-by\x02\x0dwit-component\x070.220.0\x10wit-bindgen-rust\x060.36.0";
+\0\x02\x01@\0\0\x01\x04\0\x10get-base-version\x01\x04\x01@\x02\x07version\x01\x08\
+required\x01\0\x7f\x04\0\x11version-satisfies\x01\x05\x01@\x01\x06microsw\0y\x04\
+\0\x09yield-now\x01\x06\x01@\0\0y\x04\0\x12get-remaining-fuel\x01\x07\x01@\x01\x06\
+microsw\x01\0\x04\0\x05sleep\x01\x08\x01@\0\0w\x04\0\x04time\x01\x09\x01@\x02\x02\
+id}\x08delay-usw\x01\0\x04\0\x09set-timer\x01\x0a\x01@\x01\x02id}\x01\0\x04\0\x0c\
+cancel-timer\x01\x0b\x01@\x02\x05level\x03\x07messages\x01\0\x04\0\x03log\x01\x0c\
+\x01o\x10}}}}}}}}}}}}}}}}\x01@\0\0\x0d\x04\0\x08get-name\x01\x0e\x01p}\x01@\0\0\x0f\
+\x04\0\x0aget-config\x01\x10\x03\0\x15rudel:base/base@0.0.2\x05\0\x02\x03\0\0\x10\
+semantic-version\x01B\"\x02\x03\x02\x01\x01\x04\0\x10semantic-version\x03\0\0\x01\
+r\x03\x03red}\x05green}\x04blue}\x04\0\x09led-color\x03\0\x02\x01o\x03vvv\x01o\x03\
+\x04\x04\x04\x01r\x05\x05color\x03\x07max-lux{\x13color-temperature-k{\x0dwavele\
+ngth-nm{\x0dwhite-balance\x05\x04\0\x08led-info\x03\0\x06\x01m\x02\x04none\x05ba\
+sic\x04\0\x12ambient-light-type\x03\0\x08\x01m\x02\x04none\x04ball\x04\0\x15vibr\
+ation-sensor-type\x03\0\x0a\x01@\0\0\x01\x04\0\x14get-hardware-version\x01\x0c\x01\
+p{\x01@\x02\x08first-id{\x03lux\x0d\0y\x04\0\x08set-leds\x01\x0e\x01@\x02\x05col\
+or\x03\x03luxy\0y\x04\0\x07set-rgb\x01\x0f\x01@\0\0y\x04\0\x09led-count\x01\x10\x01\
+@\x01\x02id{\0\x07\x04\0\x0cget-led-info\x01\x11\x01@\0\0\x09\x04\0\x16get-ambie\
+nt-light-type\x01\x12\x04\0\x11get-ambient-light\x01\x10\x04\0\x15get-ambient-li\
+ght-raw\x01\x10\x01@\0\0\x0b\x04\0\x19get-vibration-sensor-type\x01\x13\x04\0\x0d\
+get-vibration\x01\x10\x01@\0\0z\x04\0\x0fget-temperature\x01\x14\x01@\0\0}\x04\0\
+\x11get-battery-level\x01\x15\x03\0\x19rudel:base/hardware@0.0.2\x05\x02\x01B\x1f\
+\x02\x03\x02\x01\x01\x04\0\x10semantic-version\x03\0\0\x01r\x02\x0cmin-interval{\
+\x0cmax-interval{\x04\0\x16advertisement-settings\x03\0\x02\x01p}\x04\0\x12adver\
+tisement-data\x03\0\x04\x01p}\x01r\x02\x04uuid{\x04data\x06\x04\0\x0cservice-dat\
+a\x03\0\x07\x01o\x08yyyyyyyy\x01p\x08\x01r\x07\x07addressw\x07company{\x04data\x09\
+\x0bdata-length}\x04rssi~\x0breceived-atw\x0cservice-data\x0a\x04\0\x0dadvertise\
+ment\x03\0\x0b\x01@\0\0\x01\x04\0\x0fget-ble-version\x01\x0d\x01@\x01\x08setting\
+s\x03\0y\x04\0\x17configure-advertisement\x01\x0e\x01@\x01\x04data\x05\0y\x04\0\x16\
+set-advertisement-data\x01\x0f\x01pw\x01@\0\0\x10\x04\0\x15get-connected-devices\
+\x01\x11\x01@\x03\x06window{\x08interval{\x06active\x7f\0y\x04\0\x0econfigure-sc\
+an\x01\x12\x01p\x0c\x01@\0\0\x13\x04\0\x10get-scan-results\x01\x14\x01@\x01\x02i\
+d{\x01\0\x04\0\x0cset-group-id\x01\x15\x01@\0\0{\x04\0\x0cget-group-id\x01\x16\x03\
+\0\x14rudel:base/ble@0.0.2\x05\x03\x02\x03\0\x02\x0dadvertisement\x01B\x04\x02\x03\
+\x02\x01\x04\x04\0\x0dadvertisement\x03\0\0\x01@\x01\x0dadvertisement\x01\x01\0\x04\
+\0\x10on-advertisement\x01\x02\x04\0\x1arudel:base/ble-guest@0.0.2\x05\x05\x01B\x02\
+\x01@\x02\x02id}\x08fired-atw\x01\0\x04\0\x08on-timer\x01\0\x04\0\x1crudel:base/\
+timer-guest@0.0.2\x05\x06\x01B\x02\x01@\0\x01\0\x04\0\x03run\x01\0\x04\0\x14rude\
+l:base/run@0.0.2\x05\x07\x04\0\x16rudel:base/rudel@0.0.2\x04\0\x0b\x0b\x01\0\x05\
+rudel\x03\0\0\0G\x09producers\x01\x0cprocessed-by\x02\x0dwit-component\x070.227.\
+1\x10wit-bindgen-rust\x060.41.0";
         };
     };
 }
 #[doc(inline)]
 pub use __export_rudel_impl as export;
 #[cfg(target_arch = "wasm32")]
-#[link_section = "component-type:wit-bindgen:0.36.0:rudel:base@0.0.1:rudel-with-all-of-its-exports-removed:encoded world"]
+#[unsafe(
+    link_section = "component-type:wit-bindgen:0.41.0:rudel:base@0.0.2:rudel-with-all-of-its-exports-removed:encoded world"
+)]
 #[doc(hidden)]
-pub static __WIT_BINDGEN_COMPONENT_TYPE: [u8; 1274] = *b"\
-\0asm\x0d\0\x01\0\0\x19\x16wit-component-encoding\x04\0\x07\xde\x08\x01A\x02\x01\
-A\x07\x01B\x16\x01r\x03\x05major}\x05minor}\x05patch}\x04\0\x10semantic-version\x03\
+#[allow(clippy::octal_escapes)]
+pub static __WIT_BINDGEN_COMPONENT_TYPE: [u8; 1823] = *b"\
+\0asm\x0d\0\x01\0\0\x19\x16wit-component-encoding\x04\0\x07\x83\x0d\x01A\x02\x01\
+A\x07\x01B\x1c\x01r\x03\x05major}\x05minor}\x05patch}\x04\0\x10semantic-version\x03\
 \0\0\x01m\x05\x05error\x07warning\x04info\x05debug\x05trace\x04\0\x09log-level\x03\
-\0\x02\x01@\0\0\x01\x04\0\x10get-base-version\x01\x04\x01@\x01\x06microsw\0y\x04\
-\0\x09yield-now\x01\x05\x01@\0\0y\x04\0\x12get-remaining-fuel\x01\x06\x01@\x01\x06\
-microsw\x01\0\x04\0\x05sleep\x01\x07\x01@\0\0w\x04\0\x04time\x01\x08\x01@\x02\x05\
-level\x03\x07messages\x01\0\x04\0\x03log\x01\x09\x01o\x10}}}}}}}}}}}}}}}}\x01@\0\
-\0\x0a\x04\0\x08get-name\x01\x0b\x01p}\x01@\0\0\x0c\x04\0\x0aget-config\x01\x0d\x03\
-\0\x15rudel:base/base@0.0.1\x05\0\x02\x03\0\0\x10semantic-version\x01B\x1b\x02\x03\
-\x02\x01\x01\x04\0\x10semantic-version\x03\0\0\x01r\x03\x03red}\x05green}\x04blu\
-e}\x04\0\x09led-color\x03\0\x02\x01r\x02\x05color\x03\x07max-lux{\x04\0\x08led-i\
-nfo\x03\0\x04\x01m\x02\x04none\x05basic\x04\0\x12ambient-light-type\x03\0\x06\x01\
-m\x02\x04none\x04ball\x04\0\x15vibration-sensor-type\x03\0\x08\x01@\0\0\x01\x04\0\
-\x14get-hardware-version\x01\x0a\x01p{\x01@\x02\x08first-id{\x03lux\x0b\0y\x04\0\
-\x08set-leds\x01\x0c\x01@\x02\x05color\x03\x03luxy\0y\x04\0\x07set-rgb\x01\x0d\x01\
-@\0\0y\x04\0\x09led-count\x01\x0e\x01@\x01\x02id{\0\x05\x04\0\x0cget-led-info\x01\
-\x0f\x01@\0\0\x07\x04\0\x16get-ambient-light-type\x01\x10\x04\0\x11get-ambient-l\
-ight\x01\x0e\x01@\0\0\x09\x04\0\x19get-vibration-sensor-type\x01\x11\x04\0\x0dge\
-t-vibration\x01\x0e\x03\0\x19rudel:base/hardware@0.0.1\x05\x02\x01B\x0c\x02\x03\x02\
-\x01\x01\x04\0\x10semantic-version\x03\0\0\x01r\x02\x0cmin-interval{\x0cmax-inte\
-rval{\x04\0\x16advertisement-settings\x03\0\x02\x01p}\x04\0\x12advertisement-dat\
-a\x03\0\x04\x01@\0\0\x01\x04\0\x0fget-ble-version\x01\x06\x01@\x01\x08settings\x03\
-\0y\x04\0\x17configure-advertisement\x01\x07\x01@\x01\x04data\x05\0y\x04\0\x16se\
-t-advertisement-data\x01\x08\x03\0\x14rudel:base/ble@0.0.1\x05\x03\x04\06rudel:b\
-ase/rudel-with-all-of-its-exports-removed@0.0.1\x04\0\x0b+\x01\0%rudel-with-all-\
-of-its-exports-removed\x03\0\0\0G\x09producers\x01\x0cprocessed-by\x02\x0dwit-co\
-mponent\x070.220.0\x10wit-bindgen-rust\x060.36.0";
+\0\x02\x01@\0\0\x01\x04\0\x10get-base-version\x01\x04\x01@\x02\x07version\x01\x08\
+required\x01\0\x7f\x04\0\x11version-satisfies\x01\x05\x01@\x01\x06microsw\0y\x04\
+\0\x09yield-now\x01\x06\x01@\0\0y\x04\0\x12get-remaining-fuel\x01\x07\x01@\x01\x06\
+microsw\x01\0\x04\0\x05sleep\x01\x08\x01@\0\0w\x04\0\x04time\x01\x09\x01@\x02\x02\
+id}\x08delay-usw\x01\0\x04\0\x09set-timer\x01\x0a\x01@\x01\x02id}\x01\0\x04\0\x0c\
+cancel-timer\x01\x0b\x01@\x02\x05level\x03\x07messages\x01\0\x04\0\x03log\x01\x0c\
+\x01o\x10}}}}}}}}}}}}}}}}\x01@\0\0\x0d\x04\0\x08get-name\x01\x0e\x01p}\x01@\0\0\x0f\
+\x04\0\x0aget-config\x01\x10\x03\0\x15rudel:base/base@0.0.2\x05\0\x02\x03\0\0\x10\
+semantic-version\x01B\"\x02\x03\x02\x01\x01\x04\0\x10semantic-version\x03\0\0\x01\
+r\x03\x03red}\x05green}\x04blue}\x04\0\x09led-color\x03\0\x02\x01o\x03vvv\x01o\x03\
+\x04\x04\x04\x01r\x05\x05color\x03\x07max-lux{\x13color-temperature-k{\x0dwavele\
+ngth-nm{\x0dwhite-balance\x05\x04\0\x08led-info\x03\0\x06\x01m\x02\x04none\x05ba\
+sic\x04\0\x12ambient-light-type\x03\0\x08\x01m\x02\x04none\x04ball\x04\0\x15vibr\
+ation-sensor-type\x03\0\x0a\x01@\0\0\x01\x04\0\x14get-hardware-version\x01\x0c\x01\
+p{\x01@\x02\x08first-id{\x03lux\x0d\0y\x04\0\x08set-leds\x01\x0e\x01@\x02\x05col\
+or\x03\x03luxy\0y\x04\0\x07set-rgb\x01\x0f\x01@\0\0y\x04\0\x09led-count\x01\x10\x01\
+@\x01\x02id{\0\x07\x04\0\x0cget-led-info\x01\x11\x01@\0\0\x09\x04\0\x16get-ambie\
+nt-light-type\x01\x12\x04\0\x11get-ambient-light\x01\x10\x04\0\x15get-ambient-li\
+ght-raw\x01\x10\x01@\0\0\x0b\x04\0\x19get-vibration-sensor-type\x01\x13\x04\0\x0d\
+get-vibration\x01\x10\x01@\0\0z\x04\0\x0fget-temperature\x01\x14\x01@\0\0}\x04\0\
+\x11get-battery-level\x01\x15\x03\0\x19rudel:base/hardware@0.0.2\x05\x02\x01B\x1f\
+\x02\x03\x02\x01\x01\x04\0\x10semantic-version\x03\0\0\x01r\x02\x0cmin-interval{\
+\x0cmax-interval{\x04\0\x16advertisement-settings\x03\0\x02\x01p}\x04\0\x12adver\
+tisement-data\x03\0\x04\x01p}\x01r\x02\x04uuid{\x04data\x06\x04\0\x0cservice-dat\
+a\x03\0\x07\x01o\x08yyyyyyyy\x01p\x08\x01r\x07\x07addressw\x07company{\x04data\x09\
+\x0bdata-length}\x04rssi~\x0breceived-atw\x0cservice-data\x0a\x04\0\x0dadvertise\
+ment\x03\0\x0b\x01@\0\0\x01\x04\0\x0fget-ble-version\x01\x0d\x01@\x01\x08setting\
+s\x03\0y\x04\0\x17configure-advertisement\x01\x0e\x01@\x01\x04data\x05\0y\x04\0\x16\
+set-advertisement-data\x01\x0f\x01pw\x01@\0\0\x10\x04\0\x15get-connected-devices\
+\x01\x11\x01@\x03\x06window{\x08interval{\x06active\x7f\0y\x04\0\x0econfigure-sc\
+an\x01\x12\x01p\x0c\x01@\0\0\x13\x04\0\x10get-scan-results\x01\x14\x01@\x01\x02i\
+d{\x01\0\x04\0\x0cset-group-id\x01\x15\x01@\0\0{\x04\0\x0cget-group-id\x01\x16\x03\
+\0\x14rudel:base/ble@0.0.2\x05\x03\x04\06rudel:base/rudel-with-all-of-its-export\
+s-removed@0.0.2\x04\0\x0b+\x01\0%rudel-with-all-of-its-exports-removed\x03\0\0\0\
+G\x09producers\x01\x0cprocessed-by\x02\x0dwit-component\x070.227.1\x10wit-bindge\
+n-rust\x060.41.0";
@@ -0,0 +1,408 @@
+//! Test guest code natively, without a WASM runtime
+//!
+//! With the `testing` feature the host functions of the SDK are provided by a [MockHost] instead of the runtime, so guest code can be unit tested with `cargo test`. Every thread has its own mock host, so tests that run in parallel do not interfere. Configure one at the start of a test and install it:
+//!
+//! ```rust,ignore
+//! use rudelblinken_sdk::{testing::MockHost, time, GroupSync};
+//!
+//! #[test]
+//! fn progress_advances_one_step_every_16_ms() {
+//!     MockHost::new().with_time_ms(1_000).install();
+//!     let mut sync = GroupSync::new();
+//!     MockHost::advance_time_ms(160);
+//!     assert_eq!(sync.update((time() / 1000) as u32), 10);
+//! }
+//! ```
+//!
+//! Functions that are not mocked, like [crate::get_led_info], still panic outside of WASM.
+use crate::{Advertisement, AdvertisementData, AdvertisementSettings, LogLevel};
+use std::{cell::RefCell, collections::BTreeMap};
+
+thread_local! {
+    /// The mock host of the current test
+    static HOST: RefCell<MockHost> = RefCell::new(MockHost::new());
+}
+
+/// Mocked state of the host, that is read and modified by the host functions
+#[derive(Clone, Debug)]
+pub struct MockHost {
+    /// Current time in microseconds
+    time_us: u64,
+    name: String,
+    /// The group ID in the configuration
+    group_id: u8,
+    /// The group ID passed to [crate::set_group_id]
+    advertised_group_id: Option<u16>,
+    /// The guest configuration
+    config: Vec<u8>,
+    /// The current brightness of each LED
+    leds: Vec<u16>,
+    ambient_light: u32,
+    vibration: u32,
+    /// Temperature in millidegrees Celsius
+    temperature: i32,
+    battery_level: u8,
+    /// Time in microseconds at which each pending timer fires, by timer ID
+    timers: BTreeMap<u8, u64>,
+    advertisement_settings: Option<AdvertisementSettings>,
+    advertisement_data: AdvertisementData,
+    /// The data of the last call to [crate::set_advertisement_data] with the rudelblinken header, as a host would send it
+    manufacturer_data: Option<Vec<u8>>,
+    /// Window, interval and whether the scan is active, as passed to [crate::configure_scan]
+    scan_settings: Option<(u16, u16, bool)>,
+    /// Advertisements that are returned by the next call to [crate::get_scan_results]
+    scan_results: Vec<Advertisement>,
+    logs: Vec<(LogLevel, String)>,
+}
+
+impl Default for MockHost {
+    fn default() -> Self {
+        return MockHost::new();
+    }
+}
+
+impl MockHost {
+    /// A host with a single LED at time 0
+    pub fn new() -> Self {
+        return MockHost {
+            time_us: 0,
+            name: "mock-host".to_string(),
+            group_id: 0,
+            advertised_group_id: None,
+            config: Vec::new(),
+            leds: vec![0],
+            ambient_light: 0,
+            vibration: 0,
+            temperature: 25_000,
+            battery_level: 100,
+            timers: BTreeMap::new(),
+            advertisement_settings: None,
+            advertisement_data: Vec::new(),
+            manufacturer_data: None,
+            scan_settings: None,
+            scan_results: Vec::new(),
+            logs: Vec::new(),
+        };
+    }
+
+    /// Start at the given time in milliseconds
+    pub fn with_time_ms(mut self, time_ms: u64) -> Self {
+        self.time_us = time_ms * 1000;
+        return self;
+    }
+
+    /// Provide the given number of LEDs, all of them off
+    pub fn with_leds(mut self, count: u16) -> Self {
+        self.leds = vec![0; count as usize];
+        return self;
+    }
+
+    /// Set the device name. Only the first 16 bytes are passed to the guest
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        return self;
+    }
+
+    pub fn with_group_id(mut self, group_id: u8) -> Self {
+        self.group_id = group_id;
+        return self;
+    }
+
+    /// Set the guest configuration, for example key-value pairs as read by the [crate::config] module
+    pub fn with_config(mut self, config: &[u8]) -> Self {
+        self.config = config.to_vec();
+        return self;
+    }
+
+    pub fn with_ambient_light(mut self, ambient_light: u32) -> Self {
+        self.ambient_light = ambient_light;
+        return self;
+    }
+
+    pub fn with_vibration(mut self, vibration: u32) -> Self {
+        self.vibration = vibration;
+        return self;
+    }
+
+    /// Set the temperature in millidegrees Celsius
+    pub fn with_temperature(mut self, temperature: i32) -> Self {
+        self.temperature = temperature;
+        return self;
+    }
+
+    pub fn with_battery_level(mut self, battery_level: u8) -> Self {
+        self.battery_level = battery_level;
+        return self;
+    }
+
+    /// Return the given advertisements from the next call to [crate::get_scan_results]
+    pub fn with_scan_results(mut self, scan_results: &[Advertisement]) -> Self {
+        self.scan_results = scan_results.to_vec();
+        return self;
+    }
+
+    /// Use this host for all host functions that are called from the current thread
+    pub fn install(self) {
+        HOST.with_borrow_mut(|host| *host = self);
+    }
+
+    /// Get a copy of the host of the current thread
+    pub fn current() -> MockHost {
+        return HOST.with_borrow(|host| host.clone());
+    }
+
+    /// Advance the time of the host of the current thread
+    pub fn advance_time_ms(milliseconds: u64) {
+        HOST.with_borrow_mut(|host| host.time_us += milliseconds * 1000);
+    }
+
+    /// Current time in microseconds
+    pub fn time_us(&self) -> u64 {
+        return self.time_us;
+    }
+
+    /// The current brightness of each LED
+    pub fn leds(&self) -> &[u16] {
+        return &self.leds;
+    }
+
+    /// Time in microseconds at which each pending timer fires, by timer ID
+    pub fn timers(&self) -> &BTreeMap<u8, u64> {
+        return &self.timers;
+    }
+
+    /// The settings of the last call to [crate::configure_advertisement]
+    pub fn advertisement_settings(&self) -> Option<AdvertisementSettings> {
+        return self.advertisement_settings;
+    }
+
+    /// The data of the last call to [crate::set_advertisement_data]
+    pub fn advertisement_data(&self) -> &[u8] {
+        return &self.advertisement_data;
+    }
+
+    /// The manufacturer data a host would send after the last call to [crate::set_advertisement_data]
+    ///
+    /// The rudelblinken header contains the group ID that was active during that call and sequence number `0`.
+    pub fn manufacturer_data(&self) -> Option<&[u8]> {
+        return self.manufacturer_data.as_deref();
+    }
+
+    /// Window, interval and whether the scan is active, as passed to the last call to [crate::configure_scan]
+    pub fn scan_settings(&self) -> Option<(u16, u16, bool)> {
+        return self.scan_settings;
+    }
+
+    /// The group ID passed to the last call to [crate::set_group_id]
+    pub fn advertised_group_id(&self) -> Option<u16> {
+        return self.advertised_group_id;
+    }
+
+    /// All messages that were logged by the guest
+    pub fn logs(&self) -> &[(LogLevel, String)] {
+        return &self.logs;
+    }
+}
+
+/// Mocked host functions with the same signatures as the WASM imports
+///
+/// The SDK exports these instead of the imports if the `testing` feature is enabled.
+pub mod host {
+    use super::HOST;
+    use crate::{Advertisement, AdvertisementData, AdvertisementSettings, LedColor, LogLevel};
+    use rudelblinken_protocol::encode_manufacturer_data;
+
+    /// Fuel that is reported after yielding. The mock host does not meter fuel
+    const MOCK_FUEL: u32 = u32::MAX;
+
+    pub fn yield_now(micros: u64) -> u32 {
+        HOST.with_borrow_mut(|host| host.time_us += micros);
+        return MOCK_FUEL;
+    }
+
+    pub fn sleep(micros: u64) {
+        HOST.with_borrow_mut(|host| host.time_us += micros);
+    }
+
+    pub fn time() -> u64 {
+        return HOST.with_borrow(|host| host.time_us);
+    }
+
+    pub fn set_timer(id: u8, delay_us: u64) {
+        HOST.with_borrow_mut(|host| {
+            let fires_at = host.time_us.saturating_add(delay_us);
+            host.timers.insert(id, fires_at);
+        });
+    }
+
+    pub fn cancel_timer(id: u8) {
+        HOST.with_borrow_mut(|host| host.timers.remove(&id));
+    }
+
+    pub fn log(level: LogLevel, message: &str) {
+        HOST.with_borrow_mut(|host| host.logs.push((level, message.to_string())));
+    }
+
+    /// The name as 16 zero padded bytes, like the WASM import returns it
+    #[allow(clippy::type_complexity)]
+    pub fn get_name() -> (
+        u8,
+        u8,
+        u8,
+        u8,
+        u8,
+        u8,
+        u8,
+        u8,
+        u8,
+        u8,
+        u8,
+        u8,
+        u8,
+        u8,
+        u8,
+        u8,
+    ) {
+        let mut name = [0u8; 16];
+        HOST.with_borrow(|host| {
+            let length = std::cmp::min(host.name.len(), name.len());
+            name[..length].copy_from_slice(&host.name.as_bytes()[..length]);
+        });
+        return (
+            name[0], name[1], name[2], name[3], name[4], name[5], name[6], name[7], name[8],
+            name[9], name[10], name[11], name[12], name[13], name[14], name[15],
+        );
+    }
+
+    pub fn get_config() -> Vec<u8> {
+        return HOST.with_borrow(|host| host.config.clone());
+    }
+
+    pub fn set_leds(first_id: u16, lux: &[u16]) -> u32 {
+        return HOST.with_borrow_mut(|host| {
+            let first_id = first_id as usize;
+            if first_id >= host.leds.len() {
+                return 0;
+            }
+            let count = std::cmp::min(lux.len(), host.leds.len() - first_id);
+            host.leds[first_id..(first_id + count)].copy_from_slice(&lux[..count]);
+            count as u32
+        });
+    }
+
+    /// Sets all LEDs to the given brightness. The color is ignored
+    pub fn set_rgb(_color: LedColor, lux: u32) -> u32 {
+        return HOST.with_borrow_mut(|host| {
+            host.leds.fill(std::cmp::min(lux, u16::MAX as u32) as u16);
+            host.leds.len() as u32
+        });
+    }
+
+    pub fn led_count() -> u32 {
+        return HOST.with_borrow(|host| host.leds.len() as u32);
+    }
+
+    pub fn get_ambient_light() -> u32 {
+        return HOST.with_borrow(|host| host.ambient_light);
+    }
+
+    pub fn get_vibration() -> u32 {
+        return HOST.with_borrow(|host| host.vibration);
+    }
+
+    pub fn get_temperature() -> i32 {
+        return HOST.with_borrow(|host| host.temperature);
+    }
+
+    pub fn get_battery_level() -> u8 {
+        return HOST.with_borrow(|host| host.battery_level);
+    }
+
+    pub fn configure_advertisement(settings: AdvertisementSettings) -> u32 {
+        HOST.with_borrow_mut(|host| host.advertisement_settings = Some(settings));
+        return 0;
+    }
+
+    /// Returns `1` like the hosts, if the data does not fit into a rudelblinken advertisement
+    pub fn set_advertisement_data(data: &AdvertisementData) -> u32 {
+        return HOST.with_borrow_mut(|host| {
+            let group_id = host.advertised_group_id.unwrap_or(host.group_id as u16);
+            let Some(manufacturer_data) = encode_manufacturer_data(group_id, 0, data) else {
+                return 1;
+            };
+            host.advertisement_data = data.clone();
+            host.manufacturer_data = Some(manufacturer_data);
+            0
+        });
+    }
+
+    pub fn configure_scan(window: u16, interval: u16, active: bool) -> u32 {
+        HOST.with_borrow_mut(|host| host.scan_settings = Some((window, interval, active)));
+        return 0;
+    }
+
+    /// Returns the advertisements set with [super::MockHost::with_scan_results] once
+    pub fn get_scan_results() -> Vec<Advertisement> {
+        return HOST.with_borrow_mut(|host| std::mem::take(&mut host.scan_results));
+    }
+
+    pub fn set_group_id(id: u16) {
+        HOST.with_borrow_mut(|host| host.advertised_group_id = Some(id));
+    }
+
+    pub fn get_group_id() -> u16 {
+        return HOST.with_borrow(|host| host.advertised_group_id.unwrap_or(host.group_id as u16));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MockHost;
+    use crate::{
+        get_config, get_group_id, set_advertisement_data, set_group_id, RudelblinkenAdvertisement,
+    };
+
+    /// Group ID in the rudelblinken header of the data the mock host would send
+    fn sent_group_id() -> u16 {
+        let host = MockHost::current();
+        let manufacturer_data = host.manufacturer_data().unwrap();
+        return RudelblinkenAdvertisement::parse(&manufacturer_data[2..])
+            .unwrap()
+            .group_id();
+    }
+
+    #[test]
+    fn set_group_id_changes_the_group_in_the_header() {
+        MockHost::new().with_group_id(3).install();
+        set_advertisement_data(&vec![0x00, 0x00, 0x42]);
+        assert_eq!(sent_group_id(), 3);
+
+        set_group_id(0x1234);
+        set_advertisement_data(&vec![0x00, 0x00, 0x42]);
+        assert_eq!(sent_group_id(), 0x1234);
+        assert_eq!(MockHost::current().advertised_group_id(), Some(0x1234));
+    }
+
+    #[test]
+    fn installing_a_new_host_forgets_the_group_id() {
+        MockHost::new().install();
+        set_group_id(0x1234);
+        MockHost::new().with_group_id(3).install();
+        set_advertisement_data(&vec![0x00, 0x00, 0x42]);
+        assert_eq!(sent_group_id(), 3);
+    }
+
+    #[test]
+    fn the_group_id_is_not_part_of_the_config() {
+        MockHost::new()
+            .with_group_id(3)
+            .with_config(&[1, 2])
+            .install();
+        assert_eq!(get_config(), vec![1, 2]);
+        assert_eq!(get_group_id(), 3);
+
+        set_group_id(0x1234);
+        assert_eq!(get_config(), vec![1, 2]);
+        assert_eq!(get_group_id(), 0x1234);
+    }
+}
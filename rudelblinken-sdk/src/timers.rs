@@ -0,0 +1,44 @@
+//! Run closures when a timer fires
+//!
+//! The host calls the `on-timer` export of the guest when a timer set with [crate::set_timer] expires. [set_on_timer] sets a timer and remembers a closure for it. Forward the export to [dispatch_timer] to run the closure:
+//!
+//! ```rust,ignore
+//! impl TimerGuest for MyGuest {
+//!     fn on_timer(id: u8, fired_at: u64) {
+//!         rudelblinken_sdk::timers::dispatch_timer(id, fired_at);
+//!     }
+//! }
+//! ```
+use crate::{cancel_timer, set_timer};
+use std::{cell::RefCell, collections::HashMap};
+
+thread_local! {
+    /// Callbacks of the pending timers by their id
+    static CALLBACKS: RefCell<HashMap<u8, Box<dyn FnMut(u64)>>> = RefCell::new(HashMap::new());
+}
+
+/// Call `callback` with the time it fired at, once the timer expires in `delay_ms` milliseconds
+///
+/// Setting a timer with the id of a pending timer replaces it and its callback. The callback runs only once, but it can set its timer again.
+pub fn set_on_timer(id: u8, delay_ms: u32, callback: impl FnMut(u64) + 'static) {
+    CALLBACKS.with_borrow_mut(|callbacks| callbacks.insert(id, Box::new(callback)));
+    set_timer(id, delay_ms as u64 * 1000);
+}
+
+/// Cancel a pending timer and drop its callback
+pub fn cancel_on_timer(id: u8) {
+    cancel_timer(id);
+    CALLBACKS.with_borrow_mut(|callbacks| callbacks.remove(&id));
+}
+
+/// Run the callback of the timer with the given id
+///
+/// Returns false if no callback was set for the timer with [set_on_timer].
+pub fn dispatch_timer(id: u8, fired_at: u64) -> bool {
+    // Removed before it runs, so the callback can set its own timer again
+    let Some(mut callback) = CALLBACKS.with_borrow_mut(|callbacks| callbacks.remove(&id)) else {
+        return false;
+    };
+    callback(fired_at);
+    return true;
+}
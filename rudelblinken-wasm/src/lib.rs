@@ -3,9 +3,9 @@ use std::sync::{LazyLock, Mutex};
 use rudelblinken_sdk::{
     export,
     exports::{self},
-    get_ambient_light, get_config, get_led_info, get_name, get_vibration, led_count, log,
-    set_advertisement_data, set_rgb, sleep, time, yield_now, Advertisement, BleGuest, Guest,
-    LedColor, LogLevel,
+    get_ambient_light, get_config, get_config_string, get_group_id, get_led_info, get_name,
+    get_vibration, led_count, log, set_advertisement_data, set_rgb, sleep, time, yield_now,
+    Advertisement, BleAdvertisement, BleGuest, GroupSync, Guest, LedColor, LogLevel, TimerGuest,
 };
 use talc::{ClaimOnOom, Span, Talc, Talck};
 
@@ -16,52 +16,24 @@ static mut HEAP: [u8; HEAP_SIZE] = [0u8; HEAP_SIZE];
 static ALLOCATOR: Talck<spin::Mutex<()>, ClaimOnOom> =
     Talc::new(unsafe { ClaimOnOom::new(Span::from_array((&raw const HEAP).cast_mut())) }).lock();
 
-const NUDGE_STRENGHT: u8 = 20;
-const MS_PER_STEP: u32 = 16;
+/// How often advertisements are relayed, so devices that are out of range of each other stay in sync
+const RELAY_HOPS: u8 = 3;
 
-#[derive(Debug, Clone)]
-struct CycleState {
-    progress: u8,
-    prog_time: u32,
-    off_sum: i32,
-    off_cnt: u16,
-    nudge_rem: i8,
-}
-
-impl CycleState {
-    fn new() -> Self {
-        Self {
-            progress: 0,
-            prog_time: (time() / 1000) as u32,
-            off_sum: 0,
-            off_cnt: 0,
-            nudge_rem: 0,
-        }
+static GROUP_SYNC: LazyLock<Mutex<GroupSync>> = LazyLock::new(|| {
+    let mut sync = GroupSync::new();
+    sync.enable_relay(RELAY_HOPS);
+    // Nearby devices get more influence, if an RSSI range is configured
+    if let (Some(min), Some(max)) = (get_config_rssi("rssi_min"), get_config_rssi("rssi_max")) {
+        sync.set_rssi_range(min, max);
     }
+    Mutex::new(sync)
+});
 
-    fn update_progress(&mut self, timestamp: u32) {
-        if self.off_cnt != 0 {
-            let div = self.off_cnt as i32 * NUDGE_STRENGHT as i32;
-            let nudge_base = self.off_sum + self.nudge_rem as i32;
-            let nudge = nudge_base / div;
-            self.nudge_rem = (nudge_base % div) as i8;
-
-            self.progress = self.progress.wrapping_add(nudge as u8);
-            self.off_sum = 0;
-            self.off_cnt = 0;
-        }
-
-        let dt = self.prog_time - timestamp;
-        let t_off = dt % MS_PER_STEP;
-        self.prog_time = timestamp - t_off;
-
-        let steps = dt / MS_PER_STEP;
-        self.progress = self.progress.wrapping_add(steps as u8);
-    }
+/// Read an RSSI in dBm from the configuration. It is stored as a string, like `rudelctl set-wasm-config` does without a schema
+fn get_config_rssi(key: &str) -> Option<i8> {
+    get_config_string(key)?.parse().ok()
 }
 
-static CYCLE_STATE: LazyLock<Mutex<CycleState>> = LazyLock::new(|| Mutex::new(CycleState::new()));
-
 // relative brightness to use in bright ambient conditions (>= MAX_AMBIENT); 0-255
 const MAX_BRIGHT: u8 = 192;
 // relative brightness to use in dark ambient conditions (<= MIN_AMBIENT); 0-255
@@ -128,7 +100,10 @@ impl Guest for Test {
         );
 
         let config = get_config();
-        log(LogLevel::Info, &format!("Configuration: {:?}", config));
+        log(
+            LogLevel::Info,
+            &format!("Configuration: {:?}, group: {}", config, get_group_id()),
+        );
 
         let led_info = get_led_info(0);
         let max_lux = led_info.max_lux as u32;
@@ -156,13 +131,11 @@ impl Guest for Test {
                     vibrate = (15 * vibrate + v) / 16;
                 }
             }
-            if let Ok(mut state) = CYCLE_STATE.try_lock() {
-                let t = (time() / 1000) as u32;
-
-                state.update_progress(t);
-                prog = state.progress;
-                drop(state);
-                set_advertisement_data(&vec![0x00, 0x00, 0xca, 0x7e, 0xa2, prog]);
+            if let Ok(mut sync) = GROUP_SYNC.try_lock() {
+                prog = sync.update((time() / 1000) as u32);
+                let advertisement_data = sync.advertisement_data();
+                drop(sync);
+                set_advertisement_data(&advertisement_data);
                 set_rgb(
                     LedColor {
                         red: 0xff,
@@ -190,22 +163,16 @@ impl Guest for Test {
 
 impl BleGuest for Test {
     fn on_advertisement(advertisement: Advertisement) {
-        let data = unsafe {
-            std::mem::transmute::<[u32; 8], [u8; 32]>(
-                advertisement.data.try_into().unwrap_unchecked(),
-            )
-        };
-        let slice = &data[0..(advertisement.data_length as usize)];
-        if slice.len() == 4 && slice[0] == 0x0ca && slice[1] == 0x7e && slice[2] == 0xa2 {
-            if let Ok(mut state) = CYCLE_STATE.try_lock() {
-                state.off_cnt += 1;
-                state.off_sum += slice[3].wrapping_sub(state.progress) as i8 as i32;
-                state.update_progress((advertisement.received_at / 1000) as u32)
-            }
+        if let Ok(mut sync) = GROUP_SYNC.try_lock() {
+            sync.on_ble_advertisement(&BleAdvertisement::new(advertisement));
         }
     }
 }
 
+impl TimerGuest for Test {
+    fn on_timer(_id: u8, _fired_at: u64) {}
+}
+
 /// Main is required for `cargo run`
 #[allow(dead_code)]
 fn main() {}
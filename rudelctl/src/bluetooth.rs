@@ -87,7 +87,11 @@ use std::future::Future;
 //     }
 // }
 
+/// Scan for devices with the given adapter and call `f` for each new device until `max_devices` calls succeeded
+///
+/// Adapter names are platform specific, on Linux they look like `hci0`. The default adapter is used if `adapter` is `None`.
 pub async fn scan_for<Fut, Err>(
+    adapter: Option<&str>,
     duration: Duration,
     max_devices: u32,
     f: &dyn Fn(Device) -> Fut,
@@ -96,7 +100,10 @@ where
     Fut: Future<Output = Result<(), Err>>,
 {
     let session = bluer::Session::new().await?;
-    let adapter = session.default_adapter().await?;
+    let adapter = match adapter {
+        Some(name) => session.adapter(name)?,
+        None => session.default_adapter().await?,
+    };
     adapter.set_powered(true).await?;
 
     {
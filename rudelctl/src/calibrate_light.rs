@@ -0,0 +1,72 @@
+//! Interactive two-point calibration of the ambient light sensor of a device
+//!
+//! The user measures the illuminance next to the device with a light meter at two different light levels. The calibration is a line through both points.
+use crate::update_target::{UpdateTarget, UpdateTargetError};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines, Stdin};
+
+#[derive(Error, Debug)]
+pub enum CalibrateLightError {
+    #[error(transparent)]
+    UpdateTargetError(#[from] UpdateTargetError),
+    #[error("io error")]
+    IoError(#[from] std::io::Error),
+    #[error("Standard input was closed before the calibration was finished")]
+    InputClosed,
+    #[error("The sensor reported the same value for both light levels, try a bigger difference in brightness")]
+    SameReading,
+}
+
+/// Ask for an illuminance in lux until a valid one is entered
+async fn prompt_lux(
+    lines: &mut Lines<BufReader<Stdin>>,
+    prompt: &str,
+) -> Result<f32, CalibrateLightError> {
+    loop {
+        eprint!("{}: ", prompt);
+        let Some(line) = lines.next_line().await? else {
+            return Err(CalibrateLightError::InputClosed);
+        };
+        match line.trim().parse::<f32>() {
+            Ok(lux) if lux >= 0.0 && lux.is_finite() => return Ok(lux),
+            _ => eprintln!("{} is not a valid illuminance in lux", line.trim()),
+        }
+    }
+}
+
+/// Calibrate the light sensor of the device by asking the user for two reference measurements
+pub async fn calibrate_light(update_target: &UpdateTarget) -> Result<(), CalibrateLightError> {
+    let current = update_target.get_ambient_light_calibration().await?;
+    eprintln!(
+        "Current calibration: lux = {} * raw + {}",
+        current.slope, current.intercept
+    );
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let dark_lux = prompt_lux(
+        &mut lines,
+        "Dim the light and enter the illuminance next to the device in lux",
+    )
+    .await?;
+    let dark_raw = update_target.get_ambient_light_calibration().await?.raw;
+    let bright_lux = prompt_lux(
+        &mut lines,
+        "Make it brighter and enter the illuminance next to the device in lux",
+    )
+    .await?;
+    let bright_raw = update_target.get_ambient_light_calibration().await?.raw;
+
+    if dark_raw == bright_raw {
+        return Err(CalibrateLightError::SameReading);
+    }
+    let slope = (bright_lux - dark_lux) / (bright_raw as f32 - dark_raw as f32);
+    let intercept = dark_lux - slope * dark_raw as f32;
+    update_target
+        .set_ambient_light_calibration(slope, intercept)
+        .await?;
+    println!(
+        "New calibration: lux = {} * raw + {}. Restart the device to apply it",
+        slope, intercept
+    );
+    return Ok(());
+}
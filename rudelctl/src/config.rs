@@ -0,0 +1,49 @@
+//! Settings from the rudelctl config file
+//!
+//! The file is read from `$XDG_CONFIG_HOME/rudelctl/config.toml`, or from `~/.config/rudelctl/config.toml` if `XDG_CONFIG_HOME` is not set. All fields are optional:
+//!
+//! ```toml
+//! # Bluetooth adapter that is used if no --adapter is given
+//! adapter = "hci1"
+//! ```
+use serde::Deserialize;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read the config file")]
+    IoError(#[from] std::io::Error),
+    #[error("The config file is not valid")]
+    InvalidConfig(#[from] toml::de::Error),
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+    /// Name of the Bluetooth adapter to use, for example `hci0`
+    pub adapter: Option<String>,
+}
+
+impl Config {
+    /// Location of the config file. `None` if neither `XDG_CONFIG_HOME` nor `HOME` is set
+    pub fn path() -> Option<PathBuf> {
+        let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(config_dir) => PathBuf::from(config_dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        return Some(config_dir.join("rudelctl").join("config.toml"));
+    }
+
+    /// Load the config file. Returns the default config if there is none
+    pub async fn load() -> Result<Config, ConfigError> {
+        let Some(path) = Config::path() else {
+            return Ok(Config::default());
+        };
+        let content = match tokio::fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(err.into()),
+        };
+        return Ok(toml::from_str(&content)?);
+    }
+}
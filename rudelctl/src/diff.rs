@@ -0,0 +1,107 @@
+//! Compare the programs that are running on two devices
+//!
+//! The devices only report the hash of their program. Local WASM files can be passed to find out which of them is running.
+use crate::{
+    bluetooth::scan_for,
+    update_target::{UpdateTarget, UpdateTargetError},
+};
+use bluer::{Address, Device};
+use futures_time::time::Duration;
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DiffError {
+    #[error("BlueR error")]
+    BluerError(#[from] bluer::Error),
+    #[error("io error")]
+    IoError(#[from] std::io::Error),
+    #[error("Did not find the device {0}")]
+    DeviceNotFound(Address),
+}
+
+/// A local WASM file that may be running on one of the devices
+struct KnownFile {
+    path: PathBuf,
+    size: usize,
+}
+
+/// Hash the given files the same way as they are hashed when they are uploaded
+async fn hash_files(files: &[PathBuf]) -> Result<HashMap<[u8; 32], KnownFile>, DiffError> {
+    let mut known_files = HashMap::new();
+    for path in files {
+        let content = tokio::fs::read(path).await?;
+        let hash: [u8; 32] = blake3::hash(&content).into();
+        known_files.insert(
+            hash,
+            KnownFile {
+                path: path.clone(),
+                size: content.len(),
+            },
+        );
+    }
+    return Ok(known_files);
+}
+
+/// Describe a program hash, including the matching local file if there is one
+fn describe(hash: &Option<[u8; 32]>, known_files: &HashMap<[u8; 32], KnownFile>) -> String {
+    let Some(hash) = hash else {
+        return "no program".to_string();
+    };
+    return match known_files.get(hash) {
+        Some(file) => format!(
+            "{} ({}, {} bytes)",
+            hex::encode(hash),
+            file.path.display(),
+            file.size
+        ),
+        None => hex::encode(hash),
+    };
+}
+
+/// Read the program hashes of both devices and print whether they are running the same program
+pub async fn diff_programs(
+    adapter: Option<&str>,
+    timeout: Duration,
+    address_a: Address,
+    address_b: Address,
+    files: &[PathBuf],
+) -> Result<(), DiffError> {
+    let known_files = hash_files(files).await?;
+
+    let hashes: Mutex<HashMap<Address, Option<[u8; 32]>>> = Mutex::new(HashMap::new());
+    let device_count = if address_a == address_b { 1 } else { 2 };
+    scan_for(
+        adapter,
+        timeout,
+        device_count,
+        &async |device: Device| -> Result<(), UpdateTargetError> {
+            let address = device.address();
+            if address != address_a && address != address_b {
+                return Err(UpdateTargetError::NotTheRequestedDevice);
+            }
+            let update_target = UpdateTarget::new_from_peripheral(&device).await?;
+            let hash = update_target.get_program_hash().await?;
+            hashes.lock().unwrap().insert(address, hash);
+            return Ok(());
+        },
+    )
+    .await?;
+
+    let hashes = hashes.into_inner().unwrap();
+    let Some(hash_a) = hashes.get(&address_a) else {
+        return Err(DiffError::DeviceNotFound(address_a));
+    };
+    let Some(hash_b) = hashes.get(&address_b) else {
+        return Err(DiffError::DeviceNotFound(address_b));
+    };
+
+    if hash_a == hash_b {
+        println!("Same program: {}", describe(hash_a, &known_files));
+        return Ok(());
+    }
+    println!("Different programs");
+    println!("{}: {}", address_a, describe(hash_a, &known_files));
+    println!("{}: {}", address_b, describe(hash_b, &known_files));
+    return Ok(());
+}
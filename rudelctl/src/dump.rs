@@ -0,0 +1,149 @@
+//! Print all GATT services and characteristics of a device, similar to `gatttool --char-read-all`
+use crate::update_target::UpdateTargetError;
+use bluer::{
+    gatt::{remote::Characteristic, CharacteristicFlags},
+    Device, Uuid, UuidExt,
+};
+
+const FILE_UPLOAD_SERVICE: u16 = 0x7892;
+const CAT_MANAGEMENT_SERVICE: u16 = 0x7992;
+/// The nordic UART service used for logging
+const SERIAL_LOGGING_SERVICE: Uuid = Uuid::from_u128(0x6E400001_B5A3_F393_E0A9_E50E24DCCA9E);
+const SERIAL_LOGGING_SERVICE_RX: Uuid = Uuid::from_u128(0x6E400002_B5A3_F393_E0A9_E50E24DCCA9E);
+const SERIAL_LOGGING_SERVICE_TX: Uuid = Uuid::from_u128(0x6E400003_B5A3_F393_E0A9_E50E24DCCA9E);
+
+const FILE_UPLOAD_CHARACTERISTICS: &[(u16, &str)] = &[
+    (0x7893, "Data"),
+    (0x7894, "Hash"),
+    (0x7895, "Checksums"),
+    (0x7896, "Length"),
+    (0x7897, "Chunk length"),
+    (0x7898, "Checksum algorithm"),
+    (0x7899, "Compression"),
+    (0x789A, "Upload progress"),
+    (0x789B, "Protocol version"),
+    (0x789C, "Max protocol version"),
+    (0x789E, "Missing chunks"),
+];
+
+const CAT_MANAGEMENT_CHARACTERISTICS: &[(u16, &str)] = &[
+    (0x7893, "Program hash"),
+    (0x7894, "Name"),
+    (0x7895, "Strip color"),
+    (0x7896, "WASM guest config"),
+    (0x789B, "Group ID"),
+    (0x789C, "Program schedule"),
+    (0x789D, "LED color profile"),
+    (0x789E, "Dropped BLE events"),
+];
+
+/// Human-readable name of a rudelblinken service
+fn service_name(service: Uuid) -> Option<&'static str> {
+    if service == SERIAL_LOGGING_SERVICE {
+        return Some("Serial logging");
+    }
+    return match service.as_u16()? {
+        FILE_UPLOAD_SERVICE => Some("File upload"),
+        CAT_MANAGEMENT_SERVICE => Some("Cat management"),
+        _ => None,
+    };
+}
+
+/// Human-readable name of a characteristic of a rudelblinken service
+///
+/// The services reuse the same UUIDs for their characteristics, so the service is needed as well.
+fn characteristic_name(service: Uuid, characteristic: Uuid) -> Option<&'static str> {
+    if service == SERIAL_LOGGING_SERVICE {
+        return match characteristic {
+            SERIAL_LOGGING_SERVICE_RX => Some("RX"),
+            SERIAL_LOGGING_SERVICE_TX => Some("TX"),
+            _ => None,
+        };
+    }
+    let characteristics = match service.as_u16()? {
+        FILE_UPLOAD_SERVICE => FILE_UPLOAD_CHARACTERISTICS,
+        CAT_MANAGEMENT_SERVICE => CAT_MANAGEMENT_CHARACTERISTICS,
+        _ => return None,
+    };
+    let characteristic = characteristic.as_u16()?;
+    return characteristics
+        .iter()
+        .find(|(uuid, _)| *uuid == characteristic)
+        .map(|(_, name)| *name);
+}
+
+/// Format a UUID with its name, if it is known
+fn annotate(uuid: Uuid, name: Option<&str>) -> String {
+    return match name {
+        Some(name) => format!("{} ({})", uuid, name),
+        None => uuid.to_string(),
+    };
+}
+
+fn format_flags(flags: &CharacteristicFlags) -> String {
+    let mut properties = Vec::new();
+    if flags.read {
+        properties.push("READ");
+    }
+    if flags.write {
+        properties.push("WRITE");
+    }
+    if flags.write_without_response {
+        properties.push("WRITE_NO_RESPONSE");
+    }
+    if flags.notify {
+        properties.push("NOTIFY");
+    }
+    if flags.indicate {
+        properties.push("INDICATE");
+    }
+    return properties.join("|");
+}
+
+/// Read the value of a characteristic as hex. Returns an empty string if it is not readable
+async fn read_value(
+    characteristic: &Characteristic,
+    flags: &CharacteristicFlags,
+) -> Result<String, bluer::Error> {
+    if !flags.read {
+        return Ok(String::new());
+    }
+    return Ok(hex::encode(characteristic.read().await?));
+}
+
+/// Connect to the device and print all of its services and characteristics
+pub async fn dump_device(device: &Device) -> Result<(), UpdateTargetError> {
+    if !device.is_connected().await? {
+        device
+            .connect()
+            .await
+            .map_err(UpdateTargetError::FailedToConnect)?;
+    }
+
+    eprintln!("service, characteristic, handles, properties, value");
+    for service in device.services().await? {
+        let service_uuid = service.uuid().await?;
+        let service_column = annotate(service_uuid, service_name(service_uuid));
+        for characteristic in service.characteristics().await? {
+            let characteristic_uuid = characteristic.uuid().await?;
+            let flags = characteristic.flags().await?;
+            let value = match read_value(&characteristic, &flags).await {
+                Ok(value) => value,
+                Err(err) => format!("<{}>", err),
+            };
+            println!(
+                "{}, {}, {:#06x}/{:#06x}, {}, {}",
+                service_column,
+                annotate(
+                    characteristic_uuid,
+                    characteristic_name(service_uuid, characteristic_uuid)
+                ),
+                service.id(),
+                characteristic.id(),
+                format_flags(&flags),
+                value
+            );
+        }
+    }
+    return Ok(());
+}
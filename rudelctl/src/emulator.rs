@@ -1,18 +1,33 @@
 //! Test wasm files on an emulated rudelblinken device.
+//!
+//! Every emulated device can be controlled over a WebSocket API, see [control].
+mod control;
 mod emulated_host;
+mod host_event;
+mod led_timeline;
+mod power_model;
+mod radio_model;
+mod visualize;
 use clap::Args;
-use emulated_host::EmulatedHost;
+use control::ControlState;
+use emulated_host::{EmulatedHost, EmulatorStats, DEFAULT_TEMPERATURE};
+use host_event::{parse_host_events, HostEventError, TimedHostEvent};
+use led_timeline::{LedTimeline, DEFAULT_TIMELINE_LENGTH};
+use power_model::{PowerModel, PowerSummary};
+pub use radio_model::RadioModel;
 use rudelblinken_runtime::host::Event;
 use std::{
+    collections::HashSet,
     ffi::OsStr,
     path::PathBuf,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 use thiserror::Error;
 use tokio::{
-    fs::{create_dir_all, read, read_dir, remove_file},
-    net::UnixDatagram,
-    time::interval,
+    fs::{create_dir_all, read, read_dir, read_to_string, remove_file, write},
+    net::{TcpListener, UnixDatagram},
+    time::{interval, sleep_until},
 };
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, TryFromBytes};
 
@@ -28,6 +43,8 @@ pub enum EmulatorError {
     InvalidCharacters(),
     #[error(transparent)]
     RuntimeError(#[from] rudelblinken_runtime::Error),
+    #[error("Failed to parse the events to inject")]
+    InvalidHostEvents(#[from] HostEventError),
 }
 
 #[derive(Args, Debug)]
@@ -38,16 +55,91 @@ pub struct EmulateCommand {
     /// Name of the instance
     #[arg(short, long)]
     name: Option<String>,
+
+    /// Render the LED state to the terminal
+    #[arg(long)]
+    visualize: bool,
+
+    /// Group ID of the emulated device
+    #[arg(short, long, default_value = "0")]
+    group: u8,
+
+    /// Newline-delimited JSON file with events that are injected at the given times
+    #[arg(long)]
+    inject_events: Option<PathBuf>,
+
+    /// Transmit power in dBm used for the energy estimation
+    #[arg(long, default_value = "0", allow_negative_numbers = true)]
+    tx_power: i8,
+
+    /// Temperature in degrees Celsius reported to the guest
+    #[arg(long, default_value = "25", allow_negative_numbers = true)]
+    temperature: f32,
+
+    /// Save the LED states as CSV to this file when the emulation ends
+    #[arg(long)]
+    save_timeline: Option<PathBuf>,
+
+    /// Number of LED states that are kept for the timeline
+    #[arg(long, default_value_t = DEFAULT_TIMELINE_LENGTH)]
+    timeline_length: usize,
 }
 
+/// Maximum number of visualization updates per second
+const VISUALIZATION_FPS: u64 = 30;
+/// How often the LED state is recorded in the power model
+const POWER_SAMPLE_INTERVAL_MS: u32 = 10;
+
 pub struct Emulator {
     wasm: Vec<u8>,
     name: String,
+    visualize: bool,
+    group_id: u8,
+    /// Do not send advertisements to other instances
+    silent: bool,
+    /// Temporarily blocks advertisements between two groups of instances
+    partition: Option<PartitionConfig>,
+    /// Decides which advertisements are received and with which signal strength
+    radio_model: Arc<RadioModel>,
+    /// Events that are injected into the guest, sorted by their timestamp
+    injected_events: Vec<TimedHostEvent>,
+    /// Transmit power in dBm used for the energy estimation
+    tx_power_dbm: i8,
+    /// Temperature reported to the guest in millidegrees Celsius
+    temperature: i32,
+    /// Configuration returned to the guest by `get-config`
+    config: Vec<u8>,
+    power_model: Arc<Mutex<PowerModel>>,
+    /// Counters of the host calls, shared with the host
+    stats: Arc<Mutex<EmulatorStats>>,
+    leds: Arc<Mutex<Vec<u16>>>,
+    timeline: Arc<Mutex<LedTimeline>>,
+    /// The timeline is saved to this file by [Emulator::save_timeline]
+    timeline_path: Option<PathBuf>,
     address: [u8; 6],
     socket: UnixDatagram,
     socket_dir: PathBuf,
 }
 
+/// Cuts the connection between two groups of instances for some time
+#[derive(Clone, Debug)]
+pub struct PartitionConfig {
+    /// Names of the instances on one side of the partition. All other instances are on the other side
+    pub side_a: HashSet<String>,
+    pub start: Instant,
+    pub end: Instant,
+}
+
+impl PartitionConfig {
+    /// Check if an advertisement from one instance reaches another instance at the given time
+    pub fn allows(&self, from: &str, to: &str, now: Instant) -> bool {
+        if now < self.start || now >= self.end {
+            return true;
+        }
+        return self.side_a.contains(from) == self.side_a.contains(to);
+    }
+}
+
 /// Generate a random 6 byte mac address
 fn random_mac() -> [u8; 6] {
     use rand::distributions::Standard;
@@ -90,14 +182,57 @@ impl Into<DataType> for u8 {
     }
 }
 
+/// Encode an advertisement as a datagram for the emulator sockets
+///
+/// The first two bytes of the manufacturer data are the company ID
+pub fn encode_advertisement_packet(address: [u8; 6], manufacturer_data: &[u8]) -> Vec<u8> {
+    let mut data_packet = Vec::new();
+    data_packet.extend_from_slice(&DataType::Advertisement.as_bytes()[..1]);
+
+    let (company, payload) = match manufacturer_data.split_first_chunk::<2>() {
+        Some((company, payload)) => (u16::from_le_bytes(*company), payload),
+        None => (0u16, &[] as &[u8]),
+    };
+    let mut advertisment_data_array = [0u8; 32];
+    let advertisment_data_length = std::cmp::min(32, payload.len());
+    advertisment_data_array[0..advertisment_data_length]
+        .copy_from_slice(&payload[0..advertisment_data_length]);
+    let advertisement = Advertisement {
+        company,
+        address,
+        data: advertisment_data_array,
+        data_length: advertisment_data_length as u8,
+    };
+    data_packet.extend_from_slice(advertisement.as_bytes());
+    return data_packet;
+}
+
 impl Emulator {
     pub async fn new(command: EmulateCommand) -> Result<Self, EmulatorError> {
         eprintln!("Emulating WASM file: {:?}", command.file);
         let wasm = read(&command.file).await?;
+        let mut emulator =
+            Self::from_wasm(wasm, command.name, command.group, command.visualize).await?;
+        emulator.set_tx_power(command.tx_power);
+        emulator.set_temperature(command.temperature);
+        emulator.set_timeline_length(command.timeline_length);
+        emulator.timeline_path = command.save_timeline;
+        if let Some(events_file) = &command.inject_events {
+            emulator.set_injected_events(parse_host_events(&read_to_string(events_file).await?)?);
+        }
+        return Ok(emulator);
+    }
 
+    /// Create an emulator for an already loaded WASM binary
+    pub async fn from_wasm(
+        wasm: Vec<u8>,
+        name: Option<String>,
+        group_id: u8,
+        visualize: bool,
+    ) -> Result<Self, EmulatorError> {
         let mac: [u8; 6] = random_mac();
 
-        let name = match command.name {
+        let name = match name {
             Some(name) => name,
             None => mac_to_name(&mac),
         };
@@ -126,13 +261,108 @@ impl Emulator {
         Ok(Self {
             wasm,
             name,
+            visualize,
+            group_id,
+            silent: false,
+            partition: None,
+            radio_model: Arc::new(RadioModel::default()),
+            injected_events: Vec::new(),
+            tx_power_dbm: 0,
+            temperature: DEFAULT_TEMPERATURE,
+            config: Vec::new(),
+            power_model: Arc::new(Mutex::new(PowerModel::new())),
+            stats: Arc::new(Mutex::new(EmulatorStats::default())),
+            leds: Arc::new(Mutex::new(Vec::new())),
+            timeline: Arc::new(Mutex::new(LedTimeline::default())),
+            timeline_path: None,
             address: mac,
             socket: my_socket,
             socket_dir: tempdir,
         })
     }
 
+    /// Shared handle to the LED state of the emulated device
+    pub fn leds(&self) -> Arc<Mutex<Vec<u16>>> {
+        return self.leds.clone();
+    }
+
+    /// Only keep the latest `length` LED states in the timeline. Clears the timeline
+    pub fn set_timeline_length(&mut self, length: usize) {
+        *self.timeline.lock().unwrap() = LedTimeline::new(length);
+    }
+
+    /// Save the timeline as CSV, if a file was given with `--save-timeline`
+    pub async fn save_timeline(&self) -> Result<(), EmulatorError> {
+        let Some(path) = &self.timeline_path else {
+            return Ok(());
+        };
+        let csv = self.timeline.lock().unwrap().to_csv();
+        write(path, csv).await?;
+        println!("Saved the LED timeline to {}", path.display());
+        return Ok(());
+    }
+
+    /// The name of the emulated device
+    pub fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    /// The MAC address of the emulated device
+    pub fn address(&self) -> [u8; 6] {
+        return self.address;
+    }
+
+    /// The socket on which the emulated device receives advertisements
+    pub fn socket_path(&self) -> PathBuf {
+        return self.socket_dir.join(format!("{}.socket", self.name));
+    }
+
+    /// Set the transmit power in dBm used for the energy estimation
+    pub fn set_tx_power(&mut self, tx_power_dbm: i8) {
+        self.tx_power_dbm = tx_power_dbm;
+    }
+
+    /// Set the temperature in degrees Celsius that is reported to the guest
+    pub fn set_temperature(&mut self, celsius: f32) {
+        self.temperature = (celsius * 1000.0).round() as i32;
+    }
+
+    /// Set the configuration that is returned to the guest, encoded like the configuration set over BLE
+    pub fn set_config(&mut self, config: Vec<u8>) {
+        self.config = config;
+    }
+
+    /// Estimated energy consumption since the emulation started
+    pub fn power_summary(&self) -> PowerSummary {
+        return self.power_model.lock().unwrap().summary();
+    }
+
+    /// Inject the events into the guest at their timestamps
+    pub fn set_injected_events(&mut self, mut events: Vec<TimedHostEvent>) {
+        events.sort_by_key(|event| event.timestamp_us);
+        self.injected_events = events;
+    }
+
+    /// Stop sending advertisements to other instances, so they are not disturbed by this one
+    pub fn set_silent(&mut self) {
+        self.silent = true;
+    }
+
+    /// Place the devices with the given radio model instead of deriving their distances from the addresses
+    pub fn set_radio_model(&mut self, radio_model: Arc<RadioModel>) {
+        self.radio_model = radio_model;
+    }
+
+    /// Block advertisements between the two sides of the partition while it is active
+    pub fn set_partition(&mut self, partition: PartitionConfig) {
+        self.partition = Some(partition);
+    }
+
     pub async fn broadcast(&self, data: &[u8]) -> Result<(), EmulatorError> {
+        if self.silent {
+            return Ok(());
+        }
+        let now = Instant::now();
         let mut sockets = read_dir(&self.socket_dir).await?;
         let mut other_sockets: Vec<PathBuf> = Vec::new();
         while let Some(socket) = sockets.next_entry().await? {
@@ -142,7 +372,16 @@ impl Emulator {
             if socket.path().file_stem() == Some(&OsStr::new(self.name.as_str())) {
                 continue;
             }
-            other_sockets.push(socket.path());
+            let path = socket.path();
+            let other_name = path.file_stem().and_then(|stem| stem.to_str());
+            if let Some(partition) = &self.partition {
+                let reachable =
+                    other_name.map_or(false, |stem| partition.allows(&self.name, stem, now));
+                if !reachable {
+                    continue;
+                }
+            }
+            other_sockets.push(path);
         }
         // println!("Found {} sockets", other_sockets.len());
         let futures = other_sockets
@@ -167,22 +406,76 @@ impl Emulator {
     }
 
     pub async fn emulate(&self) -> Result<(), EmulatorError> {
-        let (sender, mut receiver, host) = EmulatedHost::new(self.address, self.name.clone());
+        let (sender, mut receiver, mut host) =
+            EmulatedHost::new(self.address, self.name.clone());
+        *self.leds.lock().unwrap() = host.current_leds();
+        host.leds = self.leds.clone();
+        host.timeline = self.timeline.clone();
+        host.group_id = self.group_id;
+        host.temperature = self.temperature;
+        host.config = self.config.clone();
+        *self.power_model.lock().unwrap() = PowerModel::new();
+        host.power_model = self.power_model.clone();
+        *self.stats.lock().unwrap() = EmulatorStats::default();
+        host.stats = self.stats.clone();
+        let leds = host.leds.clone();
+        let max_lux = host.max_lux;
+        let ambient_light = host.ambient_light.clone();
+        let paused = host.paused.clone();
         let mut instance = rudelblinken_runtime::linker::setup(&self.wasm, host)?;
         let start_time = Instant::now();
-        let mut advertisment_data: Vec<u8> = Vec::new();
+        let advertisment_data: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+
+        let control_listener = TcpListener::bind("127.0.0.1:0").await?;
+        println!(
+            "Control API of {} listening on ws://{}",
+            self.name,
+            control_listener.local_addr()?
+        );
+        let control_state = ControlState {
+            events: sender.clone(),
+            leds: leds.clone(),
+            ambient_light,
+            advertisement_data: advertisment_data.clone(),
+            start_time,
+            paused,
+        };
+        tokio::spawn(control_state.serve(control_listener));
+
+        if !self.injected_events.is_empty() {
+            let events = self.injected_events.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                for event in events {
+                    sleep_until((start_time + event.timestamp()).into()).await;
+                    let received_at = start_time.elapsed().as_micros() as u64;
+                    match event.event.to_event(received_at) {
+                        Ok(event) => {
+                            if sender.send(event).await.is_err() {
+                                return;
+                            }
+                        }
+                        Err(err) => eprintln!("Failed to inject event: {}", err),
+                    }
+                }
+            });
+        }
 
         std::thread::spawn(move || {
             instance.run().unwrap();
         });
 
         let mut advertisement_interval = interval(Duration::from_millis(150));
+        let mut visualization_interval = interval(Duration::from_millis(1000 / VISUALIZATION_FPS));
+        let mut power_interval = interval(Duration::from_millis(POWER_SAMPLE_INTERVAL_MS as u64));
 
         loop {
             let mut buffer: Vec<u8> = Vec::new();
             let ble_event = self.socket.recv_buf(&mut buffer);
             let wasm_event = receiver.recv();
             let timer_event = advertisement_interval.tick();
+            let visualization_event = visualization_interval.tick();
+            let power_event = power_interval.tick();
 
             tokio::select! {
                 _ = ble_event => {
@@ -195,6 +488,13 @@ impl Emulator {
                             else {
                                 break;
                             };
+                            let Some(rssi) = self
+                                .radio_model
+                                .receive(&received_advertisement.address, &self.address)
+                            else {
+                                // The sender is out of range
+                                continue;
+                            };
                             let advertisement = rudelblinken_runtime::host::Advertisement {
                                 address: [
                                     received_advertisement.address[0],
@@ -209,7 +509,9 @@ impl Emulator {
                                 company: received_advertisement.company,
                                 data: received_advertisement.data,
                                 data_length: received_advertisement.data_length,
+                                rssi,
                                 received_at: start_time.elapsed().as_micros() as u64,
+                                service_data: Vec::new(),
                             };
 
                             sender
@@ -226,29 +528,36 @@ impl Emulator {
                             advertisement_interval = interval(Duration::from_millis(settings.max_interval as u64));
                         },
                         emulated_host::WasmEvent::SetAdvertismentData(data) => {
-                            advertisment_data = data;
+                            *advertisment_data.lock().unwrap() = data;
+                        },
+                        emulated_host::WasmEvent::GetAdvertisementSnapshot(response) => {
+                            let _ = response.send(advertisment_data.lock().unwrap().clone());
+                        },
+                        emulated_host::WasmEvent::GetStats(response) => {
+                            let stats = EmulatorStats {
+                                total_elapsed_us: start_time.elapsed().as_micros() as u64,
+                                ..*self.stats.lock().unwrap()
+                            };
+                            let _ = response.send(stats);
                         },
                     }
                 }
                 _val = timer_event => {
-                    let mut data_packet = Vec::new();
-                    data_packet.extend_from_slice(&DataType::Advertisement.as_bytes()[..1]);
-
-
-                    let mut advertisment_data_array = [0u8; 32];
-                    let advertisment_data_length = std::cmp::min(32, advertisment_data.len());
-                    advertisment_data_array[0..advertisment_data_length]
-                        .copy_from_slice(&advertisment_data[0..advertisment_data_length]);
-                    let advertisement = Advertisement {
-                        company: 0u16,
-                        address: self.address,
-                        data: advertisment_data_array,
-                        data_length: advertisment_data_length as u8,
-                    };
-                    let advertisement_data = advertisement.as_bytes();
-                    data_packet.extend_from_slice(advertisement_data);
-
+                    let advertisment_data = advertisment_data.lock().unwrap().clone();
+                    let data_packet = encode_advertisement_packet(self.address, &advertisment_data);
                     self.broadcast(&data_packet).await.unwrap();
+                    self.power_model.lock().unwrap().record_ble_tx(self.tx_power_dbm);
+                }
+                _val = visualization_event, if self.visualize => {
+                    let current_leds = leds.lock().unwrap().clone();
+                    visualize::render_leds(&self.name, &current_leds, max_lux)?;
+                }
+                _val = power_event => {
+                    let current_leds = leds.lock().unwrap().clone();
+                    let mut power_model = self.power_model.lock().unwrap();
+                    for lux in current_leds {
+                        power_model.record_led(lux, POWER_SAMPLE_INTERVAL_MS);
+                    }
                 }
             }
         }
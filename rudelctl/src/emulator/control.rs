@@ -0,0 +1,151 @@
+//! WebSocket API to control an emulated device from external tools.
+//!
+//! Every message is a JSON object with a `type` field. Each request is answered with exactly one response.
+//!
+//! Requests:
+//! - `{"type": "inject_advertisement", "data": "0000ca7ea2"}` delivers an advertisement to the guest. `data` is the hex encoded manufacturer data, starting with the company ID.
+//! - `{"type": "set_ambient_light", "value": 1234}` sets the value reported by the ambient light sensor.
+//! - `{"type": "get_led_state"}` returns the brightness of all LEDs.
+//! - `{"type": "get_advertisement_data"}` returns the advertisement data set by the guest.
+//! - `{"type": "pause"}` pauses the guest at its next yield.
+//! - `{"type": "resume"}` continues a paused guest.
+//!
+//! Responses:
+//! - `{"type": "ok"}`
+//! - `{"type": "led_state", "leds": [1000]}`
+//! - `{"type": "advertisement_data", "data": "000000000100000000000000"}`
+//! - `{"type": "error", "message": "..."}`
+use super::host_event::HostEvent;
+use futures::{SinkExt, StreamExt};
+use rudelblinken_runtime::{host::Event, pause::PauseHandle};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc::Sender,
+};
+use tokio_tungstenite::tungstenite::Message;
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ControlRequest {
+    InjectAdvertisement { data: String },
+    SetAmbientLight { value: u32 },
+    GetLedState,
+    GetAdvertisementData,
+    Pause,
+    Resume,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ControlResponse {
+    Ok,
+    LedState { leds: Vec<u16> },
+    AdvertisementData { data: String },
+    Error { message: String },
+}
+
+/// The parts of an emulated device that can be controlled
+#[derive(Clone)]
+pub struct ControlState {
+    /// Events for the guest
+    pub events: Sender<Event>,
+    pub leds: Arc<Mutex<Vec<u16>>>,
+    pub ambient_light: Arc<AtomicU32>,
+    /// The advertisement data set by the guest
+    pub advertisement_data: Arc<Mutex<Vec<u8>>>,
+    /// Time of the guest start, used for the timestamps of injected advertisements
+    pub start_time: Instant,
+    pub paused: PauseHandle,
+}
+
+impl ControlState {
+    async fn handle(&self, request: ControlRequest) -> ControlResponse {
+        match request {
+            ControlRequest::InjectAdvertisement { data } => {
+                let event = HostEvent::Advertisement {
+                    address: None,
+                    data,
+                    rssi: None,
+                };
+                let event = match event.to_event(self.start_time.elapsed().as_micros() as u64) {
+                    Ok(event) => event,
+                    Err(err) => {
+                        return ControlResponse::Error {
+                            message: err.to_string(),
+                        }
+                    }
+                };
+                if self.events.send(event).await.is_err() {
+                    return ControlResponse::Error {
+                        message: "the guest is not running".to_string(),
+                    };
+                }
+                return ControlResponse::Ok;
+            }
+            ControlRequest::SetAmbientLight { value } => {
+                self.ambient_light.store(value, Ordering::Relaxed);
+                return ControlResponse::Ok;
+            }
+            ControlRequest::GetLedState => {
+                return ControlResponse::LedState {
+                    leds: self.leds.lock().unwrap().clone(),
+                };
+            }
+            ControlRequest::GetAdvertisementData => {
+                return ControlResponse::AdvertisementData {
+                    data: hex::encode(&*self.advertisement_data.lock().unwrap()),
+                };
+            }
+            ControlRequest::Pause => {
+                self.paused.pause();
+                return ControlResponse::Ok;
+            }
+            ControlRequest::Resume => {
+                self.paused.resume();
+                return ControlResponse::Ok;
+            }
+        }
+    }
+
+    async fn handle_connection(
+        self,
+        stream: TcpStream,
+    ) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+        let mut websocket = tokio_tungstenite::accept_async(stream).await?;
+        while let Some(message) = websocket.next().await {
+            let response = match message? {
+                Message::Text(text) => match serde_json::from_str::<ControlRequest>(&text) {
+                    Ok(request) => self.handle(request).await,
+                    Err(err) => ControlResponse::Error {
+                        message: err.to_string(),
+                    },
+                },
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            let response = serde_json::to_string(&response).expect("responses are serializable");
+            websocket.send(Message::Text(response)).await?;
+        }
+        return Ok(());
+    }
+
+    /// Accept WebSocket connections until the listener fails
+    pub async fn serve(self, listener: TcpListener) {
+        while let Ok((stream, _)) = listener.accept().await {
+            let state = self.clone();
+            tokio::spawn(async move {
+                if let Err(err) = state.handle_connection(stream).await {
+                    eprintln!("Control connection failed: {}", err);
+                }
+            });
+        }
+    }
+}
@@ -1,24 +1,103 @@
+use super::{led_timeline::LedTimeline, power_model::PowerModel};
+use rudelblinken_protocol::encode_manufacturer_data;
 use rudelblinken_runtime::{
     host::{
-        AdvertisementSettings, AmbientLightType, Event, Host, LedColor, LedInfo, LogLevel,
-        VibrationSensorType,
+        Advertisement, AdvertisementSettings, AmbientLightType, Event, Host, HostError, LedColor,
+        LedInfo, LogLevel, VibrationSensorType,
     },
     linker::linker::WrappedCaller,
+    pause::PauseHandle,
+    timer::Timers,
+};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    mpsc::{channel, Receiver, Sender},
+    oneshot,
 };
-use std::time::{Duration, Instant};
-use tokio::sync::mpsc::{channel, Receiver, Sender};
 
 pub enum WasmEvent {
     SetAdvertismentSettings(AdvertisementSettings),
     SetAdvertismentData(Vec<u8>),
+    /// Request the advertisement data that is currently broadcast
+    GetAdvertisementSnapshot(oneshot::Sender<Vec<u8>>),
+    /// Request the counters of the host calls
+    GetStats(oneshot::Sender<EmulatorStats>),
 }
 
+/// Counts how often the guest called into the host
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EmulatorStats {
+    /// Number of calls to `set_leds` and `set_rgb`
+    pub set_leds_calls: u64,
+    /// Number of advertisements that were passed to the guest
+    pub ble_events_received: u64,
+    pub yield_now_calls: u64,
+    /// Time since the emulation started in microseconds
+    pub total_elapsed_us: u64,
+}
+
+/// Number of LEDs on the emulated device
+const DEFAULT_LED_COUNT: u16 = 1;
+/// Maximum brightness of each emulated LED
+const DEFAULT_MAX_LUX: u16 = 1000;
+/// Room temperature in millidegrees Celsius
+pub const DEFAULT_TEMPERATURE: i32 = 25_000;
+/// Number of received advertisements that are kept for `get-scan-results`. Older ones are dropped first
+const SCAN_RESULTS_CAPACITY: usize = 32;
+
 pub struct EmulatedHost {
     pub start_time: Instant,
     pub host_events: Receiver<Event>,
     pub wasm_events: Sender<WasmEvent>,
     pub address: [u8; 6],
     pub name: String,
+    /// The current brightness of each LED
+    ///
+    /// Shared, so the state can still be inspected after the host was moved into the runtime
+    pub leds: Arc<Mutex<Vec<u16>>>,
+    /// Snapshots of the LEDs, recorded every time they are set
+    ///
+    /// Shared like the LEDs, so the timeline can be saved after the emulation ended
+    pub timeline: Arc<Mutex<LedTimeline>>,
+    /// The maximum brightness of each LED
+    pub max_lux: u16,
+    /// The value reported by the ambient light sensor
+    ///
+    /// Shared, so it can be changed while the guest is running
+    pub ambient_light: Arc<AtomicU32>,
+    /// The value reported by the vibration sensor
+    pub vibration: u32,
+    /// The temperature reported to the guest in millidegrees Celsius
+    pub temperature: i32,
+    /// The guest configuration
+    pub config: Vec<u8>,
+    /// The configured group ID. Guests read it with `get-group-id`, unless they set their own
+    pub group_id: u8,
+    /// Estimates the energy consumption. The host records the time the guest spends idle
+    pub power_model: Arc<Mutex<PowerModel>>,
+    /// Pauses the guest at its next yield
+    pub paused: PauseHandle,
+    /// Timers set by the guest
+    pub timers: Timers,
+    /// Counters of the host calls
+    ///
+    /// Shared, so the emulator can answer [WasmEvent::GetStats] while the guest is running
+    pub stats: Arc<Mutex<EmulatorStats>>,
+    /// Received advertisements that were not fetched with `get-scan-results` yet
+    pub scan_results: VecDeque<Advertisement>,
+    /// The data that was last set by the guest, without the rudelblinken header
+    advertisement_data: Vec<u8>,
+    /// Sequence number of the rudelblinken advertisement. Incremented whenever the data changes
+    advertisement_sequence: u8,
+    /// The group ID set by the guest with `set-group-id`. The configured group ID is sent if this is `None`
+    advertised_group_id: Option<u16>,
 }
 
 impl EmulatedHost {
@@ -34,120 +113,256 @@ impl EmulatedHost {
                 wasm_events: wasm_sender,
                 address,
                 name,
+                leds: Arc::new(Mutex::new(vec![0; DEFAULT_LED_COUNT as usize])),
+                timeline: Arc::new(Mutex::new(LedTimeline::default())),
+                max_lux: DEFAULT_MAX_LUX,
+                ambient_light: Arc::new(AtomicU32::new(0)),
+                vibration: 0,
+                temperature: DEFAULT_TEMPERATURE,
+                config: Vec::new(),
+                group_id: 0,
+                power_model: Arc::new(Mutex::new(PowerModel::new())),
+                paused: PauseHandle::new(),
+                timers: Timers::new(),
+                stats: Arc::new(Mutex::new(EmulatorStats::default())),
+                scan_results: VecDeque::with_capacity(SCAN_RESULTS_CAPACITY),
+                advertisement_data: Vec::new(),
+                advertisement_sequence: 0,
+                advertised_group_id: None,
             },
         );
     }
+
+    /// Get a snapshot of the current LED brightness values
+    pub fn current_leds(&self) -> Vec<u16> {
+        return self.leds.lock().unwrap().clone();
+    }
+
+    /// Get the recorded LED snapshots as time since the start in microseconds and the brightness of each LED
+    pub fn led_timeline(&self) -> Vec<(u64, Vec<u16>)> {
+        return self.timeline.lock().unwrap().entries();
+    }
+
+    /// Record the current LED state in the timeline
+    fn record_leds(&self, leds: &[u16]) {
+        let timestamp_us = self.start_time.elapsed().as_micros() as u64;
+        self.timeline.lock().unwrap().record(timestamp_us, leds);
+    }
+
+    /// The group ID set by the guest, or the configured one
+    fn sent_group_id(&self) -> u16 {
+        return self.advertised_group_id.unwrap_or(self.group_id as u16);
+    }
+
+    /// Wrap the data set by the guest in a rudelblinken advertisement with the current group ID. Returns `None` if the data does not fit
+    fn encode_advertisement_data(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        let sequence = if self.advertisement_data == data {
+            self.advertisement_sequence
+        } else {
+            self.advertisement_sequence.wrapping_add(1)
+        };
+        let manufacturer_data = encode_manufacturer_data(self.sent_group_id(), sequence, data)?;
+        self.advertisement_data = data.to_vec();
+        self.advertisement_sequence = sequence;
+        return Some(manufacturer_data);
+    }
+
+    /// Get the advertisement data that is currently broadcast by the emulator
+    ///
+    /// Blocks until the emulator answered, so this must not be called from an async context. Returns an empty vector if the emulator is not running.
+    pub fn get_current_advertisement(&self) -> Vec<u8> {
+        let (sender, receiver) = oneshot::channel();
+        if self
+            .wasm_events
+            .blocking_send(WasmEvent::GetAdvertisementSnapshot(sender))
+            .is_err()
+        {
+            return Vec::new();
+        }
+        return receiver.blocking_recv().unwrap_or_default();
+    }
+
+    /// Get the counters of the host calls from the emulator
+    ///
+    /// Returns the default stats if the emulator is not running.
+    pub async fn get_stats(&self) -> EmulatorStats {
+        let (sender, receiver) = oneshot::channel();
+        if self
+            .wasm_events
+            .send(WasmEvent::GetStats(sender))
+            .await
+            .is_err()
+        {
+            return EmulatorStats::default();
+        }
+        return receiver.await.unwrap_or_default();
+    }
 }
 
 impl Host for EmulatedHost {
-    fn yield_now(
-        caller: &mut WrappedCaller<'_, Self>,
-        micros: u64,
-    ) -> Result<u32, rudelblinken_runtime::Error> {
-        while let Ok(event) = caller.data_mut().host_events.try_recv() {
+    fn yield_now(caller: &mut WrappedCaller<'_, Self>, micros: u64) -> Result<u32, HostError> {
+        caller.data().paused.wait_while_paused();
+        caller.data().stats.lock().unwrap().yield_now_calls += 1;
+        caller
+            .data()
+            .power_model
+            .lock()
+            .unwrap()
+            .record_idle(micros);
+        let now = caller.data().start_time.elapsed().as_micros() as u64;
+        loop {
+            let event = match caller.data_mut().timers.pop_expired(now) {
+                Some(event) => event,
+                None => match caller.data_mut().host_events.try_recv() {
+                    Ok(event) => event,
+                    Err(_) => break,
+                },
+            };
             match event {
                 Event::AdvertisementReceived(advertisement) => {
+                    caller.data().stats.lock().unwrap().ble_events_received += 1;
+                    let scan_results = &mut caller.data_mut().scan_results;
+                    if scan_results.len() >= SCAN_RESULTS_CAPACITY {
+                        scan_results.pop_front();
+                    }
+                    scan_results.push_back(advertisement.clone());
                     caller.on_advertisement(advertisement)?;
                 }
+                Event::TimerFired { id, fired_at } => {
+                    caller.on_timer(id, fired_at)?;
+                }
             }
         }
         caller.inner().set_fuel(999_999).unwrap();
         return Ok(999_999);
     }
 
-    fn sleep(
-        _caller: &mut WrappedCaller<'_, Self>,
-        micros: u64,
-    ) -> Result<(), rudelblinken_runtime::Error> {
+    fn sleep(caller: &mut WrappedCaller<'_, Self>, micros: u64) -> Result<(), HostError> {
+        caller
+            .data()
+            .power_model
+            .lock()
+            .unwrap()
+            .record_idle(micros);
         std::thread::sleep(Duration::from_micros(micros));
         return Ok(());
     }
 
-    fn time(caller: &mut WrappedCaller<'_, Self>) -> Result<u64, rudelblinken_runtime::Error> {
+    fn time(caller: &mut WrappedCaller<'_, Self>) -> Result<u64, HostError> {
         return Ok(caller.data().start_time.elapsed().as_micros() as u64);
     }
 
+    fn set_timer(
+        caller: &mut WrappedCaller<'_, Self>,
+        id: u8,
+        delay_us: u64,
+    ) -> Result<(), HostError> {
+        let now = caller.data().start_time.elapsed().as_micros() as u64;
+        caller
+            .data_mut()
+            .timers
+            .set(id, now.saturating_add(delay_us));
+        return Ok(());
+    }
+
+    fn cancel_timer(caller: &mut WrappedCaller<'_, Self>, id: u8) -> Result<(), HostError> {
+        caller.data_mut().timers.cancel(id);
+        return Ok(());
+    }
+
     fn log(
         _caller: &mut WrappedCaller<'_, Self>,
         level: LogLevel,
         message: &str,
-    ) -> Result<(), rudelblinken_runtime::Error> {
+    ) -> Result<(), HostError> {
         println!("{}: {}", level, message);
         return Ok(());
     }
 
-    fn get_name(
-        _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<String, rudelblinken_runtime::Error> {
-        return Ok("EmulatedHost".to_string());
+    fn get_name(caller: &mut WrappedCaller<'_, Self>) -> Result<String, HostError> {
+        return Ok(caller.data().name.clone());
     }
 
-    fn get_config(
-        _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<Vec<u8>, rudelblinken_runtime::Error> {
-        return Ok(vec![]);
+    fn get_config(caller: &mut WrappedCaller<'_, Self>) -> Result<Vec<u8>, HostError> {
+        return Ok(caller.data().config.clone());
     }
 
     fn set_leds(
-        _caller: &mut WrappedCaller<'_, Self>,
-        _first_id: u16,
-        _lux: &[u16],
-    ) -> Result<u32, rudelblinken_runtime::Error> {
-        Ok(0)
+        caller: &mut WrappedCaller<'_, Self>,
+        first_id: u16,
+        lux: &[u16],
+    ) -> Result<u32, HostError> {
+        caller.data().stats.lock().unwrap().set_leds_calls += 1;
+        let mut leds = caller.data().leds.lock().unwrap();
+        let first_id = first_id as usize;
+        if first_id >= leds.len() {
+            return Ok(0);
+        }
+        let count = std::cmp::min(lux.len(), leds.len() - first_id);
+        leds[first_id..(first_id + count)].copy_from_slice(&lux[..count]);
+        caller.data().record_leds(&leds);
+        return Ok(count as u32);
     }
 
     fn set_rgb(
-        _caller: &mut WrappedCaller<'_, Self>,
+        caller: &mut WrappedCaller<'_, Self>,
         _color: &LedColor,
-        _lux: u32,
-    ) -> Result<u32, rudelblinken_runtime::Error> {
-        Ok(0)
+        lux: u32,
+    ) -> Result<u32, HostError> {
+        caller.data().stats.lock().unwrap().set_leds_calls += 1;
+        let mut leds = caller.data().leds.lock().unwrap();
+        leds.fill(std::cmp::min(lux, u16::MAX as u32) as u16);
+        caller.data().record_leds(&leds);
+        return Ok(leds.len() as u32);
     }
 
-    fn led_count(
-        _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<u16, rudelblinken_runtime::Error> {
-        return Ok(500);
+    fn led_count(caller: &mut WrappedCaller<'_, Self>) -> Result<u16, HostError> {
+        return Ok(caller.data().leds.lock().unwrap().len() as u16);
     }
 
-    fn get_led_info(
-        _caller: &mut WrappedCaller<'_, Self>,
-        _id: u16,
-    ) -> Result<LedInfo, rudelblinken_runtime::Error> {
+    fn get_led_info(caller: &mut WrappedCaller<'_, Self>, _id: u16) -> Result<LedInfo, HostError> {
         return Ok(LedInfo {
-            color: LedColor::new(0, 0, 0),
-            max_lux: 0,
+            color: LedColor::new(255, 255, 255),
+            max_lux: caller.data().max_lux,
+            color_temperature_k: 0,
+            wavelength_nm: 0,
+            white_balance: LedInfo::IDENTITY_WHITE_BALANCE,
         });
     }
 
     fn get_ambient_light_type(
         _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<AmbientLightType, rudelblinken_runtime::Error> {
-        Ok(AmbientLightType::None)
+    ) -> Result<AmbientLightType, HostError> {
+        Ok(AmbientLightType::Basic)
     }
 
-    fn get_ambient_light(
-        _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<u32, rudelblinken_runtime::Error> {
-        return Ok(0);
+    fn get_ambient_light(caller: &mut WrappedCaller<'_, Self>) -> Result<u32, HostError> {
+        return Ok(caller.data().ambient_light.load(Ordering::Relaxed));
+    }
+
+    /// The emulated sensor is already calibrated, so this returns the lux value
+    fn get_ambient_light_raw(caller: &mut WrappedCaller<'_, Self>) -> Result<u32, HostError> {
+        return Ok(caller.data().ambient_light.load(Ordering::Relaxed));
     }
 
     fn get_vibration_sensor_type(
         _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<VibrationSensorType, rudelblinken_runtime::Error> {
-        Ok(VibrationSensorType::None)
+    ) -> Result<VibrationSensorType, HostError> {
+        Ok(VibrationSensorType::Ball)
     }
 
-    fn get_vibration(
-        _caller: &mut WrappedCaller<'_, Self>,
-    ) -> Result<u32, rudelblinken_runtime::Error> {
-        return Ok(0);
+    fn get_vibration(caller: &mut WrappedCaller<'_, Self>) -> Result<u32, HostError> {
+        return Ok(caller.data().vibration);
+    }
+
+    fn get_temperature(caller: &mut WrappedCaller<'_, Self>) -> Result<i32, HostError> {
+        return Ok(caller.data().temperature);
     }
 
     fn configure_advertisement(
         caller: &mut WrappedCaller<'_, Self>,
         settings: AdvertisementSettings,
-    ) -> Result<u32, rudelblinken_runtime::Error> {
+    ) -> Result<u32, HostError> {
         caller
             .data_mut()
             .wasm_events
@@ -158,11 +373,77 @@ impl Host for EmulatedHost {
     fn set_advertisement_data(
         caller: &mut WrappedCaller<'_, Self>,
         data: &[u8],
-    ) -> Result<u32, rudelblinken_runtime::Error> {
-        caller
-            .data_mut()
-            .wasm_events
-            .blocking_send(WasmEvent::SetAdvertismentData(data.into()));
+    ) -> Result<u32, HostError> {
+        let host = caller.data_mut();
+        let Some(manufacturer_data) = host.encode_advertisement_data(data) else {
+            return Ok(1);
+        };
+        host.wasm_events
+            .blocking_send(WasmEvent::SetAdvertismentData(manufacturer_data));
         Ok(0)
     }
+
+    /// The emulator does not accept connections
+    fn get_connected_devices(_caller: &mut WrappedCaller<'_, Self>) -> Result<Vec<u64>, HostError> {
+        return Ok(Vec::new());
+    }
+
+    /// The emulator delivers every advertisement, so the scan settings have no effect
+    fn configure_scan(
+        _caller: &mut WrappedCaller<'_, Self>,
+        _window: u16,
+        _interval: u16,
+        _active: bool,
+    ) -> Result<u32, HostError> {
+        return Ok(0);
+    }
+
+    fn get_scan_results(
+        caller: &mut WrappedCaller<'_, Self>,
+    ) -> Result<Vec<Advertisement>, HostError> {
+        return Ok(caller.data_mut().scan_results.drain(..).collect());
+    }
+
+    fn set_group_id(caller: &mut WrappedCaller<'_, Self>, id: u16) -> Result<(), HostError> {
+        caller.data_mut().advertised_group_id = Some(id);
+        return Ok(());
+    }
+
+    fn get_group_id(caller: &mut WrappedCaller<'_, Self>) -> Result<u16, HostError> {
+        return Ok(caller.data().sent_group_id());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rudelblinken_protocol::RudelblinkenAdvertisement;
+
+    /// Parse the rudelblinken header after the company ID
+    fn parse(manufacturer_data: &[u8]) -> RudelblinkenAdvertisement {
+        return RudelblinkenAdvertisement::parse(&manufacturer_data[2..]).unwrap();
+    }
+
+    #[test]
+    fn advertisements_carry_the_configured_group_id() {
+        let (_, _, mut host) = EmulatedHost::new([0; 6], "test".to_string());
+        host.group_id = 7;
+        let manufacturer_data = host.encode_advertisement_data(&[0x34, 0x12, 0xaa]).unwrap();
+        assert_eq!(&manufacturer_data[..2], &[0x34, 0x12]);
+        assert_eq!(parse(&manufacturer_data).group_id(), 7);
+        assert_eq!(parse(&manufacturer_data).user_data(), &[0xaa]);
+    }
+
+    #[test]
+    fn the_group_id_set_by_the_guest_replaces_the_configured_one() {
+        let (_, _, mut host) = EmulatedHost::new([0; 6], "test".to_string());
+        host.group_id = 7;
+        let before = host.encode_advertisement_data(&[0, 0, 0xaa]).unwrap();
+        host.advertised_group_id = Some(0x0102);
+        let after = host.encode_advertisement_data(&[0, 0, 0xaa]).unwrap();
+        assert_eq!(parse(&before).group_id(), 7);
+        assert_eq!(parse(&after).group_id(), 0x0102);
+        // Only the header changed, so receivers do not see new data
+        assert_eq!(parse(&after).sequence, parse(&before).sequence);
+    }
 }
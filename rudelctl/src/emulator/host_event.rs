@@ -0,0 +1,122 @@
+//! JSON representation of events that can be injected into an emulated device.
+//!
+//! Events are stored as newline-delimited JSON, one [TimedHostEvent] per line:
+//!
+//! ```json
+//! {"timestamp_us": 500000, "type": "advertisement", "address": "24ec4b000001", "data": "0000ca7ea2010000010a00000100000000000000"}
+//! ```
+//!
+//! `timestamp_us` is relative to the start of the guest. For advertisements, `data` is the hex encoded manufacturer data, starting with the company ID. `address` and the signal strength `rssi` in dBm are optional.
+use rudelblinken_runtime::host::{Advertisement, Event};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum HostEventError {
+    #[error("Line {line} is not a valid host event")]
+    InvalidJson {
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("The address is not a valid hex encoded MAC address")]
+    InvalidAddress,
+    #[error("The data is not valid hex")]
+    InvalidData,
+    #[error("The data needs to contain a company ID and at most 32 additional bytes")]
+    InvalidDataLength,
+}
+
+/// An event for the guest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostEvent {
+    /// A received BLE advertisement
+    Advertisement {
+        /// Hex encoded MAC address of the sender
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        address: Option<String>,
+        /// Hex encoded manufacturer data, starting with the company ID
+        data: String,
+        /// Signal strength in dBm. Unknown if not set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rssi: Option<i8>,
+    },
+}
+
+/// A host event that is injected at a fixed time
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TimedHostEvent {
+    /// Time after the start of the guest in microseconds
+    pub timestamp_us: u64,
+    #[serde(flatten)]
+    pub event: HostEvent,
+}
+
+impl TimedHostEvent {
+    /// Time after the start of the guest at which the event is injected
+    pub fn timestamp(&self) -> Duration {
+        return Duration::from_micros(self.timestamp_us);
+    }
+}
+
+impl HostEvent {
+    /// Convert into an event for the runtime
+    pub fn to_event(&self, received_at: u64) -> Result<Event, HostEventError> {
+        match self {
+            HostEvent::Advertisement {
+                address,
+                data,
+                rssi,
+            } => {
+                let mut padded_address = [0u8; 8];
+                if let Some(address) = address {
+                    let address: [u8; 6] = hex::decode(address)
+                        .ok()
+                        .and_then(|address| address.try_into().ok())
+                        .ok_or(HostEventError::InvalidAddress)?;
+                    padded_address[0..6].copy_from_slice(&address);
+                }
+                let data = hex::decode(data).map_err(|_| HostEventError::InvalidData)?;
+                let Some((company, payload)) = data.split_first_chunk::<2>() else {
+                    return Err(HostEventError::InvalidDataLength);
+                };
+                if payload.len() > 32 {
+                    return Err(HostEventError::InvalidDataLength);
+                }
+                let mut advertisement_data = [0u8; 32];
+                advertisement_data[..payload.len()].copy_from_slice(payload);
+                return Ok(Event::AdvertisementReceived(Advertisement {
+                    address: padded_address,
+                    company: u16::from_le_bytes(*company),
+                    data: advertisement_data,
+                    data_length: payload.len() as u8,
+                    rssi: rssi.unwrap_or(0),
+                    received_at,
+                    service_data: Vec::new(),
+                }));
+            }
+        }
+    }
+}
+
+/// Parse newline-delimited JSON host events and sort them by their timestamp
+pub fn parse_host_events(content: &str) -> Result<Vec<TimedHostEvent>, HostEventError> {
+    let mut events = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event: TimedHostEvent =
+            serde_json::from_str(line).map_err(|source| HostEventError::InvalidJson {
+                line: index + 1,
+                source,
+            })?;
+        // Catch invalid data before the guest is started
+        event.event.to_event(0)?;
+        events.push(event);
+    }
+    events.sort_by_key(|event| event.timestamp_us);
+    return Ok(events);
+}
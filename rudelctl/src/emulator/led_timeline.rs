@@ -0,0 +1,69 @@
+//! Record how the LEDs of an emulated device change over time.
+//!
+//! A snapshot of all LEDs is taken every time the guest sets them. Only the latest snapshots are kept, so long emulations do not use unbounded memory.
+use std::collections::VecDeque;
+
+/// Number of snapshots that are kept by default
+pub const DEFAULT_TIMELINE_LENGTH: usize = 10_000;
+
+/// The latest LED states of an emulated device
+#[derive(Debug, Clone)]
+pub struct LedTimeline {
+    /// Time since the emulation started in microseconds and the brightness of each LED
+    entries: VecDeque<(u64, Vec<u16>)>,
+    max_entries: usize,
+}
+
+impl LedTimeline {
+    pub fn new(max_entries: usize) -> Self {
+        return LedTimeline {
+            entries: VecDeque::new(),
+            max_entries,
+        };
+    }
+
+    /// Record the LED state at the given time. Drops the oldest snapshot if the timeline is full
+    pub fn record(&mut self, timestamp_us: u64, leds: &[u16]) {
+        if self.max_entries == 0 {
+            return;
+        }
+        while self.entries.len() >= self.max_entries {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((timestamp_us, leds.to_vec()));
+    }
+
+    /// All recorded snapshots, oldest first
+    pub fn entries(&self) -> Vec<(u64, Vec<u16>)> {
+        return self.entries.iter().cloned().collect();
+    }
+
+    /// Format the timeline as CSV with one row per snapshot and one column per LED
+    pub fn to_csv(&self) -> String {
+        let led_count = self
+            .entries
+            .iter()
+            .map(|(_, leds)| leds.len())
+            .max()
+            .unwrap_or(0);
+        let mut csv = String::from("timestamp_us");
+        for index in 0..led_count {
+            csv.push_str(&format!(",led{}", index));
+        }
+        csv.push('\n');
+        for (timestamp_us, leds) in &self.entries {
+            csv.push_str(&timestamp_us.to_string());
+            for lux in leds {
+                csv.push_str(&format!(",{}", lux));
+            }
+            csv.push('\n');
+        }
+        return csv;
+    }
+}
+
+impl Default for LedTimeline {
+    fn default() -> Self {
+        return LedTimeline::new(DEFAULT_TIMELINE_LENGTH);
+    }
+}
@@ -0,0 +1,209 @@
+//! Estimate the energy consumption of an emulated device.
+//!
+//! The values are rough estimates for an ESP32-C3 with a single LED, they are only meant to compare programs and to get an idea of the battery life.
+use std::{fmt::Display, time::Instant};
+
+/// Supply voltage of the device in volts
+const SUPPLY_VOLTAGE: f64 = 3.3;
+/// Power used by an LED per lux of brightness in milliwatts
+const LED_MW_PER_LUX: f64 = 0.066;
+/// Power used by the radio while transmitting, without the output power, in milliwatts
+const BLE_TX_BASE_MW: f64 = 60.0;
+/// Efficiency of the power amplifier
+const BLE_TX_AMPLIFIER_EFFICIENCY: f64 = 0.2;
+/// Time the radio is transmitting for one advertisement on all three advertising channels in milliseconds
+const BLE_ADVERTISEMENT_AIRTIME_MS: f64 = 1.5;
+/// Current while scanning for advertisements in milliamperes
+const BLE_RX_MA: f64 = 84.0;
+/// Current while the CPU is running in milliamperes
+const CPU_ACTIVE_MA: f64 = 28.0;
+/// Current while the CPU is in light sleep in milliamperes
+const CPU_SLEEP_MA: f64 = 0.13;
+/// Capacity of the battery used for the estimation in milliampere hours
+const BATTERY_CAPACITY_MAH: f64 = 150.0;
+/// Nominal voltage of the battery used for the estimation in volts
+const BATTERY_VOLTAGE: f64 = 3.7;
+
+/// Something that consumes energy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerConsumer {
+    Led,
+    BleTx,
+    BleRx,
+    CpuActive,
+    CpuSleep,
+}
+
+impl PowerConsumer {
+    const ALL: [PowerConsumer; 5] = [
+        PowerConsumer::Led,
+        PowerConsumer::BleTx,
+        PowerConsumer::BleRx,
+        PowerConsumer::CpuActive,
+        PowerConsumer::CpuSleep,
+    ];
+}
+
+impl Display for PowerConsumer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PowerConsumer::Led => write!(f, "LEDs"),
+            PowerConsumer::BleTx => write!(f, "BLE advertising"),
+            PowerConsumer::BleRx => write!(f, "BLE scanning"),
+            PowerConsumer::CpuActive => write!(f, "CPU active"),
+            PowerConsumer::CpuSleep => write!(f, "CPU sleep"),
+        }
+    }
+}
+
+/// Accumulates the energy consumed by an emulated device
+#[derive(Debug, Clone)]
+pub struct PowerModel {
+    microjoules_consumed: u64,
+    /// Energy in microjoules for each consumer, in the order of [PowerConsumer::ALL]
+    consumers: [u64; 5],
+    start_time: Instant,
+    /// Time in microseconds the guest spent yielding or sleeping
+    idle_micros: u64,
+}
+
+impl PowerModel {
+    pub fn new() -> Self {
+        return PowerModel {
+            microjoules_consumed: 0,
+            consumers: [0; 5],
+            start_time: Instant::now(),
+            idle_micros: 0,
+        };
+    }
+
+    fn record(&mut self, consumer: PowerConsumer, milliwatts: f64, duration_ms: f64) {
+        // mW * ms = µJ
+        let microjoules = (milliwatts * duration_ms).round() as u64;
+        self.microjoules_consumed += microjoules;
+        self.consumers[consumer as usize] += microjoules;
+    }
+
+    /// Record an LED shining with the given brightness
+    pub fn record_led(&mut self, lux: u16, duration_ms: u32) {
+        self.record(
+            PowerConsumer::Led,
+            lux as f64 * LED_MW_PER_LUX,
+            duration_ms as f64,
+        );
+    }
+
+    /// Record sending an advertisement with the given output power
+    pub fn record_ble_tx(&mut self, power_dbm: i8) {
+        let output_mw = 10f64.powf(power_dbm as f64 / 10.0);
+        self.record(
+            PowerConsumer::BleTx,
+            BLE_TX_BASE_MW + output_mw / BLE_TX_AMPLIFIER_EFFICIENCY,
+            BLE_ADVERTISEMENT_AIRTIME_MS,
+        );
+    }
+
+    /// Record scanning for advertisements
+    pub fn record_ble_rx(&mut self, duration_ms: u32) {
+        self.record(
+            PowerConsumer::BleRx,
+            BLE_RX_MA * SUPPLY_VOLTAGE,
+            duration_ms as f64,
+        );
+    }
+
+    /// Record the CPU running
+    pub fn record_cpu_active(&mut self, duration_ms: u32) {
+        self.record(
+            PowerConsumer::CpuActive,
+            CPU_ACTIVE_MA * SUPPLY_VOLTAGE,
+            duration_ms as f64,
+        );
+    }
+
+    /// Record the CPU sleeping
+    pub fn record_cpu_sleep(&mut self, duration_ms: u32) {
+        self.record(
+            PowerConsumer::CpuSleep,
+            CPU_SLEEP_MA * SUPPLY_VOLTAGE,
+            duration_ms as f64,
+        );
+    }
+
+    /// Record the guest yielding or sleeping. The CPU is considered active for the rest of the time
+    pub fn record_idle(&mut self, micros: u64) {
+        self.idle_micros += micros;
+    }
+
+    pub fn microjoules_consumed(&self) -> u64 {
+        return self.microjoules_consumed;
+    }
+
+    /// Summarize the consumption since the model was created
+    ///
+    /// The device is always scanning, so scanning and the CPU time are accounted for here.
+    pub fn summary(&self) -> PowerSummary {
+        let elapsed_ms = self.start_time.elapsed().as_millis() as u32;
+        let idle_ms = std::cmp::min((self.idle_micros / 1000) as u32, elapsed_ms);
+        let mut model = self.clone();
+        model.record_ble_rx(elapsed_ms);
+        model.record_cpu_sleep(idle_ms);
+        model.record_cpu_active(elapsed_ms - idle_ms);
+
+        let dominant_consumer = PowerConsumer::ALL
+            .into_iter()
+            .max_by_key(|consumer| model.consumers[*consumer as usize])
+            .unwrap();
+        return PowerSummary {
+            microjoules_consumed: model.microjoules_consumed,
+            duration_ms: elapsed_ms,
+            dominant_consumer,
+        };
+    }
+}
+
+impl Default for PowerModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The energy consumption over a run
+#[derive(Debug, Clone)]
+pub struct PowerSummary {
+    pub microjoules_consumed: u64,
+    pub duration_ms: u32,
+    pub dominant_consumer: PowerConsumer,
+}
+
+impl PowerSummary {
+    /// Estimated runtime on a 150 mAh battery in hours
+    pub fn battery_hours(&self) -> Option<f64> {
+        if self.microjoules_consumed == 0 || self.duration_ms == 0 {
+            return None;
+        }
+        let average_mw = self.microjoules_consumed as f64 / self.duration_ms as f64;
+        let battery_mwh = BATTERY_CAPACITY_MAH * BATTERY_VOLTAGE;
+        return Some(battery_mwh / average_mw);
+    }
+}
+
+impl Display for PowerSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Energy consumed: {:.3} J in {:.1} s",
+            self.microjoules_consumed as f64 / 1_000_000.0,
+            self.duration_ms as f64 / 1000.0
+        )?;
+        match self.battery_hours() {
+            Some(hours) => writeln!(
+                f,
+                "Estimated battery life: {:.1} h on a {} mAh battery",
+                hours, BATTERY_CAPACITY_MAH
+            )?,
+            None => writeln!(f, "Estimated battery life: unknown")?,
+        }
+        write!(f, "Dominant consumer: {}", self.dominant_consumer)
+    }
+}
@@ -0,0 +1,122 @@
+//! Estimate the signal strength of advertisements between emulated devices.
+//!
+//! By default emulated devices have no positions, so every pair of devices gets a fixed distance derived from their addresses. Nearby devices stay nearby for the whole emulation, which is enough to try out proximity based grouping.
+//!
+//! A [RadioModel] places devices at fixed positions instead. With a range, devices that are too far apart do not receive each other's advertisements, so multi-hop topologies can be emulated.
+use rand::Rng;
+use std::collections::HashMap;
+
+/// Signal strength of an advertisement sent with 0 dBm at a distance of one meter in dBm
+const RSSI_AT_ONE_METER_DBM: f64 = -59.0;
+/// Path loss exponent of free space
+const PATH_LOSS_EXPONENT: f64 = 2.0;
+/// Distance between the closest emulated devices in meters
+const MIN_DISTANCE_M: f64 = 0.5;
+/// Distance between the most distant emulated devices in meters
+const MAX_DISTANCE_M: f64 = 10.0;
+/// Maximum deviation of the signal strength of a single advertisement in dB
+const NOISE_DB: f64 = 4.0;
+
+/// Distance between two emulated devices in meters. The same in both directions
+pub fn distance_m(a: &[u8; 6], b: &[u8; 6]) -> f64 {
+    let seed = a
+        .iter()
+        .zip(b)
+        .fold(0u32, |seed, (a, b)| seed.wrapping_mul(31).wrapping_add((a ^ b) as u32));
+    let fraction = (seed % 1000) as f64 / 999.0;
+    return MIN_DISTANCE_M + fraction * (MAX_DISTANCE_M - MIN_DISTANCE_M);
+}
+
+/// Signal strength in dBm of an advertisement received at the given distance in meters
+///
+/// The signal gets weaker with the distance between the devices, and every advertisement gets some random noise. The result is always negative, as 0 means that the RSSI is unknown.
+fn rssi_at_distance(distance_m: f64) -> i8 {
+    let path_loss_db = 10.0 * PATH_LOSS_EXPONENT * distance_m.max(MIN_DISTANCE_M).log10();
+    let noise_db = rand::thread_rng().gen_range(-NOISE_DB..=NOISE_DB);
+    let rssi = RSSI_AT_ONE_METER_DBM - path_loss_db + noise_db;
+    return rssi.round().clamp(i8::MIN as f64, -1.0) as i8;
+}
+
+/// Positions of the emulated devices and the range of their radios
+#[derive(Clone, Debug, Default)]
+pub struct RadioModel {
+    /// Position of each device in meters, by address
+    positions: HashMap<[u8; 6], (f64, f64)>,
+    /// Advertisements are only received by devices that are at most this many meters away from the sender
+    range_m: Option<f64>,
+}
+
+impl RadioModel {
+    pub fn new(positions: HashMap<[u8; 6], (f64, f64)>, range_m: Option<f64>) -> Self {
+        return RadioModel { positions, range_m };
+    }
+
+    /// Distance between two devices in meters. Falls back to [distance_m] if one of them has no position
+    pub fn distance_m(&self, a: &[u8; 6], b: &[u8; 6]) -> f64 {
+        let (Some(a_position), Some(b_position)) = (self.positions.get(a), self.positions.get(b))
+        else {
+            return distance_m(a, b);
+        };
+        return (a_position.0 - b_position.0).hypot(a_position.1 - b_position.1);
+    }
+
+    /// Signal strength in dBm of an advertisement from `sender` received by `receiver`. Returns `None` if the receiver is out of range
+    pub fn receive(&self, sender: &[u8; 6], receiver: &[u8; 6]) -> Option<i8> {
+        let distance = self.distance_m(sender, receiver);
+        if self.range_m.is_some_and(|range_m| distance > range_m) {
+            return None;
+        }
+        return Some(rssi_at_distance(distance));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emulated_advertisements_have_a_negative_rssi() {
+        let sender = [0x24, 0xec, 0x4b, 0x00, 0x00, 0x01];
+        let receiver = [0x24, 0xec, 0x4b, 0x00, 0x00, 0x02];
+        for _ in 0..100 {
+            let rssi = RadioModel::default().receive(&sender, &receiver).unwrap();
+            assert_ne!(rssi, 0);
+            assert!((-100..0).contains(&rssi), "unrealistic RSSI {}", rssi);
+        }
+    }
+
+    #[test]
+    fn distance_is_the_same_in_both_directions() {
+        let a = [0x24, 0xec, 0x4b, 0x12, 0x34, 0x56];
+        let b = [0x24, 0xec, 0x4b, 0xab, 0xcd, 0xef];
+        assert_eq!(distance_m(&a, &b), distance_m(&b, &a));
+        assert!((MIN_DISTANCE_M..=MAX_DISTANCE_M).contains(&distance_m(&a, &b)));
+    }
+
+    #[test]
+    fn devices_out_of_range_do_not_receive_advertisements() {
+        let a = [0, 0, 0, 0, 0, 1];
+        let b = [0, 0, 0, 0, 0, 2];
+        let c = [0, 0, 0, 0, 0, 3];
+        let positions = HashMap::from([(a, (0.0, 0.0)), (b, (5.0, 0.0)), (c, (10.0, 0.0))]);
+        let model = RadioModel::new(positions, Some(6.0));
+        assert!(model.receive(&a, &b).is_some());
+        assert!(model.receive(&b, &c).is_some());
+        // a and c can only reach each other through b
+        assert!(model.receive(&a, &c).is_none());
+        assert!(model.receive(&c, &a).is_none());
+    }
+
+    #[test]
+    fn positions_determine_the_distance() {
+        let a = [0, 0, 0, 0, 0, 1];
+        let b = [0, 0, 0, 0, 0, 2];
+        let unplaced = [0, 0, 0, 0, 0, 3];
+        let model = RadioModel::new(HashMap::from([(a, (0.0, 0.0)), (b, (3.0, 4.0))]), None);
+        assert_eq!(model.distance_m(&a, &b), 5.0);
+        assert_eq!(model.distance_m(&a, &unplaced), distance_m(&a, &unplaced));
+        // Without a range every device is reached
+        let far = RadioModel::new(HashMap::from([(a, (0.0, 0.0)), (b, (1000.0, 0.0))]), None);
+        assert!(far.receive(&a, &b).unwrap() < 0);
+    }
+}
@@ -0,0 +1,41 @@
+//! Render the LED state of an emulated device to the terminal
+use crossterm::{
+    cursor::MoveToColumn,
+    queue,
+    style::{Color, Print, ResetColor, SetBackgroundColor},
+    terminal::{Clear, ClearType},
+};
+use std::io::Write;
+
+/// Width of the bar for a single LED in characters
+const LED_WIDTH: usize = 4;
+
+/// Map a brightness to one of the 24 ANSI 256-color grayscale shades
+fn grayscale(lux: u16, max_lux: u16) -> Color {
+    if lux == 0 || max_lux == 0 {
+        return Color::AnsiValue(16);
+    }
+    let level = (std::cmp::min(lux, max_lux) as u32 * 23) / max_lux as u32;
+    return Color::AnsiValue(232 + level as u8);
+}
+
+/// Draw all LEDs as a horizontal bar, overwriting the current line
+pub fn render_leds(name: &str, leds: &[u16], max_lux: u16) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout();
+    queue!(
+        stdout,
+        MoveToColumn(0),
+        Clear(ClearType::CurrentLine),
+        Print(format!("{:<16} ", name))
+    )?;
+    for lux in leds {
+        queue!(
+            stdout,
+            SetBackgroundColor(grayscale(*lux, max_lux)),
+            Print(" ".repeat(LED_WIDTH))
+        )?;
+    }
+    queue!(stdout, ResetColor)?;
+    stdout.flush()?;
+    return Ok(());
+}
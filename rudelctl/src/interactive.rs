@@ -0,0 +1,188 @@
+//! Run commands on a single device over one persistent connection
+//!
+//! Connecting takes a few seconds on some adapters. `rudelctl interactive <address>` connects once and then reads commands from stdin, one per line. The commands use the same syntax as the subcommands, but without the address and the timeout. Arguments are separated by whitespace, so paths can not contain spaces.
+use crate::{
+    bluetooth::scan_for,
+    dump::dump_device,
+    update_target::{UpdateTarget, UpdateTargetError},
+    wasm_config::{encode_config, load_schema, WasmConfigError},
+};
+use bluer::{Address, Device};
+use clap::Parser;
+use futures_time::time::Duration;
+use std::{path::PathBuf, sync::Mutex};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+#[derive(Error, Debug)]
+pub enum InteractiveError {
+    #[error("BlueR error")]
+    BluerError(#[from] bluer::Error),
+    #[error("io error")]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    UpdateTargetError(#[from] UpdateTargetError),
+    #[error(transparent)]
+    WasmConfigError(#[from] WasmConfigError),
+    #[error("Did not find the device {0}")]
+    DeviceNotFound(Address),
+}
+
+/// A command in an interactive session
+#[derive(Parser, Debug)]
+#[command(no_binary_name = true)]
+enum InteractiveCommand {
+    /// Upload a file
+    Upload {
+        /// Compress the file with zstd before sending it
+        #[arg(long)]
+        compress: bool,
+
+        /// WASM file that will get uploaded
+        file: PathBuf,
+    },
+    /// Run a WASM binary
+    Run {
+        /// Compress the file with zstd before sending it
+        #[arg(long)]
+        compress: bool,
+
+        /// WASM file that will get run
+        file: PathBuf,
+    },
+    /// Set the group ID of the device
+    SetGroup {
+        /// The new group ID
+        group: u8,
+    },
+    /// Set the configuration of the WASM guest
+    SetWasmConfig {
+        /// TOML file that maps every key to its type, for example `ms_per_step = "u32"`
+        #[arg(short, long)]
+        schema: Option<PathBuf>,
+
+        /// The values as key=value pairs
+        #[arg(required = true)]
+        values: Vec<String>,
+    },
+    /// Print all services and characteristics of the device
+    Dump,
+    /// Disconnect and connect to the device again
+    Reconnect,
+    /// End the session
+    Exit,
+}
+
+/// Scan until the device with the given address is found
+async fn find_device(
+    adapter: Option<&str>,
+    timeout: Duration,
+    address: Address,
+) -> Result<Device, InteractiveError> {
+    let found_device: Mutex<Option<Device>> = Mutex::new(None);
+    let remember_device = async |device: Device| -> Result<(), UpdateTargetError> {
+        if device.address() != address {
+            return Err(UpdateTargetError::NotTheRequestedDevice);
+        }
+        *found_device.lock().unwrap() = Some(device);
+        return Ok(());
+    };
+    scan_for(adapter, timeout, 1, &remember_device).await?;
+    return found_device
+        .into_inner()
+        .unwrap()
+        .ok_or(InteractiveError::DeviceNotFound(address));
+}
+
+/// Run a single command on the connected device
+async fn run_command(
+    device: &Device,
+    update_target: &UpdateTarget,
+    command: InteractiveCommand,
+) -> Result<(), InteractiveError> {
+    match command {
+        InteractiveCommand::Upload { compress, file } => {
+            let file_content = tokio::fs::read(&file).await?;
+            let hash = update_target.upload_file(&file_content, compress).await?;
+            println!("Uploaded {} as {}", file.display(), hex::encode(hash));
+        }
+        InteractiveCommand::Run { compress, file } => {
+            let file_content = tokio::fs::read(&file).await?;
+            update_target.run_program(&file_content, compress).await?;
+            println!("Running {}", file.display());
+        }
+        InteractiveCommand::SetGroup { group } => {
+            update_target.set_group_id(group).await?;
+            println!("Set group to {}", group);
+        }
+        InteractiveCommand::SetWasmConfig { schema, values } => {
+            let schema = match schema {
+                Some(path) => Some(load_schema(&path).await?),
+                None => None,
+            };
+            let config = encode_config(&values, schema.as_ref())?;
+            update_target.set_wasm_config(&config).await?;
+            println!("Set the WASM config ({} bytes)", config.len());
+        }
+        InteractiveCommand::Dump => {
+            dump_device(device).await?;
+        }
+        // Handled by the session, as they change the connection
+        InteractiveCommand::Reconnect | InteractiveCommand::Exit => {}
+    }
+    return Ok(());
+}
+
+/// Connect to the device and run commands from stdin until `exit` is entered or stdin is closed
+pub async fn interactive(
+    adapter: Option<&str>,
+    timeout: Duration,
+    address: Address,
+) -> Result<(), InteractiveError> {
+    let device = find_device(adapter, timeout, address).await?;
+    let mut update_target = UpdateTarget::new_from_peripheral(&device).await?;
+    eprintln!(
+        "Connected to {}. Enter help for a list of commands",
+        address
+    );
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        eprint!("> ");
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match InteractiveCommand::try_parse_from(line.split_whitespace()) {
+            Ok(command) => command,
+            Err(err) => {
+                // Also prints the help
+                let _ = err.print();
+                continue;
+            }
+        };
+        match command {
+            InteractiveCommand::Exit => break,
+            InteractiveCommand::Reconnect => {
+                device.disconnect().await?;
+                match UpdateTarget::new_from_peripheral(&device).await {
+                    Ok(new_update_target) => {
+                        update_target = new_update_target;
+                        eprintln!("Reconnected to {}", address);
+                    }
+                    Err(err) => eprintln!("Failed to reconnect: {}", err),
+                }
+            }
+            command => {
+                if let Err(err) = run_command(&device, &update_target, command).await {
+                    eprintln!("Command failed: {}", err);
+                }
+            }
+        }
+    }
+
+    device.disconnect().await?;
+    return Ok(());
+}
@@ -0,0 +1,121 @@
+//! Discover nearby rudelblinken devices without knowing their addresses
+//!
+//! Devices are recognized by the services they advertise or by a rudelblinken header in their manufacturer data. Guests replace the advertised services with their own data, so both are needed. Every recognized device is connected to briefly to read the name from the cat management service. Devices that only provide the file upload service are listed with the name `unknown`.
+use crate::{
+    bluetooth::scan_for,
+    update_target::{find_characteristic, find_service, UpdateTargetError},
+};
+use bluer::{Device, Uuid, UuidExt};
+use futures_time::time::Duration;
+use rudelblinken_protocol::RudelblinkenAdvertisement;
+use serde::Serialize;
+use std::cell::RefCell;
+
+const FILE_UPLOAD_SERVICE: u16 = 0x7892;
+const CAT_MANAGEMENT_SERVICE: u16 = 0x7992;
+const CAT_MANAGEMENT_SERVICE_NAME: u16 = 0x7894;
+/// Listed for devices whose name can not be read
+const UNKNOWN_NAME: &str = "unknown";
+
+/// A rudelblinken device that was found while scanning
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ListedDevice {
+    /// MAC address of the device
+    pub address: String,
+    pub name: String,
+    /// Signal strength in dBm, if known
+    pub rssi: Option<i16>,
+}
+
+/// Whether the advertisements of the device suggest that it is a rudelblinken device
+async fn looks_like_rudelblinken(device: &Device) -> bluer::Result<bool> {
+    let services = device.uuids().await?.unwrap_or_default();
+    if services.contains(&Uuid::from_u16(CAT_MANAGEMENT_SERVICE))
+        || services.contains(&Uuid::from_u16(FILE_UPLOAD_SERVICE))
+    {
+        return Ok(true);
+    }
+    let manufacturer_data = device.manufacturer_data().await?.unwrap_or_default();
+    return Ok(manufacturer_data
+        .values()
+        .any(|data| RudelblinkenAdvertisement::parse(data).is_some()));
+}
+
+/// Read the name from the cat management service. Returns `None` if the device does not provide it
+async fn read_name(device: &Device) -> Option<String> {
+    let service = find_service(device, CAT_MANAGEMENT_SERVICE).await.ok()?;
+    let characteristic = find_characteristic(&service, CAT_MANAGEMENT_SERVICE_NAME)
+        .await
+        .ok()?;
+    let name = characteristic.read().await.ok()?;
+    return Some(String::from_utf8_lossy(&name).to_string());
+}
+
+/// Get the address, name and signal strength of a device. Returns `None` if it is not a rudelblinken device
+///
+/// Devices that were not connected before are disconnected again after reading the name.
+pub async fn inspect_device(device: &Device) -> Result<Option<ListedDevice>, UpdateTargetError> {
+    if !looks_like_rudelblinken(device).await? {
+        return Ok(None);
+    }
+    let rssi = device.rssi().await?;
+    let was_connected = device.is_connected().await?;
+    let name = if was_connected || device.connect().await.is_ok() {
+        read_name(device).await
+    } else {
+        None
+    };
+    if !was_connected {
+        let _ = device.disconnect().await;
+    }
+    return Ok(Some(ListedDevice {
+        address: device.address().to_string(),
+        name: name.unwrap_or_else(|| UNKNOWN_NAME.to_string()),
+        rssi,
+    }));
+}
+
+/// Scan for rudelblinken devices with the given adapter. The devices with the strongest signal come first
+pub async fn list_devices(
+    adapter: Option<&str>,
+    duration: Duration,
+) -> bluer::Result<Vec<ListedDevice>> {
+    let devices = RefCell::new(Vec::new());
+    scan_for(
+        adapter,
+        duration,
+        u32::MAX,
+        &async |device: Device| -> Result<(), UpdateTargetError> {
+            if let Some(listed) = inspect_device(&device).await? {
+                devices.borrow_mut().push(listed);
+            }
+            return Ok(());
+        },
+    )
+    .await?;
+    let mut devices = devices.into_inner();
+    devices.sort_by_key(|device| std::cmp::Reverse(device.rssi.unwrap_or(i16::MIN)));
+    return Ok(devices);
+}
+
+/// Format the devices as a table with a header and aligned columns
+pub fn format_table(devices: &[ListedDevice]) -> String {
+    let name_width = devices
+        .iter()
+        .map(|device| device.name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("NAME".len());
+    let mut table = format!("{:<17}  {:<name_width$}  RSSI\n", "MAC", "NAME");
+    for device in devices {
+        let rssi = match device.rssi {
+            Some(rssi) => rssi.to_string(),
+            None => "unknown".to_string(),
+        };
+        table.push_str(&format!(
+            "{:<17}  {:<name_width$}  {}\n",
+            device.address, device.name, rssi
+        ));
+    }
+    return table;
+}
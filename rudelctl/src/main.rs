@@ -13,24 +13,53 @@
 //! upload   Upload a file
 //! run      Run a WASM binary
 //! scan     Scan for cats
+//! list     List nearby devices with their name and signal strength
+//! set-group Set the group ID of a device
+//! set-wasm-config Set the configuration of the WASM guest on a device
+//! dump     Print all services and characteristics of a device
+//! diff     Check if two devices are running the same program
+//! calibrate-light Calibrate the ambient light sensor of a device
+//! schedule Run multiple WASM binaries in sequence
+//! interactive Run commands on a device over a single connection
 //! emulate  Emulate a rudelblinken device
+//! emulate-replay Replay captured advertisements to an emulated device
+//! scenario Run multiple emulated devices and check assertions
 //! help     Print this message or the help of the given subcommand(s)
 //!
 //! Options:
+//!     --adapter <ADAPTER>  Bluetooth adapter to use, for example `hci0`
 //! -h, --help     Print help
 //! ```
 #![feature(async_closure)]
 
 mod bluetooth;
+mod calibrate_light;
+mod config;
+mod diff;
+mod dump;
 mod emulator;
+mod interactive;
+mod list;
+mod replay;
+mod scenario;
 mod update_target;
-use bluer::Device;
+mod wasm_config;
+use bluer::{Address, Device};
 use bluetooth::scan_for;
-use clap::{Parser, Subcommand};
+use calibrate_light::calibrate_light;
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
+use config::Config;
+use diff::diff_programs;
+use dump::dump_device;
 use emulator::{EmulateCommand, Emulator};
 use futures_time::time::Duration;
+use interactive::interactive;
+use list::{format_table, list_devices};
+use replay::{replay, ReplayCommand};
+use scenario::{run_scenario, ScenarioCommand};
 use std::{path::PathBuf, time::Instant};
-use update_target::{UpdateTarget, UpdateTargetError};
+use update_target::{CharacteristicHandles, UpdateTarget, UpdateTargetError, REQUIRED_HANDLES};
+use wasm_config::{encode_config, load_schema};
 
 /// Rudelblinken cli utility
 #[derive(Parser, Debug)]
@@ -38,6 +67,12 @@ use update_target::{UpdateTarget, UpdateTargetError};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Bluetooth adapter to use, for example `hci0`
+    ///
+    /// Adapter names are platform specific, on Linux they look like `hci0`. Defaults to the `adapter` from the config file or the default adapter of the system
+    #[arg(long, global = true)]
+    adapter: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -52,8 +87,21 @@ enum Commands {
         #[arg(short, long, default_value = "1")]
         devices: u32,
 
+        /// Compress the file with zstd before sending it
+        #[arg(long)]
+        compress: bool,
+
         /// WASM file that will get flashed to the devices
         file: PathBuf,
+
+        /// Connect with the given characteristic handles instead of discovering the services
+        ///
+        /// Discovery takes a few seconds per device. Skipping it is useful to program many devices with the same firmware, but nothing checks the handles. Get them with `rudelctl dump` from a device with the same firmware
+        #[arg(long, requires_all = REQUIRED_HANDLES)]
+        skip_discovery: bool,
+
+        #[command(flatten)]
+        handles: CharacteristicHandles,
     },
     /// Run a WASM binary
     Run {
@@ -65,44 +113,194 @@ enum Commands {
         #[arg(short, long, default_value = "1")]
         devices: u32,
 
+        /// Compress the file with zstd before sending it
+        #[arg(long)]
+        compress: bool,
+
         /// WASM file that will get flashed to the devices
         file: PathBuf,
+
+        /// Connect with the given characteristic handles instead of discovering the services
+        ///
+        /// Discovery takes a few seconds per device. Skipping it is useful to program many devices with the same firmware, but nothing checks the handles. Get them with `rudelctl dump` from a device with the same firmware
+        #[arg(long, requires_all = REQUIRED_HANDLES)]
+        skip_discovery: bool,
+
+        #[command(flatten)]
+        handles: CharacteristicHandles,
     },
     /// Scan for cats
     Scan {
         /// Stop scanning after this many seconds
         #[arg(short, long, default_value = "2")]
         timeout: f32,
+
+        /// Only show devices in this group
+        #[arg(short, long)]
+        group: Option<u8>,
+    },
+    /// List nearby devices with their name and signal strength
+    ///
+    /// Finds devices without knowing their MAC addresses. Devices that do not provide the cat management service are listed with the name `unknown`
+    List {
+        /// Stop scanning after this many seconds
+        #[arg(short, long, default_value = "5")]
+        timeout: f32,
+
+        /// Print the devices as a JSON array instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Set the group ID of a device
+    ///
+    /// Devices only synchronize with other devices in the same group
+    SetGroup {
+        /// Stop scanning after this many seconds
+        #[arg(short, long, default_value = "5")]
+        timeout: f32,
+
+        /// MAC address of the device
+        address: Address,
+
+        /// The new group ID
+        group: u8,
+    },
+    /// Set the configuration of the WASM guest on a device
+    ///
+    /// The values are encoded as key-value pairs that can be read with the `config` module of the SDK. Without a schema all values are stored as strings
+    SetWasmConfig {
+        /// Stop scanning after this many seconds
+        #[arg(short, long, default_value = "5")]
+        timeout: f32,
+
+        /// TOML file that maps every key to its type, for example `ms_per_step = "u32"`
+        #[arg(short, long)]
+        schema: Option<PathBuf>,
+
+        /// MAC address of the device
+        address: Address,
+
+        /// The values as key=value pairs
+        #[arg(required = true)]
+        values: Vec<String>,
+    },
+    /// Print all services and characteristics of a device
+    ///
+    /// Readable characteristics are read and their values are printed as hex. Known rudelblinken UUIDs are annotated with their names. The handles can be used with `--skip-discovery`
+    Dump {
+        /// Stop scanning after this many seconds
+        #[arg(short, long, default_value = "5")]
+        timeout: f32,
+
+        /// MAC address of the device
+        address: Address,
+    },
+    /// Check if two devices are running the same program
+    ///
+    /// Prints the program hashes if they differ. Pass the WASM files you uploaded to see which of them is running
+    Diff {
+        /// Stop scanning after this many seconds
+        #[arg(short, long, default_value = "5")]
+        timeout: f32,
+
+        /// MAC address of the first device
+        address_a: Address,
+
+        /// MAC address of the second device
+        address_b: Address,
+
+        /// Local WASM files to compare the program hashes against
+        #[arg(short, long)]
+        files: Vec<PathBuf>,
+    },
+    /// Calibrate the ambient light sensor of a device
+    ///
+    /// Asks for the illuminance measured with a light meter next to the device at two different light levels
+    CalibrateLight {
+        /// Stop scanning after this many seconds
+        #[arg(short, long, default_value = "5")]
+        timeout: f32,
+
+        /// MAC address of the device
+        address: Address,
+    },
+    /// Run multiple WASM binaries in sequence
+    ///
+    /// Each program runs for the given number of seconds, then the next one starts. The schedule repeats until another program or schedule is set.
+    Schedule {
+        /// Stop scanning after this many seconds
+        #[arg(short, long, default_value = "5")]
+        timeout: f32,
+
+        /// Compress the files with zstd before sending them
+        #[arg(long)]
+        compress: bool,
+
+        /// MAC address of the device
+        address: Address,
+
+        /// WASM files and durations in seconds, for example `sync.wasm 300 sparkle.wasm 300`
+        #[arg(required = true, num_args = 2..)]
+        programs: Vec<String>,
+    },
+    /// Run commands on a device over a single connection
+    ///
+    /// Reads commands from stdin, one per line. They use the same syntax as the subcommands, but without the address and the timeout. Enter `help` for a list of commands, `reconnect` to connect again and `exit` to end the session
+    Interactive {
+        /// Stop scanning after this many seconds
+        #[arg(short, long, default_value = "5")]
+        timeout: f32,
+
+        /// MAC address of the device
+        address: Address,
     },
     /// Emulate a rudelblinken device
     Emulate(EmulateCommand),
+    /// Replay captured advertisements to an emulated device
+    ///
+    /// Records the LED state while replaying, to reproduce synchronization issues without hardware
+    EmulateReplay(ReplayCommand),
+    /// Run multiple emulated devices and check assertions
+    Scenario(ScenarioCommand),
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> bluer::Result<()> {
     env_logger::init();
     let cli = Cli::parse();
+    let config = Config::load()
+        .await
+        .expect("Failed to load the config file");
+    let adapter = cli.adapter.or(config.adapter);
 
     match cli.command {
         Commands::Upload {
             timeout,
             devices,
+            compress,
             file,
+            skip_discovery,
+            handles,
         } => {
             let file_content = tokio::fs::read(file)
                 .await
                 .expect("Failed to read the WASM file");
 
             scan_for(
+                adapter.as_deref(),
                 Duration::from_millis((timeout * 1000.0) as u64),
                 devices,
                 &async |device: Device| -> Result<(), UpdateTargetError> {
-                    let update_target = UpdateTarget::new_from_peripheral(&device).await?;
+                    let update_target = if skip_discovery {
+                        UpdateTarget::new_from_handles(&device, &handles).await?
+                    } else {
+                        UpdateTarget::new_from_peripheral(&device).await?
+                    };
 
                     let data = &file_content;
 
                     let now = Instant::now();
-                    update_target.upload_file(&data).await?;
+                    update_target.upload_file(&data, compress).await?;
                     let duration = now.elapsed();
                     println!(
                         "Sending {}k took {} millis",
@@ -123,30 +321,39 @@ async fn main() -> bluer::Result<()> {
         Commands::Run {
             timeout,
             devices,
+            compress,
             file,
+            skip_discovery,
+            handles,
         } => {
             let file_content = tokio::fs::read(file)
                 .await
                 .expect("Failed to read the WASM file");
 
             scan_for(
+                adapter.as_deref(),
                 Duration::from_millis((timeout * 1000.0) as u64),
                 devices,
                 &async |device: Device| -> Result<(), UpdateTargetError> {
-                    let update_target = UpdateTarget::new_from_peripheral(&device).await?;
+                    let update_target = if skip_discovery {
+                        UpdateTarget::new_from_handles(&device, &handles).await?
+                    } else {
+                        UpdateTarget::new_from_peripheral(&device).await?
+                    };
 
                     let data = &file_content;
 
-                    update_target.run_program(&data).await?;
+                    update_target.run_program(&data, compress).await?;
                     return Ok(());
                 },
             )
             .await
             .unwrap();
         }
-        Commands::Scan { timeout } => {
-            eprintln!("name, mac, rssi");
+        Commands::Scan { timeout, group } => {
+            eprintln!("name, mac, rssi, group");
             scan_for(
+                adapter.as_deref(),
                 Duration::from_millis((timeout * 1000.0) as u64),
                 999,
                 &async |device: Device| -> Result<(), UpdateTargetError> {
@@ -154,8 +361,19 @@ async fn main() -> bluer::Result<()> {
                     let update_target = UpdateTarget::new_from_peripheral(&device).await?;
                     let rssi = device.rssi().await?;
 
+                    let device_group = update_target.get_group_id().await?;
+                    if group.is_some_and(|group| group != device_group) {
+                        return Ok(());
+                    }
+
                     let name = update_target.get_name().await?;
-                    println!("{}, {}, {}", name, address, rssi.unwrap_or(-200));
+                    println!(
+                        "{}, {}, {}, {}",
+                        name,
+                        address,
+                        rssi.unwrap_or(-200),
+                        device_group
+                    );
                     return Ok(());
                     // update_target.device.disconnect().await.unwrap();
                 },
@@ -163,9 +381,220 @@ async fn main() -> bluer::Result<()> {
             .await
             .unwrap();
         }
+        Commands::List { timeout, json } => {
+            let devices = list_devices(
+                adapter.as_deref(),
+                Duration::from_millis((timeout * 1000.0) as u64),
+            )
+            .await?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&devices).expect("Failed to encode the devices")
+                );
+            } else {
+                print!("{}", format_table(&devices));
+            }
+        }
+        Commands::SetGroup {
+            timeout,
+            address,
+            group,
+        } => {
+            scan_for(
+                adapter.as_deref(),
+                Duration::from_millis((timeout * 1000.0) as u64),
+                1,
+                &async |device: Device| -> Result<(), UpdateTargetError> {
+                    if device.address() != address {
+                        return Err(UpdateTargetError::NotTheRequestedDevice);
+                    }
+                    let update_target = UpdateTarget::new_from_peripheral(&device).await?;
+                    update_target.set_group_id(group).await?;
+                    println!("Set group of {} to {}", address, group);
+                    return Ok(());
+                },
+            )
+            .await
+            .unwrap();
+        }
+        Commands::SetWasmConfig {
+            timeout,
+            schema,
+            address,
+            values,
+        } => {
+            let schema = match schema {
+                Some(path) => match load_schema(&path).await {
+                    Ok(schema) => Some(schema),
+                    Err(err) => {
+                        eprintln!("Failed to load the schema: {}", err);
+                        return Ok(());
+                    }
+                },
+                None => None,
+            };
+            let config = match encode_config(&values, schema.as_ref()) {
+                Ok(config) => config,
+                Err(err) => {
+                    eprintln!("Invalid config: {}", err);
+                    return Ok(());
+                }
+            };
+            scan_for(
+                adapter.as_deref(),
+                Duration::from_millis((timeout * 1000.0) as u64),
+                1,
+                &async |device: Device| -> Result<(), UpdateTargetError> {
+                    if device.address() != address {
+                        return Err(UpdateTargetError::NotTheRequestedDevice);
+                    }
+                    let update_target = UpdateTarget::new_from_peripheral(&device).await?;
+                    update_target.set_wasm_config(&config).await?;
+                    println!(
+                        "Set the WASM config of {} ({} bytes)",
+                        address,
+                        config.len()
+                    );
+                    return Ok(());
+                },
+            )
+            .await
+            .unwrap();
+        }
+        Commands::Dump { timeout, address } => {
+            scan_for(
+                adapter.as_deref(),
+                Duration::from_millis((timeout * 1000.0) as u64),
+                1,
+                &async |device: Device| -> Result<(), UpdateTargetError> {
+                    if device.address() != address {
+                        return Err(UpdateTargetError::NotTheRequestedDevice);
+                    }
+                    dump_device(&device).await?;
+                    return Ok(());
+                },
+            )
+            .await
+            .unwrap();
+        }
+        Commands::Diff {
+            timeout,
+            address_a,
+            address_b,
+            files,
+        } => {
+            if let Err(err) = diff_programs(
+                adapter.as_deref(),
+                Duration::from_millis((timeout * 1000.0) as u64),
+                address_a,
+                address_b,
+                &files,
+            )
+            .await
+            {
+                eprintln!("Failed to compare the programs: {}", err);
+            }
+        }
+        Commands::CalibrateLight { timeout, address } => {
+            scan_for(
+                adapter.as_deref(),
+                Duration::from_millis((timeout * 1000.0) as u64),
+                1,
+                &async |device: Device| -> Result<(), UpdateTargetError> {
+                    if device.address() != address {
+                        return Err(UpdateTargetError::NotTheRequestedDevice);
+                    }
+                    let update_target = UpdateTarget::new_from_peripheral(&device).await?;
+                    // Only one device is calibrated, so a failed calibration also stops the scan
+                    if let Err(err) = calibrate_light(&update_target).await {
+                        eprintln!("Calibration failed: {}", err);
+                    }
+                    return Ok(());
+                },
+            )
+            .await
+            .unwrap();
+        }
+        Commands::Schedule {
+            timeout,
+            compress,
+            address,
+            programs,
+        } => {
+            if programs.len() % 2 != 0 {
+                Cli::command()
+                    .error(
+                        ErrorKind::WrongNumberOfValues,
+                        "Every WASM file needs to be followed by a duration",
+                    )
+                    .exit();
+            }
+            let mut schedule = Vec::new();
+            for entry in programs.chunks(2) {
+                let Ok(duration) = entry[1].parse::<f32>() else {
+                    Cli::command()
+                        .error(
+                            ErrorKind::InvalidValue,
+                            format!("{} is not a valid duration", entry[1]),
+                        )
+                        .exit();
+                };
+                let file_content = tokio::fs::read(&entry[0])
+                    .await
+                    .expect("Failed to read the WASM file");
+                schedule.push((file_content, (duration * 1000.0) as u32));
+            }
+
+            scan_for(
+                adapter.as_deref(),
+                Duration::from_millis((timeout * 1000.0) as u64),
+                1,
+                &async |device: Device| -> Result<(), UpdateTargetError> {
+                    if device.address() != address {
+                        return Err(UpdateTargetError::NotTheRequestedDevice);
+                    }
+                    let update_target = UpdateTarget::new_from_peripheral(&device).await?;
+                    update_target.run_schedule(&schedule, compress).await?;
+                    println!(
+                        "Set a schedule with {} programs on {}",
+                        schedule.len(),
+                        address
+                    );
+                    return Ok(());
+                },
+            )
+            .await
+            .unwrap();
+        }
+        Commands::Interactive { timeout, address } => {
+            if let Err(err) = interactive(
+                adapter.as_deref(),
+                Duration::from_millis((timeout * 1000.0) as u64),
+                address,
+            )
+            .await
+            {
+                eprintln!("Interactive session failed: {}", err);
+            }
+        }
         Commands::Emulate(emulate_command) => {
             let emulator = Emulator::new(emulate_command).await.unwrap();
-            emulator.emulate().await.unwrap();
+            tokio::select! {
+                result = emulator.emulate() => result.unwrap(),
+                _ = tokio::signal::ctrl_c() => {}
+            }
+            println!("{}", emulator.power_summary());
+            emulator.save_timeline().await.unwrap();
+        }
+        Commands::EmulateReplay(replay_command) => {
+            replay(replay_command).await.unwrap();
+        }
+        Commands::Scenario(scenario_command) => {
+            let passed = run_scenario(scenario_command).await.unwrap();
+            if !passed {
+                std::process::exit(1);
+            }
         }
     };
 
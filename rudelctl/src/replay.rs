@@ -0,0 +1,174 @@
+//! Replay captured BLE advertisements to an emulated device.
+//!
+//! The capture is a newline-delimited JSON file with one [BleEvent] per line:
+//!
+//! ```json
+//! {"timestamp_us": 0, "address": "24ec4b000001", "data": "0000ca7ea2010000010a00000100000000000000"}
+//! {"timestamp_us": 150000, "address": "24ec4b000001", "data": "0000ca7ea2010000020a00090100000096000000"}
+//! ```
+//!
+//! `data` is the hex encoded manufacturer data, starting with the company ID. `address` is optional.
+//!
+//! The advertisements are injected in timestamp order, relative to the first one. The LED state is recorded while replaying and written as CSV with the time in milliseconds followed by the brightness of each LED.
+use crate::{
+    emulator::{encode_advertisement_packet, Emulator, EmulatorError},
+    scenario::Evaluator,
+};
+use clap::Args;
+use serde::Deserialize;
+use std::{
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::{
+    fs::{read, read_to_string},
+    net::UnixDatagram,
+    time::{interval, sleep, sleep_until},
+};
+
+/// How long the emulator keeps running after the last advertisement was injected
+const REPLAY_TAIL: Duration = Duration::from_secs(1);
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("Failed to read or write a file")]
+    IoError(#[from] std::io::Error),
+    #[error("Line {line} of the capture is not a valid BLE event")]
+    InvalidEvent {
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("Line {0} of the capture contains invalid hex data")]
+    InvalidHex(usize),
+    #[error(transparent)]
+    EmulatorError(#[from] EmulatorError),
+}
+
+#[derive(Args, Debug)]
+pub struct ReplayCommand {
+    /// Newline-delimited JSON file with the captured advertisements
+    capture: PathBuf,
+
+    /// WASM file to run
+    file: PathBuf,
+
+    /// Write the LED timeline to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// How often the LED state is recorded
+    #[arg(long, default_value = "20")]
+    snapshot_interval_ms: u64,
+
+    /// Group ID of the emulated device
+    #[arg(short, long, default_value = "0")]
+    group: u8,
+}
+
+/// A captured advertisement
+#[derive(Deserialize, Debug)]
+pub struct BleEvent {
+    /// Time at which the advertisement was received in microseconds
+    pub timestamp_us: u64,
+    /// Hex encoded MAC address of the sender
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Hex encoded manufacturer data, starting with the company ID
+    pub data: String,
+}
+
+/// A decoded captured advertisement
+struct CapturedAdvertisement {
+    timestamp: Duration,
+    address: [u8; 6],
+    data: Vec<u8>,
+}
+
+fn parse_capture(capture: &str) -> Result<Vec<CapturedAdvertisement>, ReplayError> {
+    let mut advertisements = Vec::new();
+    for (index, line) in capture.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_number = index + 1;
+        let event: BleEvent =
+            serde_json::from_str(line).map_err(|source| ReplayError::InvalidEvent {
+                line: line_number,
+                source,
+            })?;
+        let data = hex::decode(&event.data).map_err(|_| ReplayError::InvalidHex(line_number))?;
+        let address = match event.address {
+            Some(address) => hex::decode(address)
+                .ok()
+                .and_then(|address| address.try_into().ok())
+                .ok_or(ReplayError::InvalidHex(line_number))?,
+            None => [0u8; 6],
+        };
+        advertisements.push(CapturedAdvertisement {
+            timestamp: Duration::from_micros(event.timestamp_us),
+            address,
+            data,
+        });
+    }
+    advertisements.sort_by_key(|advertisement| advertisement.timestamp);
+    return Ok(advertisements);
+}
+
+/// Replay a capture to an emulated device and write the LED timeline
+pub async fn replay(command: ReplayCommand) -> Result<(), ReplayError> {
+    let advertisements = parse_capture(&read_to_string(&command.capture).await?)?;
+    let wasm = read(&command.file).await?;
+    let mut emulator =
+        Emulator::from_wasm(wasm, Some("replay".to_string()), command.group, false).await?;
+    // Do not disturb other emulated devices
+    emulator.set_silent();
+
+    let leds = emulator.leds();
+    let socket_path = emulator.socket_path();
+    let mut evaluator = Evaluator::new(vec![emulator.name().to_string()]);
+
+    let start_time = Instant::now();
+    let inject_advertisements = async {
+        let socket = UnixDatagram::unbound()?;
+        let first_timestamp = advertisements
+            .first()
+            .map_or(Duration::ZERO, |advertisement| advertisement.timestamp);
+        for advertisement in &advertisements {
+            sleep_until((start_time + (advertisement.timestamp - first_timestamp)).into()).await;
+            let packet = encode_advertisement_packet(advertisement.address, &advertisement.data);
+            socket.send_to(&packet, &socket_path).await?;
+        }
+        sleep(REPLAY_TAIL).await;
+        Ok(()) as Result<(), ReplayError>
+    };
+    let record_snapshots = async {
+        let mut snapshot_interval = interval(Duration::from_millis(command.snapshot_interval_ms));
+        loop {
+            snapshot_interval.tick().await;
+            evaluator.record(start_time.elapsed(), vec![leds.lock().unwrap().clone()]);
+        }
+    };
+
+    tokio::select! {
+        result = emulator.emulate() => result?,
+        result = inject_advertisements => result?,
+        _ = record_snapshots => {}
+    }
+
+    let mut output: Box<dyn Write> = match &command.output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    for snapshot in evaluator.snapshots() {
+        let leds = snapshot.leds[0]
+            .iter()
+            .map(|brightness| brightness.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(output, "{},{}", snapshot.time.as_millis(), leds)?;
+    }
+    return Ok(());
+}
@@ -0,0 +1,309 @@
+//! Run multiple emulated devices and check assertions about their behaviour.
+//!
+//! A scenario is described by a TOML file:
+//!
+//! ```toml
+//! duration_secs = 30.0
+//! snapshot_interval_ms = 20
+//!
+//! [[instances]]
+//! name = "cat-a"
+//! file = "sync.wasm"
+//!
+//! [[instances]]
+//! name = "cat-b"
+//! file = "sync.wasm"
+//!
+//! [[assertions]]
+//! metric = "max_phase_difference_ms"
+//! less_than = 10.0
+//! ```
+//!
+//! Paths to WASM files are relative to the scenario file.
+//!
+//! By default all instances receive the advertisements of all other instances. Instances can be placed at a `position` in meters. The distance between them determines the signal strength of the advertisements they receive. Instances without a position get a distance derived from their addresses. With a `range_m`, instances only receive advertisements from instances that are at most that far away, so every instance needs a position:
+//!
+//! ```toml
+//! range_m = 6.0
+//!
+//! [[instances]]
+//! name = "cat-a"
+//! file = "sync.wasm"
+//! position = [0.0, 0.0]
+//!
+//! [[instances]]
+//! name = "cat-b"
+//! file = "sync.wasm"
+//! position = [5.0, 0.0]
+//!
+//! [[instances]]
+//! name = "cat-c"
+//! file = "sync.wasm"
+//! position = [10.0, 0.0]
+//! ```
+//!
+//! Guests read their configuration from `config`, given as `key=value` pairs like for `rudelctl set-wasm-config` without a schema:
+//!
+//! ```toml
+//! [[instances]]
+//! name = "cat-a"
+//! file = "sync.wasm"
+//! config = ["rssi_min=-90", "rssi_max=-50"]
+//! ```
+//!
+//! Assertions are checked for all instances by default. To check them for some instances only, list their names as `instances`:
+//!
+//! ```toml
+//! [[assertions]]
+//! metric = "max_phase_difference_ms"
+//! instances = ["cat-a", "cat-b"]
+//! less_than = 10.0
+//! ```
+//!
+//! `--partition <SECONDS>` splits the instances into two halves that can not hear each other for the given time. The first half of the listed instances is on one side, the rest on the other side. After the partition healed, the `resync_time_ms` metric measures how long it took until all instances were synchronized again.
+mod evaluator;
+use crate::{
+    emulator::{Emulator, EmulatorError, PartitionConfig, RadioModel},
+    wasm_config::{encode_config, WasmConfigError},
+};
+use clap::Args;
+pub use evaluator::{Assertion, Evaluator, Metric};
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::{
+    fs::{read, read_to_string},
+    time::{interval, sleep},
+};
+
+#[derive(Error, Debug)]
+pub enum ScenarioError {
+    #[error("Failed to read a scenario file")]
+    FailedToReadFile(#[from] std::io::Error),
+    #[error("Failed to parse the scenario")]
+    InvalidScenario(#[from] toml::de::Error),
+    #[error("The scenario needs at least one instance")]
+    NoInstances,
+    #[error("{0} needs a position, because the scenario has a range")]
+    MissingPosition(String),
+    #[error("{0} is listed in an assertion, but there is no instance with that name")]
+    UnknownInstance(String),
+    #[error("The config of an instance is not valid")]
+    InvalidConfig(#[from] WasmConfigError),
+    #[error(transparent)]
+    EmulatorError(#[from] EmulatorError),
+}
+
+#[derive(Args, Debug)]
+pub struct ScenarioCommand {
+    /// TOML file describing the scenario
+    file: PathBuf,
+
+    /// Split the instances into two halves that can not hear each other for this many seconds
+    #[arg(long)]
+    partition: Option<f32>,
+
+    /// Seconds after the start of the scenario when the partition starts
+    #[arg(long, default_value = "0", requires = "partition")]
+    partition_start: f32,
+}
+
+/// A single emulated device
+#[derive(Deserialize, Debug)]
+pub struct Instance {
+    /// Name of the instance. Needs to be unique within the scenario
+    pub name: String,
+    /// WASM file to run
+    pub file: PathBuf,
+    /// Group ID of the instance
+    #[serde(default)]
+    pub group: u8,
+    /// Position of the instance in meters
+    pub position: Option<[f64; 2]>,
+    /// Configuration of the guest as `key=value` pairs. The values are stored as strings, like `rudelctl set-wasm-config` does without a schema
+    #[serde(default)]
+    pub config: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Scenario {
+    /// How long the instances are run
+    pub duration_secs: f32,
+    /// How often the LED state of all instances is recorded
+    #[serde(default = "default_snapshot_interval_ms")]
+    pub snapshot_interval_ms: u64,
+    /// Instances only receive advertisements from instances that are at most this many meters away
+    pub range_m: Option<f64>,
+    pub instances: Vec<Instance>,
+    #[serde(default)]
+    pub assertions: Vec<Assertion>,
+}
+
+fn default_snapshot_interval_ms() -> u64 {
+    return 20;
+}
+
+/// Check that every instance has a position, if the scenario has a range
+fn check_positions(instances: &[Instance], range_m: Option<f64>) -> Result<(), ScenarioError> {
+    if range_m.is_none() {
+        return Ok(());
+    }
+    if let Some(instance) = instances
+        .iter()
+        .find(|instance| instance.position.is_none())
+    {
+        return Err(ScenarioError::MissingPosition(instance.name.clone()));
+    }
+    return Ok(());
+}
+
+/// Check that the assertions only refer to instances of the scenario
+fn check_assertions(scenario: &Scenario) -> Result<(), ScenarioError> {
+    let names = scenario
+        .assertions
+        .iter()
+        .filter_map(|assertion| assertion.instances.as_ref())
+        .flatten();
+    for name in names {
+        if !scenario
+            .instances
+            .iter()
+            .any(|instance| instance.name == *name)
+        {
+            return Err(ScenarioError::UnknownInstance(name.clone()));
+        }
+    }
+    return Ok(());
+}
+
+/// Place the emulators at the positions of their instances. Returns `None` if no instance has a position and there is no range
+fn radio_model(
+    instances: &[Instance],
+    emulators: &[Emulator],
+    range_m: Option<f64>,
+) -> Option<RadioModel> {
+    if range_m.is_none() && instances.iter().all(|instance| instance.position.is_none()) {
+        return None;
+    }
+    let positions = instances
+        .iter()
+        .zip(emulators)
+        .filter_map(|(instance, emulator)| {
+            let [x, y] = instance.position?;
+            Some((emulator.address(), (x, y)))
+        })
+        .collect();
+    return Some(RadioModel::new(positions, range_m));
+}
+
+/// Run a scenario and print the results
+///
+/// Returns whether all assertions passed
+pub async fn run_scenario(command: ScenarioCommand) -> Result<bool, ScenarioError> {
+    let scenario: Scenario = toml::from_str(&read_to_string(&command.file).await?)?;
+    if scenario.instances.is_empty() {
+        return Err(ScenarioError::NoInstances);
+    }
+    let base_dir = command
+        .file
+        .parent()
+        .map(|dir| dir.to_path_buf())
+        .unwrap_or_default();
+
+    check_positions(&scenario.instances, scenario.range_m)?;
+    check_assertions(&scenario)?;
+
+    let mut emulators = Vec::new();
+    for instance in &scenario.instances {
+        let wasm = read(base_dir.join(&instance.file)).await?;
+        let mut emulator =
+            Emulator::from_wasm(wasm, Some(instance.name.clone()), instance.group, false).await?;
+        emulator.set_config(encode_config(&instance.config, None)?);
+        emulators.push(emulator);
+    }
+    if let Some(radio_model) = radio_model(&scenario.instances, &emulators, scenario.range_m) {
+        let radio_model = Arc::new(radio_model);
+        for emulator in &mut emulators {
+            emulator.set_radio_model(radio_model.clone());
+        }
+    }
+    let leds: Vec<_> = emulators.iter().map(|emulator| emulator.leds()).collect();
+    let mut evaluator = Evaluator::new(
+        emulators
+            .iter()
+            .map(|emulator| emulator.name().to_string())
+            .collect(),
+    );
+
+    let start_time = Instant::now();
+    if let Some(partition_secs) = command.partition {
+        let partition_start = Duration::from_secs_f32(command.partition_start);
+        let partition_end = partition_start + Duration::from_secs_f32(partition_secs);
+        let side_a: HashSet<String> = scenario
+            .instances
+            .iter()
+            .take(scenario.instances.len().div_ceil(2))
+            .map(|instance| instance.name.clone())
+            .collect();
+        println!(
+            "Partitioning {:?} from the other instances between {:.1}s and {:.1}s",
+            side_a,
+            partition_start.as_secs_f32(),
+            partition_end.as_secs_f32()
+        );
+        for emulator in &mut emulators {
+            emulator.set_partition(PartitionConfig {
+                side_a: side_a.clone(),
+                start: start_time + partition_start,
+                end: start_time + partition_end,
+            });
+        }
+        evaluator.set_partition_end(partition_end);
+    }
+    let run_instances =
+        futures::future::join_all(emulators.iter().map(|emulator| emulator.emulate()));
+    let record_snapshots = async {
+        let mut snapshot_interval = interval(Duration::from_millis(scenario.snapshot_interval_ms));
+        loop {
+            snapshot_interval.tick().await;
+            let snapshot = leds
+                .iter()
+                .map(|leds| leds.lock().unwrap().clone())
+                .collect();
+            evaluator.record(start_time.elapsed(), snapshot);
+        }
+    };
+
+    tokio::select! {
+        results = run_instances => {
+            for result in results {
+                result?;
+            }
+        }
+        _ = record_snapshots => {}
+        _ = sleep(Duration::from_secs_f32(scenario.duration_secs)) => {}
+    }
+
+    if command.partition.is_some() {
+        match evaluator.metric(Metric::ResyncTimeMs) {
+            Some(resync_time) => println!(
+                "Resynchronized {:.0}ms after the partition healed",
+                resync_time
+            ),
+            None => println!("Did not resynchronize after the partition healed"),
+        }
+    }
+
+    let mut passed = true;
+    for assertion in &scenario.assertions {
+        let result = evaluator.evaluate(assertion);
+        println!("{}", result);
+        passed &= result.passed;
+    }
+    return Ok(passed);
+}
@@ -0,0 +1,237 @@
+//! Evaluate assertions on recorded LED states.
+use serde::Deserialize;
+use std::{fmt::Display, time::Duration};
+
+/// Instances count as synchronized if their brightness peaks are at most this far apart
+pub const SYNCHRONIZED_PHASE_DIFFERENCE_MS: f64 = 50.0;
+
+/// A value that can be calculated from the recorded LED states
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Largest distance between the last brightness peaks of any two instances in milliseconds
+    MaxPhaseDifferenceMs,
+    /// Largest difference in brightness of the first LED between any two instances in the last snapshot
+    MaxBrightnessDifference,
+    /// Time from the end of the network partition until the phase difference stays below [SYNCHRONIZED_PHASE_DIFFERENCE_MS]
+    ResyncTimeMs,
+}
+
+impl Display for Metric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Metric::MaxPhaseDifferenceMs => write!(f, "max_phase_difference_ms"),
+            Metric::MaxBrightnessDifference => write!(f, "max_brightness_difference"),
+            Metric::ResyncTimeMs => write!(f, "resync_time_ms"),
+        }
+    }
+}
+
+/// Check that a metric is inside the given bounds
+#[derive(Deserialize, Debug, Clone)]
+pub struct Assertion {
+    pub metric: Metric,
+    /// Only calculate the metric for the instances with these names. All instances are used if this is `None`
+    pub instances: Option<Vec<String>>,
+    pub less_than: Option<f64>,
+    pub greater_than: Option<f64>,
+}
+
+/// The outcome of evaluating a single assertion
+#[derive(Debug, Clone)]
+pub struct AssertionResult {
+    pub assertion: Assertion,
+    /// The calculated value. None if there was not enough data
+    pub value: Option<f64>,
+    pub passed: bool,
+}
+
+impl Display for AssertionResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = if self.passed { "PASS" } else { "FAIL" };
+        match self.value {
+            Some(value) => write!(f, "{}: {} = {:.2}", status, self.assertion.metric, value)?,
+            None => write!(f, "{}: {} has no value", status, self.assertion.metric)?,
+        }
+        if let Some(instances) = &self.assertion.instances {
+            write!(f, " for {}", instances.join(", "))?;
+        }
+        if let Some(less_than) = self.assertion.less_than {
+            write!(f, " (expected < {})", less_than)?;
+        }
+        if let Some(greater_than) = self.assertion.greater_than {
+            write!(f, " (expected > {})", greater_than)?;
+        }
+        return Ok(());
+    }
+}
+
+/// The LED state of all instances at a point in time
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub time: Duration,
+    /// LED brightness values for each instance
+    pub leds: Vec<Vec<u16>>,
+}
+
+/// Records snapshots and evaluates assertions on them
+pub struct Evaluator {
+    names: Vec<String>,
+    snapshots: Vec<Snapshot>,
+    /// When the network partition ended, if there was one
+    partition_end: Option<Duration>,
+}
+
+impl Evaluator {
+    pub fn new(names: Vec<String>) -> Self {
+        return Evaluator {
+            names,
+            snapshots: Vec::new(),
+            partition_end: None,
+        };
+    }
+
+    /// Record the LED state of all instances
+    pub fn record(&mut self, time: Duration, leds: Vec<Vec<u16>>) {
+        self.snapshots.push(Snapshot { time, leds });
+    }
+
+    pub fn snapshots(&self) -> &[Snapshot] {
+        return &self.snapshots;
+    }
+
+    /// Set the time at which the network partition ended, relative to the first snapshot
+    pub fn set_partition_end(&mut self, partition_end: Duration) {
+        self.partition_end = Some(partition_end);
+    }
+
+    /// Brightness of the first LED of an instance in a snapshot
+    fn brightness(snapshot: &Snapshot, instance: usize) -> u16 {
+        return snapshot
+            .leds
+            .get(instance)
+            .and_then(|leds| leds.first())
+            .copied()
+            .unwrap_or(0);
+    }
+
+    /// Times of all local brightness maxima of an instance
+    fn peaks(&self, instance: usize) -> Vec<Duration> {
+        return self
+            .snapshots
+            .windows(3)
+            .filter(|window| {
+                let before = Self::brightness(&window[0], instance);
+                let current = Self::brightness(&window[1], instance);
+                let after = Self::brightness(&window[2], instance);
+                current > before && current >= after
+            })
+            .map(|window| window[1].time)
+            .collect();
+    }
+
+    /// Time of the last local brightness maximum of an instance
+    fn last_peak(&self, instance: usize) -> Option<Duration> {
+        return self.peaks(instance).last().copied();
+    }
+
+    /// Time from the end of the partition until the given instances stay synchronized
+    ///
+    /// None if there was no partition or if the instances were not synchronized at the end
+    fn resync_time(&self, instances: &[usize]) -> Option<Duration> {
+        let partition_end = self.partition_end?;
+        let peaks = instances
+            .iter()
+            .map(|instance| self.peaks(*instance))
+            .collect::<Vec<_>>();
+        // The phase difference between the latest peaks of all instances at the given time
+        let phase_difference_ms = |time: Duration| -> Option<f64> {
+            let latest_peaks = peaks
+                .iter()
+                .map(|peaks| {
+                    let count = peaks.partition_point(|peak| *peak <= time);
+                    count.checked_sub(1).map(|index| peaks[index])
+                })
+                .collect::<Option<Vec<_>>>()?;
+            let earliest = latest_peaks.iter().min()?;
+            let latest = latest_peaks.iter().max()?;
+            return Some((*latest - *earliest).as_secs_f64() * 1000.0);
+        };
+
+        let mut synchronized_since = None;
+        for snapshot in self
+            .snapshots
+            .iter()
+            .filter(|snapshot| snapshot.time >= partition_end)
+        {
+            let synchronized = phase_difference_ms(snapshot.time)
+                .is_some_and(|difference| difference <= SYNCHRONIZED_PHASE_DIFFERENCE_MS);
+            match (synchronized, synchronized_since) {
+                (true, None) => synchronized_since = Some(snapshot.time),
+                (false, _) => synchronized_since = None,
+                (true, Some(_)) => {}
+            }
+        }
+        return Some(synchronized_since? - partition_end);
+    }
+
+    /// Indices of the instances with the given names. Returns `None` if one of the names is unknown
+    fn instance_indices(&self, names: &[String]) -> Option<Vec<usize>> {
+        return names
+            .iter()
+            .map(|name| self.names.iter().position(|other| other == name))
+            .collect();
+    }
+
+    /// Calculate the value of a metric for all instances
+    pub fn metric(&self, metric: Metric) -> Option<f64> {
+        return self.metric_for(metric, &(0..self.names.len()).collect::<Vec<_>>());
+    }
+
+    /// Calculate the value of a metric for the instances with the given indices
+    fn metric_for(&self, metric: Metric, instances: &[usize]) -> Option<f64> {
+        match metric {
+            Metric::MaxPhaseDifferenceMs => {
+                let peaks = instances
+                    .iter()
+                    .map(|instance| self.last_peak(*instance))
+                    .collect::<Option<Vec<_>>>()?;
+                let earliest = peaks.iter().min()?;
+                let latest = peaks.iter().max()?;
+                return Some((*latest - *earliest).as_secs_f64() * 1000.0);
+            }
+            Metric::MaxBrightnessDifference => {
+                let snapshot = self.snapshots.last()?;
+                let brightness = instances
+                    .iter()
+                    .map(|instance| Self::brightness(snapshot, *instance))
+                    .collect::<Vec<_>>();
+                let min = brightness.iter().min()?;
+                let max = brightness.iter().max()?;
+                return Some((max - min) as f64);
+            }
+            Metric::ResyncTimeMs => {
+                return Some(self.resync_time(instances)?.as_secs_f64() * 1000.0);
+            }
+        }
+    }
+
+    /// Evaluate an assertion on the recorded snapshots
+    pub fn evaluate(&self, assertion: &Assertion) -> AssertionResult {
+        let value = match &assertion.instances {
+            Some(names) => self
+                .instance_indices(names)
+                .and_then(|instances| self.metric_for(assertion.metric, &instances)),
+            None => self.metric(assertion.metric),
+        };
+        let passed = value.map_or(false, |value| {
+            assertion.less_than.map_or(true, |bound| value < bound)
+                && assertion.greater_than.map_or(true, |bound| value > bound)
+        });
+        return AssertionResult {
+            assertion: assertion.clone(),
+            value,
+            passed,
+        };
+    }
+}
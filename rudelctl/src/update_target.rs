@@ -2,22 +2,47 @@
 
 use async_recursion::async_recursion;
 use bluer::{
-    gatt::remote::{Characteristic, CharacteristicWriteRequest, Service},
+    gatt::{
+        remote::{Characteristic, CharacteristicWriteRequest, Service},
+        CharacteristicWriter,
+    },
     Device, UuidExt,
 };
 use thiserror::Error;
 use tokio::io::AsyncWriteExt;
 
+// The upload parameters are written to separate characteristics before the data. They have to match the FileUploadService in the firmware
 const FILE_UPLOAD_SERVICE: u16 = 0x7892;
 const FILE_UPLOAD_SERVICE_DATA: u16 = 0x7893;
 const FILE_UPLOAD_SERVICE_HASH: u16 = 0x7894;
 const FILE_UPLOAD_SERVICE_CHECKSUMS: u16 = 0x7895;
 const FILE_UPLOAD_SERVICE_LENGTH: u16 = 0x7896;
 const FILE_UPLOAD_SERVICE_CHUNK_LENGTH: u16 = 0x7897;
+const FILE_UPLOAD_SERVICE_CHECKSUM_ALGORITHM: u16 = 0x7898;
+const FILE_UPLOAD_SERVICE_COMPRESSION: u16 = 0x7899;
+const FILE_UPLOAD_SERVICE_PROTOCOL_VERSION: u16 = 0x789B;
+const FILE_UPLOAD_SERVICE_MAX_PROTOCOL_VERSION: u16 = 0x789C;
+const FILE_UPLOAD_SERVICE_MISSING_CHUNKS: u16 = 0x789E;
+
+/// The newest version of the upload protocol that rudelctl supports
+///
+/// Devices that do not report a protocol version only support version 1.
+const PROTOCOL_VERSION: u8 = 1;
+
+/// Number of bytes that are sent without waiting before asking the device for missing chunks
+const PIPELINE_BYTES: usize = 4096;
+/// Number of times missing chunks are resent before giving up
+const MAX_RESEND_ROUNDS: usize = 8;
+/// Base 2 logarithm of the zstd window used for compressed uploads. Devices reject windows larger than 32 KiB
+const ZSTD_WINDOW_LOG: u32 = 15;
 
 const CAT_MANAGEMENT_SERVICE: u16 = 0x7992;
 const CAT_MANAGEMENT_SERVICE_PROGRAM_HASH: u16 = 0x7893;
 const CAT_MANAGEMENT_SERVICE_NAME: u16 = 0x7894;
+const CAT_MANAGEMENT_SERVICE_WASM_GUEST_CONFIG: u16 = 0x7896;
+const CAT_MANAGEMENT_SERVICE_GROUP_ID: u16 = 0x789B;
+const CAT_MANAGEMENT_SERVICE_PROGRAM_SCHEDULE: u16 = 0x789C;
+const CAT_MANAGEMENT_SERVICE_AMBIENT_LIGHT_CALIBRATION: u16 = 0x789F;
 
 #[derive(Error, Debug)]
 pub enum UpdateTargetError {
@@ -29,6 +54,33 @@ pub enum UpdateTargetError {
     MacDoesNotLookLikeAnUpdateTarget,
     #[error("Failed to connect to device")]
     FailedToConnect(bluer::Error),
+    #[error("The device does not support compressed uploads")]
+    CompressionNotSupported,
+    #[error("The device only supports the unknown upload protocol version {0}")]
+    UnsupportedProtocolVersion(u8),
+    #[error("The device does not support groups")]
+    GroupsNotSupported,
+    #[error("The device returned an invalid group ID")]
+    InvalidGroupId,
+    #[error("The device does not support program schedules")]
+    SchedulesNotSupported,
+    #[error("The device does not support a WASM guest configuration")]
+    WasmConfigNotSupported,
+    #[error("The device does not support ambient light calibration")]
+    AmbientLightCalibrationNotSupported,
+    #[error("The device returned an invalid ambient light calibration")]
+    InvalidAmbientLightCalibration,
+    #[error("The device returned an invalid program hash")]
+    InvalidProgramHash,
+    #[error("Not the requested device")]
+    NotTheRequestedDevice,
+    #[error(
+        "The device is still missing chunks after resending them {} times",
+        MAX_RESEND_ROUNDS
+    )]
+    ChunksMissing,
+    #[error("No handle was given for the {0} characteristic")]
+    MissingHandle(&'static str),
     #[error(transparent)]
     DoesNotProvideUpdateService(#[from] FindUpdateServiceError),
     #[error(transparent)]
@@ -74,43 +126,195 @@ pub async fn find_characteristic(
     return Err(FindCharacteristicError::NotFound);
 }
 
+/// The algorithm used for the per-chunk checksums
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ChecksumAlgorithm {
+    /// One CRC-8/LTE byte per chunk
+    Crc8 = 0,
+    /// One little-endian CRC-32/ISO-HDLC value per chunk
+    Crc32 = 1,
+}
+
+impl ChecksumAlgorithm {
+    /// Calculate the checksums for all chunks as little-endian bytes
+    fn checksums(&self, data: &[u8], chunk_size: usize) -> Vec<u8> {
+        match self {
+            ChecksumAlgorithm::Crc8 => {
+                let crc8_generator = crc::Crc::<u8>::new(&crc::CRC_8_LTE);
+                data.chunks(chunk_size)
+                    .map(|chunk| crc8_generator.checksum(chunk))
+                    .collect()
+            }
+            ChecksumAlgorithm::Crc32 => {
+                let crc32_generator = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+                data.chunks(chunk_size)
+                    .flat_map(|chunk| crc32_generator.checksum(chunk).to_le_bytes())
+                    .collect()
+            }
+        }
+    }
+}
+
+/// The ambient light calibration of a device together with the current sensor reading
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientLightCalibration {
+    /// Lux per raw sensor count
+    pub slope: f32,
+    /// Lux at a raw reading of 0
+    pub intercept: f32,
+    /// The uncalibrated sensor reading at the time the calibration was read
+    pub raw: u32,
+}
+
+/// Attribute handles of the services and characteristics of a device
+///
+/// Used to connect without discovering the services. The handles can change between firmware versions, `rudelctl dump` prints them for a device. Handles can be given in decimal or as hex with a `0x` prefix.
+#[derive(clap::Args, Debug, Clone, Default)]
+#[command(next_help_heading = "Characteristic handles")]
+pub struct CharacteristicHandles {
+    /// Handle of the file upload service
+    #[arg(long = "handle-upload-service", value_parser = parse_handle)]
+    pub upload_service: Option<u16>,
+    /// Handle of the data characteristic of the file upload service
+    #[arg(long = "handle-data", value_parser = parse_handle)]
+    pub data: Option<u16>,
+    /// Handle of the hash characteristic of the file upload service
+    #[arg(long = "handle-hash", value_parser = parse_handle)]
+    pub hash: Option<u16>,
+    /// Handle of the checksums characteristic of the file upload service
+    #[arg(long = "handle-checksums", value_parser = parse_handle)]
+    pub checksums: Option<u16>,
+    /// Handle of the length characteristic of the file upload service. Writing it starts the upload
+    #[arg(long = "handle-length", value_parser = parse_handle)]
+    pub length: Option<u16>,
+    /// Handle of the chunk length characteristic of the file upload service
+    #[arg(long = "handle-chunk-length", value_parser = parse_handle)]
+    pub chunk_length: Option<u16>,
+    /// Handle of the checksum algorithm characteristic. Without it CRC-8 checksums are used
+    #[arg(long = "handle-checksum-algorithm", value_parser = parse_handle)]
+    pub checksum_algorithm: Option<u16>,
+    /// Handle of the compression characteristic. Without it files can not be compressed
+    #[arg(long = "handle-compression", value_parser = parse_handle)]
+    pub compression: Option<u16>,
+    /// Handle of the missing chunks characteristic. Without it missing chunks are not resent
+    #[arg(long = "handle-missing-chunks", value_parser = parse_handle)]
+    pub missing_chunks: Option<u16>,
+    /// Handle of the protocol version characteristic
+    #[arg(long = "handle-protocol-version", value_parser = parse_handle)]
+    pub protocol_version: Option<u16>,
+    /// Handle of the max protocol version characteristic
+    #[arg(long = "handle-max-protocol-version", value_parser = parse_handle)]
+    pub max_protocol_version: Option<u16>,
+    /// Handle of the cat management service
+    #[arg(long = "handle-management-service", value_parser = parse_handle)]
+    pub management_service: Option<u16>,
+    /// Handle of the program hash characteristic of the cat management service
+    #[arg(long = "handle-program-hash", value_parser = parse_handle)]
+    pub program_hash: Option<u16>,
+    /// Handle of the name characteristic of the cat management service
+    #[arg(long = "handle-name", value_parser = parse_handle)]
+    pub name: Option<u16>,
+}
+
+/// IDs of the [CharacteristicHandles] arguments that are needed to connect without discovery
+pub const REQUIRED_HANDLES: [&str; 9] = [
+    "upload_service",
+    "data",
+    "hash",
+    "checksums",
+    "length",
+    "chunk_length",
+    "management_service",
+    "program_hash",
+    "name",
+];
+
+/// Parse a handle in decimal or as hex with a `0x` prefix
+fn parse_handle(value: &str) -> Result<u16, std::num::ParseIntError> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        return u16::from_str_radix(hex, 16);
+    }
+    return value.parse();
+}
+
+/// Get a handle that is needed to connect without discovery
+fn required_handle(handle: Option<u16>, name: &'static str) -> Result<u16, UpdateTargetError> {
+    return handle.ok_or(UpdateTargetError::MissingHandle(name));
+}
+
+/// Get the characteristic with the given handle, if there is one
+async fn optional_characteristic(
+    service: &Service,
+    handle: Option<u16>,
+) -> Result<Option<Characteristic>, bluer::Error> {
+    let Some(handle) = handle else {
+        return Ok(None);
+    };
+    return Ok(Some(service.characteristic(handle).await?));
+}
+
 pub struct UpdateTarget {
     data_characteristic: Characteristic,
     hash_characteristic: Characteristic,
     checksums_characteristic: Characteristic,
     length_characteristic: Characteristic,
     chunk_length_characteristic: Characteristic,
+    /// Only present on devices that support CRC-32 chunk checksums
+    checksum_algorithm_characteristic: Option<Characteristic>,
+    /// Only present on devices that support compressed uploads
+    compression_characteristic: Option<Characteristic>,
+    /// Only present on devices that report missing chunks
+    missing_chunks_characteristic: Option<Characteristic>,
+    /// Only present on devices that support protocol versioning
+    protocol_version_characteristic: Option<Characteristic>,
+    /// Only present on devices that support protocol versioning
+    max_protocol_version_characteristic: Option<Characteristic>,
 
     program_hash_characteristic: Characteristic,
     name_characteristic: Characteristic,
+    /// Only present on devices that support a guest configuration
+    wasm_guest_config_characteristic: Option<Characteristic>,
+    /// Only present on devices that support groups
+    group_id_characteristic: Option<Characteristic>,
+    /// Only present on devices that support program schedules
+    program_schedule_characteristic: Option<Characteristic>,
+    /// Only present on devices that support ambient light calibration
+    ambient_light_calibration_characteristic: Option<Characteristic>,
 }
 
-impl UpdateTarget {
-    pub async fn new_from_peripheral(device: &Device) -> Result<UpdateTarget, UpdateTargetError> {
-        let address = device.address();
-        // println!("Checking {}", address);
-        if !(address.0.starts_with(&[0x24, 0xec, 0x4b])) {
-            return Err(UpdateTargetError::MacDoesNotLookLikeAnUpdateTarget);
-        }
-        // println!("Found MAC {}", address);
-
-        if !device.is_connected().await? {
-            // println!("Connecting...");
-            for attempt in 0..=2 {
-                match device.connect().await {
-                    Ok(()) => break,
-                    Err(err) if attempt == 2 => {
-                        if !(device.is_connected().await.unwrap_or(false)) {
-                            return Err(UpdateTargetError::FailedToConnect(err));
-                        }
-                        break;
-                    }
-                    Err(err) => {
-                        eprintln!("Connect error: {}", &err);
+/// Check that the device looks like a rudelblinken device and connect to it
+async fn connect(device: &Device) -> Result<(), UpdateTargetError> {
+    let address = device.address();
+    // println!("Checking {}", address);
+    if !(address.0.starts_with(&[0x24, 0xec, 0x4b])) {
+        return Err(UpdateTargetError::MacDoesNotLookLikeAnUpdateTarget);
+    }
+    // println!("Found MAC {}", address);
+
+    if !device.is_connected().await? {
+        // println!("Connecting...");
+        for attempt in 0..=2 {
+            match device.connect().await {
+                Ok(()) => break,
+                Err(err) if attempt == 2 => {
+                    if !(device.is_connected().await.unwrap_or(false)) {
+                        return Err(UpdateTargetError::FailedToConnect(err));
                     }
+                    break;
+                }
+                Err(err) => {
+                    eprintln!("Connect error: {}", &err);
                 }
             }
         }
+    }
+    return Ok(());
+}
+
+impl UpdateTarget {
+    pub async fn new_from_peripheral(device: &Device) -> Result<UpdateTarget, UpdateTargetError> {
+        connect(device).await?;
 
         // // // Sometimes this is required to actually discover services
         let update_service = find_service(&device, FILE_UPLOAD_SERVICE).await?;
@@ -126,6 +330,45 @@ impl UpdateTarget {
             find_characteristic(&update_service, FILE_UPLOAD_SERVICE_LENGTH).await?;
         let chunk_length_characteristic =
             find_characteristic(&update_service, FILE_UPLOAD_SERVICE_CHUNK_LENGTH).await?;
+        let checksum_algorithm_characteristic =
+            match find_characteristic(&update_service, FILE_UPLOAD_SERVICE_CHECKSUM_ALGORITHM)
+                .await
+            {
+                Ok(characteristic) => Some(characteristic),
+                Err(FindCharacteristicError::NotFound) => None,
+                Err(err) => return Err(err.into()),
+            };
+        let compression_characteristic =
+            match find_characteristic(&update_service, FILE_UPLOAD_SERVICE_COMPRESSION).await {
+                Ok(characteristic) => Some(characteristic),
+                Err(FindCharacteristicError::NotFound) => None,
+                Err(err) => return Err(err.into()),
+            };
+
+        let missing_chunks_characteristic =
+            match find_characteristic(&update_service, FILE_UPLOAD_SERVICE_MISSING_CHUNKS).await {
+                Ok(characteristic) => Some(characteristic),
+                Err(FindCharacteristicError::NotFound) => None,
+                Err(err) => return Err(err.into()),
+            };
+        let protocol_version_characteristic = match find_characteristic(
+            &update_service,
+            FILE_UPLOAD_SERVICE_PROTOCOL_VERSION,
+        )
+        .await
+        {
+            Ok(characteristic) => Some(characteristic),
+            Err(FindCharacteristicError::NotFound) => None,
+            Err(err) => return Err(err.into()),
+        };
+        let max_protocol_version_characteristic =
+            match find_characteristic(&update_service, FILE_UPLOAD_SERVICE_MAX_PROTOCOL_VERSION)
+                .await
+            {
+                Ok(characteristic) => Some(characteristic),
+                Err(FindCharacteristicError::NotFound) => None,
+                Err(err) => return Err(err.into()),
+            };
 
         let cat_management_service = find_service(&device, CAT_MANAGEMENT_SERVICE).await?;
 
@@ -134,6 +377,44 @@ impl UpdateTarget {
         let program_hash_characteristic =
             find_characteristic(&cat_management_service, CAT_MANAGEMENT_SERVICE_PROGRAM_HASH)
                 .await?;
+        let wasm_guest_config_characteristic = match find_characteristic(
+            &cat_management_service,
+            CAT_MANAGEMENT_SERVICE_WASM_GUEST_CONFIG,
+        )
+        .await
+        {
+            Ok(characteristic) => Some(characteristic),
+            Err(FindCharacteristicError::NotFound) => None,
+            Err(err) => return Err(err.into()),
+        };
+        let group_id_characteristic =
+            match find_characteristic(&cat_management_service, CAT_MANAGEMENT_SERVICE_GROUP_ID)
+                .await
+            {
+                Ok(characteristic) => Some(characteristic),
+                Err(FindCharacteristicError::NotFound) => None,
+                Err(err) => return Err(err.into()),
+            };
+        let program_schedule_characteristic = match find_characteristic(
+            &cat_management_service,
+            CAT_MANAGEMENT_SERVICE_PROGRAM_SCHEDULE,
+        )
+        .await
+        {
+            Ok(characteristic) => Some(characteristic),
+            Err(FindCharacteristicError::NotFound) => None,
+            Err(err) => return Err(err.into()),
+        };
+        let ambient_light_calibration_characteristic = match find_characteristic(
+            &cat_management_service,
+            CAT_MANAGEMENT_SERVICE_AMBIENT_LIGHT_CALIBRATION,
+        )
+        .await
+        {
+            Ok(characteristic) => Some(characteristic),
+            Err(FindCharacteristicError::NotFound) => None,
+            Err(err) => return Err(err.into()),
+        };
 
         return Ok(UpdateTarget {
             data_characteristic,
@@ -141,8 +422,88 @@ impl UpdateTarget {
             checksums_characteristic,
             length_characteristic,
             chunk_length_characteristic,
+            checksum_algorithm_characteristic,
+            compression_characteristic,
+            missing_chunks_characteristic,
+            protocol_version_characteristic,
+            max_protocol_version_characteristic,
             name_characteristic,
             program_hash_characteristic,
+            wasm_guest_config_characteristic,
+            group_id_characteristic,
+            program_schedule_characteristic,
+            ambient_light_calibration_characteristic,
+        });
+    }
+
+    /// Connect to a device using known handles instead of discovering its services
+    ///
+    /// This is a lot faster for programming many devices with the same firmware. It is unsafe in the sense that nothing checks that the handles belong to the expected characteristics, so wrong handles can write garbage to the device. The guest configuration, group, schedule and calibration characteristics are not available.
+    pub async fn new_from_handles(
+        device: &Device,
+        handles: &CharacteristicHandles,
+    ) -> Result<UpdateTarget, UpdateTargetError> {
+        connect(device).await?;
+
+        let update_service = device
+            .service(required_handle(handles.upload_service, "upload service")?)
+            .await?;
+        let data_characteristic = update_service
+            .characteristic(required_handle(handles.data, "data")?)
+            .await?;
+        let hash_characteristic = update_service
+            .characteristic(required_handle(handles.hash, "hash")?)
+            .await?;
+        let checksums_characteristic = update_service
+            .characteristic(required_handle(handles.checksums, "checksums")?)
+            .await?;
+        let length_characteristic = update_service
+            .characteristic(required_handle(handles.length, "length")?)
+            .await?;
+        let chunk_length_characteristic = update_service
+            .characteristic(required_handle(handles.chunk_length, "chunk length")?)
+            .await?;
+        let checksum_algorithm_characteristic =
+            optional_characteristic(&update_service, handles.checksum_algorithm).await?;
+        let compression_characteristic =
+            optional_characteristic(&update_service, handles.compression).await?;
+        let missing_chunks_characteristic =
+            optional_characteristic(&update_service, handles.missing_chunks).await?;
+        let protocol_version_characteristic =
+            optional_characteristic(&update_service, handles.protocol_version).await?;
+        let max_protocol_version_characteristic =
+            optional_characteristic(&update_service, handles.max_protocol_version).await?;
+
+        let cat_management_service = device
+            .service(required_handle(
+                handles.management_service,
+                "management service",
+            )?)
+            .await?;
+        let name_characteristic = cat_management_service
+            .characteristic(required_handle(handles.name, "name")?)
+            .await?;
+        let program_hash_characteristic = cat_management_service
+            .characteristic(required_handle(handles.program_hash, "program hash")?)
+            .await?;
+
+        return Ok(UpdateTarget {
+            data_characteristic,
+            hash_characteristic,
+            checksums_characteristic,
+            length_characteristic,
+            chunk_length_characteristic,
+            checksum_algorithm_characteristic,
+            compression_characteristic,
+            missing_chunks_characteristic,
+            protocol_version_characteristic,
+            max_protocol_version_characteristic,
+            name_characteristic,
+            program_hash_characteristic,
+            wasm_guest_config_characteristic: None,
+            group_id_characteristic: None,
+            program_schedule_characteristic: None,
+            ambient_light_calibration_characteristic: None,
         });
     }
 
@@ -155,6 +516,101 @@ impl UpdateTarget {
         return Ok(name.to_string());
     }
 
+    /// Get the group ID of the device. Devices without group support are in group 0
+    pub async fn get_group_id(&self) -> Result<u8, UpdateTargetError> {
+        let Some(group_id_characteristic) = &self.group_id_characteristic else {
+            return Ok(0);
+        };
+        let group_id = group_id_characteristic.read().await?;
+        let [group_id] = group_id[..] else {
+            return Err(UpdateTargetError::InvalidGroupId);
+        };
+        return Ok(group_id);
+    }
+
+    /// Set the group ID of the device
+    pub async fn set_group_id(&self, group_id: u8) -> Result<(), UpdateTargetError> {
+        let Some(group_id_characteristic) = &self.group_id_characteristic else {
+            return Err(UpdateTargetError::GroupsNotSupported);
+        };
+        group_id_characteristic.write(&[group_id]).await?;
+        return Ok(());
+    }
+
+    /// Set the configuration that is passed to the WASM guest
+    pub async fn set_wasm_config(&self, config: &[u8]) -> Result<(), UpdateTargetError> {
+        let Some(wasm_guest_config_characteristic) = &self.wasm_guest_config_characteristic else {
+            return Err(UpdateTargetError::WasmConfigNotSupported);
+        };
+        wasm_guest_config_characteristic.write(config).await?;
+        return Ok(());
+    }
+
+    /// Upload the programs and run them in sequence, each for the given duration in milliseconds
+    ///
+    /// The schedule repeats until another program or schedule is set.
+    pub async fn run_schedule(
+        &self,
+        programs: &[(Vec<u8>, u32)],
+        compress: bool,
+    ) -> Result<(), UpdateTargetError> {
+        let Some(program_schedule_characteristic) = &self.program_schedule_characteristic else {
+            return Err(UpdateTargetError::SchedulesNotSupported);
+        };
+        let mut schedule = Vec::new();
+        for (data, duration_ms) in programs {
+            let hash = self.upload_file(data, compress).await?;
+            schedule.extend_from_slice(&hash);
+            schedule.extend_from_slice(&duration_ms.to_le_bytes());
+        }
+        program_schedule_characteristic
+            .write_ext(
+                &schedule,
+                &CharacteristicWriteRequest {
+                    offset: 0,
+                    op_type: bluer::gatt::WriteOp::Reliable,
+                    prepare_authorize: false,
+                    _non_exhaustive: (),
+                },
+            )
+            .await?;
+        return Ok(());
+    }
+
+    /// Get the ambient light calibration and the current raw reading of the light sensor
+    pub async fn get_ambient_light_calibration(
+        &self,
+    ) -> Result<AmbientLightCalibration, UpdateTargetError> {
+        let Some(characteristic) = &self.ambient_light_calibration_characteristic else {
+            return Err(UpdateTargetError::AmbientLightCalibrationNotSupported);
+        };
+        let encoded = characteristic.read().await?;
+        let Ok(encoded): Result<[u8; 12], _> = encoded.try_into() else {
+            return Err(UpdateTargetError::InvalidAmbientLightCalibration);
+        };
+        return Ok(AmbientLightCalibration {
+            slope: f32::from_le_bytes(encoded[0..4].try_into().unwrap()),
+            intercept: f32::from_le_bytes(encoded[4..8].try_into().unwrap()),
+            raw: u32::from_le_bytes(encoded[8..12].try_into().unwrap()),
+        });
+    }
+
+    /// Set the ambient light calibration. It takes effect after the device restarted
+    pub async fn set_ambient_light_calibration(
+        &self,
+        slope: f32,
+        intercept: f32,
+    ) -> Result<(), UpdateTargetError> {
+        let Some(characteristic) = &self.ambient_light_calibration_characteristic else {
+            return Err(UpdateTargetError::AmbientLightCalibrationNotSupported);
+        };
+        let mut encoded = Vec::with_capacity(8);
+        encoded.extend_from_slice(&slope.to_le_bytes());
+        encoded.extend_from_slice(&intercept.to_le_bytes());
+        characteristic.write(&encoded).await?;
+        return Ok(());
+    }
+
     // pub async fn set_name(&self, name: String) -> Result<String, UpdateTargetError> {
     //     let name_bytes = self.name_characteristic.read().await?;
     //     if name_bytes.len() < 3 || name_bytes.len() > 32 {
@@ -164,16 +620,29 @@ impl UpdateTarget {
     //     return Ok(name.to_string());
     // }
 
-    // pub async fn get_program_hash(&self) -> Result<[u8; 32], UpdateTargetError> {
-    //     let program_hash = self.program_hash_characteristic.read().await?;
-    //     let Ok(program_hash): Result<[u8; 32], _> = program_hash.try_into() else {
-    //         todo!();
-    //     };
-    //     return Ok(program_hash);
-    // }
+    /// Get the hash of the program that is currently running. `None` if no program is running
+    pub async fn get_program_hash(&self) -> Result<Option<[u8; 32]>, UpdateTargetError> {
+        let program_hash = self.program_hash_characteristic.read().await?;
+        let Ok(program_hash): Result<[u8; 32], _> = program_hash.try_into() else {
+            return Err(UpdateTargetError::InvalidProgramHash);
+        };
+        // The device reports zeroes if there is no main program
+        if program_hash == [0u8; 32] {
+            return Ok(None);
+        }
+        return Ok(Some(program_hash));
+    }
 
-    pub async fn run_program(&self, data: &[u8]) -> Result<(), UpdateTargetError> {
-        let program_hash = self.upload_file(data).await?;
+    /// The strongest checksum algorithm supported by the device
+    pub fn checksum_algorithm(&self) -> ChecksumAlgorithm {
+        if self.checksum_algorithm_characteristic.is_some() {
+            return ChecksumAlgorithm::Crc32;
+        }
+        return ChecksumAlgorithm::Crc8;
+    }
+
+    pub async fn run_program(&self, data: &[u8], compress: bool) -> Result<(), UpdateTargetError> {
+        let program_hash = self.upload_file(data, compress).await?;
         self.program_hash_characteristic
             .write_ext(
                 &program_hash,
@@ -188,24 +657,64 @@ impl UpdateTarget {
         return Ok(());
     }
 
+    /// The newest upload protocol version that is supported by both rudelctl and the device
+    async fn protocol_version(&self) -> Result<u8, UpdateTargetError> {
+        let Some(max_protocol_version_characteristic) = &self.max_protocol_version_characteristic
+        else {
+            return Ok(1);
+        };
+        let max_protocol_version = max_protocol_version_characteristic
+            .read()
+            .await?
+            .first()
+            .copied()
+            .unwrap_or(1);
+        if max_protocol_version == 0 {
+            return Err(UpdateTargetError::UnsupportedProtocolVersion(
+                max_protocol_version,
+            ));
+        }
+        return Ok(std::cmp::min(max_protocol_version, PROTOCOL_VERSION));
+    }
+
+    /// Upload a file and return its hash
+    ///
+    /// If `compress` is set, the file is transferred as a zstd frame. The hash is always calculated over the uncompressed data.
     #[async_recursion]
-    pub async fn upload_file(&self, data: &[u8]) -> Result<[u8; 32], UpdateTargetError> {
+    pub async fn upload_file(
+        &self,
+        data: &[u8],
+        compress: bool,
+    ) -> Result<[u8; 32], UpdateTargetError> {
         let mut hasher = blake3::Hasher::new();
         hasher.update(&data);
         // TODO: I am sure there is a better way to convert this into an array but I didnt find it after 10 minutes.
         let mut hash: [u8; 32] = [0; 32];
         hash.copy_from_slice(hasher.finalize().as_bytes());
 
+        // Compressed uploads still specify the length of the uncompressed content
+        let length = data.len() as u32;
+        let compressed_data;
+        let data = if compress {
+            if self.compression_characteristic.is_none() {
+                return Err(UpdateTargetError::CompressionNotSupported);
+            }
+            let mut compressor = zstd::bulk::Compressor::new(zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            // The device decompresses while receiving and only keeps a small window in memory
+            compressor.set_parameter(zstd::stream::raw::CParameter::WindowLog(ZSTD_WINDOW_LOG))?;
+            compressed_data = compressor.compress(data)?;
+            compressed_data.as_slice()
+        } else {
+            data
+        };
+
         // -2 for the length
         // -28 was found to be good by empirical methods
         let chunk_size: u16 = (self.data_characteristic.mtu().await? as u16) - 28 - 2;
         // println!("{chunk_size}");
 
-        let crc8_generator = crc::Crc::<u8>::new(&crc::CRC_8_LTE);
-        let checksums: Vec<u8> = data
-            .chunks(chunk_size as usize)
-            .map(|chunk| crc8_generator.checksum(chunk))
-            .collect();
+        let checksum_algorithm = self.checksum_algorithm();
+        let checksums = checksum_algorithm.checksums(data, chunk_size as usize);
 
         let chunks: Vec<Vec<u8>> = data
             .chunks(chunk_size as usize)
@@ -218,29 +727,54 @@ impl UpdateTarget {
             })
             .collect();
 
+        let protocol_version = self.protocol_version().await?;
+        if let Some(protocol_version_characteristic) = &self.protocol_version_characteristic {
+            protocol_version_characteristic
+                .write(&[protocol_version])
+                .await?;
+        }
+
         let checksums_data = checksums.as_slice();
         if checksums_data.len() < 32 {
             self.checksums_characteristic.write(checksums_data).await?;
         } else {
-            let checksums_file_hash = self.upload_file(checksums_data).await?;
+            let checksums_file_hash = self.upload_file(checksums_data, false).await?;
             self.checksums_characteristic
                 .write(&checksums_file_hash)
                 .await?;
         }
 
+        if let Some(checksum_algorithm_characteristic) = &self.checksum_algorithm_characteristic {
+            checksum_algorithm_characteristic
+                .write(&[checksum_algorithm as u8])
+                .await?;
+        }
+        if let Some(compression_characteristic) = &self.compression_characteristic {
+            compression_characteristic
+                .write(&[compress as u8])
+                .await?;
+        }
         self.length_characteristic
-            .write(&(data.len() as u32).to_le_bytes())
+            .write(&length.to_le_bytes())
             .await?;
         self.chunk_length_characteristic
             .write(&(chunk_size as u16).to_le_bytes())
             .await?;
         self.hash_characteristic.write(&hash).await?;
 
+        // Send the chunks in batches without waiting for responses. After each batch, chunks that got lost are resent.
+        let batch_size = std::cmp::max(1, PIPELINE_BYTES / chunk_size as usize);
         let mut write_io = self.data_characteristic.write_io().await?;
-        for chunk in chunks {
-            write_io.send(chunk.as_slice()).await?;
+        let mut sent_chunks = 0;
+        for batch in chunks.chunks(batch_size) {
+            for chunk in batch {
+                write_io.send(chunk.as_slice()).await?;
+            }
+            write_io.flush().await?;
+            sent_chunks += batch.len();
+            self.resend_missing_chunks(&mut write_io, &chunks, sent_chunks)
+                .await?;
         }
-        write_io.flush().await?;
         write_io.shutdown().await?;
 
         // Force flushing by doing a reliable write to any property
@@ -258,4 +792,36 @@ impl UpdateTarget {
 
         return Ok(hash);
     }
+
+    /// Resend the chunks among the first `sent_chunks` chunks that the device reports as missing
+    ///
+    /// Does nothing if the device does not report missing chunks.
+    async fn resend_missing_chunks(
+        &self,
+        write_io: &mut CharacteristicWriter,
+        chunks: &[Vec<u8>],
+        sent_chunks: usize,
+    ) -> Result<(), UpdateTargetError> {
+        let Some(missing_chunks_characteristic) = &self.missing_chunks_characteristic else {
+            return Ok(());
+        };
+        for _ in 0..MAX_RESEND_ROUNDS {
+            // Reading is answered after the chunks before it were processed
+            let missing_chunks: Vec<usize> = missing_chunks_characteristic
+                .read()
+                .await?
+                .chunks_exact(2)
+                .map(|index| u16::from_le_bytes([index[0], index[1]]) as usize)
+                .filter(|index| *index < sent_chunks)
+                .collect();
+            if missing_chunks.is_empty() {
+                return Ok(());
+            }
+            for index in missing_chunks {
+                write_io.send(chunks[index].as_slice()).await?;
+            }
+            write_io.flush().await?;
+        }
+        return Err(UpdateTargetError::ChunksMissing);
+    }
 }
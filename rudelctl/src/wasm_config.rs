@@ -0,0 +1,143 @@
+//! Encode `key=value` pairs into the configuration format of the WASM guests
+//!
+//! The format is described in the `config` module of the SDK. Every entry is the key and the value, each prefixed with its length as a single byte.
+//!
+//! Without a schema all values are stored as strings. A schema is a TOML file that maps every key to its type:
+//!
+//! ```toml
+//! nudge_strength = "f32"
+//! ms_per_step = "u32"
+//! leader = "bool"
+//! ```
+//!
+//! Supported types are `u8`, `u16`, `u32`, `i8`, `i16`, `i32`, `f32`, `bool` and `string`.
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+use thiserror::Error;
+
+/// The maximum length of a GATT attribute value
+///
+/// The firmware stores the config as a single NVS blob, which can be a lot larger, so this is the limiting factor.
+pub const MAX_CONFIG_LENGTH: usize = 512;
+/// Keys and values are prefixed with a single byte length
+const MAX_ENTRY_LENGTH: usize = u8::MAX as usize;
+
+#[derive(Error, Debug)]
+pub enum WasmConfigError {
+    #[error("Failed to read the schema")]
+    IoError(#[from] std::io::Error),
+    #[error("The schema is not valid")]
+    InvalidSchema(#[from] toml::de::Error),
+    #[error("{0} is not a key=value pair")]
+    MissingEquals(String),
+    #[error("The key {0} is not in the schema")]
+    UnknownKey(String),
+    #[error("{value} is not a valid {value_type} for {key}")]
+    InvalidValue {
+        key: String,
+        value: String,
+        value_type: ValueType,
+    },
+    #[error("The key or value of {0} is longer than 255 bytes")]
+    EntryTooLong(String),
+    #[error("The config is {0} bytes long, but can be at most {MAX_CONFIG_LENGTH} bytes")]
+    ConfigTooLong(usize),
+}
+
+/// Type of a value in the schema
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    U8,
+    U16,
+    U32,
+    I8,
+    I16,
+    I32,
+    F32,
+    Bool,
+    String,
+}
+
+impl std::fmt::Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ValueType::U8 => "u8",
+            ValueType::U16 => "u16",
+            ValueType::U32 => "u32",
+            ValueType::I8 => "i8",
+            ValueType::I16 => "i16",
+            ValueType::I32 => "i32",
+            ValueType::F32 => "f32",
+            ValueType::Bool => "bool",
+            ValueType::String => "string",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+impl ValueType {
+    /// Convert a value to its little-endian representation. Returns `None` if it is not valid for this type
+    fn encode(&self, value: &str) -> Option<Vec<u8>> {
+        return Some(match self {
+            ValueType::U8 => value.parse::<u8>().ok()?.to_le_bytes().to_vec(),
+            ValueType::U16 => value.parse::<u16>().ok()?.to_le_bytes().to_vec(),
+            ValueType::U32 => value.parse::<u32>().ok()?.to_le_bytes().to_vec(),
+            ValueType::I8 => value.parse::<i8>().ok()?.to_le_bytes().to_vec(),
+            ValueType::I16 => value.parse::<i16>().ok()?.to_le_bytes().to_vec(),
+            ValueType::I32 => value.parse::<i32>().ok()?.to_le_bytes().to_vec(),
+            ValueType::F32 => value.parse::<f32>().ok()?.to_le_bytes().to_vec(),
+            ValueType::Bool => vec![value.parse::<bool>().ok()? as u8],
+            ValueType::String => value.as_bytes().to_vec(),
+        });
+    }
+}
+
+/// Maps the keys of a guest config to the types of their values
+pub type Schema = HashMap<String, ValueType>;
+
+/// Load a schema from a TOML file
+pub async fn load_schema(path: &Path) -> Result<Schema, WasmConfigError> {
+    let content = tokio::fs::read_to_string(path).await?;
+    return Ok(toml::from_str(&content)?);
+}
+
+/// Encode `key=value` pairs into a guest config
+///
+/// If a schema is given, all keys have to be in it and the values are encoded according to their type.
+pub fn encode_config(
+    pairs: &[String],
+    schema: Option<&Schema>,
+) -> Result<Vec<u8>, WasmConfigError> {
+    let mut config = Vec::new();
+    for pair in pairs {
+        let Some((key, value)) = pair.split_once('=') else {
+            return Err(WasmConfigError::MissingEquals(pair.clone()));
+        };
+        let value_type = match schema {
+            Some(schema) => *schema
+                .get(key)
+                .ok_or_else(|| WasmConfigError::UnknownKey(key.to_string()))?,
+            None => ValueType::String,
+        };
+        let encoded_value =
+            value_type
+                .encode(value)
+                .ok_or_else(|| WasmConfigError::InvalidValue {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                    value_type,
+                })?;
+        if key.len() > MAX_ENTRY_LENGTH || encoded_value.len() > MAX_ENTRY_LENGTH {
+            return Err(WasmConfigError::EntryTooLong(key.to_string()));
+        }
+        config.push(key.len() as u8);
+        config.extend_from_slice(key.as_bytes());
+        config.push(encoded_value.len() as u8);
+        config.extend_from_slice(&encoded_value);
+    }
+    if config.len() > MAX_CONFIG_LENGTH {
+        return Err(WasmConfigError::ConfigTooLong(config.len()));
+    }
+    return Ok(config);
+}
@@ -0,0 +1,141 @@
+//! Run the `rudelctl` binary against emulated devices
+//!
+//! The upload commands talk to the devices over BlueZ, which has no in-memory replacement yet. These tests cover everything from loading the WASM files to evaluating the LED states, without Bluetooth.
+use std::{path::Path, process::Command};
+
+/// Write a scenario with two instances of the given WASM file and run it with `rudelctl scenario`
+///
+/// The guest needs to keep running for the whole scenario, the emulator stops when a guest exits.
+fn run_scenario(directory: &Path, wasm_file: &str, assertions: &str) -> std::process::Output {
+    let wasm_path = std::fs::canonicalize(wasm_file).unwrap();
+    let scenario = format!(
+        r#"
+duration_secs = 1.0
+
+[[instances]]
+name = "cat-a"
+file = '{0}'
+
+[[instances]]
+name = "cat-b"
+file = '{0}'
+
+{1}
+"#,
+        wasm_path.display(),
+        assertions
+    );
+    return run_scenario_file(directory, &scenario);
+}
+
+/// Write the scenario to a file and run it with `rudelctl scenario`
+fn run_scenario_file(directory: &Path, scenario: &str) -> std::process::Output {
+    let scenario_path = directory.join("scenario.toml");
+    std::fs::write(&scenario_path, scenario).unwrap();
+
+    return Command::new(env!("CARGO_BIN_EXE_rudelctl"))
+        .arg("scenario")
+        .arg(&scenario_path)
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn emulated_devices_that_do_not_set_leds_pass_a_brightness_assertion() {
+    let directory = tempfile::tempdir().unwrap();
+    let output = run_scenario(
+        directory.path(),
+        "../wasm-binaries/binaries/infinite_loop_yielding.wasm",
+        r#"
+[[assertions]]
+metric = "max_brightness_difference"
+less_than = 1.0
+"#,
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("PASS"));
+}
+
+#[test]
+fn a_failed_assertion_fails_the_scenario() {
+    let directory = tempfile::tempdir().unwrap();
+    let output = run_scenario(
+        directory.path(),
+        "../wasm-binaries/binaries/infinite_loop_yielding.wasm",
+        r#"
+[[assertions]]
+metric = "max_brightness_difference"
+greater_than = 1.0
+"#,
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("FAIL"));
+}
+
+#[test]
+fn instances_can_be_placed_in_a_chain() {
+    let directory = tempfile::tempdir().unwrap();
+    let wasm_path =
+        std::fs::canonicalize("../wasm-binaries/binaries/infinite_loop_yielding.wasm").unwrap();
+    let scenario = format!(
+        r#"
+duration_secs = 1.0
+range_m = 6.0
+
+[[instances]]
+name = "chain-a"
+file = '{0}'
+position = [0.0, 0.0]
+
+[[instances]]
+name = "chain-b"
+file = '{0}'
+position = [5.0, 0.0]
+
+[[instances]]
+name = "chain-c"
+file = '{0}'
+position = [10.0, 0.0]
+
+[[assertions]]
+metric = "max_brightness_difference"
+less_than = 1.0
+"#,
+        wasm_path.display()
+    );
+    let output = run_scenario_file(directory.path(), &scenario);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn a_range_needs_positions_for_all_instances() {
+    let directory = tempfile::tempdir().unwrap();
+    let wasm_path =
+        std::fs::canonicalize("../wasm-binaries/binaries/infinite_loop_yielding.wasm").unwrap();
+    let scenario = format!(
+        r#"
+duration_secs = 1.0
+range_m = 6.0
+
+[[instances]]
+name = "range-a"
+file = '{0}'
+position = [0.0, 0.0]
+
+[[instances]]
+name = "range-b"
+file = '{0}'
+"#,
+        wasm_path.display()
+    );
+    let output = run_scenario_file(directory.path(), &scenario);
+    assert!(!output.status.success());
+}
@@ -1,5 +1,6 @@
 use rudelblinken_sdk::{
     export, exports, get_name, log, time, yield_now, Advertisement, BleGuest, Guest, LogLevel,
+    TimerGuest,
 };
 use talc::{ClaimOnOom, Span, Talc, Talck};
 
@@ -18,5 +19,8 @@ impl Guest for TestGuest {
 impl BleGuest for TestGuest {
     fn on_advertisement(_advertisement: Advertisement) {}
 }
+impl TimerGuest for TestGuest {
+    fn on_timer(_id: u8, _fired_at: u64) {}
+}
 
 export! {TestGuest}
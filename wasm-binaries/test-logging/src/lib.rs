@@ -1,5 +1,6 @@
 use rudelblinken_sdk::{
     export, exports, get_name, log, time, yield_now, Advertisement, BleGuest, Guest, LogLevel,
+    TimerGuest,
 };
 use talc::{ClaimOnOom, Span, Talc, Talck};
 
@@ -22,5 +23,8 @@ impl Guest for TestLogging {
 impl BleGuest for TestLogging {
     fn on_advertisement(_advertisement: Advertisement) {}
 }
+impl TimerGuest for TestLogging {
+    fn on_timer(_id: u8, _fired_at: u64) {}
+}
 
 export! {TestLogging}